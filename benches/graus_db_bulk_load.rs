@@ -0,0 +1,44 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use graus_db::GrausDb;
+use tempfile::TempDir;
+
+const KEYS: u64 = 1_000_000;
+
+fn entries() -> impl Iterator<Item = (Vec<u8>, Vec<u8>)> {
+    (0..KEYS).map(|i| (format!("key{}", i).into_bytes(), b"value".to_vec()))
+}
+
+fn bulk_load_bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("bulk_load_bench");
+    group.throughput(Throughput::Elements(KEYS));
+    group.sample_size(10);
+    group.bench_function("graus_db_set", |b| {
+        b.iter_batched(
+            || {
+                let temp_dir = TempDir::new().unwrap();
+                (GrausDb::open(temp_dir.path()).unwrap(), temp_dir)
+            },
+            |(store, _temp_dir)| {
+                for (key, value) in entries() {
+                    store.set(key, &value).unwrap();
+                }
+                store.sync_until(store.current_seq()).unwrap();
+            },
+            BatchSize::PerIteration,
+        )
+    });
+    group.bench_function("graus_db_bulk_load", |b| {
+        b.iter_batched(
+            || {
+                let temp_dir = TempDir::new().unwrap();
+                (GrausDb::open(temp_dir.path()).unwrap(), temp_dir)
+            },
+            |(store, _temp_dir)| store.bulk_load(entries()).unwrap(),
+            BatchSize::PerIteration,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bulk_load_bench);
+criterion_main!(benches);
@@ -0,0 +1,71 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use graus_db::{GrausDb, GrausDbOptions};
+use std::thread;
+use tempfile::TempDir;
+
+const THREADS: usize = 16;
+const SETS_PER_THREAD: usize = 32;
+
+// Sets keys from several threads, calling `after_set` after every write.
+fn concurrent_sets(store: &GrausDb, after_set: fn(&GrausDb)) {
+    let handles: Vec<_> = (0..THREADS)
+        .map(|thread_id| {
+            let store = store.clone();
+            thread::spawn(move || {
+                for i in 0..SETS_PER_THREAD {
+                    let key = format!("key{}_{}", thread_id, i);
+                    store.set(key.into_bytes(), b"value").unwrap();
+                    after_set(&store);
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+}
+
+fn group_commit_bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("group_commit_bench");
+    group.throughput(Throughput::Elements((THREADS * SETS_PER_THREAD) as u64));
+    group.sample_size(10);
+    group.bench_function("graus_db_flush_each_write", |b| {
+        b.iter_batched(
+            || {
+                let temp_dir = TempDir::new().unwrap();
+                (GrausDb::open(temp_dir.path()).unwrap(), temp_dir)
+            },
+            |(store, _temp_dir)| concurrent_sets(&store, |_| {}),
+            BatchSize::SmallInput,
+        )
+    });
+    group.bench_function("graus_db_fsync_each_write", |b| {
+        b.iter_batched(
+            || {
+                let temp_dir = TempDir::new().unwrap();
+                (GrausDb::open(temp_dir.path()).unwrap(), temp_dir)
+            },
+            // Every writer fsyncs on its own
+            |(store, _temp_dir)| concurrent_sets(&store, |store| store.clone().close().unwrap()),
+            BatchSize::SmallInput,
+        )
+    });
+    group.bench_function("graus_db_group_commit", |b| {
+        b.iter_batched(
+            || {
+                let temp_dir = TempDir::new().unwrap();
+                let options = GrausDbOptions::default().sync_each_write(true);
+                (
+                    GrausDb::open_with_options(temp_dir.path(), options).unwrap(),
+                    temp_dir,
+                )
+            },
+            |(store, _temp_dir)| concurrent_sets(&store, |_| {}),
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, group_commit_bench);
+criterion_main!(benches);
@@ -0,0 +1,68 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use graus_db::{GrausDb, ShardedGrausDb};
+use std::thread;
+use tempfile::TempDir;
+
+const THREADS: usize = 8;
+const SETS_PER_THREAD: usize = 512;
+
+fn concurrent_set_bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("concurrent_set_bench");
+    group.bench_function("graus_db_single_writer", |b| {
+        b.iter_batched(
+            || {
+                let temp_dir = TempDir::new().unwrap();
+                (GrausDb::open(temp_dir.path()).unwrap(), temp_dir)
+            },
+            |(store, _temp_dir)| {
+                let handles: Vec<_> = (0..THREADS)
+                    .map(|thread_id| {
+                        let store = store.clone();
+                        thread::spawn(move || {
+                            for i in 0..SETS_PER_THREAD {
+                                let key = format!("key{}_{}", thread_id, i);
+                                store.set(key.into_bytes(), b"value").unwrap();
+                            }
+                        })
+                    })
+                    .collect();
+                for handle in handles {
+                    handle.join().unwrap();
+                }
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    group.bench_function("graus_db_sharded", |b| {
+        b.iter_batched(
+            || {
+                let temp_dir = TempDir::new().unwrap();
+                (
+                    ShardedGrausDb::open(temp_dir.path(), THREADS).unwrap(),
+                    temp_dir,
+                )
+            },
+            |(store, _temp_dir)| {
+                let handles: Vec<_> = (0..THREADS)
+                    .map(|thread_id| {
+                        let store = store.clone();
+                        thread::spawn(move || {
+                            for i in 0..SETS_PER_THREAD {
+                                let key = format!("key{}_{}", thread_id, i);
+                                store.set(key.into_bytes(), b"value").unwrap();
+                            }
+                        })
+                    })
+                    .collect();
+                for handle in handles {
+                    handle.join().unwrap();
+                }
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, concurrent_set_bench);
+criterion_main!(benches);
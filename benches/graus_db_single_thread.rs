@@ -1,5 +1,5 @@
 use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
-use graus_db::GrausDb;
+use graus_db::{GrausDb, GrausDbOptions};
 use rand::prelude::*;
 use std::convert::TryInto;
 use tempfile::TempDir;
@@ -23,6 +23,28 @@ fn set_bench(c: &mut Criterion) {
             BatchSize::SmallInput,
         )
     });
+    group.bench_function("graus_db_set_deferred_flush", |b| {
+        b.iter_batched(
+            || {
+                let temp_dir = TempDir::new().unwrap();
+                let options = GrausDbOptions::default().flush_each_write(false);
+                (
+                    GrausDb::open_with_options(temp_dir.path(), options).unwrap(),
+                    temp_dir,
+                )
+            },
+            |(store, _temp_dir)| {
+                let value = b"value".to_vec();
+                for i in 1..(1 << 12) {
+                    store
+                        .set(format!("key{}", i).into_bytes(), &value.clone())
+                        .unwrap();
+                }
+                store.flush().unwrap();
+            },
+            BatchSize::SmallInput,
+        )
+    });
     group.finish();
 }
 
@@ -35,9 +57,7 @@ fn update_if_bench(c: &mut Criterion) {
                 let store = GrausDb::open(temp_dir.path()).unwrap();
                 let key = b"key1".to_vec();
                 let value: u64 = 3500;
-                store
-                    .set(key.clone(), &value.to_le_bytes().to_vec())
-                    .unwrap();
+                store.set(key.clone(), &value.to_le_bytes()).unwrap();
                 (store, temp_dir, key)
             },
             |(store, _temp_dir, key)| {
@@ -63,12 +83,38 @@ fn update_if_bench(c: &mut Criterion) {
             BatchSize::SmallInput,
         )
     });
+    // Small in-place edits of large values
+    group.bench_function("graus_db_update_if_large_value", |b| {
+        b.iter_batched(
+            || {
+                let temp_dir = TempDir::new().unwrap();
+                let store = GrausDb::open(temp_dir.path()).unwrap();
+                let key = b"key1".to_vec();
+                store.set(key.clone(), &[0; 64 * 1024]).unwrap();
+                (store, temp_dir, key)
+            },
+            |(store, _temp_dir, key)| {
+                let update_fn = |value: &mut Vec<u8>| {
+                    let num =
+                        u64::from_le_bytes(value[..8].try_into().expect("incorrect length")) + 1;
+                    value[..8].copy_from_slice(&num.to_le_bytes());
+                };
+
+                for _ in 1..(1 << 8) {
+                    store
+                        .update_if::<_, fn(&[u8]) -> bool>(key.to_owned(), update_fn, None, None)
+                        .unwrap();
+                }
+            },
+            BatchSize::SmallInput,
+        )
+    });
     group.finish();
 }
 
 fn get_bench(c: &mut Criterion) {
     let mut group = c.benchmark_group("get_bench");
-    for i in &vec![8, 20] {
+    for i in &[8, 20] {
         group.bench_with_input(format!("graus_db_get_{}", i), i, |b, i| {
             let temp_dir = TempDir::new().unwrap();
             let store = GrausDb::open(temp_dir.path()).unwrap();
@@ -89,5 +135,82 @@ fn get_bench(c: &mut Criterion) {
     group.finish();
 }
 
-criterion_group!(benches, set_bench, update_if_bench, get_bench);
+// Point lookups only use the in-memory index. Run it with and without the `hash-index`
+// feature to compare both indexes.
+fn contains_key_bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("contains_key_bench");
+    for i in &[8, 20] {
+        group.bench_with_input(format!("graus_db_contains_key_{}", i), i, |b, i| {
+            let temp_dir = TempDir::new().unwrap();
+            let options = GrausDbOptions::default().flush_each_write(false);
+            let store = GrausDb::open_with_options(temp_dir.path(), options).unwrap();
+            for key_i in 1..(1 << i) {
+                store
+                    .set(format!("key{}", key_i).into_bytes(), b"value")
+                    .unwrap();
+            }
+            let keys: Vec<Vec<u8>> = (0..1024)
+                .map(|key_i| format!("key{}", key_i * 7 % (1 << i)).into_bytes())
+                .collect();
+            let mut key_i = 0;
+            b.iter(|| {
+                key_i = (key_i + 1) % keys.len();
+                store.contains_key(&keys[key_i])
+            })
+        });
+    }
+    group.finish();
+}
+
+// Bulk writes and sequential reads of the whole store with larger buffers
+fn buffer_capacity_bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("buffer_capacity_bench");
+    for capacity in [8 * 1024, 64 * 1024, 1024 * 1024] {
+        let options = GrausDbOptions::default()
+            .flush_each_write(false)
+            .read_buffer_capacity(capacity)
+            .write_buffer_capacity(capacity);
+        let value = vec![7; 1024];
+        group.bench_function(format!("graus_db_bulk_set_{}k", capacity / 1024), |b| {
+            b.iter_batched(
+                || {
+                    let temp_dir = TempDir::new().unwrap();
+                    let store =
+                        GrausDb::open_with_options(temp_dir.path(), options.clone()).unwrap();
+                    (store, temp_dir)
+                },
+                |(store, _temp_dir)| {
+                    for i in 1..(1 << 12) {
+                        store.set(format!("key{}", i).into_bytes(), &value).unwrap();
+                    }
+                    store.flush().unwrap();
+                },
+                BatchSize::SmallInput,
+            )
+        });
+        group.bench_function(format!("graus_db_bulk_get_{}k", capacity / 1024), |b| {
+            let temp_dir = TempDir::new().unwrap();
+            let store = GrausDb::open_with_options(temp_dir.path(), options.clone()).unwrap();
+            for i in 1..(1 << 12) {
+                store.set(format!("key{}", i).into_bytes(), &value).unwrap();
+            }
+            store.flush().unwrap();
+            b.iter(|| {
+                for i in 1..(1 << 12) {
+                    store.get(format!("key{}", i).as_bytes()).unwrap();
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    set_bench,
+    update_if_bench,
+    get_bench,
+    contains_key_bench,
+    buffer_capacity_bench
+);
 criterion_main!(benches);
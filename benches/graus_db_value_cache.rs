@@ -0,0 +1,74 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use graus_db::{GrausDb, GrausDbOptions};
+use std::thread;
+use tempfile::TempDir;
+
+const KEYS: u64 = 1_000;
+const VALUE_LEN: usize = 1024;
+const THREADS: u64 = 4;
+// Hot keys read by every thread
+const HOT_KEYS: u64 = 100;
+
+// Reads the same hot key over and over, as in a skewed workload
+fn value_cache_bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("value_cache_bench");
+    for (name, options) in [
+        ("graus_db_get_uncached_hot_key", GrausDbOptions::default()),
+        (
+            "graus_db_get_cached_hot_key",
+            GrausDbOptions::default().value_cache_bytes(1024 * 1024),
+        ),
+    ] {
+        let temp_dir = TempDir::new().unwrap();
+        let store = GrausDb::open_with_options(temp_dir.path(), options).unwrap();
+        let value = vec![b'v'; VALUE_LEN];
+        for i in 0..KEYS {
+            store.set(format!("key{}", i).into_bytes(), &value).unwrap();
+        }
+        group.bench_function(name, |b| {
+            b.iter(|| store.get(b"key42").unwrap().unwrap());
+        });
+    }
+    group.finish();
+}
+
+// Reads a few hot keys from several threads at once, so they contend for the cache
+fn value_cache_multithreaded_bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("value_cache_multithreaded_bench");
+    for (name, options) in [
+        (
+            "graus_db_get_uncached_hot_keys_4_threads",
+            GrausDbOptions::default(),
+        ),
+        (
+            "graus_db_get_cached_hot_keys_4_threads",
+            GrausDbOptions::default().value_cache_bytes(1024 * 1024),
+        ),
+    ] {
+        let temp_dir = TempDir::new().unwrap();
+        let store = GrausDb::open_with_options(temp_dir.path(), options).unwrap();
+        let value = vec![b'v'; VALUE_LEN];
+        for i in 0..KEYS {
+            store.set(format!("key{}", i).into_bytes(), &value).unwrap();
+        }
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                thread::scope(|scope| {
+                    for thread in 0..THREADS {
+                        let store = store.clone();
+                        scope.spawn(move || {
+                            for i in 0..HOT_KEYS {
+                                let key = format!("key{}", thread * HOT_KEYS + i);
+                                store.get(key.as_bytes()).unwrap().unwrap();
+                            }
+                        });
+                    }
+                });
+            });
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, value_cache_bench, value_cache_multithreaded_bench);
+criterion_main!(benches);
@@ -0,0 +1,47 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use graus_db::{GrausDb, GrausDbOptions};
+use tempfile::TempDir;
+
+const KEYS: u64 = 1_000;
+const WRITES: u64 = 20_000;
+const VALUE_LEN: usize = 4096;
+
+// Overwrites the keys round-robin, so the compactions have large live values to keep
+fn overwrite(store: &GrausDb) {
+    let value = vec![b'v'; VALUE_LEN];
+    for i in 0..WRITES {
+        store
+            .set(format!("key{}", i % KEYS).into_bytes(), &value)
+            .unwrap();
+    }
+}
+
+fn value_log_bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("value_log_bench");
+    group.throughput(Throughput::Bytes(WRITES * VALUE_LEN as u64));
+    group.sample_size(10);
+    for (name, options) in [
+        ("graus_db_inline_values", GrausDbOptions::default()),
+        (
+            "graus_db_value_log",
+            GrausDbOptions::default().value_log_threshold(1024),
+        ),
+    ] {
+        group.bench_function(name, |b| {
+            b.iter_batched(
+                || {
+                    let temp_dir = TempDir::new().unwrap();
+                    let store =
+                        GrausDb::open_with_options(temp_dir.path(), options.clone()).unwrap();
+                    (store, temp_dir)
+                },
+                |(store, _temp_dir)| overwrite(&store),
+                BatchSize::PerIteration,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, value_log_bench);
+criterion_main!(benches);
@@ -35,12 +35,12 @@ impl Product {
     /// Deserializes a `Product` reference from a byte slice using zero-copy.
     /// This function returns a reference to the `Product` directly from the input bytes,
     /// without allocating new memory for the struct itself.
-    fn from_bytes<'a>(bytes: &'a [u8]) -> &'a Product {
+    fn from_bytes(bytes: &[u8]) -> &Product {
         // Create a `Buf` from the input byte slice.
         let loaded_buf = Buf::new(bytes);
         // Create a `Ref` to the `Product` at the beginning of the buffer (offset 0).
         // This assumes the `Product` struct is at the start of the serialized data.
-        let loaded_product_ref = Ref::<Product, endian::Little>::new(0 as usize);
+        let loaded_product_ref = Ref::<Product, endian::Little>::new(0usize);
         // Load the `Product` reference from the buffer. `unwrap()` is used here for simplicity,
         // but in a real application, error handling would be necessary.
         loaded_buf.load(loaded_product_ref).unwrap()
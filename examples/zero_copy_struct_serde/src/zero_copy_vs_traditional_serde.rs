@@ -55,6 +55,7 @@ struct DimensionsJson {
 }
 
 impl Product {
+    #[allow(clippy::too_many_arguments)]
     fn to_bytes(
         product_id: u64,
         stock: u16,
@@ -92,12 +93,13 @@ impl Product {
         buf
     }
 
-    fn from_bytes<'a>(bytes: &'a [u8]) -> &'a Product {
+    fn from_bytes(bytes: &[u8]) -> &Product {
         let loaded_buf = Buf::new(bytes);
-        let loaded_product_ref = Ref::<Product, endian::Little>::new(0 as usize);
+        let loaded_product_ref = Ref::<Product, endian::Little>::new(0usize);
         loaded_buf.load(loaded_product_ref).unwrap()
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn to_bytes_json(
         product_id: u64,
         stock: u16,
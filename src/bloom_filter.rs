@@ -0,0 +1,108 @@
+// The bloom filter is not used when the `hash-index` feature replaces the index.
+#![cfg_attr(feature = "hash-index", allow(dead_code))]
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::mem;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+// Smallest number of keys a filter is sized for.
+const MIN_CAPACITY: usize = 1024;
+
+/// A counting bloom filter, so keys can be removed as well as inserted.
+///
+/// It may report that an absent key is present (false positive), but never the opposite.
+/// Counters saturate instead of overflowing, and saturated counters are never decremented.
+pub(crate) struct BloomFilter {
+    counters: Vec<AtomicU8>,
+    num_hashes: u32,
+    capacity: usize,
+    false_positive_rate: f64,
+}
+
+impl BloomFilter {
+    /// Creates a filter that keeps `false_positive_rate` with up to `capacity` keys.
+    pub fn new(capacity: usize, false_positive_rate: f64) -> BloomFilter {
+        let capacity = capacity.max(MIN_CAPACITY);
+        let ln2 = std::f64::consts::LN_2;
+        let num_counters =
+            (-(capacity as f64) * false_positive_rate.ln() / (ln2 * ln2)).ceil() as usize;
+        let num_hashes = ((num_counters as f64 / capacity as f64) * ln2).round() as u32;
+        BloomFilter {
+            counters: (0..num_counters.max(1)).map(|_| AtomicU8::new(0)).collect(),
+            num_hashes: num_hashes.max(1),
+            capacity,
+            false_positive_rate,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn false_positive_rate(&self) -> f64 {
+        self.false_positive_rate
+    }
+
+    /// Returns the bytes of memory held by the counters.
+    pub fn memory_len(&self) -> usize {
+        self.counters.len() * mem::size_of::<AtomicU8>()
+    }
+
+    pub fn insert(&self, key: &[u8]) {
+        for counter in self.counters_of(key) {
+            let _ = counter.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+                count.checked_add(1)
+            });
+        }
+    }
+
+    pub fn remove(&self, key: &[u8]) {
+        for counter in self.counters_of(key) {
+            let _ = counter.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+                (count != 0 && count != u8::MAX).then(|| count - 1)
+            });
+        }
+    }
+
+    /// Returns false if the key is definitely not present.
+    pub fn may_contain(&self, key: &[u8]) -> bool {
+        self.counters_of(key)
+            .all(|counter| counter.load(Ordering::SeqCst) > 0)
+    }
+
+    // Double hashing: the i-th counter is at `h1 + i * h2`.
+    fn counters_of<'a>(&'a self, key: &[u8]) -> impl Iterator<Item = &'a AtomicU8> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let hash = hasher.finish();
+        let (h1, h2) = (hash as u32 as u64, (hash >> 32) | 1);
+        let len = self.counters.len() as u64;
+        (0..self.num_hashes as u64)
+            .map(move |i| &self.counters[(h1.wrapping_add(i.wrapping_mul(h2)) % len) as usize])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bloom_filter_has_no_false_negatives() {
+        let filter = BloomFilter::new(10_000, 0.01);
+        for i in 0..10_000u32 {
+            filter.insert(&i.to_le_bytes());
+        }
+        for i in 0..5_000u32 {
+            filter.remove(&i.to_le_bytes());
+        }
+        for i in 5_000..10_000u32 {
+            assert!(filter.may_contain(&i.to_le_bytes()));
+        }
+
+        let false_positives = (10_000..20_000u32)
+            .filter(|i| filter.may_contain(&i.to_le_bytes()))
+            .count();
+        assert!(false_positives < 500);
+    }
+}
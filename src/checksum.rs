@@ -0,0 +1,74 @@
+use std::io::Read;
+
+use crate::Result;
+
+// Lookup table of the CRC-32 (IEEE) polynomial, in its reflected form.
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// Running CRC-32 checksum, used to detect corrupted data in the logs.
+#[derive(Clone, Copy)]
+pub(crate) struct Crc32(u32);
+
+impl Crc32 {
+    pub fn new() -> Crc32 {
+        Crc32(!0)
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = CRC32_TABLE[((self.0 ^ byte as u32) & 0xFF) as usize] ^ (self.0 >> 8);
+        }
+    }
+
+    pub fn finalize(self) -> u32 {
+        !self.0
+    }
+}
+
+/// Returns the checksum of the next `len` bytes of the reader.
+pub(crate) fn checksum_of<R: Read>(reader: &mut R, len: u64) -> Result<u32> {
+    let mut crc = Crc32::new();
+    let mut buf = [0; 64 * 1024];
+    let mut remaining = len;
+    while remaining > 0 {
+        let chunk = &mut buf[..remaining.min(64 * 1024) as usize];
+        reader.read_exact(chunk)?;
+        crc.update(chunk);
+        remaining -= chunk.len() as u64;
+    }
+    Ok(crc.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32() {
+        let mut crc = Crc32::new();
+        crc.update(b"1234");
+        crc.update(b"56789");
+        assert_eq!(crc.finalize(), 0xCBF4_3926);
+        assert_eq!(Crc32::new().finalize(), 0);
+    }
+}
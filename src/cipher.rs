@@ -0,0 +1,68 @@
+use crate::{GrausError, Result};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Length of the nonces a `ValueCipher` seals values with.
+pub const NONCE_LEN: usize = 12;
+
+/// Nonce a single value is sealed with, stored in its record.
+pub type Nonce = [u8; NONCE_LEN];
+
+/// Authenticated cipher (AEAD) that encrypts the values at rest.
+///
+/// See [`GrausDbOptions::value_cipher`](crate::GrausDbOptions::value_cipher). Every value is
+/// sealed with a new nonce, which is never reused while the database is open.
+pub trait ValueCipher: Send + Sync {
+    /// Encrypts `plaintext` with `nonce`, returning the ciphertext along with its
+    /// authentication tag.
+    fn seal(&self, nonce: &Nonce, plaintext: &[u8]) -> Vec<u8>;
+
+    /// Decrypts a ciphertext returned by `seal` with the same nonce. Returns `None` if it
+    /// can't be authenticated.
+    fn open(&self, nonce: &Nonce, ciphertext: &[u8]) -> Option<Vec<u8>>;
+}
+
+/// Seals values with a `ValueCipher` and unique nonces.
+///
+/// A nonce is made of 4 random bytes, drawn when the database is opened, followed by a
+/// counter that starts at a random value, so nonces don't repeat across opens either.
+pub(crate) struct Sealer {
+    cipher: Arc<dyn ValueCipher>,
+    prefix: [u8; 4],
+    counter: AtomicU64,
+}
+
+impl Sealer {
+    pub(crate) fn new(cipher: Arc<dyn ValueCipher>) -> Sealer {
+        // Every `RandomState` is seeded with new random keys
+        let random = |seed: u64| {
+            let mut hasher = RandomState::new().build_hasher();
+            hasher.write_u64(seed);
+            hasher.finish()
+        };
+        Sealer {
+            cipher,
+            prefix: (random(0) as u32).to_le_bytes(),
+            counter: AtomicU64::new(random(1)),
+        }
+    }
+
+    /// Seals `plaintext` with a new nonce, returned along with the ciphertext.
+    pub(crate) fn seal(&self, plaintext: &[u8]) -> (Nonce, Vec<u8>) {
+        let mut nonce = [0; NONCE_LEN];
+        nonce[..4].copy_from_slice(&self.prefix);
+        let counter = self.counter.fetch_add(1, Ordering::Relaxed);
+        nonce[4..].copy_from_slice(&counter.to_le_bytes());
+        let ciphertext = self.cipher.seal(&nonce, plaintext);
+        (nonce, ciphertext)
+    }
+
+    /// Opens a value sealed with `nonce`.
+    pub(crate) fn open(&self, nonce: &Nonce, ciphertext: &[u8]) -> Result<Vec<u8>> {
+        self.cipher
+            .open(nonce, ciphertext)
+            .ok_or(GrausError::DecryptionFailed)
+    }
+}
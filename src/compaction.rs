@@ -0,0 +1,61 @@
+use std::sync::Arc;
+
+/// Function that rewrites every live value copied by a compaction.
+///
+/// See [`GrausDbOptions::compaction_value_transform`](crate::GrausDbOptions::compaction_value_transform).
+pub type ValueTransform = Arc<dyn Fn(&[u8]) -> Vec<u8> + Send + Sync>;
+
+/// Decides when the logs must be compacted.
+///
+/// It is consulted by the writer after every write.
+pub trait CompactionStrategy: Send + Sync {
+    /// Returns whether a compaction must be triggered.
+    ///
+    /// * `uncompacted` - bytes that would be saved after a compaction (stale commands).
+    /// * `total` - total bytes of all the log files.
+    /// * `num_logs` - number of log files, including the active one.
+    fn should_compact(&self, uncompacted: u64, total: u64, num_logs: usize) -> bool;
+}
+
+/// Compacts when the stale bytes exceed a fixed threshold.
+///
+/// This is the default strategy, with a threshold of 1 MB.
+#[derive(Debug, Clone, Copy)]
+pub struct ThresholdStrategy {
+    /// Stale bytes that trigger a compaction.
+    pub threshold: u64,
+}
+
+impl Default for ThresholdStrategy {
+    fn default() -> Self {
+        ThresholdStrategy {
+            threshold: 1024 * 1024,
+        }
+    }
+}
+
+impl CompactionStrategy for ThresholdStrategy {
+    fn should_compact(&self, uncompacted: u64, _total: u64, _num_logs: usize) -> bool {
+        uncompacted > self.threshold
+    }
+}
+
+/// Compacts when the ratio of stale bytes over the total size of the logs exceeds `ratio`.
+///
+/// It scales with the size of the database, avoiding compaction storms on large ones.
+/// `min_uncompacted` avoids compacting tiny databases too often.
+#[derive(Debug, Clone, Copy)]
+pub struct RatioStrategy {
+    /// Ratio of stale bytes, between 0 and 1, that triggers a compaction.
+    pub ratio: f64,
+    /// Minimum stale bytes required to trigger a compaction.
+    pub min_uncompacted: u64,
+}
+
+impl CompactionStrategy for RatioStrategy {
+    fn should_compact(&self, uncompacted: u64, total: u64, _num_logs: usize) -> bool {
+        total > 0
+            && uncompacted >= self.min_uncompacted
+            && uncompacted as f64 / total as f64 > self.ratio
+    }
+}
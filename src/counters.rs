@@ -0,0 +1,89 @@
+use crate::{GrausDb, GrausError, Result};
+
+/// How counters handle increments that overflow an `i64`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CounterOverflow {
+    /// Clamps the counter to `i64::MIN` or `i64::MAX`.
+    #[default]
+    Saturating,
+    /// Wraps around the boundary of the type.
+    Wrapping,
+}
+
+/// A view of the keys of a `GrausDb` as numeric counters, created by
+/// [`GrausDb::counters`].
+///
+/// Counters are stored as 8 byte little-endian `i64` values, and a missing key counts as 0.
+/// Every operation is atomic under the writer lock.
+///
+/// ```rust
+/// # use graus_db::{CounterOverflow, GrausDb, Result};
+/// # fn try_main() -> Result<()> {
+/// use std::env::current_dir;
+///
+/// let store = GrausDb::open(current_dir()?)?;
+/// let counters = store.counters().overflow(CounterOverflow::Wrapping);
+/// counters.incr(b"visits".to_vec(), 1)?;
+/// assert_eq!(counters.get(b"visits")?, 1);
+/// counters.reset(b"visits")?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Counters<'a> {
+    db: &'a GrausDb,
+    overflow: CounterOverflow,
+}
+
+impl<'a> Counters<'a> {
+    pub(crate) fn new(db: &'a GrausDb) -> Counters<'a> {
+        Counters {
+            db,
+            overflow: CounterOverflow::default(),
+        }
+    }
+
+    /// Sets how increments that overflow are handled. They saturate by default.
+    pub fn overflow(mut self, overflow: CounterOverflow) -> Counters<'a> {
+        self.overflow = overflow;
+        self
+    }
+
+    /// Adds `delta` to a counter, which can be negative, and returns its new value.
+    ///
+    /// Returns `GrausError::InvalidCounter` if the key holds a value that is not a counter.
+    pub fn incr(&self, key: Vec<u8>, delta: i64) -> Result<i64> {
+        self.db.increment(key, delta, self.overflow)
+    }
+
+    /// Returns the value of a counter, or 0 if it does not exist.
+    ///
+    /// Returns `GrausError::InvalidCounter` if the key holds a value that is not a counter.
+    pub fn get(&self, key: &[u8]) -> Result<i64> {
+        match self.db.get(key)? {
+            Some(value) => decode_counter(&value),
+            None => Ok(0),
+        }
+    }
+
+    /// Resets a counter to 0, removing its key.
+    pub fn reset(&self, key: &[u8]) -> Result<()> {
+        self.db.remove_many(vec![key.to_vec()])?;
+        Ok(())
+    }
+}
+
+/// Decodes the value of a counter.
+pub(crate) fn decode_counter(value: &[u8]) -> Result<i64> {
+    let bytes = value
+        .try_into()
+        .map_err(|_| GrausError::InvalidCounter { len: value.len() })?;
+    Ok(i64::from_le_bytes(bytes))
+}
+
+/// Adds `delta` to a counter, handling overflows as configured.
+pub(crate) fn add_to_counter(counter: i64, delta: i64, overflow: CounterOverflow) -> i64 {
+    match overflow {
+        CounterOverflow::Saturating => counter.saturating_add(delta),
+        CounterOverflow::Wrapping => counter.wrapping_add(delta),
+    }
+}
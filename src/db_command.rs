@@ -1,52 +1,272 @@
-use std::ops::Range;
+use crate::cipher::Nonce;
 
 /// Struct representing an owned command to the database.
+///
+/// Values and chunks sealed by a `ValueCipher` are stored along with their nonce.
+/// `seq` is the sequence number of the write, or 0 for legacy commands written without it.
 #[derive(Debug, PartialEq, Clone)]
 pub enum CommandOwned {
-    Set { key: Vec<u8>, value: Vec<u8> },
-    Remove { key: Vec<u8> },
+    Set {
+        key: Vec<u8>,
+        value: Vec<u8>,
+        version: u64,
+        timestamp: Option<u64>,
+        nonce: Option<Nonce>,
+        seq: u64,
+    },
+    /// A "set" command whose value is stored in a value log. `checksum` is the checksum of
+    /// the value, if it was stored with it.
+    SetRef {
+        key: Vec<u8>,
+        value_ref: ValueRef,
+        version: u64,
+        timestamp: Option<u64>,
+        nonce: Option<Nonce>,
+        checksum: Option<u32>,
+        seq: u64,
+    },
+    Remove {
+        key: Vec<u8>,
+        seq: u64,
+    },
+    Append {
+        key: Vec<u8>,
+        chunk: Vec<u8>,
+        version: u64,
+        timestamp: Option<u64>,
+        prev_log_id: u64,
+        prev_pos: u64,
+        nonce: Option<Nonce>,
+        seq: u64,
+    },
 }
 
 impl CommandOwned {
-    pub fn set(key: Vec<u8>, value: Vec<u8>) -> CommandOwned {
-        CommandOwned::Set { key, value }
+    pub fn set(key: Vec<u8>, value: Vec<u8>, version: u64, timestamp: Option<u64>) -> CommandOwned {
+        CommandOwned::Set {
+            key,
+            value,
+            version,
+            timestamp,
+            nonce: None,
+            seq: 0,
+        }
     }
 
     pub fn remove(key: Vec<u8>) -> CommandOwned {
-        CommandOwned::Remove { key }
+        CommandOwned::Remove { key, seq: 0 }
+    }
+
+    /// Returns the sequence number of the write, or 0 if it was written without it.
+    pub fn seq(&self) -> u64 {
+        match self {
+            CommandOwned::Set { seq, .. }
+            | CommandOwned::SetRef { seq, .. }
+            | CommandOwned::Remove { seq, .. }
+            | CommandOwned::Append { seq, .. } => *seq,
+        }
     }
 }
 
 /// Struct representing a borrowed command to the database.
+///
+/// `checksum` tells whether a checksum of the value, or of the chunk, is stored with it.
+/// `seq` is the sequence number of the write, which is not stored if it is 0.
 #[derive(Debug, PartialEq)]
 pub enum CommandRef<'a> {
-    Set { key: &'a [u8], value: &'a [u8] },
-    Remove { key: &'a [u8] },
+    Set {
+        key: &'a [u8],
+        value: &'a [u8],
+        version: u64,
+        timestamp: Option<u64>,
+        nonce: Option<Nonce>,
+        checksum: bool,
+        seq: u64,
+    },
+    SetRef {
+        key: &'a [u8],
+        value_ref: ValueRef,
+        version: u64,
+        timestamp: Option<u64>,
+        nonce: Option<Nonce>,
+        checksum: Option<u32>,
+        seq: u64,
+    },
+    Remove {
+        key: &'a [u8],
+        seq: u64,
+    },
+    Append {
+        key: &'a [u8],
+        chunk: &'a [u8],
+        version: u64,
+        timestamp: Option<u64>,
+        prev_log_id: u64,
+        prev_pos: u64,
+        nonce: Option<Nonce>,
+        checksum: bool,
+        seq: u64,
+    },
 }
 
 impl<'a> CommandRef<'a> {
-    pub fn set(key: &'a [u8], value: &'a [u8]) -> CommandRef<'a> {
-        CommandRef::Set { key, value }
+    pub fn set(
+        key: &'a [u8],
+        value: &'a [u8],
+        version: u64,
+        timestamp: Option<u64>,
+    ) -> CommandRef<'a> {
+        CommandRef::Set {
+            key,
+            value,
+            version,
+            timestamp,
+            nonce: None,
+            checksum: false,
+            seq: 0,
+        }
+    }
+
+    /// Builds a "set" command whose value was written into a value log at `value_ref`.
+    /// `checksum` is the checksum of the value, if it is stored with it.
+    pub fn set_ref(
+        key: &'a [u8],
+        value_ref: ValueRef,
+        version: u64,
+        timestamp: Option<u64>,
+        checksum: Option<u32>,
+    ) -> CommandRef<'a> {
+        CommandRef::SetRef {
+            key,
+            value_ref,
+            version,
+            timestamp,
+            nonce: None,
+            checksum,
+            seq: 0,
+        }
     }
 
     pub fn remove(key: &'a [u8]) -> CommandRef<'a> {
-        CommandRef::Remove { key }
+        CommandRef::Remove { key, seq: 0 }
     }
+
+    pub fn append(
+        key: &'a [u8],
+        chunk: &'a [u8],
+        version: u64,
+        timestamp: Option<u64>,
+        prev: CommandPos,
+    ) -> CommandRef<'a> {
+        CommandRef::Append {
+            key,
+            chunk,
+            version,
+            timestamp,
+            prev_log_id: prev.log_id,
+            prev_pos: prev.pos,
+            nonce: None,
+            checksum: false,
+            seq: 0,
+        }
+    }
+
+    /// Marks the value or chunk of a "set" or "append" command as sealed with `nonce`.
+    pub fn with_nonce(mut self, sealed_with: Option<Nonce>) -> CommandRef<'a> {
+        if let CommandRef::Set { nonce, .. }
+        | CommandRef::SetRef { nonce, .. }
+        | CommandRef::Append { nonce, .. } = &mut self
+        {
+            *nonce = sealed_with;
+        }
+        self
+    }
+
+    /// Sets whether a checksum of the value is stored with the command, so it is verified
+    /// when the value is read. It has no effect on "remove" commands, nor on commands whose
+    /// value is in a value log, as their checksum is computed when the value is written.
+    pub fn with_checksum(mut self, enabled: bool) -> CommandRef<'a> {
+        if let CommandRef::Set { checksum, .. } | CommandRef::Append { checksum, .. } = &mut self {
+            *checksum = enabled;
+        }
+        self
+    }
+
+    /// Sets the sequence number of the write, stored with the command.
+    pub fn with_seq(mut self, new_seq: u64) -> CommandRef<'a> {
+        match &mut self {
+            CommandRef::Set { seq, .. }
+            | CommandRef::SetRef { seq, .. }
+            | CommandRef::Remove { seq, .. }
+            | CommandRef::Append { seq, .. } => *seq = new_seq,
+        }
+        self
+    }
+}
+/// Position of a value stored in a value log, apart from the command of its key.
+///
+/// Value logs only contain the bytes of the values, one after the other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValueRef {
+    /// The value log where the value is stored.
+    pub log_id: u64,
+    /// The position of the value in the value log.
+    pub pos: u64,
+    /// The length of the value.
+    pub len: u64,
 }
+
 /// Struct representing the position of a command in a given file.
-#[derive(Debug, Clone, Copy)]
+///
+/// Log files are stored in the database directory as `<log_id>.log`, and value logs as
+/// `values/<log_id>.log`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct CommandPos {
-    pub log_id: u64, // the file where the command is stored.
-    pub pos: u64,    // The position of the command's start in the file.
-    pub len: u64,    // The length of the command.
+    /// The file where the command is stored.
+    pub log_id: u64,
+    /// The position of the command's start in the file.
+    pub pos: u64,
+    /// The length of the command.
+    pub len: u64,
+    /// The length of the value stored at the end of the command. For commands that append
+    /// to a value, it is only the length of the appended chunk.
+    pub value_len: u64,
+    /// The length of the part of the value stored in the previous commands this one
+    /// appends to, or 0 if it doesn't append to any.
+    pub appended_len: u64,
+    /// The version of the key, the sequence number of its last write.
+    pub version: u64,
+    /// The value log where the value is stored, or `None` if it is stored at the end of
+    /// the command. For commands that append to a value, it is the value log of the first
+    /// part of the value, as the chunk is always stored in the command.
+    pub value_log_id: Option<u64>,
 }
 
-impl From<(u64, Range<u64>)> for CommandPos {
-    fn from((log_id, range): (u64, Range<u64>)) -> Self {
-        CommandPos {
-            log_id,
-            pos: range.start,
-            len: range.end - range.start,
-        }
+impl CommandPos {
+    /// Returns the position of the value in the file. It is only meaningful if the value
+    /// is not stored in a value log.
+    pub fn value_pos(&self) -> u64 {
+        self.pos + self.len - self.value_len
+    }
+
+    /// Returns whether the value is stored in a value log instead of in the command.
+    pub fn is_separated(&self) -> bool {
+        self.value_log_id.is_some() && self.appended_len == 0
+    }
+
+    /// Returns the length of the whole value, including the appended chunks.
+    pub fn total_value_len(&self) -> u64 {
+        self.appended_len + self.value_len
+    }
+
+    // Estimates the bytes of the logs that become stale when the key is overwritten or
+    // removed, including the commands this one appends to and the value in a value log.
+    pub(crate) fn stale_len(&self) -> u64 {
+        let separated_len = if self.is_separated() {
+            self.value_len
+        } else {
+            0
+        };
+        self.len + self.appended_len + separated_len
     }
 }
@@ -0,0 +1,78 @@
+use crate::{GrausDb, Result};
+
+type ModifyFn<'a> = Box<dyn FnOnce(&mut Vec<u8>) + 'a>;
+
+/// A view into a single key of a `GrausDb`, created by [`GrausDb::entry`].
+///
+/// Nothing is read or written until a terminal method (`or_insert` or `or_insert_with`)
+/// is called. Then the whole operation is performed atomically under the writer lock.
+///
+/// ```rust
+/// # use graus_db::{GrausDb, Result};
+/// # fn try_main() -> Result<()> {
+/// use std::env::current_dir;
+///
+/// let store = GrausDb::open(current_dir()?)?;
+/// // Increments the counter, or initializes it to 1 if it does not exist.
+/// let counter = store
+///     .entry(b"counter".to_vec())
+///     .and_modify(|value| {
+///         let num = u64::from_le_bytes(value[..8].try_into().unwrap()) + 1;
+///         value.copy_from_slice(&num.to_le_bytes());
+///     })
+///     .or_insert(1u64.to_le_bytes().to_vec())?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Entry<'a> {
+    db: &'a GrausDb,
+    key: Vec<u8>,
+    modify: Option<ModifyFn<'a>>,
+}
+
+impl<'a> Entry<'a> {
+    pub(crate) fn new(db: &'a GrausDb, key: Vec<u8>) -> Entry<'a> {
+        Entry {
+            db,
+            key,
+            modify: None,
+        }
+    }
+
+    /// Registers a function that modifies the value if the key already exists.
+    ///
+    /// Several calls are applied in order.
+    pub fn and_modify<F>(mut self, f: F) -> Entry<'a>
+    where
+        F: FnOnce(&mut Vec<u8>) + 'a,
+    {
+        self.modify = Some(match self.modify.take() {
+            Some(previous) => Box::new(move |value: &mut Vec<u8>| {
+                previous(value);
+                f(value);
+            }),
+            None => Box::new(f),
+        });
+        self
+    }
+
+    /// Inserts `default` if the key does not exist, otherwise applies the pending
+    /// modifications.
+    ///
+    /// Returns the value stored for the key after the operation.
+    pub fn or_insert(self, default: Vec<u8>) -> Result<Vec<u8>> {
+        self.or_insert_with(|| default)
+    }
+
+    /// Inserts the value returned by `default` if the key does not exist, otherwise
+    /// applies the pending modifications.
+    ///
+    /// `default` is only called when the key does not exist. Returns the value stored
+    /// for the key after the operation.
+    pub fn or_insert_with<F>(self, default: F) -> Result<Vec<u8>>
+    where
+        F: FnOnce() -> Vec<u8>,
+    {
+        self.db.upsert(self.key, self.modify, default)
+    }
+}
@@ -1,4 +1,5 @@
 use std::io;
+use std::path::PathBuf;
 use std::string::FromUtf8Error;
 use thiserror::Error;
 
@@ -8,12 +9,26 @@ pub enum GrausError {
     /// IO Error
     #[error("GrausDb IO error")]
     Io(#[from] io::Error),
+    /// IO error on a file or directory of the database, with the operation that failed.
+    #[error("GrausDb IO error: failed to {op} {path:?}: {source}")]
+    FileIo {
+        /// The underlying IO error.
+        source: io::Error,
+        /// Path of the file or directory.
+        path: PathBuf,
+        /// Operation that failed, like "open" or "flush".
+        op: &'static str,
+    },
     /// Removing non-existent key error.
     #[error("Key not found")]
     KeyNotFound,
     /// Serialization or deserialization error.
     #[error("{0}")]
     SerializationError(String),
+    /// A value is encrypted but no cipher is set, or it can't be authenticated by the
+    /// cipher of the database.
+    #[error("Value can't be decrypted")]
+    DecryptionFailed,
     /// Unexpected command type error.
     /// It indicated a corrupted log or a program bug.
     #[error("Unexpected command type")]
@@ -27,7 +42,100 @@ pub enum GrausError {
     /// Predicate passed to update_if was not satisfied.
     #[error("Predicate not satisfied")]
     PredicateNotSatisfied,
+    /// A counter was read from a value that is not 8 bytes long.
+    #[error("Counter values must be 8 bytes long, but the value has {len} bytes")]
+    InvalidCounter {
+        /// Length of the value.
+        len: usize,
+    },
+    /// No secondary index is registered with the given name.
+    #[error("Secondary index not found: {0}")]
+    IndexNotFound(String),
+    /// The checksum stored with a value doesn't match its bytes, so it is corrupted.
+    #[error("Value checksum mismatch")]
+    ChecksumMismatch,
+    /// A write was rejected because the logs exceed a high-water mark set in
+    /// `GrausDbOptions`, until a compaction reclaims enough space.
+    #[error("Write stalled until the logs are compacted below the high-water mark")]
+    WriteStalled,
+    /// The database was created with a different number of shards.
+    #[error("Database has {found} shards, but it was opened with {expected}")]
+    ShardCountMismatch {
+        /// Number of shards of the database.
+        found: usize,
+        /// Number of shards it was opened with.
+        expected: usize,
+    },
+    /// The writer could not be locked before the timeout of the write.
+    #[error("Write timed out waiting for the writer lock")]
+    WriteTimeout,
+    /// The database was written with a newer on-disk format than this version of GrausDb
+    /// supports.
+    #[error("Database has format version {found}, but only up to {supported} is supported")]
+    UnsupportedFormatVersion {
+        /// Format version of the database.
+        found: u64,
+        /// Newest format version supported.
+        supported: u64,
+    },
+    /// A log holding the value of a key was deleted while the database was open, e.g.
+    /// by another process.
+    #[error("Log {log_id} is missing, it was deleted while the database was open")]
+    LogFileMissing {
+        /// Id of the missing log.
+        log_id: u64,
+    },
+    /// A command read back after writing it, with `GrausDbOptions::verify_writes`, doesn't
+    /// hold what was written.
+    #[error("Command written at position {pos} of log {log_id} doesn't match when read back")]
+    WriteVerificationFailed {
+        /// Id of the log of the command.
+        log_id: u64,
+        /// Position of the command in the log.
+        pos: u64,
+    },
+    /// A write was rejected because the database was opened read-only, with
+    /// `GrausDb::open_up_to`.
+    #[error("Database is opened read-only")]
+    ReadOnly,
+    /// The database was opened with options that can't be used together.
+    #[error("Incompatible options: {0}")]
+    IncompatibleOptions(&'static str),
+    /// The sequence number waited for was not assigned to any write yet.
+    #[error("Sequence number {seq} was not written yet, the last one is {current}")]
+    SeqNotWritten {
+        /// Sequence number waited for.
+        seq: u64,
+        /// Sequence number of the last write.
+        current: u64,
+    },
 }
 
 /// Result type for GrausDb.
 pub type Result<T> = std::result::Result<T, GrausError>;
+
+/// Adds the failed operation and the path it was working on to IO errors.
+///
+/// The path is only built if there is an error, so it can be used in hot paths.
+pub(crate) trait IoContext<T> {
+    fn io_context(self, op: &'static str, path: impl FnOnce() -> PathBuf) -> Result<T>;
+}
+
+impl<T> IoContext<T> for io::Result<T> {
+    fn io_context(self, op: &'static str, path: impl FnOnce() -> PathBuf) -> Result<T> {
+        self.map_err(|source| GrausError::FileIo {
+            source,
+            path: path(),
+            op,
+        })
+    }
+}
+
+impl<T> IoContext<T> for Result<T> {
+    fn io_context(self, op: &'static str, path: impl FnOnce() -> PathBuf) -> Result<T> {
+        match self {
+            Err(GrausError::Io(source)) => Err(source).io_context(op, path),
+            result => result,
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+/// A unit of background work.
+pub type Task = Box<dyn FnOnce() + Send>;
+
+/// Runs the background work of the databases, like compactions.
+///
+/// An executor can be shared by many databases through
+/// [`GrausDbOptions::executor`](crate::GrausDbOptions::executor), so their background work
+/// is multiplexed onto the same threads instead of needing threads for every database.
+pub trait Executor: Send + Sync {
+    /// Runs `task` in the background.
+    fn execute(&self, task: Task);
+}
+
+/// Executor that runs tasks in a fixed number of threads, in the order they are submitted.
+///
+/// Dropping it waits until the pending tasks are completed.
+///
+/// ```rust
+/// # use graus_db::{GrausDb, GrausDbOptions, Result, ThreadPool};
+/// # fn try_main() -> Result<()> {
+/// use std::sync::Arc;
+/// use tempfile::TempDir;
+///
+/// let pool = Arc::new(ThreadPool::new(2));
+/// let dir = TempDir::new()?;
+/// let tenants = (0..10)
+///     .map(|tenant| {
+///         let options = GrausDbOptions::default().executor(pool.clone());
+///         GrausDb::open_with_options(dir.path().join(tenant.to_string()), options)
+///     })
+///     .collect::<Result<Vec<GrausDb>>>()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct ThreadPool {
+    sender: Option<mpsc::Sender<Task>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl ThreadPool {
+    /// Creates a pool with `num_threads` threads.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `num_threads` is 0.
+    pub fn new(num_threads: usize) -> ThreadPool {
+        assert!(num_threads > 0, "number of threads must be greater than 0");
+        let (sender, receiver) = mpsc::channel::<Task>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let workers = (0..num_threads)
+            .map(|_| {
+                let receiver = Arc::clone(&receiver);
+                thread::spawn(move || loop {
+                    // The lock is released before running the task
+                    let task = receiver.lock().unwrap().recv();
+                    match task {
+                        // A panicking task must not stop the worker
+                        Ok(task) => {
+                            let _ = panic::catch_unwind(AssertUnwindSafe(task));
+                        }
+                        // The pool was dropped
+                        Err(_) => break,
+                    }
+                })
+            })
+            .collect();
+        ThreadPool {
+            sender: Some(sender),
+            workers,
+        }
+    }
+}
+
+impl Executor for ThreadPool {
+    fn execute(&self, task: Task) {
+        if let Some(sender) = &self.sender {
+            // Workers only stop after the sender is dropped, so it can't fail
+            let _ = sender.send(task);
+        }
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        self.sender.take();
+        for worker in self.workers.drain(..) {
+            // The last reference to the pool may be dropped by one of its own tasks
+            if worker.thread().id() != thread::current().id() {
+                let _ = worker.join();
+            }
+        }
+    }
+}
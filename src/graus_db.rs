@@ -1,15 +1,54 @@
+use crate::cipher::Sealer;
+use crate::counters::{add_to_counter, decode_counter, CounterOverflow, Counters};
 use crate::db_command::{CommandOwned, CommandPos};
-use crate::io_types::BufReaderWithPos;
-use crate::log_storage::log_helpers::{get_log_ids, load_log, log_path, new_log_file};
-use crate::log_storage::log_reader::LogReader;
-use crate::log_storage::log_writer::LogWriter;
-use crate::{GrausError, Result};
-use crossbeam_skiplist::SkipMap;
+use crate::entry::Entry;
+use crate::error::IoContext;
+use crate::io_types::{BufReaderWithPos, LogFile};
+use crate::key_index::{lexicographic, stored_key, Index, KeyComparator, KeyIndex, KeyTransform};
+use crate::log_storage::db_command_serde::{
+    deserialize_command, read_nonce, LOG_FOOTER_LEN, SEQ_MARK_LEN,
+};
+use crate::log_storage::group_commit::GroupCommit;
+use crate::log_storage::index_snapshot::{load_index_snapshot, save_index_snapshot};
+use crate::log_storage::log_helpers::{
+    existing_value_dir, for_each_command, get_log_ids, get_logs_size, get_value_log_ids,
+    get_value_logs_size, load_log, new_log_file, relocate_logs, remove_empty_logs,
+    remove_value_logs, seal_log, LogDir,
+};
+use crate::log_storage::log_reader::{FlushedPos, LogReader};
+use crate::log_storage::log_writer::{self, LogWriter};
+use crate::log_storage::mirror::{restore_from_mirror, Mirror};
+use crate::log_storage::periodic_flush::PeriodicFlush;
+use crate::manifest::{load_manifest, manifest_path, save_manifest, DbMetadata};
+use crate::namespace::Namespace;
+use crate::secondary_index::SecondaryIndexes;
+use crate::snapshot::{Snapshot, SnapshotPins};
+use crate::stats::{
+    CompactionEstimate, CompactionStats, KeyStat, Latencies, LatencyReport, LogLayout,
+    RecoverySummary, SizeHistogram,
+};
+use crate::storage::MemoryStorage;
+use crate::transaction::Transaction;
+use crate::value_cache::ValueCache;
+use crate::{GrausDbOptions, GrausError, IndexExtractor, Result};
+use crossbeam_utils::atomic::AtomicCell;
+use log::error;
+use std::borrow::Cow;
 use std::cell::RefCell;
-use std::fs::{self, File};
-use std::sync::atomic::AtomicU64;
-use std::sync::{Arc, Mutex};
-use std::{collections::HashMap, path::PathBuf};
+use std::fs;
+use std::io::{self, Cursor, Read};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, TryLockError, Weak};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
+    path::{Path, PathBuf},
+};
+
+/// Entries grouped by the prefix their keys start with, returned by
+/// [`GrausDb::scan_prefixes`].
+pub type PrefixGroups = HashMap<Vec<u8>, Vec<(Vec<u8>, Vec<u8>)>>;
 
 /// The `GrausDb` stores string key/value pairs.
 ///
@@ -19,6 +58,13 @@ use std::{collections::HashMap, path::PathBuf};
 ///
 /// GrausDb is thead-safe. It can be cloned to use it on new threads.
 ///
+/// Reads always see the writes that returned before them, from any thread: the index is
+/// only updated after the command is written, and reading a command that has not been
+/// flushed yet flushes the log first.
+///
+/// Buffered writes are flushed on a best-effort basis when the database is dropped.
+/// Use [`GrausDb::close`] to shut it down deterministically.
+///
 /// ```rust
 /// # use graus_db::{GrausDb, Result};
 /// # fn try_main() -> Result<()> {
@@ -34,11 +80,38 @@ use std::{collections::HashMap, path::PathBuf};
 #[derive(Clone)]
 pub struct GrausDb {
     // Index that maps every Key to a position in a log file.
-    index: Arc<SkipMap<Vec<u8>, CommandPos>>,
+    index: Arc<KeyIndex>,
     // Writes new data into the file system logs. Protected by a mutex.
-    writer: Arc<Mutex<LogWriter>>,
+    pub(crate) writer: Arc<Mutex<LogWriter>>,
     // Reads data from the file system logs.
     reader: LogReader,
+    // Secondary indexes registered at runtime. Updated by the writer.
+    secondary_indexes: Arc<SecondaryIndexes>,
+    // Corrupted data skipped when the database was opened.
+    recovery_summary: RecoverySummary,
+    // Whether writes wait until they are durable before returning.
+    sync_each_write: bool,
+    // Shares the fsyncs of concurrent writes.
+    group_commit: Arc<GroupCommit>,
+    // Completed compactions, readable without locking the writer.
+    compaction_stats: Arc<CompactionStats>,
+    // Whether the index is saved when the database is closed.
+    index_snapshot: bool,
+    // Live snapshots, which keep the compacted logs from being deleted.
+    snapshot_pins: Arc<SnapshotPins>,
+    // Syncs the logs in the background. It is only held to stop it when the last handle
+    // is dropped.
+    _periodic_flush: Option<Arc<PeriodicFlush>>,
+    // Manifest storing the metadata, `None` for databases in memory.
+    manifest_path: Option<PathBuf>,
+    // Metadata of the database, locked while the manifest is saved.
+    metadata: Arc<Mutex<DbMetadata>>,
+    // Latencies of the operations, if they are recorded.
+    latencies: Option<Arc<Latencies>>,
+    // Values read by `get`, and the pinned keys.
+    value_cache: Arc<ValueCache>,
+    // Turns the keys given by the caller into the keys stored.
+    key_transform: Option<KeyTransform>,
 }
 
 impl GrausDb {
@@ -50,45 +123,369 @@ impl GrausDb {
     ///
     /// It propagates I/O or deserialization errors during the log replay.
     pub fn open(path: impl Into<PathBuf>) -> Result<GrausDb> {
-        let path = Arc::new(path.into());
-        fs::create_dir_all(&*path)?;
+        GrausDb::open_with_options(path, GrausDbOptions::default())
+    }
 
-        let mut readers = HashMap::new();
-        let index = Arc::new(SkipMap::new());
+    /// Opens a `GrausDb` with the given path and options.
+    ///
+    /// This will create a new directory if the given one does not exist.
+    ///
+    /// # Errors
+    ///
+    /// It propagates I/O or deserialization errors during the log replay.
+    pub fn open_with_options(path: impl Into<PathBuf>, options: GrausDbOptions) -> Result<GrausDb> {
+        let path: PathBuf = path.into();
+        fs::create_dir_all(&path).io_context("create directory", || path.clone())?;
+        let dir = LogDir {
+            root: path,
+            logs_per_dir: options.logs_per_dir,
+            preallocate_len: options.preallocate_len,
+            read_buffer_capacity: options.read_buffer_capacity,
+            write_buffer_capacity: options.write_buffer_capacity,
+            storage: options.storage.clone(),
+        };
+        let manifest_path = manifest_path(&dir.root);
+        GrausDb::open_dir(dir, Some(manifest_path), options)
+    }
 
-        let log_ids = get_log_ids(&path)?;
-        let mut uncompacted = 0;
+    /// Opens the database in the given path as it was when `max_log_id` was its newest
+    /// log, only replaying the logs up to it, e.g. to inspect it before an import.
+    ///
+    /// The database is opened read-only: writes fail with `GrausError::ReadOnly`, and the
+    /// files are left untouched, so it can be opened normally afterwards. Compactions
+    /// delete the logs they replace, so only the states since the last compaction can be
+    /// reached.
+    ///
+    /// # Errors
+    ///
+    /// It propagates I/O or deserialization errors during the log replay.
+    pub fn open_up_to(path: impl Into<PathBuf>, max_log_id: u64) -> Result<GrausDb> {
+        let path: PathBuf = path.into();
+        let options = GrausDbOptions {
+            create_active_log: false,
+            max_log_id: Some(max_log_id),
+            ..GrausDbOptions::default()
+        };
+        let dir = LogDir {
+            root: path,
+            logs_per_dir: options.logs_per_dir,
+            preallocate_len: None,
+            read_buffer_capacity: options.read_buffer_capacity,
+            write_buffer_capacity: options.write_buffer_capacity,
+            storage: None,
+        };
+        let manifest_path = manifest_path(&dir.root);
+        GrausDb::open_dir(dir, Some(manifest_path), options)
+    }
 
-        for &log_id in &log_ids {
-            let log_path = log_path(&path, log_id);
-            let mut reader = BufReaderWithPos::new(File::open(&log_path)?)?;
-            uncompacted += load_log(log_id, &mut reader, &index)?;
-            readers.insert(log_id, reader);
+    /// Opens an empty `GrausDb` that keeps its logs in memory instead of on disk.
+    ///
+    /// It behaves like a database opened from a directory, compactions included, but
+    /// nothing is written to disk and its data is lost when the last handle is dropped.
+    /// It is meant for tests and ephemeral caches.
+    pub fn open_in_memory() -> Result<GrausDb> {
+        let options = GrausDbOptions::default().storage(Arc::new(MemoryStorage::default()));
+        let dir = LogDir {
+            root: PathBuf::new(),
+            logs_per_dir: None,
+            preallocate_len: None,
+            read_buffer_capacity: options.read_buffer_capacity,
+            write_buffer_capacity: options.write_buffer_capacity,
+            storage: options.storage.clone(),
+        };
+        GrausDb::open_dir(dir, None, options)
+    }
+
+    // Opens the database whose logs are in `dir`. Its metadata is only kept in memory if
+    // there is no manifest path.
+    fn open_dir(
+        dir: LogDir,
+        manifest_path: Option<PathBuf>,
+        options: GrausDbOptions,
+    ) -> Result<GrausDb> {
+        if cfg!(feature = "hash-index") {
+            if !std::ptr::fn_addr_eq(options.key_comparator, lexicographic as KeyComparator) {
+                return Err(GrausError::IncompatibleOptions(
+                    "the hash index doesn't order keys with a comparator",
+                ));
+            }
+            if options.bloom_false_positive_rate.is_some() {
+                return Err(GrausError::IncompatibleOptions(
+                    "the hash index has no bloom filter",
+                ));
+            }
+        }
+        if options.value_log_threshold.is_some() {
+            if dir.storage.is_some() {
+                return Err(GrausError::IncompatibleOptions(
+                    "value logs can't be kept in a storage",
+                ));
+            }
+            if options.mirror_dir.is_some() {
+                return Err(GrausError::IncompatibleOptions(
+                    "value logs are not mirrored",
+                ));
+            }
+        }
+        // The format version is checked before the logs are touched
+        let metadata = match &manifest_path {
+            Some(manifest_path) => load_manifest(manifest_path)?,
+            None => DbMetadata::default(),
+        };
+        let dir = Arc::new(dir);
+        let read_only = options.max_log_id.is_some();
+        if !read_only {
+            relocate_logs(&dir)?;
+            if let Some(value_dir) = existing_value_dir(&dir) {
+                relocate_logs(&value_dir)?;
+            }
         }
 
+        // The mirror is only copied, so its logs are not preallocated
+        let mirror_dir = options.mirror_dir.clone().map(|root| LogDir {
+            root,
+            logs_per_dir: options.logs_per_dir,
+            preallocate_len: None,
+            read_buffer_capacity: options.read_buffer_capacity,
+            write_buffer_capacity: options.write_buffer_capacity,
+            storage: None,
+        });
+        let loaded = match (GrausDb::load_logs(&dir, &options), &mirror_dir) {
+            (Err(e), Some(mirror_dir)) if restore_from_mirror(&dir, mirror_dir)? => {
+                error!(
+                    "Restored the logs from the mirror after failing to load them: {}",
+                    e
+                );
+                GrausDb::load_logs(&dir, &options)?
+            }
+            (loaded, _) => loaded?,
+        };
+        let LoadedLogs {
+            index,
+            mut readers,
+            log_ids,
+            uncompacted,
+            seq,
+            recovery_summary,
+        } = loaded;
+        let index = Arc::new(index);
+
+        // The value logs no command points to were left by an interrupted compaction
+        let referenced_value_logs: BTreeSet<u64> = index
+            .iter()
+            .filter_map(|(_, cmd_pos)| cmd_pos.value_log_id)
+            .collect();
+        let orphaned_value_logs: Vec<u64> = get_value_log_ids(&dir)?
+            .into_iter()
+            .filter(|log_id| !referenced_value_logs.contains(log_id))
+            .collect();
+        // The logs after the ones loaded read-only may point to any value log
+        if !read_only {
+            remove_value_logs(&dir, &orphaned_value_logs);
+        }
+
+        // The manifest is stamped before the first value log is written, so older versions
+        // of GrausDb never open a database with value logs
+        if let (Some(_), Some(value_dir)) = (options.value_log_threshold, dir.value_dir()) {
+            if !value_dir.root.exists() {
+                if let Some(manifest_path) = &manifest_path {
+                    save_manifest(manifest_path, &metadata, true)?;
+                }
+                fs::create_dir_all(&value_dir.root)
+                    .io_context("create directory", || value_dir.root.clone())?;
+            }
+        }
+
+        // The preallocated space of the new active log is not written yet
+        let total_bytes = get_logs_size(&dir)? + get_value_logs_size(&dir)?;
         let new_log_id = log_ids.last().unwrap_or(&0) + 1;
-        let writer = new_log_file(&path, new_log_id)?;
+        let writer = if options.create_active_log {
+            Some(new_log_file(&dir, new_log_id)?)
+        } else {
+            None
+        };
+        let mirror = match mirror_dir {
+            Some(mirror_dir) => Some(Mirror::open(
+                mirror_dir,
+                &dir,
+                &log_ids,
+                new_log_id,
+                options.mirror_mode,
+            )?),
+            None => None,
+        };
         let safe_point = Arc::new(AtomicU64::new(0));
 
+        let flushed = Arc::new(AtomicCell::new(FlushedPos {
+            log_id: new_log_id,
+            pos: 0,
+        }));
+
+        // Only the readers of the newest logs are kept open
+        if let Some(max_open_readers) = options.max_open_readers {
+            while readers.len() > max_open_readers {
+                readers.pop_first();
+            }
+        }
+
+        if recovery_summary.dropped_records > 0 {
+            error!(
+                "Dropped {} corrupted records ({} bytes) while opening the database",
+                recovery_summary.dropped_records, recovery_summary.dropped_bytes
+            );
+        }
+
         let reader = LogReader {
-            path: Arc::clone(&path),
+            dir: Arc::clone(&dir),
             safe_point,
+            flushed,
             readers: RefCell::new(readers),
+            max_open_readers: options.max_open_readers,
+            recently_used: RefCell::new(VecDeque::new()),
+            sealer: options
+                .value_cipher
+                .map(|cipher| Arc::new(Sealer::new(cipher))),
+            value_dir: dir.value_dir().map(Arc::new),
+            value_readers: RefCell::new(BTreeMap::new()),
         };
 
-        let writer = LogWriter {
-            writer,
-            index: Arc::clone(&index),
-            reader: reader.clone(),
-            current_log_id: new_log_id,
-            uncompacted,
-            path: Arc::clone(&path),
-        };
+        let secondary_indexes = Arc::new(SecondaryIndexes::default());
+        let group_commit = Arc::new(GroupCommit::default());
+        let compaction_stats = Arc::new(CompactionStats::default());
+        let snapshot_pins = Arc::new(SnapshotPins::default());
+        let value_cache = Arc::new(ValueCache::new(options.value_cache_bytes));
+
+        let writer = Arc::new_cyclic(|this| {
+            Mutex::new(LogWriter {
+                writer,
+                index: Arc::clone(&index),
+                reader: reader.clone(),
+                current_log_id: new_log_id,
+                uncompacted,
+                seq,
+                total_bytes,
+                num_logs: log_ids.len() + options.create_active_log as usize,
+                compaction_strategy: options.compaction_strategy,
+                dir: Arc::clone(&dir),
+                flush_each_write: options.flush_each_write,
+                sync_before_visible: options.sync_before_visible,
+                secondary_indexes: Arc::clone(&secondary_indexes),
+                group_commit: Arc::clone(&group_commit),
+                executor: options.executor,
+                this: Weak::clone(this),
+                compaction_scheduled: false,
+                compacting: None,
+                compaction_stats: Arc::clone(&compaction_stats),
+                mirror,
+                max_uncompacted: options.max_uncompacted,
+                max_total_bytes: options.max_total_bytes,
+                value_checksums: options.value_checksums,
+                snapshot_pins: Arc::clone(&snapshot_pins),
+                value_log_threshold: options.value_log_threshold,
+                value_writer: None,
+                verify_writes: options.verify_writes,
+                value_transform: options.compaction_value_transform,
+                read_only,
+                value_cache: Arc::clone(&value_cache),
+            })
+        });
+
+        let periodic_flush = options.flush_interval.map(|interval| {
+            Arc::new(PeriodicFlush::start(
+                interval,
+                Arc::downgrade(&writer),
+                Arc::clone(&group_commit),
+            ))
+        });
 
         Ok(GrausDb {
             reader,
             index,
-            writer: Arc::new(Mutex::new(writer)),
+            writer,
+            secondary_indexes,
+            recovery_summary,
+            sync_each_write: options.sync_each_write,
+            group_commit,
+            compaction_stats,
+            index_snapshot: options.index_snapshot,
+            snapshot_pins,
+            _periodic_flush: periodic_flush,
+            manifest_path,
+            metadata: Arc::new(Mutex::new(metadata)),
+            latencies: options.record_latencies.then(Arc::default),
+            value_cache,
+            key_transform: options.key_transform,
+        })
+    }
+
+    // Loads the index from the logs, or from its snapshot if it is valid, and opens
+    // readers of the logs.
+    fn load_logs(dir: &LogDir, options: &GrausDbOptions) -> Result<LoadedLogs> {
+        let mut readers = BTreeMap::new();
+        let index = KeyIndex::new(options.key_comparator, options.bloom_false_positive_rate);
+
+        let log_ids = match options.max_log_id {
+            // The logs are left untouched when only some of them are loaded
+            Some(max_log_id) => get_log_ids(dir)?
+                .into_iter()
+                .filter(|log_id| *log_id <= max_log_id)
+                .collect(),
+            None => remove_empty_logs(dir)?,
+        };
+        let mut uncompacted = 0;
+        let mut seq = 0;
+        let mut recovery_summary = RecoverySummary::default();
+
+        // The logs are only replayed if there is no valid snapshot of the index
+        let snapshot = if options.index_snapshot {
+            load_index_snapshot(dir, &log_ids, options.snapshot_verification)?
+        } else {
+            None
+        };
+        let snapshot_loaded = snapshot.is_some();
+        if let Some(snapshot) = snapshot {
+            uncompacted = snapshot.uncompacted;
+            seq = snapshot.seq;
+            for (key, cmd_pos) in snapshot.entries {
+                index.insert(key, cmd_pos);
+            }
+        }
+
+        for &log_id in &log_ids {
+            let log_path = dir.log_path(log_id);
+            let file = dir
+                .open_log(log_id)
+                .io_context("open", || log_path.clone())?;
+            let mut reader = BufReaderWithPos::with_capacity(dir.read_buffer_capacity, file)
+                .io_context("seek", || log_path.clone())?;
+            if snapshot_loaded {
+                readers.insert(log_id, reader);
+                continue;
+            }
+            let loaded_log = load_log(log_id, &mut reader, &index, options.recovery_mode)
+                .io_context("read", || log_path.clone())?;
+            uncompacted += loaded_log.uncompacted;
+            seq = seq.max(loaded_log.max_seq);
+            recovery_summary.dropped_records += loaded_log.dropped.dropped_records;
+            recovery_summary.dropped_bytes += loaded_log.dropped.dropped_bytes;
+            // A new active log is created after loading, so the logs will not be written again.
+            // Logs with skipped records are left unsealed, as they are still corrupted.
+            if !loaded_log.sealed
+                && loaded_log.dropped.dropped_records == 0
+                && options.max_log_id.is_none()
+            {
+                seal_log(dir, log_id, loaded_log.len, loaded_log.commands)?;
+            }
+            readers.insert(log_id, reader);
+        }
+
+        Ok(LoadedLogs {
+            index,
+            readers,
+            log_ids,
+            uncompacted,
+            seq,
+            recovery_summary,
         })
     }
 
@@ -96,35 +493,1004 @@ impl GrausDb {
     ///
     /// If the key already exists, the previous value will be overwritten.
     pub fn set(&self, key: Vec<u8>, value: &[u8]) -> Result<()> {
-        self.writer.lock().unwrap().set(key, value)
+        let start = self.latencies.as_ref().map(|_| Instant::now());
+        let key = self.stored_key_owned(key);
+        let result = self.write(|writer| writer.set(key, value));
+        if let (Some(latencies), Some(start)) = (&self.latencies, start) {
+            latencies.set.record(start.elapsed());
+        }
+        result
+    }
+
+    /// Sets the value of a key like `set`, but fails with `GrausError::WriteTimeout` if
+    /// the writer can't be locked within `timeout` because of other writes.
+    ///
+    /// It lets latency-sensitive callers give up instead of waiting behind a burst of
+    /// writes. Once the writer is locked, the write itself is not interrupted, including
+    /// a compaction it has to wait for when writes are stalled or a sync with
+    /// `sync_each_write`.
+    pub fn set_timeout(&self, key: Vec<u8>, value: &[u8], timeout: Duration) -> Result<()> {
+        let start = self.latencies.as_ref().map(|_| Instant::now());
+        let key = self.stored_key_owned(key);
+        let result = self
+            .lock_writer_timeout(timeout)
+            .and_then(|writer| self.write_locked(writer, |writer| writer.set(key, value)));
+        if let (Some(latencies), Some(start)) = (&self.latencies, start) {
+            latencies.set.record(start.elapsed());
+        }
+        result
+    }
+
+    /// Appends `chunk` to the value of a key, or sets it if the key does not exist.
+    ///
+    /// Only the chunk is written, so appending to a growing value doesn't rewrite it. The
+    /// chunks are concatenated when the value is read, and folded into a single value
+    /// when the logs are compacted.
+    pub fn append(&self, key: Vec<u8>, chunk: &[u8]) -> Result<()> {
+        let key = self.stored_key_owned(key);
+        self.write(|writer| writer.append(key, chunk))
     }
 
     /// Gets the string value of a given string key.
     ///
     /// Returns `None` if the given key does not exist.
     pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
-        if let Some(cmd_pos) = self.index.get(key) {
-            if let CommandOwned::Set { value, .. } = self.reader.read_command(*cmd_pos.value())? {
-                Ok(Some(value))
-            } else {
-                Err(GrausError::UnexpectedCommandType)
+        let start = self.latencies.as_ref().map(|_| Instant::now());
+        let result = self.get_versioned(key);
+        if let (Some(latencies), Some(start)) = (&self.latencies, start) {
+            latencies.get.record(start.elapsed());
+        }
+        Ok(result?.map(|(value, _)| value))
+    }
+
+    /// Gets the value of a given key, or `default` if the key does not exist.
+    ///
+    /// The default is not stored, unlike with `get_or_set`.
+    pub fn get_or(&self, key: &[u8], default: Vec<u8>) -> Result<Vec<u8>> {
+        Ok(self.get(key)?.unwrap_or(default))
+    }
+
+    /// Gets the value of a given key, or the value returned by `f` if the key does not
+    /// exist.
+    ///
+    /// `f` is only called if the key does not exist, so it can compute an expensive
+    /// default. The default is not stored.
+    pub fn get_or_else<F>(&self, key: &[u8], f: F) -> Result<Vec<u8>>
+    where
+        F: FnOnce() -> Vec<u8>,
+    {
+        Ok(self.get(key)?.unwrap_or_else(f))
+    }
+
+    /// Gets the value of a given key, always reading it from the logs.
+    ///
+    /// Unlike `get`, it never uses a cached value, so it can be used to check what is stored
+    /// on disk, or to read values that won't be read again without caching them.
+    /// Returns `None` if the given key does not exist.
+    pub fn get_uncached(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        match self.get_command(&self.stored_key(key))? {
+            Some((CommandOwned::Set { value, .. }, _)) => Ok(Some(value)),
+            Some(_) => Err(GrausError::UnexpectedCommandType),
+            None => Ok(None),
+        }
+    }
+
+    /// Gets the value of a given key along with its version.
+    ///
+    /// The version of a key is the sequence number of its last write, see
+    /// [`GrausDb::current_seq`], so it increases on every write, even if the key was
+    /// removed in between. Returns `None` if the given key does not exist.
+    pub fn get_versioned(&self, key: &[u8]) -> Result<Option<(Vec<u8>, u64)>> {
+        Ok(self
+            .get_cached(&self.stored_key(key))?
+            .map(|(value, cmd_pos)| (value, cmd_pos.version)))
+    }
+
+    // Gets the value of a given key from the value cache, or reads it from the logs and
+    // caches it. Returns it with the position of its command.
+    fn get_cached(&self, key: &[u8]) -> Result<Option<(Vec<u8>, CommandPos)>> {
+        if self.value_cache.is_enabled() {
+            if let Some(cmd_pos) = self.index.get(key) {
+                if let Some(value) = self.value_cache.get(key, cmd_pos) {
+                    return Ok(Some((value, cmd_pos)));
+                }
             }
+        }
+        match self.get_command(key)? {
+            Some((CommandOwned::Set { value, .. }, cmd_pos)) => {
+                if self.value_cache.is_enabled() {
+                    self.value_cache.insert(key, cmd_pos, value.clone());
+                    // The writer may have dropped the value of the key before it was cached
+                    if self.index.get(key) != Some(cmd_pos) {
+                        self.value_cache.remove(key);
+                    }
+                }
+                Ok(Some((value, cmd_pos)))
+            }
+            Some(_) => Err(GrausError::UnexpectedCommandType),
+            None => Ok(None),
+        }
+    }
+
+    /// Pins a key in the value cache, so its value stays in memory and `get` never reads
+    /// it from the logs, except the first time after it is written or after
+    /// [`GrausDb::rebuild_index`].
+    ///
+    /// The value is loaded right away if the key exists. Pinned values are kept even if
+    /// the cache is disabled or full, and don't count towards
+    /// [`value_cache_bytes`](GrausDbOptions::value_cache_bytes). Pins are not persisted.
+    pub fn pin(&self, key: &[u8]) -> Result<()> {
+        let key = self.stored_key(key);
+        self.value_cache.pin(&key);
+        self.get_cached(&key)?;
+        Ok(())
+    }
+
+    /// Unpins a key pinned with `pin`, dropping its value from the value cache.
+    pub fn unpin(&self, key: &[u8]) {
+        self.value_cache.unpin(&self.stored_key(key));
+    }
+
+    /// Gets the value of a given key along with the wall clock time when it was written.
+    ///
+    /// The time is `None` for values written by versions of GrausDb that didn't record it.
+    /// Returns `None` if the given key does not exist.
+    pub fn get_with_timestamp(&self, key: &[u8]) -> Result<Option<(Vec<u8>, Option<SystemTime>)>> {
+        match self.get_command(&self.stored_key(key))? {
+            Some((
+                CommandOwned::Set {
+                    value, timestamp, ..
+                },
+                _,
+            )) => {
+                let time = timestamp.map(|micros| UNIX_EPOCH + Duration::from_micros(micros));
+                Ok(Some((value, time)))
+            }
+            Some(_) => Err(GrausError::UnexpectedCommandType),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the bytes of the last command of a key as they are stored in the logs, or
+    /// `None` if the key does not exist.
+    ///
+    /// It is meant for debugging and for replicas that store the commands as they are.
+    /// The format of the commands is internal and may change across versions of GrausDb.
+    /// For a value grown with `append`, only the command of the last chunk is returned,
+    /// and for a value stored in a value log, only the command that points to it.
+    pub fn raw_record(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let record = self.read_last(&self.stored_key(key), |cmd_pos| {
+            self.reader.read_and(cmd_pos, |reader| {
+                let mut record = vec![0; cmd_pos.len as usize];
+                reader.read_exact(&mut record)?;
+                Ok(record)
+            })
+        })?;
+        Ok(record.map(|(record, _)| record))
+    }
+
+    // Reads the last command of a given key and its position.
+    fn get_command(&self, key: &[u8]) -> Result<Option<(CommandOwned, CommandPos)>> {
+        self.read_last(key, |cmd_pos| self.reader.read_command(cmd_pos))
+    }
+
+    // Reads the last command of a given key with `read`, and returns it with its position.
+    fn read_last<R>(
+        &self,
+        key: &[u8],
+        read: impl Fn(CommandPos) -> Result<R>,
+    ) -> Result<Option<(R, CommandPos)>> {
+        loop {
+            let Some(cmd_pos) = self.index.get(key) else {
+                return Ok(None);
+            };
+            if !self.reader.is_flushed(cmd_pos) {
+                self.writer.lock().unwrap().flush()?;
+            }
+            match read(cmd_pos) {
+                Ok(command) => return Ok(Some((command, cmd_pos))),
+                // A compaction moved the key to a new log and deleted the old one after the
+                // position was read from the index, so the index has to be queried again.
+                Err(_) if self.reader.is_stale(cmd_pos.log_id) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Returns the values written for a key that are still in the logs, from the oldest to
+    /// the newest, along with the log where each one was found. Removals are returned as
+    /// `None`.
+    ///
+    /// It scans all the log files, so it is meant for debugging. Compactions only keep the
+    /// last value of every key, so older history is lost once the logs are compacted, and a
+    /// value can appear twice while a compaction is copying it.
+    pub fn get_history(&self, key: &[u8]) -> Result<Vec<(u64, Option<Vec<u8>>)>> {
+        let key = &*self.stored_key(key);
+        let mut history = Vec::new();
+        self.for_each_logged_command(|log_id, command| {
+            match command {
+                CommandOwned::Set {
+                    key: ref command_key,
+                    ..
+                }
+                | CommandOwned::SetRef {
+                    key: ref command_key,
+                    ..
+                } if command_key == key => {
+                    let CommandOwned::Set { value, nonce, .. } =
+                        self.reader.resolve_value(command)?
+                    else {
+                        unreachable!("resolved commands are \"set\" commands");
+                    };
+                    let value = self.reader.open_value(value, nonce)?;
+                    history.push((log_id, Some(value)))
+                }
+                CommandOwned::Remove {
+                    key: command_key, ..
+                } if command_key == key => history.push((log_id, None)),
+                CommandOwned::Append {
+                    key: command_key,
+                    chunk,
+                    nonce,
+                    ..
+                } if command_key == key => {
+                    // The value it appends to is always before it in the logs
+                    let mut value = history
+                        .last()
+                        .and_then(|(_, value)| value.clone())
+                        .unwrap_or_default();
+                    value.extend_from_slice(&self.reader.open_value(chunk, nonce)?);
+                    history.push((log_id, Some(value)))
+                }
+                _ => {}
+            }
+            Ok(())
+        })?;
+        Ok(history)
+    }
+
+    /// Returns how many values of every key are still in the logs, live or not compacted
+    /// yet, to see the write amplification of each key. Appended chunks count as values,
+    /// and removed keys are included as long as their values are in the logs.
+    ///
+    /// It scans all the log files and is read-only, so it is meant for diagnostics. Keys
+    /// with many values are the ones that benefit from [`GrausDb::compact_key`]. Like
+    /// [`GrausDb::get_history`], a value can be counted twice while a compaction is copying
+    /// it.
+    pub fn value_counts(&self) -> Result<BTreeMap<Vec<u8>, u64>> {
+        self.check_keys_scannable()?;
+        let mut counts = BTreeMap::new();
+        self.for_each_logged_command(|_, command| {
+            match command {
+                CommandOwned::Set { key, .. }
+                | CommandOwned::SetRef { key, .. }
+                | CommandOwned::Append { key, .. } => *counts.entry(key).or_insert(0) += 1,
+                CommandOwned::Remove { .. } => {}
+            }
+            Ok(())
+        })?;
+        Ok(counts)
+    }
+
+    // Reads every command of the logs in the order they were written, along with its log.
+    fn for_each_logged_command(
+        &self,
+        mut f: impl FnMut(u64, CommandOwned) -> Result<()>,
+    ) -> Result<()> {
+        self.writer.lock().unwrap().flush()?;
+        // Commands written after this point may be partially written, so they are skipped
+        let flushed = self.reader.flushed.load();
+
+        for log_id in get_log_ids(&self.reader.dir)? {
+            if log_id > flushed.log_id {
+                break;
+            }
+            let file = match self.reader.dir.open_log(log_id) {
+                Ok(file) => file,
+                // Deleted by a compaction after listing it
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e).io_context("open", || self.reader.dir.log_path(log_id)),
+            };
+            let max_end = (log_id == flushed.log_id).then_some(flushed.pos);
+            let mut reader =
+                BufReaderWithPos::with_capacity(self.reader.dir.read_buffer_capacity, file)?;
+            for_each_command(&mut reader, max_end, |command| f(log_id, command))?;
+        }
+        Ok(())
+    }
+
+    /// Returns whether the given key exists, without reading its value.
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.index.contains_key(&self.stored_key(key))
+    }
+
+    /// Gets a reader of the value of a given key, without loading it into memory.
+    ///
+    /// The reader owns its own handle to the log file, so it remains valid even if the
+    /// key is overwritten or the log is compacted while reading. Only the last chunk of
+    /// appended values is streamed, the previous ones are read into memory, and so are
+    /// encrypted values. Returns `None` if the given key does not exist.
+    pub fn get_stream(&self, key: &[u8]) -> Result<Option<impl Read>> {
+        let key = &*self.stored_key(key);
+        loop {
+            let Some(cmd_pos) = self.index.get(key) else {
+                return Ok(None);
+            };
+            if !self.reader.is_flushed(cmd_pos) {
+                self.writer.lock().unwrap().flush()?;
+            }
+            match self.open_value(cmd_pos) {
+                Ok(value_reader) => return Ok(Some(value_reader)),
+                // The log was deleted by a compaction, query the index again
+                Err(_) if self.reader.is_stale(cmd_pos.log_id) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Gets the values of the given keys lazily, in the same order, as the returned
+    /// iterator is consumed.
+    ///
+    /// Only one value is held in memory at a time, so it suits fetching very many keys,
+    /// e.g. to write them to a network stream. Every value is read like `get`, when the
+    /// iterator reaches its key, through the log handles of this `GrausDb`.
+    pub fn get_batch_stream<'a>(
+        &'a self,
+        keys: impl Iterator<Item = Vec<u8>> + 'a,
+    ) -> impl Iterator<Item = Result<Option<Vec<u8>>>> + 'a {
+        keys.map(move |key| self.get(&key))
+    }
+
+    // Opens a reader of the value at `cmd_pos`. The chunks appended before the one stored
+    // in that command are spread across several commands, so they are read into memory.
+    // Encrypted values are decrypted as a whole, so they are read into memory too.
+    fn open_value(&self, cmd_pos: CommandPos) -> Result<Box<dyn Read>> {
+        if self.reader.sealer.is_some() {
+            let CommandOwned::Set { value, .. } = self.reader.read_command(cmd_pos)? else {
+                return Err(GrausError::UnexpectedCommandType);
+            };
+            return Ok(Box::new(Cursor::new(value)));
+        }
+        if cmd_pos.is_separated() {
+            // Only the command is read, the value is streamed from the value log
+            return match self.reader.read_and(cmd_pos, deserialize_command)? {
+                CommandOwned::SetRef { nonce: Some(_), .. } => Err(GrausError::DecryptionFailed),
+                CommandOwned::SetRef { value_ref, .. } => {
+                    Ok(Box::new(self.reader.open_value_at(value_ref)?))
+                }
+                _ => Err(GrausError::UnexpectedCommandType),
+            };
+        }
+        if self.reader.read_and(cmd_pos, read_nonce)?.is_some() {
+            return Err(GrausError::DecryptionFailed);
+        }
+        let mut appended = Vec::new();
+        if cmd_pos.appended_len > 0 {
+            if let CommandOwned::Set { value, .. } = self.reader.read_command(cmd_pos)? {
+                appended = value;
+                appended.truncate(cmd_pos.appended_len as usize);
+            }
+        }
+        let value_reader =
+            self.reader
+                .open_at(cmd_pos.log_id, cmd_pos.value_pos(), cmd_pos.value_len)?;
+        Ok(Box::new(Cursor::new(appended).chain(value_reader)))
+    }
+
+    /// Returns up to `limit` keys that come strictly after `after`, ordered by the index
+    /// comparator (by their bytes with the `hash-index` feature). With `after` set to
+    /// `None` it starts from the first key.
+    ///
+    /// Passing the last key returned as `after` fetches the next page. Keys written or
+    /// removed between pages don't break the pagination: the next page just continues
+    /// after that key, whether it still exists or not.
+    pub fn list_keys(&self, after: Option<&[u8]>, limit: usize) -> Result<Vec<Vec<u8>>> {
+        self.check_keys_scannable()?;
+        Ok(self.index.keys_after(after, limit))
+    }
+
+    /// Iterates over the keys that encode a `u64` in big-endian (`u64::to_be_bytes`) in the
+    /// range `[start, end)`, in ascending order, along with their values.
+    ///
+    /// Only keys of exactly 8 bytes are matched. The matching keys are collected from the
+    /// index when it is called, and their values are read lazily, so keys removed meanwhile
+    /// are skipped.
+    pub fn scan_int_range(
+        &self,
+        start: u64,
+        end: u64,
+    ) -> impl Iterator<Item = Result<(u64, Vec<u8>)>> + '_ {
+        let scannable = self.check_keys_scannable();
+        let mut keys: Vec<u64> = if start < end && scannable.is_ok() {
+            let (start, end) = (start.to_be_bytes(), end.to_be_bytes());
+            self.index
+                .range_iter(&start, &end)
+                .filter_map(|(key, _)| key.try_into().ok().map(u64::from_be_bytes))
+                .collect()
         } else {
-            Ok(None)
+            Vec::new()
+        };
+        // Neither custom comparators nor the hash index order the keys numerically
+        keys.sort_unstable();
+        let values = keys
+            .into_iter()
+            .filter_map(move |key| match self.get(&key.to_be_bytes()) {
+                Ok(value) => value.map(|value| Ok((key, value))),
+                Err(e) => Some(Err(e)),
+            });
+        scannable.err().map(Err).into_iter().chain(values)
+    }
+
+    /// Iterates over all the keys and their values in the order they are stored in the
+    /// logs, from the oldest log to the newest, instead of the key order.
+    ///
+    /// Reading values in this order is mostly sequential, so it is useful to warm up the
+    /// page cache. The positions are collected from the index when it is called, and the
+    /// values are read lazily: keys removed meanwhile are skipped, and keys written or moved
+    /// by a compaction meanwhile are read from their new position.
+    pub fn iter_physical(&self) -> impl Iterator<Item = Result<(Vec<u8>, Vec<u8>)>> + '_ {
+        let scannable = self.check_keys_scannable();
+        let mut entries: Vec<(Vec<u8>, CommandPos)> = match scannable {
+            Ok(()) => self.index.iter().collect(),
+            Err(_) => Vec::new(),
+        };
+        entries.sort_unstable_by_key(|(_, cmd_pos)| (cmd_pos.log_id, cmd_pos.pos));
+        let values = entries.into_iter().filter_map(move |(key, cmd_pos)| {
+            // Keys written after collecting the positions are read from the index again
+            if self.index.get(&key) != Some(cmd_pos) {
+                return self
+                    .get(&key)
+                    .transpose()
+                    .map(|value| value.map(|value| (key, value)));
+            }
+            if !self.reader.is_flushed(cmd_pos) {
+                if let Err(e) = self.writer.lock().unwrap().flush() {
+                    return Some(Err(e));
+                }
+            }
+            let value = match self.reader.read_command(cmd_pos) {
+                Ok(CommandOwned::Set { value, .. }) => Ok(Some(value)),
+                Ok(_) => Err(GrausError::UnexpectedCommandType),
+                Err(_) if self.reader.is_stale(cmd_pos.log_id) => self.get(&key),
+                Err(e) => Err(e),
+            };
+            value
+                .transpose()
+                .map(|value| value.map(|value| (key, value)))
+        });
+        scannable.err().map(Err).into_iter().chain(values)
+    }
+
+    /// Returns the keys whose values contain `needle`, ordered by the index comparator.
+    ///
+    /// Every live value is read from the logs, so it is meant for small datasets, debugging
+    /// and admin tooling. An empty `needle` matches every value.
+    pub fn scan_values_containing(&self, needle: &[u8]) -> Result<Vec<Vec<u8>>> {
+        self.scan_values_containing_bounded(needle, usize::MAX)
+    }
+
+    /// Returns up to `max_results` keys whose values contain `needle`, ordered by the index
+    /// comparator.
+    ///
+    /// Unlike `scan_values_containing`, it stops reading values once `max_results` keys
+    /// are found.
+    pub fn scan_values_containing_bounded(
+        &self,
+        needle: &[u8],
+        max_results: usize,
+    ) -> Result<Vec<Vec<u8>>> {
+        self.check_keys_scannable()?;
+        let mut keys = Vec::new();
+        for (key, _) in self.index.iter() {
+            if keys.len() >= max_results {
+                break;
+            }
+            // Keys removed after the scan started are skipped
+            let Some(value) = self.get(&key)? else {
+                continue;
+            };
+            if needle.is_empty() || value.windows(needle.len()).any(|window| window == needle) {
+                keys.push(key);
+            }
         }
+        Ok(keys)
+    }
+
+    /// Returns the entries for which `f(key, value)` returns true, ordered by the index
+    /// comparator.
+    ///
+    /// Every live value is read once from the logs, so it is meant for ad-hoc queries in
+    /// admin tooling rather than for the hot path.
+    pub fn retain_scan(&self, f: impl Fn(&[u8], &[u8]) -> bool) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        self.retain_scan_bounded(f, usize::MAX)
+    }
+
+    /// Returns up to `max_results` entries for which `f(key, value)` returns true, ordered
+    /// by the index comparator.
+    ///
+    /// Unlike `retain_scan`, it stops reading values once `max_results` entries are found.
+    pub fn retain_scan_bounded(
+        &self,
+        f: impl Fn(&[u8], &[u8]) -> bool,
+        max_results: usize,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        self.retain_scan_until(f, max_results, None)
+    }
+
+    /// Returns the entries for which `f(key, value)` returns true, like `retain_scan`, but
+    /// stops early once `cancel` is set, e.g. when the client waiting for them disconnects.
+    ///
+    /// The flag is checked before reading every value, and the entries found until then are
+    /// returned.
+    pub fn retain_scan_cancellable(
+        &self,
+        f: impl Fn(&[u8], &[u8]) -> bool,
+        cancel: &AtomicBool,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        self.retain_scan_until(f, usize::MAX, Some(cancel))
+    }
+
+    /// Returns the keys whose values contain `needle`, like `scan_values_containing`, but
+    /// stops early once `cancel` is set.
+    ///
+    /// The flag is checked before reading every value, and the keys found until then are
+    /// returned.
+    pub fn scan_values_containing_cancellable(
+        &self,
+        needle: &[u8],
+        cancel: &AtomicBool,
+    ) -> Result<Vec<Vec<u8>>> {
+        let contains = |_: &[u8], value: &[u8]| {
+            needle.is_empty() || value.windows(needle.len()).any(|window| window == needle)
+        };
+        let entries = self.retain_scan_until(contains, usize::MAX, Some(cancel))?;
+        Ok(entries.into_iter().map(|(key, _)| key).collect())
+    }
+
+    // Returns up to `max_results` entries for which `f(key, value)` returns true, stopping
+    // early if `cancel` is set.
+    fn retain_scan_until(
+        &self,
+        f: impl Fn(&[u8], &[u8]) -> bool,
+        max_results: usize,
+        cancel: Option<&AtomicBool>,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        self.check_keys_scannable()?;
+        let mut entries = Vec::new();
+        for (key, _) in self.index.iter() {
+            if entries.len() >= max_results
+                || cancel.is_some_and(|cancel| cancel.load(Ordering::Relaxed))
+            {
+                break;
+            }
+            // Keys removed after the scan started are skipped
+            let Some(value) = self.get(&key)? else {
+                continue;
+            };
+            if f(&key, &value) {
+                entries.push((key, value));
+            }
+        }
+        Ok(entries)
     }
 
     /// Removes a given key.
     ///
     /// Returns GrausError::KeyNotFound if the key does not exist.
     pub fn remove(&self, key: &[u8]) -> Result<()> {
-        self.writer.lock().unwrap().remove(key)
+        let key = self.stored_key(key);
+        self.write(|writer| writer.remove(&key))
+    }
+
+    /// Returns the current size in bytes of all the log files of the database.
+    ///
+    /// It only reads the file system metadata of the logs.
+    pub fn disk_size(&self) -> Result<u64> {
+        Ok(get_logs_size(&self.reader.dir)? + get_value_logs_size(&self.reader.dir)?)
+    }
+
+    /// Returns the log and value length of a given key, or `None` if it does not exist.
+    ///
+    /// It only uses the in-memory index, so the value is not read.
+    pub fn stat_key(&self, key: &[u8]) -> Option<KeyStat> {
+        self.index
+            .get(&self.stored_key(key))
+            .map(|cmd_pos| KeyStat {
+                log_id: cmd_pos.log_id,
+                value_len: cmd_pos.total_value_len(),
+            })
+    }
+
+    /// Returns the corrupted data that was skipped when the database was opened.
+    ///
+    /// It is always empty unless the database was opened in `RecoveryMode::Salvage`.
+    pub fn recovery_summary(&self) -> RecoverySummary {
+        self.recovery_summary
+    }
+
+    /// Returns the position in the logs of the last command of every key, ordered by the
+    /// index comparator.
+    ///
+    /// It is meant for diagnostics and external tools that read the log files directly, so
+    /// the keys are returned as they are stored, transformed by
+    /// [`key_transform`](GrausDbOptions::key_transform). It is not an atomic snapshot: writes that happen while it is taken may or may not be
+    /// included, and a compaction can move or delete the logs it points to.
+    pub fn index_snapshot(&self) -> Vec<(Vec<u8>, CommandPos)> {
+        self.index.iter().collect()
+    }
+
+    /// Iterates over every key and the position in the logs of its last command, ordered
+    /// by the index comparator.
+    ///
+    /// Unlike `index_snapshot`, the entries are streamed from the live index instead of
+    /// collected first, so it has the same consistency caveats. Commands may still be in
+    /// the log buffer, so call [`GrausDb::flush`] before reading the logs directly. The
+    /// value of a key that was appended to is spread over several commands, see
+    /// [`CommandPos::appended_len`].
+    pub fn scan_with_pos(&self) -> impl Iterator<Item = (Vec<u8>, CommandPos)> + '_ {
+        self.index.iter()
+    }
+
+    /// Returns the distribution of key and value lengths of all the entries.
+    ///
+    /// It is computed from the in-memory index, without reading any value from disk.
+    pub fn size_histogram(&self) -> SizeHistogram {
+        let mut histogram = SizeHistogram::default();
+        for (key, cmd_pos) in self.index.iter() {
+            histogram.record(key.len() as u64, cmd_pos.total_value_len());
+        }
+        histogram
+    }
+
+    /// Estimates the bytes of memory used by the in-memory index, for capacity planning.
+    ///
+    /// Every key is held in memory, so large key sets can use lots of RAM. It is an
+    /// approximation: it adds the length of the keys, the size of their positions and an
+    /// estimate of the overhead of every entry, but not the unused capacity of the
+    /// allocations. It goes through all the keys, so it takes time proportional to their
+    /// number.
+    pub fn index_memory_estimate(&self) -> usize {
+        self.index.memory_estimate()
+    }
+
+    /// Estimates how much space a compaction would reclaim, to decide whether it is
+    /// worth it or to monitor the dead space over time.
+    ///
+    /// It reads the counters used by the compaction strategy and the in-memory index, so
+    /// no log is read or rewritten. Writes that happen meanwhile may or may not be included.
+    pub fn compaction_estimate(&self) -> CompactionEstimate {
+        let (total_bytes, uncompacted, seq) = {
+            let writer = self.writer.lock().unwrap();
+            (writer.total_bytes, writer.uncompacted, writer.seq)
+        };
+        let mut projected_size = 0;
+        for (_, cmd_pos) in self.index.iter() {
+            // Appended values are folded, so their previous chunks move into the last command
+            projected_size += cmd_pos.stale_len();
+        }
+        if projected_size > 0 {
+            projected_size += LOG_FOOTER_LEN;
+        }
+        // The new active log starts with the sequence number of the last write
+        if seq > 0 {
+            projected_size += SEQ_MARK_LEN;
+        }
+        CompactionEstimate {
+            live_bytes: total_bytes.saturating_sub(uncompacted),
+            dead_bytes: uncompacted,
+            projected_size,
+        }
+    }
+
+    /// Returns the number of compactions completed since the database was opened.
+    ///
+    /// It does not lock the writer, so it can be polled while writes or compactions are
+    /// running, e.g. to detect compactions happening too often.
+    pub fn compaction_count(&self) -> u64 {
+        self.compaction_stats.count()
+    }
+
+    /// Returns when the last compaction completed, or `None` if there was no compaction
+    /// since the database was opened.
+    ///
+    /// Like [`GrausDb::compaction_count`], it does not lock the writer.
+    pub fn last_compaction(&self) -> Option<Instant> {
+        self.compaction_stats.last()
+    }
+
+    /// Returns the ids of the logs on disk, along with the active log and the one written
+    /// by the last compaction, to inspect the layout of the logs after compactions.
+    ///
+    /// The writer is locked while the logs are listed, so no log is created meanwhile.
+    pub fn log_ids(&self) -> Result<LogLayout> {
+        let writer = self.writer.lock().unwrap();
+        let log_ids = get_log_ids(&self.reader.dir)?;
+        let safe_point = self.reader.safe_point.load(Ordering::SeqCst);
+        let last_compaction = Some(safe_point).filter(|log_id| log_ids.contains(log_id));
+        Ok(LogLayout {
+            log_ids,
+            active: writer.current_log_id,
+            last_compaction,
+        })
+    }
+
+    /// Returns the percentiles of the latencies of `set` and `get` since the database was
+    /// opened, or `None` if they are not recorded, see
+    /// [`GrausDbOptions::record_latencies`].
+    ///
+    /// The latencies are recorded in atomic counters, so it doesn't block the writes. The
+    /// methods built on `set` and `get`, like scans, record their operations too.
+    pub fn latency_report(&self) -> Option<LatencyReport> {
+        self.latencies.as_ref().map(|latencies| latencies.report())
+    }
+
+    /// Returns the sequence number of the last write, or 0 if nothing was written.
+    ///
+    /// Every write, including removes, is assigned the next sequence number under the
+    /// writer lock and stores it in its command, so the sequence numbers give a total
+    /// order of the writes of the whole database. They resume after the highest one found
+    /// in the logs when the database is opened again.
+    pub fn current_seq(&self) -> u64 {
+        self.writer.lock().unwrap().seq
+    }
+
+    /// Returns the metadata of the database, or the default metadata if it was never set.
+    pub fn metadata(&self) -> DbMetadata {
+        self.metadata.lock().unwrap().clone()
+    }
+
+    /// Sets the metadata of the database, saving it in its `manifest` file.
+    ///
+    /// The manifest is separate from the logs, so the metadata can be read by tools
+    /// without replaying them. It also records the version of the on-disk format, so
+    /// versions of GrausDb that don't support it fail to open the database with
+    /// `GrausError::UnsupportedFormatVersion` instead of misreading it. Databases opened
+    /// in memory only keep it in memory.
+    pub fn set_metadata(&self, metadata: DbMetadata) -> Result<()> {
+        if self.writer.lock().unwrap().read_only {
+            return Err(GrausError::ReadOnly);
+        }
+        let mut current = self.metadata.lock().unwrap();
+        if let Some(manifest_path) = &self.manifest_path {
+            let value_logs = existing_value_dir(&self.reader.dir).is_some();
+            save_manifest(manifest_path, &metadata, value_logs)?;
+        }
+        *current = metadata;
+        Ok(())
+    }
+
+    /// Waits until the writes up to the sequence number `seq` are durable on disk
+    /// (fsynced), returning immediately if they already are.
+    ///
+    /// It lets writes skip `sync_each_write` and confirm later that a given write reached
+    /// the disk, e.g. before acknowledging a message of a queue. Like `sync_each_write`,
+    /// concurrent callers share their fsyncs. It fails with `GrausError::SeqNotWritten` if
+    /// `seq` is greater than [`GrausDb::current_seq`].
+    pub fn sync_until(&self, seq: u64) -> Result<()> {
+        let current = self.current_seq();
+        if seq > current {
+            return Err(GrausError::SeqNotWritten { seq, current });
+        }
+        self.group_commit.wait_durable_seq(seq, &self.writer)
+    }
+
+    /// Closes the handles of this `GrausDb` to the logs deleted by compactions.
+    ///
+    /// Every clone has its own handles. They are closed on the next read anyway, but a clone
+    /// used by an idle thread keeps them open until then, so the file descriptors and the
+    /// disk space are not released (and on Windows the logs can't be deleted). It is safe
+    /// to call at any time.
+    pub fn release_stale_handles(&self) {
+        self.reader.close_stale_readers();
+    }
+
+    /// Flushes the buffered writes of the active log to the file system.
+    ///
+    /// It is only needed when `flush_each_write` is disabled in `GrausDbOptions`.
+    pub fn flush(&self) -> Result<()> {
+        self.writer.lock().unwrap().flush()
+    }
+
+    /// Flushes and fsyncs the active log, so all the writes done so far are durable
+    /// before returning.
+    ///
+    /// This is the recommended way to shut down the database, as flushing on drop is only
+    /// best-effort. Other clones of this `GrausDb` remain usable after closing it.
+    ///
+    /// With [`GrausDbOptions::index_snapshot`], it also saves the index so the next open
+    /// doesn't replay the logs. It is not saved while a compaction is running.
+    pub fn close(self) -> Result<()> {
+        let mut writer = self.writer.lock().unwrap();
+        writer.truncate()?;
+        writer.sync()?;
+        if self.index_snapshot && writer.compacting.is_none() {
+            save_index_snapshot(
+                &self.reader.dir,
+                &self.index,
+                writer.uncompacted,
+                writer.seq,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Sets the value of a key only if its current version is `expected_version`.
+    ///
+    /// An `expected_version` of 0 means the key must not exist. Returns whether the value
+    /// was written. It is an optimistic alternative to `update_if` that doesn't compare
+    /// the values.
+    pub fn set_if_version(
+        &self,
+        key: Vec<u8>,
+        value: &[u8],
+        expected_version: u64,
+    ) -> Result<bool> {
+        let key = self.stored_key_owned(key);
+        self.write(|writer| {
+            let version = self.index.get(&key).map_or(0, |cmd_pos| cmd_pos.version);
+            if version != expected_version {
+                return Ok(false);
+            }
+            writer.set(key, value)?;
+            Ok(true)
+        })
+    }
+
+    /// Sets the value of a key only if it exists, e.g. to update a session without
+    /// recreating it once it was removed.
+    ///
+    /// Returns whether the value was written, so updates can be told apart from missing
+    /// keys. The check and the write happen under the writer lock.
+    pub fn set_if_exists(&self, key: Vec<u8>, value: &[u8]) -> Result<bool> {
+        let key = self.stored_key_owned(key);
+        self.write(|writer| {
+            if !self.index.contains_key(&key) {
+                return Ok(false);
+            }
+            writer.set(key, value)?;
+            Ok(true)
+        })
+    }
+
+    /// Moves the value of `from` to `to`, overwriting `to` only if `overwrite` is true.
+    ///
+    /// Returns whether the key was renamed: it is not if `from` does not exist, or if `to`
+    /// exists and `overwrite` is false. Renaming a key to itself returns whether it exists.
+    /// The value is written at `to` and `from` is removed under the writer lock, so no
+    /// other write happens in between. Concurrent readers may see both keys for a moment,
+    /// but never neither.
+    pub fn rename(&self, from: &[u8], to: Vec<u8>, overwrite: bool) -> Result<bool> {
+        let (from, to) = (&*self.stored_key(from), self.stored_key_owned(to));
+        self.write(|writer| {
+            if from == to.as_slice() {
+                return Ok(self.index.contains_key(from));
+            }
+            if !overwrite && self.index.contains_key(&to) {
+                return Ok(false);
+            }
+            let Some(value) = self.read_under_lock(writer, from)? else {
+                return Ok(false);
+            };
+            writer.set(to, &value)?;
+            writer.remove(from)?;
+            Ok(true)
+        })
+    }
+
+    /// Removes the given keys acquiring the writer lock only once.
+    ///
+    /// Returns, for each key, whether it existed and was removed. Missing keys
+    /// don't make the whole batch fail.
+    pub fn remove_many(&self, keys: Vec<Vec<u8>>) -> Result<Vec<bool>> {
+        let keys = keys
+            .into_iter()
+            .map(|key| self.stored_key_owned(key))
+            .collect();
+        self.write(|writer| writer.remove_many(keys))
+    }
+
+    /// Sets every entry acquiring the writer lock only once, e.g. to load a new database.
+    ///
+    /// The entries are written without flushing each one or compacting in between, and
+    /// the log is fsynced once at the end, whatever the options. The compaction strategy is
+    /// only checked once all the entries are written, so a single compaction runs at the
+    /// end if it decides so, e.g. because the entries overwrote each other. Writes are not
+    /// stalled by the high-water marks during the load.
+    ///
+    /// Other writes, and reads of the loaded entries, wait until the load ends. If it fails,
+    /// the entries written before the error are kept.
+    pub fn bulk_load(&self, entries: impl IntoIterator<Item = (Vec<u8>, Vec<u8>)>) -> Result<()> {
+        let entries = entries
+            .into_iter()
+            .map(|(key, value)| (self.stored_key_owned(key), value));
+        self.write(|writer| writer.bulk_load(entries))
+    }
+
+    /// Returns the entries of the keys that start with any of the given prefixes, grouped
+    /// by prefix and ordered by the index comparator within each group.
+    ///
+    /// A key that matches several overlapping prefixes only belongs to the group of the
+    /// longest one. Every prefix has a group, even if it is empty. The keys are found in a
+    /// single pass over the index, as the keys of a prefix that extends another one are
+    /// found while scanning the shorter one. Keys removed during the scan are skipped.
+    pub fn scan_prefixes(&self, prefixes: &[&[u8]]) -> Result<PrefixGroups> {
+        self.check_keys_scannable()?;
+        let mut groups: PrefixGroups = prefixes
+            .iter()
+            .map(|prefix| (prefix.to_vec(), Vec::new()))
+            .collect();
+        // Longest first, so the first prefix a key matches is the longest one
+        let mut by_len = prefixes.to_vec();
+        by_len.sort_unstable_by(|a, b| b.len().cmp(&a.len()).then(a.cmp(b)));
+        by_len.dedup();
+
+        let mut roots: Vec<&[u8]> = by_len
+            .iter()
+            .copied()
+            .filter(|prefix| {
+                !by_len
+                    .iter()
+                    .any(|other| other.len() < prefix.len() && prefix.starts_with(other))
+            })
+            .collect();
+        roots.sort_unstable();
+        for root in roots {
+            for (key, _) in self.index.prefix_iter(root) {
+                let Some(value) = self.get(&key)? else {
+                    continue;
+                };
+                let group = by_len
+                    .iter()
+                    .find(|prefix| key.starts_with(prefix))
+                    .and_then(|prefix| groups.get_mut(*prefix));
+                if let Some(group) = group {
+                    group.push((key, value));
+                }
+            }
+        }
+        Ok(groups)
+    }
+
+    /// Removes atomically all the keys that start with the given prefix and returns how
+    /// many were removed.
+    ///
+    /// The tombstones written count as stale data, so they may trigger a compaction.
+    pub fn remove_prefix(&self, prefix: &[u8]) -> Result<u64> {
+        self.check_keys_scannable()?;
+        self.write(|writer| writer.remove_prefix(prefix))
+    }
+
+    /// Removes atomically all the keys that start with the given prefix and returns them
+    /// with their values, ordered by the index comparator.
+    ///
+    /// It holds the writer lock for the whole operation, so writes under the same prefix
+    /// that happen concurrently are either drained, if they were written before the drain
+    /// started, or kept in the database for the next drain. They are never lost.
+    pub fn drain_prefix(&self, prefix: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        self.check_keys_scannable()?;
+        self.write(|writer| {
+            let keys: Vec<Vec<u8>> = self.index.prefix_iter(prefix).map(|(key, _)| key).collect();
+
+            let mut entries = Vec::with_capacity(keys.len());
+            for key in keys {
+                // The key can't be removed meanwhile, as the writer lock is held
+                let value = self
+                    .read_under_lock(writer, &key)?
+                    .ok_or(GrausError::KeyNotFound)?;
+                entries.push((key, value));
+            }
+            writer.remove_many(entries.iter().map(|(key, _)| key.clone()).collect())?;
+            Ok(entries)
+        })
     }
 
     /// Updates atomically an existing value.
     ///
     /// If predicate_key and predicate are provided, it won´t update the value if the predicate
     /// is not satisfied for predicate_key.
+    ///
+    /// `update_fn` mutates the buffer the value was read into, so small edits of large
+    /// values don't copy them before writing the new value.
     pub fn update_if<F, P>(
         &self,
         key: Vec<u8>,
@@ -136,24 +1502,367 @@ impl GrausDb {
         F: FnOnce(&mut Vec<u8>),
         P: FnOnce(&[u8]) -> bool,
     {
-        let mut writer = self.writer.lock().unwrap();
-        let current_value = self.get(&key)?;
-        let Some(current_value) = current_value else {
-            return Err(GrausError::KeyNotFound);
+        let key = self.stored_key_owned(key);
+        let predicate_key = predicate_key.map(|predicate_key| self.stored_key(predicate_key));
+        self.write(|writer| {
+            let Some(mut value) = self.read_under_lock(writer, &key)? else {
+                return Err(GrausError::KeyNotFound);
+            };
+
+            if let (Some(predicate_key), Some(predicate)) = (predicate_key, predicate) {
+                let current_predicate_key_value = self.read_under_lock(writer, &predicate_key)?;
+                let Some(current_predicate_key_value) = current_predicate_key_value else {
+                    return Err(GrausError::KeyNotFound);
+                };
+                if !predicate(&current_predicate_key_value) {
+                    return Err(GrausError::PredicateNotSatisfied);
+                }
+            }
+
+            update_fn(&mut value);
+            writer.set(key, &value)
+        })
+    }
+
+    /// Removes atomically a key only if its current value satisfies `predicate`.
+    ///
+    /// Returns whether the key was removed, which is false if it does not exist.
+    pub fn remove_if<P>(&self, key: &[u8], predicate: P) -> Result<bool>
+    where
+        P: FnOnce(&[u8]) -> bool,
+    {
+        let key = self.stored_key(key);
+        self.write(|writer| match self.read_under_lock(writer, &key)? {
+            Some(value) if predicate(&value) => {
+                writer.remove(&key)?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        })
+    }
+
+    /// Gets the entry of the given key for in-place manipulation.
+    ///
+    /// See [`Entry`] for more details.
+    pub fn entry(&self, key: Vec<u8>) -> Entry<'_> {
+        Entry::new(self, key)
+    }
+
+    /// Returns a view of the keys under the namespace `name`, so several datasets can share
+    /// the database without their keys colliding.
+    ///
+    /// See [`Namespace`] for how the keys are stored.
+    pub fn namespace(&self, name: &[u8]) -> Namespace<'_> {
+        Namespace::new(self, name)
+    }
+
+    /// Returns the value of a key, or stores and returns the value computed by `default` if
+    /// it does not exist.
+    ///
+    /// It is atomic: `default` is only called once even if several threads call it for
+    /// the same missing key, and all of them get the same value. It is a shortcut for
+    /// `entry(key).or_insert_with(default)`.
+    pub fn get_or_set<F>(&self, key: Vec<u8>, default: F) -> Result<Vec<u8>>
+    where
+        F: FnOnce() -> Vec<u8>,
+    {
+        self.upsert(key, None::<fn(&mut Vec<u8>)>, default)
+    }
+
+    /// Rewrites the current value of a key into the active log, so all its previous
+    /// commands can be reclaimed by the next compaction.
+    ///
+    /// It is a targeted alternative to a full compaction for a hot key, mainly one grown
+    /// with many `append`s, whose chunks are folded into a single value. The version and
+    /// the timestamp of the value are kept. Concurrent reads of the key see either the old
+    /// or the new copy, which hold the same value.
+    ///
+    /// Returns GrausError::KeyNotFound if the key does not exist.
+    pub fn compact_key(&self, key: &[u8]) -> Result<()> {
+        let key = self.stored_key(key).into_owned();
+        self.write(|writer| writer.rewrite(key))
+    }
+
+    /// Rebuilds the index by replaying the logs, e.g. after copying a log file into the
+    /// directory of the open database.
+    ///
+    /// It is a recovery tool: the writer is locked while the logs are replayed, which
+    /// takes as long as opening the database. The index is then updated in place, so
+    /// concurrent reads see every key either at its old or at its new position. The
+    /// secondary indexes are updated along with it. Imported logs are replayed in the
+    /// order of their ids, and the logs older than the last compaction are ignored.
+    pub fn rebuild_index(&self) -> Result<()> {
+        self.write(|writer| writer.rebuild_index())
+    }
+
+    /// Writes the live entries into a new database in `dest`, leaving this one untouched.
+    ///
+    /// The entries are copied from a snapshot, the way a compaction copies them, into a
+    /// single sealed log, along with the metadata. Writes can continue meanwhile, but they
+    /// are not copied. `dest` is created if needed and must not contain any file, so the
+    /// result can be checked before replacing this database with it.
+    pub fn compact_into(&self, dest: &Path) -> Result<()> {
+        fs::create_dir_all(dest).io_context("create directory", || dest.to_path_buf())?;
+        let mut entries = fs::read_dir(dest).io_context("read directory", || dest.to_path_buf())?;
+        if entries.next().is_some() {
+            return Err(io::Error::from(io::ErrorKind::AlreadyExists))
+                .io_context("compact into", || dest.to_path_buf());
+        }
+
+        let (value_checksums, value_log_threshold) = {
+            let writer = self.writer.lock().unwrap();
+            (writer.value_checksums, writer.value_log_threshold)
+        };
+        let snapshot = self.snapshot()?;
+        let dest_dir = LogDir {
+            root: dest.to_path_buf(),
+            logs_per_dir: None,
+            preallocate_len: None,
+            read_buffer_capacity: self.reader.dir.read_buffer_capacity,
+            write_buffer_capacity: self.reader.dir.write_buffer_capacity,
+            storage: None,
         };
+        let dest_dir = Arc::new(dest_dir);
+        snapshot.copy_into(
+            Arc::clone(&dest_dir),
+            1,
+            value_checksums,
+            value_log_threshold,
+        )?;
+        let metadata = self.metadata();
+        let value_logs = existing_value_dir(&dest_dir).is_some();
+        if metadata != DbMetadata::default() || value_logs {
+            save_manifest(&manifest_path(dest), &metadata, value_logs)?;
+        }
+        Ok(())
+    }
 
-        if let (Some(predicate_key), Some(predicate)) = (predicate_key, predicate) {
-            let current_predicate_key_value = self.get(predicate_key)?;
-            let Some(current_predicate_key_value) = current_predicate_key_value else {
-                return Err(GrausError::KeyNotFound);
+    /// Returns a snapshot of the database, whose reads reflect the current values even
+    /// while writes continue.
+    ///
+    /// The index is copied under the writer lock, so it takes time proportional to the
+    /// number of keys, and compactions don't delete the logs until the snapshot is dropped.
+    pub fn snapshot(&self) -> Result<Snapshot> {
+        let mut writer = self.writer.lock().unwrap();
+        // The values of the snapshot must be readable from the logs
+        writer.flush()?;
+        let entries = self.index.iter().collect();
+        Ok(Snapshot::new(
+            entries,
+            &self.reader,
+            Arc::clone(&self.snapshot_pins),
+            self.key_transform.clone(),
+        ))
+    }
+
+    /// Runs `f` in a transaction, whose buffered writes are applied together if it returns
+    /// `Ok`, and discarded if it returns an error, which is returned as is.
+    ///
+    /// See [`Transaction`] for the atomicity and isolation it provides. A write stalled by a
+    /// high-water mark fails the commit before any write is applied.
+    pub fn transaction<F, R>(&self, f: F) -> Result<R>
+    where
+        F: FnOnce(&mut Transaction<'_>) -> Result<R>,
+    {
+        let mut tx = Transaction::new(self);
+        let result = f(&mut tx)?;
+        let writes: BTreeMap<Vec<u8>, Option<Vec<u8>>> = tx
+            .into_writes()
+            .into_iter()
+            .map(|(key, value)| (self.stored_key_owned(key), value))
+            .collect();
+        if writes.is_empty() {
+            return Ok(result);
+        }
+        self.write(|writer| {
+            if writes.values().any(Option::is_some) {
+                writer.check_stalled()?;
+            }
+            writer.write_batch(writes)
+        })?;
+        Ok(result)
+    }
+
+    /// Returns a view of the keys as numeric counters.
+    ///
+    /// See [`Counters`] for more details.
+    pub fn counters(&self) -> Counters<'_> {
+        Counters::new(self)
+    }
+
+    // Adds `delta` to the counter stored in a key, or stores `delta` if it does not exist.
+    // Returns the new value of the counter.
+    pub(crate) fn increment(
+        &self,
+        key: Vec<u8>,
+        delta: i64,
+        overflow: CounterOverflow,
+    ) -> Result<i64> {
+        let key = self.stored_key_owned(key);
+        self.write(|writer| {
+            let counter = match self.read_under_lock(writer, &key)? {
+                Some(value) => decode_counter(&value)?,
+                None => 0,
             };
-            if !predicate(&current_predicate_key_value) {
-                return Err(GrausError::PredicateNotSatisfied);
+            let counter = add_to_counter(counter, delta, overflow);
+            writer.set(key, &counter.to_le_bytes())?;
+            Ok(counter)
+        })
+    }
+
+    // Applies `modify` to the value of an existing key, or stores `default` if it does not
+    // exist. Both paths happen under the writer lock.
+    pub(crate) fn upsert<M, D>(
+        &self,
+        key: Vec<u8>,
+        modify: Option<M>,
+        default: D,
+    ) -> Result<Vec<u8>>
+    where
+        M: FnOnce(&mut Vec<u8>),
+        D: FnOnce() -> Vec<u8>,
+    {
+        let key = self.stored_key_owned(key);
+        self.write(|writer| match self.read_under_lock(writer, &key)? {
+            Some(mut value) => {
+                if let Some(modify) = modify {
+                    modify(&mut value);
+                    writer.set(key, &value)?;
+                }
+                Ok(value)
+            }
+            None => {
+                let value = default();
+                writer.set(key, &value)?;
+                Ok(value)
             }
+        })
+    }
+
+    // Returns the key stored for `key`, see `GrausDbOptions::key_transform`.
+    fn stored_key<'a>(&self, key: &'a [u8]) -> Cow<'a, [u8]> {
+        stored_key(self.key_transform.as_ref(), key)
+    }
+
+    // Returns the key stored for `key`, reusing it if the keys are not transformed.
+    fn stored_key_owned(&self, key: Vec<u8>) -> Vec<u8> {
+        match &self.key_transform {
+            Some(transform) => transform(&key),
+            None => key,
         }
+    }
 
-        let mut current_value_mut = current_value;
-        update_fn(&mut current_value_mut);
-        writer.set(key, &current_value_mut)
+    // Fails if the keys are transformed, as the stored keys can't be turned back into the
+    // keys of the caller nor scanned in their order.
+    fn check_keys_scannable(&self) -> Result<()> {
+        if self.key_transform.is_some() {
+            return Err(GrausError::IncompatibleOptions(
+                "transformed keys can't be listed or scanned",
+            ));
+        }
+        Ok(())
+    }
+
+    // Reads the value of a key while `writer` is locked. Reading a value that was not
+    // flushed yet would try to lock the writer again, so the log is flushed first.
+    fn read_under_lock(&self, writer: &mut LogWriter, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        writer.flush()?;
+        Ok(self.get_cached(key)?.map(|(value, _)| value))
+    }
+
+    // Runs a write under the writer lock. When `sync_each_write` is enabled, it waits after
+    // releasing the lock until the write is durable, sharing the fsync with concurrent writes.
+    fn write<R>(&self, write: impl FnOnce(&mut LogWriter) -> Result<R>) -> Result<R> {
+        self.write_locked(self.writer.lock().unwrap(), write)
+    }
+
+    // Locks the writer, polling it with an increasing backoff until `timeout`, as the
+    // standard mutex has no timed lock.
+    fn lock_writer_timeout(&self, timeout: Duration) -> Result<MutexGuard<'_, LogWriter>> {
+        let deadline = Instant::now() + timeout;
+        let mut backoff = Duration::from_micros(10);
+        loop {
+            match self.writer.try_lock() {
+                Ok(writer) => return Ok(writer),
+                Err(TryLockError::Poisoned(e)) => panic!("writer lock poisoned: {}", e),
+                Err(TryLockError::WouldBlock) => {}
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(GrausError::WriteTimeout);
+            }
+            thread::sleep(backoff.min(deadline - now));
+            backoff = (backoff * 2).min(Duration::from_millis(1));
+        }
+    }
+
+    // Runs a write like `write`, with the writer already locked.
+    fn write_locked<'a, R>(
+        &'a self,
+        mut writer: MutexGuard<'a, LogWriter>,
+        write: impl FnOnce(&mut LogWriter) -> Result<R>,
+    ) -> Result<R> {
+        if writer.read_only {
+            return Err(GrausError::ReadOnly);
+        }
+        writer.compact_if_stalled();
+        if writer.is_stalled() && writer.compaction_pending() {
+            // Without an executor, a stalled write waits for the compaction instead
+            drop(writer);
+            log_writer::compact(&self.writer)?;
+            writer = self.writer.lock().unwrap();
+        }
+        let result = write(&mut writer);
+        let (written_pos, compaction_pending) = (writer.written_pos(), writer.compaction_pending());
+        // The compaction and the sync lock the writer again
+        drop(writer);
+        let result = result?;
+        if compaction_pending {
+            // Other writes can proceed while the compaction copies the logs
+            log_writer::compact(&self.writer)?;
+        }
+        if self.sync_each_write {
+            self.group_commit.wait_durable(written_pos, &self.writer)?;
+        }
+        Ok(result)
     }
+
+    /// Registers a secondary index named `name`, replacing any index with the same name.
+    ///
+    /// `extractor` derives an index key from every value, and the index maps each index key
+    /// to the primary keys whose values produced it. It is built from the existing entries
+    /// and kept up to date on every write.
+    ///
+    /// Secondary indexes only live in memory, so they must be registered again every time
+    /// the database is opened.
+    pub fn register_index(&self, name: &str, extractor: IndexExtractor) -> Result<()> {
+        let mut writer = self.writer.lock().unwrap();
+        let entries = self.index.iter().map(|(key, _)| {
+            let value = self
+                .read_under_lock(&mut writer, &key)?
+                .ok_or(GrausError::KeyNotFound)?;
+            Ok((key, value))
+        });
+        self.secondary_indexes.register(name, extractor, entries)
+    }
+
+    /// Returns the primary keys whose values are indexed under `index_key` in the
+    /// secondary index `name`, in lexicographic order.
+    ///
+    /// Returns GrausError::IndexNotFound if no index is registered with that name.
+    pub fn lookup_by_index(&self, name: &str, index_key: &[u8]) -> Result<Vec<Vec<u8>>> {
+        self.check_keys_scannable()?;
+        self.secondary_indexes.lookup(name, index_key)
+    }
+}
+
+// Index and readers of the logs loaded when the database is opened.
+struct LoadedLogs {
+    index: KeyIndex,
+    readers: BTreeMap<u64, BufReaderWithPos<LogFile>>,
+    log_ids: Vec<u64>,
+    uncompacted: u64,
+    // Sequence number of the last write.
+    seq: u64,
+    recovery_summary: RecoverySummary,
 }
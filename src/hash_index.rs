@@ -0,0 +1,113 @@
+use crate::db_command::CommandPos;
+use crate::key_index::{Index, KeyComparator};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::mem;
+use std::sync::RwLock;
+
+// Number of independent maps, so concurrent lookups rarely wait for the writer.
+const SHARDS: usize = 16;
+
+/// Index that maps every key to the position of its last command in the logs, using hash
+/// maps split in shards.
+///
+/// It is faster than the `SkipMapIndex` for point lookups, but keys are not ordered, so
+/// iterations return them in arbitrary order and prefix lookups go through all the keys.
+/// Custom comparators and the bloom filter are not supported, `GrausDb` fails to open with
+/// the options that set them.
+pub(crate) struct HashIndex {
+    shards: Vec<RwLock<HashMap<Vec<u8>, CommandPos>>>,
+}
+
+impl HashIndex {
+    pub fn new(_comparator: KeyComparator, _bloom_false_positive_rate: Option<f64>) -> HashIndex {
+        HashIndex {
+            shards: (0..SHARDS).map(|_| RwLock::default()).collect(),
+        }
+    }
+
+    fn shard(&self, key: &[u8]) -> &RwLock<HashMap<Vec<u8>, CommandPos>> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        &self.shards[hasher.finish() as usize % SHARDS]
+    }
+}
+
+impl Index for HashIndex {
+    fn get(&self, key: &[u8]) -> Option<CommandPos> {
+        self.shard(key).read().unwrap().get(key).copied()
+    }
+
+    fn contains_key(&self, key: &[u8]) -> bool {
+        self.shard(key).read().unwrap().contains_key(key)
+    }
+
+    fn insert(&self, key: Vec<u8>, cmd_pos: CommandPos) {
+        self.shard(&key).write().unwrap().insert(key, cmd_pos);
+    }
+
+    fn remove(&self, key: &[u8]) -> Option<CommandPos> {
+        self.shard(key).write().unwrap().remove(key)
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (Vec<u8>, CommandPos)> + '_ {
+        self.prefix_iter(&[])
+    }
+
+    fn prefix_iter<'a>(
+        &'a self,
+        prefix: &'a [u8],
+    ) -> impl Iterator<Item = (Vec<u8>, CommandPos)> + 'a {
+        // Every shard is copied at once, so its lock is not held while iterating
+        self.shards.iter().flat_map(move |shard| {
+            shard
+                .read()
+                .unwrap()
+                .iter()
+                .filter(|(key, _)| key.starts_with(prefix))
+                .map(|(key, cmd_pos)| (key.clone(), *cmd_pos))
+                .collect::<Vec<_>>()
+        })
+    }
+
+    fn range_iter<'a>(
+        &'a self,
+        start: &'a [u8],
+        end: &'a [u8],
+    ) -> impl Iterator<Item = (Vec<u8>, CommandPos)> + 'a {
+        self.iter()
+            .filter(move |(key, _)| (start..end).contains(&key.as_slice()))
+    }
+
+    // Keys are ordered by their bytes, as the comparator is not used
+    fn keys_after(&self, after: Option<&[u8]>, limit: usize) -> Vec<Vec<u8>> {
+        let mut keys: Vec<Vec<u8>> = self
+            .iter()
+            .map(|(key, _)| key)
+            .filter(|key| after.is_none_or(|after| key.as_slice() > after))
+            .collect();
+        keys.sort_unstable();
+        keys.truncate(limit);
+        keys
+    }
+
+    fn empty_like(&self) -> HashIndex {
+        HashIndex {
+            shards: (0..SHARDS).map(|_| RwLock::default()).collect(),
+        }
+    }
+
+    // The maps hold a slot and a control byte for every entry they have room for
+    fn memory_estimate(&self) -> usize {
+        let slot_len = mem::size_of::<(Vec<u8>, CommandPos)>() + 1;
+        self.shards
+            .iter()
+            .map(|shard| {
+                let shard = shard.read().unwrap();
+                let keys_len: usize = shard.keys().map(Vec::len).sum();
+                shard.capacity() * slot_len + keys_len
+            })
+            .sum()
+    }
+}
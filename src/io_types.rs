@@ -1,5 +1,12 @@
+use crate::storage::Storage;
 use crate::Result;
+use std::fs::File;
 use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::sync::Arc;
+
+/// Capacity of the buffers of readers and writers if it is not configured, the same as
+/// the default of `BufReader` and `BufWriter`.
+pub const DEFAULT_BUF_CAPACITY: usize = 8 * 1024;
 
 /// A buffered reader that stores the current position
 pub struct BufReaderWithPos<R: Read + Seek> {
@@ -8,18 +15,78 @@ pub struct BufReaderWithPos<R: Read + Seek> {
 }
 
 impl<R: Read + Seek> BufReaderWithPos<R> {
-    pub fn new(mut inner: R) -> Result<Self> {
-        let pos = inner.seek(SeekFrom::Current(0))?;
+    pub fn new(inner: R) -> Result<Self> {
+        Self::with_capacity(DEFAULT_BUF_CAPACITY, inner)
+    }
+
+    pub fn with_capacity(capacity: usize, mut inner: R) -> Result<Self> {
+        let pos = inner.stream_position()?;
         Ok(BufReaderWithPos {
-            reader: BufReader::new(inner),
+            reader: BufReader::with_capacity(capacity, inner),
             pos,
         })
     }
 
+    /// Skips the next `len` bytes, keeping the buffered data if they are buffered.
+    pub fn skip(&mut self, len: u64) -> io::Result<()> {
+        self.reader.seek_relative(len as i64)?;
+        self.pos += len;
+        Ok(())
+    }
+
+    /// Returns the number of bytes after the current position that are already buffered.
+    pub fn buffered_len(&self) -> usize {
+        self.reader.buffer().len()
+    }
+
+    /// Returns the number of bytes from the current position to the end of the stream,
+    /// keeping the buffered data.
+    pub fn remaining_len(&mut self) -> io::Result<u64> {
+        let buffered = self.buffered_len() as u64;
+        let inner = self.reader.get_mut();
+        let end = inner.seek(SeekFrom::End(0))?;
+        inner.seek(SeekFrom::Start(self.pos + buffered))?;
+        Ok(end.saturating_sub(self.pos))
+    }
+
     pub fn is_exhausted(&mut self) -> Result<bool> {
         let buf = self.reader.fill_buf()?;
         Ok(buf.is_empty())
     }
+
+    /// Returns the byte at the current position without consuming it, or `None` at the end
+    /// of the stream.
+    pub fn peek(&mut self) -> io::Result<Option<u8>> {
+        Ok(self.reader.fill_buf()?.first().copied())
+    }
+
+    /// Returns whether all the bytes from the current position up to `end` are zero,
+    /// without moving the position.
+    pub fn is_zeroed_until(&mut self, end: u64) -> io::Result<bool> {
+        if self
+            .reader
+            .fill_buf()?
+            .first()
+            .is_some_and(|&byte| byte != 0)
+        {
+            return Ok(false);
+        }
+        let start = self.pos;
+        let mut buf = [0; 4096];
+        let mut zeroed = true;
+        while zeroed && self.pos < end {
+            let len = (end - self.pos).min(buf.len() as u64) as usize;
+            let read = self.read(&mut buf[..len])?;
+            if read == 0 {
+                break;
+            }
+            zeroed = buf[..read].iter().all(|&byte| byte == 0);
+        }
+        // The buffered data is kept if the bytes read were already buffered
+        self.reader.seek_relative(start as i64 - self.pos as i64)?;
+        self.pos = start;
+        Ok(zeroed)
+    }
 }
 
 impl<R: Read + Seek> Read for BufReaderWithPos<R> {
@@ -44,13 +111,36 @@ pub struct BufWriterWithPos<W: Write + Seek> {
 }
 
 impl<W: Write + Seek> BufWriterWithPos<W> {
-    pub fn new(mut inner: W) -> Result<Self> {
+    pub fn new(inner: W) -> Result<Self> {
+        Self::with_capacity(DEFAULT_BUF_CAPACITY, inner)
+    }
+
+    pub fn with_capacity(capacity: usize, mut inner: W) -> Result<Self> {
         let pos = inner.seek(SeekFrom::End(0))?;
         Ok(BufWriterWithPos {
-            writer: BufWriter::new(inner),
+            writer: BufWriter::with_capacity(capacity, inner),
             pos,
         })
     }
+
+    pub fn get_ref(&self) -> &W {
+        self.writer.get_ref()
+    }
+}
+
+impl BufWriterWithPos<LogFile> {
+    /// Flushes the buffered data and waits until it is persisted on disk.
+    pub fn sync_all(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        self.writer.get_ref().sync_all()
+    }
+
+    /// Flushes the buffered data and drops the rest of the file after the current
+    /// position, like space preallocated for writes that never happened.
+    pub fn truncate(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        self.writer.get_ref().set_len(self.pos)
+    }
 }
 
 impl<W: Write + Seek> Write for BufWriterWithPos<W> {
@@ -71,3 +161,136 @@ impl<W: Write + Seek> Seek for BufWriterWithPos<W> {
         Ok(self.pos)
     }
 }
+
+/// A log file, stored on disk or in a custom storage.
+pub enum LogFile {
+    Disk(File),
+    Storage {
+        storage: Arc<dyn Storage>,
+        log_id: u64,
+        pos: u64,
+    },
+}
+
+impl LogFile {
+    pub fn len(&self) -> io::Result<u64> {
+        match self {
+            LogFile::Disk(file) => Ok(file.metadata()?.len()),
+            LogFile::Storage {
+                storage, log_id, ..
+            } => storage.log_len(*log_id),
+        }
+    }
+
+    /// Resizes the file. Files in a storage can only be truncated.
+    pub fn set_len(&self, len: u64) -> io::Result<()> {
+        match self {
+            LogFile::Disk(file) => file.set_len(len),
+            LogFile::Storage {
+                storage, log_id, ..
+            } => storage.truncate(*log_id, len),
+        }
+    }
+
+    pub fn sync_all(&self) -> io::Result<()> {
+        match self {
+            LogFile::Disk(file) => file.sync_all(),
+            LogFile::Storage {
+                storage, log_id, ..
+            } => storage.sync(*log_id),
+        }
+    }
+
+    pub fn sync_data(&self) -> io::Result<()> {
+        match self {
+            LogFile::Disk(file) => file.sync_data(),
+            LogFile::Storage {
+                storage, log_id, ..
+            } => storage.sync(*log_id),
+        }
+    }
+
+    /// Returns a new handle to the same file.
+    pub fn try_clone(&self) -> io::Result<LogFile> {
+        match self {
+            LogFile::Disk(file) => file.try_clone().map(LogFile::Disk),
+            LogFile::Storage {
+                storage,
+                log_id,
+                pos,
+            } => Ok(LogFile::Storage {
+                storage: Arc::clone(storage),
+                log_id: *log_id,
+                pos: *pos,
+            }),
+        }
+    }
+}
+
+impl Read for LogFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            LogFile::Disk(file) => file.read(buf),
+            LogFile::Storage {
+                storage,
+                log_id,
+                pos,
+            } => {
+                let len = storage.read_at(*log_id, *pos, buf)?;
+                *pos += len as u64;
+                Ok(len)
+            }
+        }
+    }
+}
+
+impl Write for LogFile {
+    // Files in a storage are only appended to, so they are always written at the end.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            LogFile::Disk(file) => file.write(buf),
+            LogFile::Storage {
+                storage,
+                log_id,
+                pos,
+            } => {
+                storage.append(*log_id, buf)?;
+                *pos = storage.log_len(*log_id)?;
+                Ok(buf.len())
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            LogFile::Disk(file) => file.flush(),
+            LogFile::Storage { .. } => Ok(()),
+        }
+    }
+}
+
+impl Seek for LogFile {
+    fn seek(&mut self, seek_from: SeekFrom) -> io::Result<u64> {
+        match self {
+            LogFile::Disk(file) => file.seek(seek_from),
+            LogFile::Storage {
+                storage,
+                log_id,
+                pos,
+            } => {
+                let new_pos = match seek_from {
+                    SeekFrom::Start(offset) => Some(offset),
+                    SeekFrom::End(offset) => storage.log_len(*log_id)?.checked_add_signed(offset),
+                    SeekFrom::Current(offset) => pos.checked_add_signed(offset),
+                };
+                *pos = new_pos.ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "invalid seek to a negative position",
+                    )
+                })?;
+                Ok(*pos)
+            }
+        }
+    }
+}
@@ -0,0 +1,423 @@
+// The skip map index is not used when the `hash-index` feature replaces the index.
+#![cfg_attr(feature = "hash-index", allow(dead_code))]
+
+use crate::bloom_filter::BloomFilter;
+use crate::db_command::CommandPos;
+use crossbeam_skiplist::map::Entry;
+use crossbeam_skiplist::SkipMap;
+use crossbeam_utils::atomic::AtomicCell;
+use std::borrow::{Borrow, Cow};
+use std::cmp::Ordering;
+use std::mem;
+use std::ops::Bound;
+use std::sync::{Arc, RwLock};
+
+// Estimated bytes of a `SkipMap` node besides its key and value: its reference count, its
+// height and two tower pointers, the average for a skip list with p = 1/2.
+const SKIP_MAP_NODE_OVERHEAD: usize = 32;
+
+/// Function used to order the keys of the in-memory index.
+///
+/// It must define a total order that is consistent with byte equality, i.e. it can only
+/// return `Ordering::Equal` for identical keys. Otherwise different keys would be merged
+/// in the index.
+pub type KeyComparator = fn(&[u8], &[u8]) -> Ordering;
+
+/// Function that turns every key given to `GrausDb` into the key it stores.
+///
+/// See [`GrausDbOptions::key_transform`](crate::GrausDbOptions::key_transform).
+pub type KeyTransform = Arc<dyn Fn(&[u8]) -> Vec<u8> + Send + Sync>;
+
+/// Returns the key stored for `key`, transformed by `transform` if there is one.
+pub(crate) fn stored_key<'a>(transform: Option<&KeyTransform>, key: &'a [u8]) -> Cow<'a, [u8]> {
+    match transform {
+        Some(transform) => Cow::Owned(transform(key)),
+        None => Cow::Borrowed(key),
+    }
+}
+
+/// Default comparator, it orders keys lexicographically by their bytes.
+pub(crate) fn lexicographic(a: &[u8], b: &[u8]) -> Ordering {
+    a.cmp(b)
+}
+
+/// Common view over owned and borrowed index keys, so the `SkipMap` can be queried
+/// with a borrowed slice without allocating a new key.
+pub(crate) trait KeyLookup {
+    fn bytes(&self) -> &[u8];
+    fn comparator(&self) -> KeyComparator;
+}
+
+impl PartialEq for dyn KeyLookup + '_ {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for dyn KeyLookup + '_ {}
+
+impl PartialOrd for dyn KeyLookup + '_ {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for dyn KeyLookup + '_ {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.comparator())(self.bytes(), other.bytes())
+    }
+}
+
+/// A key stored in the index. It carries the comparator used to order it.
+pub(crate) struct IndexKey {
+    key: Vec<u8>,
+    comparator: KeyComparator,
+}
+
+impl IndexKey {
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.key
+    }
+}
+
+impl KeyLookup for IndexKey {
+    fn bytes(&self) -> &[u8] {
+        &self.key
+    }
+
+    fn comparator(&self) -> KeyComparator {
+        self.comparator
+    }
+}
+
+impl<'a> Borrow<dyn KeyLookup + 'a> for IndexKey {
+    fn borrow(&self) -> &(dyn KeyLookup + 'a) {
+        self
+    }
+}
+
+impl PartialEq for IndexKey {
+    fn eq(&self, other: &Self) -> bool {
+        (self as &dyn KeyLookup).eq(other as &dyn KeyLookup)
+    }
+}
+
+impl Eq for IndexKey {}
+
+impl PartialOrd for IndexKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for IndexKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self as &dyn KeyLookup).cmp(other as &dyn KeyLookup)
+    }
+}
+
+/// A borrowed key used to query the index.
+struct KeyRef<'a> {
+    key: &'a [u8],
+    comparator: KeyComparator,
+}
+
+impl KeyLookup for KeyRef<'_> {
+    fn bytes(&self) -> &[u8] {
+        self.key
+    }
+
+    fn comparator(&self) -> KeyComparator {
+        self.comparator
+    }
+}
+
+/// Index that maps every key to the position of its last command in the logs.
+///
+/// It must only be mutated under the writer lock, while it can be read concurrently.
+pub(crate) trait Index: Send + Sync {
+    fn get(&self, key: &[u8]) -> Option<CommandPos>;
+
+    fn contains_key(&self, key: &[u8]) -> bool;
+
+    fn insert(&self, key: Vec<u8>, cmd_pos: CommandPos);
+
+    fn remove(&self, key: &[u8]) -> Option<CommandPos>;
+
+    /// Iterates over all the entries, ordered by the comparator if the index is ordered.
+    fn iter(&self) -> impl Iterator<Item = (Vec<u8>, CommandPos)> + '_;
+
+    /// Iterates over the entries whose key starts with `prefix`, ordered by the comparator
+    /// if the index is ordered.
+    fn prefix_iter<'a>(
+        &'a self,
+        prefix: &'a [u8],
+    ) -> impl Iterator<Item = (Vec<u8>, CommandPos)> + 'a;
+
+    /// Iterates over the entries whose key is in the range `[start, end)` of the byte
+    /// order, ordered by the comparator if the index is ordered.
+    fn range_iter<'a>(
+        &'a self,
+        start: &'a [u8],
+        end: &'a [u8],
+    ) -> impl Iterator<Item = (Vec<u8>, CommandPos)> + 'a;
+
+    /// Returns up to `limit` keys that come strictly after `after`, or from the first key
+    /// if it is `None`, in order.
+    fn keys_after(&self, after: Option<&[u8]>, limit: usize) -> Vec<Vec<u8>>;
+
+    /// Estimates the bytes of memory held by the index, going through all the keys.
+    fn memory_estimate(&self) -> usize;
+
+    /// Returns a new empty index that orders the keys the same way.
+    fn empty_like(&self) -> Self
+    where
+        Self: Sized;
+}
+
+/// Index used by `GrausDb`. It is a `SkipMapIndex` unless the `hash-index` feature is
+/// enabled, which replaces it with a `HashIndex`.
+#[cfg(not(feature = "hash-index"))]
+pub(crate) type KeyIndex = SkipMapIndex;
+#[cfg(feature = "hash-index")]
+pub(crate) type KeyIndex = crate::hash_index::HashIndex;
+
+/// Lock-free index that maps every key to the position of its last command in the logs.
+///
+/// Keys are ordered with the `KeyComparator` provided when the database is opened.
+///
+/// Positions are stored in an `AtomicCell` and updated in place, because replacing an
+/// entry of the `SkipMap` removes it before inserting the new one, and concurrent readers
+/// would see the key as missing in between. It must only be mutated under the writer lock.
+///
+/// An optional bloom filter is checked before the `SkipMap`, so lookups of absent keys
+/// can return early. It is resized when the number of keys exceeds its capacity.
+pub(crate) struct SkipMapIndex {
+    map: SkipMap<IndexKey, AtomicCell<CommandPos>>,
+    comparator: KeyComparator,
+    bloom_filter: Option<RwLock<BloomFilter>>,
+}
+
+impl SkipMapIndex {
+    pub fn new(comparator: KeyComparator, bloom_false_positive_rate: Option<f64>) -> SkipMapIndex {
+        SkipMapIndex {
+            map: SkipMap::new(),
+            comparator,
+            bloom_filter: bloom_false_positive_rate
+                .map(|rate| RwLock::new(BloomFilter::new(0, rate))),
+        }
+    }
+}
+
+impl Index for SkipMapIndex {
+    fn get(&self, key: &[u8]) -> Option<CommandPos> {
+        if !self.may_contain(key) {
+            return None;
+        }
+        let key = self.key_ref(key);
+        self.map
+            .get(&key as &dyn KeyLookup)
+            .map(|entry| entry.value().load())
+    }
+
+    fn contains_key(&self, key: &[u8]) -> bool {
+        if !self.may_contain(key) {
+            return false;
+        }
+        let key = self.key_ref(key);
+        self.map.contains_key(&key as &dyn KeyLookup)
+    }
+
+    fn insert(&self, key: Vec<u8>, cmd_pos: CommandPos) {
+        if let Some(entry) = self.map.get(&self.key_ref(&key) as &dyn KeyLookup) {
+            entry.value().store(cmd_pos);
+            return;
+        }
+        if let Some(bloom_filter) = &self.bloom_filter {
+            // Added to the filter first, so readers that find it in the map also pass the filter
+            bloom_filter.read().unwrap().insert(&key);
+        }
+        let key = IndexKey {
+            key,
+            comparator: self.comparator,
+        };
+        self.map.insert(key, AtomicCell::new(cmd_pos));
+        self.resize_bloom_filter_if_full();
+    }
+
+    fn remove(&self, key: &[u8]) -> Option<CommandPos> {
+        let key = self.key_ref(key);
+        let removed = self
+            .map
+            .remove(&key as &dyn KeyLookup)
+            .map(|entry| entry.value().load());
+        if let (Some(bloom_filter), Some(_)) = (&self.bloom_filter, removed) {
+            bloom_filter.read().unwrap().remove(key.key);
+        }
+        removed
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (Vec<u8>, CommandPos)> + '_ {
+        self.map.iter().map(entry_to_owned)
+    }
+
+    fn prefix_iter<'a>(
+        &'a self,
+        prefix: &'a [u8],
+    ) -> impl Iterator<Item = (Vec<u8>, CommandPos)> + 'a {
+        // Keys sharing a prefix are only contiguous when they are ordered by their bytes
+        let contiguous = std::ptr::fn_addr_eq(self.comparator, lexicographic as KeyComparator);
+        let has_prefix = move |entry: &Entry<'a, IndexKey, AtomicCell<CommandPos>>| {
+            entry.key().as_bytes().starts_with(prefix)
+        };
+        let entries: Box<dyn Iterator<Item = Entry<'a, IndexKey, AtomicCell<CommandPos>>>> =
+            if contiguous {
+                let start = IndexKey {
+                    key: prefix.to_vec(),
+                    comparator: self.comparator,
+                };
+                let range = self.map.range((Bound::Included(start), Bound::Unbounded));
+                Box::new(range.take_while(has_prefix))
+            } else {
+                Box::new(self.map.iter().filter(has_prefix))
+            };
+        entries.map(entry_to_owned)
+    }
+
+    fn range_iter<'a>(
+        &'a self,
+        start: &'a [u8],
+        end: &'a [u8],
+    ) -> impl Iterator<Item = (Vec<u8>, CommandPos)> + 'a {
+        // The range is only contiguous when the keys are ordered by their bytes
+        let contiguous = std::ptr::fn_addr_eq(self.comparator, lexicographic as KeyComparator);
+        let in_range = move |entry: &Entry<'a, IndexKey, AtomicCell<CommandPos>>| {
+            (start..end).contains(&entry.key().as_bytes())
+        };
+        let entries: Box<dyn Iterator<Item = Entry<'a, IndexKey, AtomicCell<CommandPos>>>> =
+            if contiguous {
+                let start = IndexKey {
+                    key: start.to_vec(),
+                    comparator: self.comparator,
+                };
+                let end = IndexKey {
+                    key: end.to_vec(),
+                    comparator: self.comparator,
+                };
+                Box::new(self.map.range(start..end))
+            } else {
+                Box::new(self.map.iter().filter(in_range))
+            };
+        entries.map(entry_to_owned)
+    }
+
+    fn keys_after(&self, after: Option<&[u8]>, limit: usize) -> Vec<Vec<u8>> {
+        let start = match after {
+            Some(after) => Bound::Excluded(IndexKey {
+                key: after.to_vec(),
+                comparator: self.comparator,
+            }),
+            None => Bound::Unbounded,
+        };
+        self.map
+            .range((start, Bound::Unbounded))
+            .take(limit)
+            .map(|entry| entry.key().as_bytes().to_vec())
+            .collect()
+    }
+
+    // The bloom filter only speeds up lookups, so it is left out
+    fn empty_like(&self) -> SkipMapIndex {
+        SkipMapIndex::new(self.comparator, None)
+    }
+
+    fn memory_estimate(&self) -> usize {
+        let entry_len = mem::size_of::<IndexKey>()
+            + mem::size_of::<AtomicCell<CommandPos>>()
+            + SKIP_MAP_NODE_OVERHEAD;
+        let keys_len: usize = self
+            .map
+            .iter()
+            .map(|entry| entry.key().as_bytes().len())
+            .sum();
+        let bloom_filter_len = self
+            .bloom_filter
+            .as_ref()
+            .map_or(0, |bloom_filter| bloom_filter.read().unwrap().memory_len());
+        self.map.len() * entry_len + keys_len + bloom_filter_len
+    }
+}
+
+fn entry_to_owned(entry: Entry<'_, IndexKey, AtomicCell<CommandPos>>) -> (Vec<u8>, CommandPos) {
+    (entry.key().as_bytes().to_vec(), entry.value().load())
+}
+
+impl SkipMapIndex {
+    fn may_contain(&self, key: &[u8]) -> bool {
+        match &self.bloom_filter {
+            Some(bloom_filter) => bloom_filter.read().unwrap().may_contain(key),
+            None => true,
+        }
+    }
+
+    // Rebuilds the bloom filter with twice the current number of keys when it is full,
+    // so its false positive rate does not degrade.
+    fn resize_bloom_filter_if_full(&self) {
+        let Some(bloom_filter) = &self.bloom_filter else {
+            return;
+        };
+        let (capacity, rate) = {
+            let bloom_filter = bloom_filter.read().unwrap();
+            (bloom_filter.capacity(), bloom_filter.false_positive_rate())
+        };
+        if self.map.len() <= capacity {
+            return;
+        }
+        let mut bloom_filter = bloom_filter.write().unwrap();
+        let resized = BloomFilter::new(self.map.len() * 2, rate);
+        for entry in self.map.iter() {
+            resized.insert(entry.key().as_bytes());
+        }
+        *bloom_filter = resized;
+    }
+
+    fn key_ref<'a>(&self, key: &'a [u8]) -> KeyRef<'a> {
+        KeyRef {
+            key,
+            comparator: self.comparator,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reverse(a: &[u8], b: &[u8]) -> Ordering {
+        b.cmp(a)
+    }
+
+    #[test]
+    fn test_index_is_ordered_by_comparator() {
+        let cmd_pos = CommandPos {
+            log_id: 1,
+            pos: 0,
+            len: 0,
+            value_len: 0,
+            appended_len: 0,
+            version: 1,
+            value_log_id: None,
+        };
+        let index = SkipMapIndex::new(reverse, None);
+        index.insert(b"a".to_vec(), cmd_pos);
+        index.insert(b"c".to_vec(), cmd_pos);
+        index.insert(b"b".to_vec(), cmd_pos);
+
+        let keys: Vec<Vec<u8>> = index.iter().map(|(key, _)| key).collect();
+        assert_eq!(keys, vec![b"c".to_vec(), b"b".to_vec(), b"a".to_vec()]);
+
+        assert!(index.contains_key(b"b"));
+        assert!(index.remove(b"b").is_some());
+        assert!(index.get(b"b").is_none());
+    }
+}
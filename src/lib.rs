@@ -1,10 +1,49 @@
 #![deny(missing_docs)]
 //! A performant thread safe key/value store.
 
+pub use cipher::{Nonce, ValueCipher, NONCE_LEN};
+pub use compaction::{CompactionStrategy, RatioStrategy, ThresholdStrategy, ValueTransform};
+pub use counters::{CounterOverflow, Counters};
+pub use db_command::CommandPos;
+pub use entry::Entry;
 pub use error::{GrausError, Result};
-pub use graus_db::GrausDb;
+pub use executor::{Executor, Task, ThreadPool};
+pub use graus_db::{GrausDb, PrefixGroups};
+pub use key_index::{KeyComparator, KeyTransform};
+pub use manifest::DbMetadata;
+pub use namespace::Namespace;
+pub use options::{GrausDbOptions, MirrorMode, RecoveryMode, SnapshotVerification};
+pub use secondary_index::IndexExtractor;
+pub use sharded::ShardedGrausDb;
+pub use snapshot::Snapshot;
+pub use stats::{
+    CompactionEstimate, KeyStat, LatencyPercentiles, LatencyReport, LogLayout, RecoverySummary,
+    SizeHistogram,
+};
+pub use storage::{MemoryStorage, Storage};
+pub use transaction::Transaction;
+mod bloom_filter;
+mod checksum;
+mod cipher;
+mod compaction;
+mod counters;
 mod db_command;
+mod entry;
 mod error;
+mod executor;
 mod graus_db;
+#[cfg(feature = "hash-index")]
+mod hash_index;
 mod io_types;
+mod key_index;
 mod log_storage;
+mod manifest;
+mod namespace;
+mod options;
+mod secondary_index;
+mod sharded;
+mod snapshot;
+mod stats;
+mod storage;
+mod transaction;
+mod value_cache;
@@ -1,54 +1,399 @@
-use std::io::{Read, Seek, Write};
+use std::io::{Read, Seek, SeekFrom, Write};
 
-use crate::db_command::{CommandOwned, CommandRef};
+use crate::checksum::Crc32;
+use crate::cipher::{Nonce, NONCE_LEN};
+use crate::db_command::{CommandOwned, CommandRef, ValueRef};
 use crate::io_types::{BufReaderWithPos, BufWriterWithPos};
 use crate::{GrausError, Result};
 
+// Commands are serialized as their type followed by their fields. The value of a "set"
+// command is always stored at the end of it.
+//
+// Legacy "set" command: [type][key len][key][value len][value]
 const SET_COMMAND_KEY: u8 = 0;
+// "remove" command: [type][key len][key]
 const REMOVE_COMMAND_KEY: u8 = 1;
+// "set" command with a header: [type][flags][header fields][key len][key][value len][value]
+// The flags tell which header fields are present, in the order of their flag bits.
+const SET_WITH_HEADER_COMMAND_KEY: u8 = 2;
+// "append" command, which appends a chunk to the value of the previous command of the key:
+// [type][flags][header fields][prev log id u64][prev pos u64][key len][key][chunk len][chunk]
+const APPEND_COMMAND_KEY: u8 = 4;
+// "remove" command with a header: [type][flags][header fields][key len][key]
+const REMOVE_WITH_HEADER_COMMAND_KEY: u8 = 5;
+// "set" command whose value is stored in a value log:
+// [type][flags][header fields][value log id u64][value pos u64][key len][key][value len]
+// The checksum of the header, if any, is the checksum of the value.
+const SET_REF_COMMAND_KEY: u8 = 6;
+
+// The header contains the version of the key (u64).
+const HEADER_FLAG_VERSION: u8 = 1;
+// The header contains the time of the write, in microseconds since the Unix epoch (u64).
+const HEADER_FLAG_TIMESTAMP: u8 = 2;
+// The value is encrypted, and the header contains the nonce it is sealed with (12 bytes).
+const HEADER_FLAG_NONCE: u8 = 4;
+// The header contains the checksum of the value, or of the chunk of an "append" (u32).
+const HEADER_FLAG_CHECKSUM: u8 = 8;
+// The header contains the sequence number of the write (u64).
+const HEADER_FLAG_SEQ: u8 = 16;
+const SUPPORTED_HEADER_FLAGS: u8 = HEADER_FLAG_VERSION
+    | HEADER_FLAG_TIMESTAMP
+    | HEADER_FLAG_NONCE
+    | HEADER_FLAG_CHECKSUM
+    | HEADER_FLAG_SEQ;
+
+// Fields of a command header. A version or sequence number of 0 is not stored.
+#[derive(Default)]
+struct CommandHeader {
+    version: u64,
+    timestamp: Option<u64>,
+    nonce: Option<Nonce>,
+    // Checksum of the value, or of the chunk of an "append".
+    checksum: Option<u32>,
+    seq: u64,
+}
+
+// Footer written at the end of sealed logs: [type][magic][command count u64][checksum u32]
+// The checksum covers all the bytes of the log before the footer.
+const FOOTER_KEY: u8 = 3;
+const FOOTER_MAGIC: &[u8; 4] = b"GRSF";
+pub(crate) const LOG_FOOTER_LEN: u64 = 1 + 4 + 8 + 4;
+
+/// Footer of a sealed log, a log that will not receive more commands.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) struct LogFooter {
+    pub commands: u64,
+    pub checksum: u32,
+}
+
+pub(crate) fn serialize_footer<W: Write + Seek>(
+    footer: &LogFooter,
+    writer: &mut BufWriterWithPos<W>,
+) -> Result<()> {
+    writer.write_all(&[FOOTER_KEY])?;
+    writer.write_all(FOOTER_MAGIC)?;
+    writer.write_all(&footer.commands.to_le_bytes())?;
+    writer.write_all(&footer.checksum.to_le_bytes())?;
+    Ok(())
+}
+
+/// Parses the last `LOG_FOOTER_LEN` bytes of a log. Returns `None` if they are not a footer.
+pub(crate) fn deserialize_footer(bytes: &[u8]) -> Option<LogFooter> {
+    if bytes.len() as u64 != LOG_FOOTER_LEN
+        || bytes[0] != FOOTER_KEY
+        || &bytes[1..5] != FOOTER_MAGIC
+    {
+        return None;
+    }
+    Some(LogFooter {
+        commands: u64::from_le_bytes(bytes[5..13].try_into().unwrap()),
+        checksum: u32::from_le_bytes(bytes[13..17].try_into().unwrap()),
+    })
+}
+
+// Batch of commands written together, like the writes of a transaction:
+// [type][commands len u64][checksum u32][commands]
+// The commands are only replayed if all of them were written, as told by the checksum.
+const BATCH_KEY: u8 = 7;
+pub(crate) const BATCH_HEADER_LEN: u64 = 1 + 8 + 4;
+
+/// Writes serialized `commands` as a batch.
+pub(crate) fn serialize_batch<W: Write + Seek>(
+    commands: &[u8],
+    writer: &mut BufWriterWithPos<W>,
+) -> Result<()> {
+    writer.write_all(&[BATCH_KEY])?;
+    writer.write_all(&(commands.len() as u64).to_le_bytes())?;
+    writer.write_all(&crc_of(commands).to_le_bytes())?;
+    writer.write_all(commands)?;
+    Ok(())
+}
+
+// Sequence mark written at the start of the active log created by a compaction:
+// [type][seq u64]
+// It holds the sequence number of the last write before the compaction, as the commands
+// with the highest sequence numbers may be dropped by it.
+const SEQ_MARK_KEY: u8 = 8;
+pub(crate) const SEQ_MARK_LEN: u64 = 1 + 8;
+
+/// Writes a sequence mark with `seq`.
+pub(crate) fn serialize_seq_mark<W: Write + Seek>(
+    seq: u64,
+    writer: &mut BufWriterWithPos<W>,
+) -> Result<()> {
+    writer.write_all(&[SEQ_MARK_KEY])?;
+    writer.write_all(&seq.to_le_bytes())?;
+    Ok(())
+}
+
+/// Reads the sequence mark at the current position, if there is one.
+pub(crate) fn read_seq_mark<R: Read + Seek>(
+    reader: &mut BufReaderWithPos<R>,
+) -> Result<Option<u64>> {
+    if reader.peek()? != Some(SEQ_MARK_KEY) {
+        return Ok(None);
+    }
+    reader.skip(1)?;
+    read_u64_from_reader(reader).map(Some)
+}
 
 pub(crate) fn serialize_command<W: Write + Seek>(
     command: &CommandRef<'_>,
     writer: &mut BufWriterWithPos<W>,
 ) -> Result<()> {
     match command {
-        CommandRef::Set { key, value } => {
+        CommandRef::Set {
+            key,
+            value,
+            version,
+            timestamp,
+            nonce,
+            checksum,
+            seq,
+        } => {
             let key_size = key.len() as u32;
             let value_size = value.len() as u32;
 
-            writer.write_all(&[SET_COMMAND_KEY])?;
+            writer.write_all(&[SET_WITH_HEADER_COMMAND_KEY])?;
+            let header = CommandHeader {
+                version: *version,
+                timestamp: *timestamp,
+                nonce: *nonce,
+                checksum: checksum.then(|| crc_of(value)),
+                seq: *seq,
+            };
+            write_header(writer, &header)?;
             writer.write_all(&key_size.to_le_bytes())?;
             writer.write_all(key)?;
             writer.write_all(&value_size.to_le_bytes())?;
             writer.write_all(value)?;
         }
-        CommandRef::Remove { key } => {
+        CommandRef::SetRef {
+            key,
+            value_ref,
+            version,
+            timestamp,
+            nonce,
+            checksum,
+            seq,
+        } => {
+            let key_size = key.len() as u32;
+            let value_size = value_ref.len as u32;
+
+            writer.write_all(&[SET_REF_COMMAND_KEY])?;
+            let header = CommandHeader {
+                version: *version,
+                timestamp: *timestamp,
+                nonce: *nonce,
+                checksum: *checksum,
+                seq: *seq,
+            };
+            write_header(writer, &header)?;
+            writer.write_all(&value_ref.log_id.to_le_bytes())?;
+            writer.write_all(&value_ref.pos.to_le_bytes())?;
+            writer.write_all(&key_size.to_le_bytes())?;
+            writer.write_all(key)?;
+            writer.write_all(&value_size.to_le_bytes())?;
+        }
+        CommandRef::Remove { key, seq } => {
+            let key_size = key.len() as u32;
+
+            if *seq == 0 {
+                writer.write_all(&[REMOVE_COMMAND_KEY])?;
+            } else {
+                writer.write_all(&[REMOVE_WITH_HEADER_COMMAND_KEY])?;
+                let header = CommandHeader {
+                    seq: *seq,
+                    ..CommandHeader::default()
+                };
+                write_header(writer, &header)?;
+            }
+            writer.write_all(&key_size.to_le_bytes())?;
+            writer.write_all(key)?;
+        }
+        CommandRef::Append {
+            key,
+            chunk,
+            version,
+            timestamp,
+            prev_log_id,
+            prev_pos,
+            nonce,
+            checksum,
+            seq,
+        } => {
             let key_size = key.len() as u32;
+            let chunk_size = chunk.len() as u32;
 
-            writer.write_all(&[REMOVE_COMMAND_KEY])?;
+            writer.write_all(&[APPEND_COMMAND_KEY])?;
+            let header = CommandHeader {
+                version: *version,
+                timestamp: *timestamp,
+                nonce: *nonce,
+                checksum: checksum.then(|| crc_of(chunk)),
+                seq: *seq,
+            };
+            write_header(writer, &header)?;
+            writer.write_all(&prev_log_id.to_le_bytes())?;
+            writer.write_all(&prev_pos.to_le_bytes())?;
             writer.write_all(&key_size.to_le_bytes())?;
             writer.write_all(key)?;
+            writer.write_all(&chunk_size.to_le_bytes())?;
+            writer.write_all(chunk)?;
         }
     }
-    writer.flush()?;
     Ok(())
 }
 
+// Writes the flags and fields of a command header.
+fn write_header<W: Write + Seek>(
+    writer: &mut BufWriterWithPos<W>,
+    header: &CommandHeader,
+) -> Result<()> {
+    let mut flags = 0;
+    if header.version != 0 {
+        flags |= HEADER_FLAG_VERSION;
+    }
+    if header.timestamp.is_some() {
+        flags |= HEADER_FLAG_TIMESTAMP;
+    }
+    if header.nonce.is_some() {
+        flags |= HEADER_FLAG_NONCE;
+    }
+    if header.checksum.is_some() {
+        flags |= HEADER_FLAG_CHECKSUM;
+    }
+    if header.seq != 0 {
+        flags |= HEADER_FLAG_SEQ;
+    }
+    writer.write_all(&[flags])?;
+    if header.version != 0 {
+        writer.write_all(&header.version.to_le_bytes())?;
+    }
+    if let Some(timestamp) = header.timestamp {
+        writer.write_all(&timestamp.to_le_bytes())?;
+    }
+    if let Some(nonce) = header.nonce {
+        writer.write_all(&nonce)?;
+    }
+    if let Some(checksum) = header.checksum {
+        writer.write_all(&checksum.to_le_bytes())?;
+    }
+    if header.seq != 0 {
+        writer.write_all(&header.seq.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+pub(crate) fn crc_of(bytes: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(bytes);
+    crc.finalize()
+}
+
+// Fails if a value that was read doesn't match its checksum. Skipped values are not checked.
+pub(crate) fn verify_value(value: &[u8], checksum: Option<u32>, skipped: bool) -> Result<()> {
+    match checksum {
+        Some(checksum) if !skipped && crc_of(value) != checksum => {
+            Err(GrausError::ChecksumMismatch)
+        }
+        _ => Ok(()),
+    }
+}
+
 pub(crate) fn deserialize_command<R: Read + Seek>(
     reader: &mut BufReaderWithPos<R>,
 ) -> Result<CommandOwned> {
+    deserialize_command_with(reader, false).map(|(command, _)| command)
+}
+
+// Deserializes a command and returns it along with the length of its value. If
+// `skip_value` is true, the value is skipped instead of read, so it is left empty.
+fn deserialize_command_with<R: Read + Seek>(
+    reader: &mut BufReaderWithPos<R>,
+    skip_value: bool,
+) -> Result<(CommandOwned, u64)> {
     let mut command_type = [0u8; 1];
     reader.read_exact(&mut command_type)?;
 
     match command_type[0] {
         SET_COMMAND_KEY => {
             let key = read_word_from_reader(reader)?;
-            let value = read_word_from_reader(reader)?;
-            Ok(CommandOwned::set(key, value))
+            let (value, value_len) = read_value_from_reader(reader, skip_value)?;
+            // Legacy commands have no version, it is assigned when loading the log
+            Ok((CommandOwned::set(key, value, 0, None), value_len))
+        }
+        SET_WITH_HEADER_COMMAND_KEY => {
+            let header = read_header(reader)?;
+            let key = read_word_from_reader(reader)?;
+            let (value, value_len) = read_value_from_reader(reader, skip_value)?;
+            verify_value(&value, header.checksum, skip_value)?;
+            let command = CommandOwned::Set {
+                key,
+                value,
+                version: header.version,
+                timestamp: header.timestamp,
+                nonce: header.nonce,
+                seq: header.seq,
+            };
+            Ok((command, value_len))
+        }
+        SET_REF_COMMAND_KEY => {
+            let header = read_header(reader)?;
+            let log_id = read_u64_from_reader(reader)?;
+            let pos = read_u64_from_reader(reader)?;
+            let key = read_word_from_reader(reader)?;
+            let mut len_buf = [0u8; 4];
+            reader.read_exact(&mut len_buf)?;
+            let value_ref = ValueRef {
+                log_id,
+                pos,
+                len: u32::from_le_bytes(len_buf) as u64,
+            };
+            let command = CommandOwned::SetRef {
+                key,
+                value_ref,
+                version: header.version,
+                timestamp: header.timestamp,
+                nonce: header.nonce,
+                checksum: header.checksum,
+                seq: header.seq,
+            };
+            Ok((command, value_ref.len))
         }
         REMOVE_COMMAND_KEY => {
             let key = read_word_from_reader(reader)?;
-            Ok(CommandOwned::remove(key))
+            Ok((CommandOwned::remove(key), 0))
+        }
+        REMOVE_WITH_HEADER_COMMAND_KEY => {
+            let header = read_header(reader)?;
+            let key = read_word_from_reader(reader)?;
+            Ok((
+                CommandOwned::Remove {
+                    key,
+                    seq: header.seq,
+                },
+                0,
+            ))
+        }
+        APPEND_COMMAND_KEY => {
+            let header = read_header(reader)?;
+            let prev_log_id = read_u64_from_reader(reader)?;
+            let prev_pos = read_u64_from_reader(reader)?;
+            let key = read_word_from_reader(reader)?;
+            let (chunk, chunk_len) = read_value_from_reader(reader, skip_value)?;
+            verify_value(&chunk, header.checksum, skip_value)?;
+            let command = CommandOwned::Append {
+                key,
+                chunk,
+                version: header.version,
+                timestamp: header.timestamp,
+                prev_log_id,
+                prev_pos,
+                nonce: header.nonce,
+                seq: header.seq,
+            };
+            Ok((command, chunk_len))
         }
         _ => Err(GrausError::SerializationError(String::from(
             "Invalid command found",
@@ -56,28 +401,212 @@ pub(crate) fn deserialize_command<R: Read + Seek>(
     }
 }
 
+// Reads the nonce a command is sealed with, without reading the rest of the command.
+pub(crate) fn read_nonce<R: Read + Seek>(
+    reader: &mut BufReaderWithPos<R>,
+) -> Result<Option<Nonce>> {
+    let mut command_type = [0u8; 1];
+    reader.read_exact(&mut command_type)?;
+    match command_type[0] {
+        SET_WITH_HEADER_COMMAND_KEY | SET_REF_COMMAND_KEY | APPEND_COMMAND_KEY => {
+            Ok(read_header(reader)?.nonce)
+        }
+        _ => Ok(None),
+    }
+}
+
+// Reads the flags and fields of a command header. Missing versions and sequence numbers
+// are 0.
+fn read_header<R: Read + Seek>(reader: &mut BufReaderWithPos<R>) -> Result<CommandHeader> {
+    let mut flags = [0u8; 1];
+    reader.read_exact(&mut flags)?;
+    if flags[0] & !SUPPORTED_HEADER_FLAGS != 0 {
+        return Err(GrausError::SerializationError(String::from(
+            "Unsupported command header",
+        )));
+    }
+    let mut version = 0;
+    if flags[0] & HEADER_FLAG_VERSION != 0 {
+        version = read_u64_from_reader(reader)?;
+    }
+    let mut timestamp = None;
+    if flags[0] & HEADER_FLAG_TIMESTAMP != 0 {
+        timestamp = Some(read_u64_from_reader(reader)?);
+    }
+    let mut nonce = None;
+    if flags[0] & HEADER_FLAG_NONCE != 0 {
+        let mut buf = [0u8; NONCE_LEN];
+        reader.read_exact(&mut buf)?;
+        nonce = Some(buf);
+    }
+    let mut checksum = None;
+    if flags[0] & HEADER_FLAG_CHECKSUM != 0 {
+        let mut buf = [0u8; 4];
+        reader.read_exact(&mut buf)?;
+        checksum = Some(u32::from_le_bytes(buf));
+    }
+    let mut seq = 0;
+    if flags[0] & HEADER_FLAG_SEQ != 0 {
+        seq = read_u64_from_reader(reader)?;
+    }
+    Ok(CommandHeader {
+        version,
+        timestamp,
+        nonce,
+        checksum,
+        seq,
+    })
+}
+
+fn read_u64_from_reader<R: Read + Seek>(reader: &mut BufReaderWithPos<R>) -> Result<u64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
 fn read_word_from_reader<R: Read + Seek>(reader: &mut BufReaderWithPos<R>) -> Result<Vec<u8>> {
     // Read the length of the word as a u32
     let mut len_buf = [0u8; 4];
     reader.read_exact(&mut len_buf)?;
     let word_len = u32::from_le_bytes(len_buf) as usize;
 
-    // Read the actual word data
-    let mut word_buf = Vec::with_capacity(word_len as usize);
-    word_buf.resize(word_len, 0);
+    // A corrupted length can be huge, so it is checked against the rest of the log before
+    // allocating the word
+    if word_len > reader.buffered_len() && word_len as u64 > reader.remaining_len()? {
+        return Err(GrausError::SerializationError(format!(
+            "Length {} exceeds the end of the log",
+            word_len
+        )));
+    }
+    let mut word_buf = vec![0; word_len];
     reader.read_exact(&mut word_buf)?;
 
     Ok(word_buf)
 }
 
+// Reads a value, or skips it if `skip` is true. Returns it along with its length.
+fn read_value_from_reader<R: Read + Seek>(
+    reader: &mut BufReaderWithPos<R>,
+    skip: bool,
+) -> Result<(Vec<u8>, u64)> {
+    if !skip {
+        let value = read_word_from_reader(reader)?;
+        let value_len = value.len() as u64;
+        return Ok((value, value_len));
+    }
+    let mut len_buf = [0u8; 4];
+    reader.read_exact(&mut len_buf)?;
+    let value_len = u32::from_le_bytes(len_buf) as u64;
+    // A value that exceeds the end of the log is detected by the caller
+    reader.skip(value_len)?;
+    Ok((Vec::new(), value_len))
+}
+
+/// Iterator over the commands of a log, from the current position of the reader up to `end`.
+///
+/// The commands of a batch are returned one by one. A batch cut off at the end of the log,
+/// as it was being written, ends the iteration before its first command. Sequence marks
+/// are skipped.
 pub struct CommandDeserializer<'a, R: Read + Seek> {
     reader: &'a mut BufReaderWithPos<R>,
+    end: u64,
     pub pos: usize,
+    /// Position of the last command returned, which is not the end of the previous one
+    /// if it is the first command of a batch.
+    pub start: usize,
+    skip_values: bool,
+    /// Length of the value of the last command returned.
+    pub value_len: u64,
 }
 
 impl<'a, R: Read + Seek> CommandDeserializer<'a, R> {
-    pub fn new(reader: &'a mut BufReaderWithPos<R>) -> Self {
-        Self { reader, pos: 0 }
+    pub fn new(reader: &'a mut BufReaderWithPos<R>, end: u64) -> Self {
+        let pos = reader.pos as usize;
+        Self {
+            reader,
+            end,
+            pos,
+            start: pos,
+            skip_values: false,
+            value_len: 0,
+        }
+    }
+
+    /// Skips the values of the commands instead of reading them, so they are returned
+    /// empty and only `value_len` is set. Memory is bounded by the reader buffer however
+    /// large the values are, so loading the logs always skips them.
+    pub fn skip_values(mut self) -> Self {
+        self.skip_values = true;
+        self
+    }
+
+    /// Advances past the corrupted command at `pos` to the next position where a command
+    /// can be deserialized, or to the end. Returns the number of bytes skipped.
+    ///
+    /// A position is only considered valid if the command that follows it can also be
+    /// deserialized, or if it reaches the end, so garbage is less likely to be accepted.
+    pub fn skip_corrupted(&mut self) -> Result<u64> {
+        let corrupted_pos = self.pos as u64;
+        let mut candidate = corrupted_pos + 1;
+        while candidate < self.end {
+            if self.is_valid_at(candidate)? {
+                break;
+            }
+            candidate += 1;
+        }
+        let next_pos = candidate.min(self.end);
+        self.reader.seek(SeekFrom::Start(next_pos))?;
+        self.pos = next_pos as usize;
+        Ok(next_pos - corrupted_pos)
+    }
+
+    // Reads the header of the batch at the current position and checks the checksum of its
+    // commands, leaving the reader at the first one. Returns false if the batch was cut off
+    // at the end of the log: it exceeds the end, or only zeros follow it.
+    fn enter_batch(&mut self) -> Result<bool> {
+        let commands_pos = self.reader.pos + BATCH_HEADER_LEN;
+        if commands_pos > self.end {
+            return Ok(false);
+        }
+        self.reader.skip(1)?;
+        let len = read_u64_from_reader(self.reader)?;
+        let mut checksum = [0u8; 4];
+        self.reader.read_exact(&mut checksum)?;
+        if len > self.end - commands_pos {
+            return Ok(false);
+        }
+
+        let mut crc = Crc32::new();
+        let mut buf = [0; 4096];
+        let mut remaining = len;
+        while remaining > 0 {
+            let chunk = &mut buf[..remaining.min(4096) as usize];
+            self.reader.read_exact(chunk)?;
+            crc.update(chunk);
+            remaining -= chunk.len() as u64;
+        }
+        if crc.finalize() != u32::from_le_bytes(checksum) {
+            return if self.reader.is_zeroed_until(self.end)? {
+                Ok(false)
+            } else {
+                Err(GrausError::ChecksumMismatch)
+            };
+        }
+        self.reader.seek(SeekFrom::Start(commands_pos))?;
+        Ok(true)
+    }
+
+    fn is_valid_at(&mut self, pos: u64) -> Result<bool> {
+        self.reader.seek(SeekFrom::Start(pos))?;
+        for _ in 0..2 {
+            if deserialize_command(self.reader).is_err() || self.reader.pos > self.end {
+                return Ok(false);
+            }
+            if self.reader.pos == self.end {
+                return Ok(true);
+            }
+        }
+        Ok(true)
     }
 }
 
@@ -85,14 +614,44 @@ impl<'a, R: Read + Seek> Iterator for CommandDeserializer<'a, R> {
     type Item = Result<CommandOwned>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.reader.is_exhausted().unwrap_or(true) {
+        if self.reader.pos >= self.end || self.reader.is_exhausted().unwrap_or(true) {
             return None;
         }
+        // Preallocated logs end with zeros where no command was written yet
+        match self.reader.is_zeroed_until(self.end) {
+            Ok(true) => return None,
+            Ok(false) => {}
+            Err(e) => return Some(Err(e.into())),
+        }
+        if let Ok(Some(BATCH_KEY)) = self.reader.peek() {
+            return match self.enter_batch() {
+                Ok(true) => self.next(),
+                Ok(false) => None,
+                Err(e) => Some(Err(e)),
+            };
+        }
+        if let Ok(Some(SEQ_MARK_KEY)) = self.reader.peek() {
+            return match read_seq_mark(self.reader) {
+                Ok(_) if self.reader.pos > self.end => Some(Err(GrausError::SerializationError(
+                    String::from("Sequence mark exceeds the end of the log"),
+                ))),
+                Ok(_) => {
+                    self.pos = self.reader.pos as usize;
+                    self.next()
+                }
+                Err(e) => Some(Err(e)),
+            };
+        }
 
-        match deserialize_command(self.reader) {
-            Ok(command) => {
-                let end_pos = self.reader.stream_position().unwrap() as usize;
-                self.pos = end_pos;
+        let start = self.reader.pos as usize;
+        match deserialize_command_with(self.reader, self.skip_values) {
+            Ok(_) if self.reader.pos > self.end => Some(Err(GrausError::SerializationError(
+                String::from("Command exceeds the end of the log"),
+            ))),
+            Ok((command, value_len)) => {
+                self.start = start;
+                self.pos = self.reader.pos as usize;
+                self.value_len = value_len;
                 Some(Ok(command))
             }
             Err(e) => Some(Err(e)),
@@ -103,13 +662,190 @@ impl<'a, R: Read + Seek> Iterator for CommandDeserializer<'a, R> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::db_command::CommandPos;
+    use proptest::collection::vec;
+    use proptest::prelude::*;
     use std::io::Cursor;
 
+    // Bytes of any length, mostly short but sometimes larger than the buffers
+    fn bytes() -> impl Strategy<Value = Vec<u8>> {
+        prop_oneof![
+            8 => vec(any::<u8>(), 0..64),
+            1 => vec(any::<u8>(), 16_384..100_000),
+        ]
+    }
+
+    // Commands with their fields set to arbitrary values, and whether they store a checksum
+    fn command() -> impl Strategy<Value = (CommandOwned, bool)> {
+        let command = prop_oneof![
+            (
+                bytes(),
+                bytes(),
+                any::<u64>(),
+                any::<Option<u64>>(),
+                any::<Option<Nonce>>(),
+                any::<u64>()
+            )
+                .prop_map(|(key, value, version, timestamp, nonce, seq)| {
+                    CommandOwned::Set {
+                        key,
+                        value,
+                        version,
+                        timestamp,
+                        nonce,
+                        seq,
+                    }
+                }),
+            (
+                bytes(),
+                any::<(u64, u64, u32)>(),
+                any::<u64>(),
+                any::<Option<u64>>(),
+                any::<Option<Nonce>>(),
+                any::<Option<u32>>(),
+                any::<u64>()
+            )
+                .prop_map(
+                    |(key, (log_id, pos, len), version, timestamp, nonce, checksum, seq)| {
+                        CommandOwned::SetRef {
+                            key,
+                            value_ref: ValueRef {
+                                log_id,
+                                pos,
+                                len: len as u64,
+                            },
+                            version,
+                            timestamp,
+                            nonce,
+                            checksum,
+                            seq,
+                        }
+                    }
+                ),
+            (bytes(), any::<u64>()).prop_map(|(key, seq)| CommandOwned::Remove { key, seq }),
+            (
+                bytes(),
+                bytes(),
+                any::<u64>(),
+                any::<Option<u64>>(),
+                any::<u64>(),
+                any::<u64>(),
+                any::<Option<Nonce>>(),
+                any::<u64>()
+            )
+                .prop_map(
+                    |(key, chunk, version, timestamp, prev_log_id, prev_pos, nonce, seq)| {
+                        CommandOwned::Append {
+                            key,
+                            chunk,
+                            version,
+                            timestamp,
+                            prev_log_id,
+                            prev_pos,
+                            nonce,
+                            seq,
+                        }
+                    }
+                ),
+        ];
+        (command, any::<bool>())
+    }
+
+    fn to_ref(command: &CommandOwned, checksum: bool) -> CommandRef<'_> {
+        match command {
+            CommandOwned::Set {
+                key,
+                value,
+                version,
+                timestamp,
+                nonce,
+                ..
+            } => CommandRef::set(key, value, *version, *timestamp).with_nonce(*nonce),
+            CommandOwned::SetRef {
+                key,
+                value_ref,
+                version,
+                timestamp,
+                nonce,
+                checksum,
+                ..
+            } => CommandRef::set_ref(key, *value_ref, *version, *timestamp, *checksum)
+                .with_nonce(*nonce),
+            CommandOwned::Remove { key, .. } => CommandRef::remove(key),
+            CommandOwned::Append {
+                key,
+                chunk,
+                version,
+                timestamp,
+                prev_log_id,
+                prev_pos,
+                nonce,
+                ..
+            } => CommandRef::Append {
+                key,
+                chunk,
+                version: *version,
+                timestamp: *timestamp,
+                prev_log_id: *prev_log_id,
+                prev_pos: *prev_pos,
+                nonce: *nonce,
+                checksum: false,
+                seq: 0,
+            },
+        }
+        .with_checksum(checksum)
+        .with_seq(command.seq())
+    }
+
+    proptest! {
+        #[test]
+        fn prop_serde_round_trip(commands in vec(command(), 1..8)) {
+            let mut buffer = Vec::new();
+            {
+                let mut writer = BufWriterWithPos::new(Cursor::new(&mut buffer))?;
+                for (command, checksum) in &commands {
+                    serialize_command(&to_ref(command, *checksum), &mut writer)?;
+                }
+                writer.flush()?;
+            }
+
+            let end = buffer.len() as u64;
+            let mut reader = BufReaderWithPos::new(Cursor::new(&mut buffer))?;
+            for (command, _) in &commands {
+                prop_assert_eq!(&deserialize_command(&mut reader)?, command);
+            }
+            prop_assert_eq!(reader.pos, end);
+        }
+    }
+
     #[test]
     fn test_serde_command() -> Result<()> {
         let key = b"key value".to_vec();
-        let set_command_owned = CommandOwned::set(key.clone(), b"Ricardo".to_vec());
-        let remove_command_owned = CommandOwned::remove(key.clone());
+        let set_command_owned =
+            CommandOwned::set(key.clone(), b"Ricardo".to_vec(), 3, Some(1_700_000_000));
+        let remove_command_owned = CommandOwned::Remove {
+            key: key.clone(),
+            seq: 9,
+        };
+        let prev = CommandPos {
+            log_id: 7,
+            pos: 120,
+            len: 30,
+            value_len: 7,
+            appended_len: 0,
+            version: 3,
+            value_log_id: None,
+        };
+        let append_command_owned = CommandOwned::Append {
+            key: key.clone(),
+            chunk: b" Pallas".to_vec(),
+            version: 4,
+            timestamp: None,
+            prev_log_id: 7,
+            prev_pos: 120,
+            nonce: Some([9; NONCE_LEN]),
+            seq: 0,
+        };
 
         let mut buffer = Vec::new();
 
@@ -119,19 +855,185 @@ mod tests {
                 &CommandRef::Set {
                     key: &key,
                     value: b"Ricardo",
+                    version: 3,
+                    timestamp: Some(1_700_000_000),
+                    nonce: None,
+                    checksum: true,
+                    seq: 0,
                 },
                 &mut writer,
             )?;
-            serialize_command(&CommandRef::Remove { key: &key }, &mut writer)?;
+            serialize_command(&CommandRef::remove(&key).with_seq(9), &mut writer)?;
+            serialize_command(
+                &CommandRef::append(&key, b" Pallas", 4, None, prev)
+                    .with_nonce(Some([9; NONCE_LEN])),
+                &mut writer,
+            )?;
+            writer.flush()?;
         }
 
         let mut reader = BufReaderWithPos::new(Cursor::new(&mut buffer))?;
         let deserialized_set_command = deserialize_command(&mut reader)?;
         let deserialized_remove_command = deserialize_command(&mut reader)?;
+        let deserialized_append_command = deserialize_command(&mut reader)?;
 
         assert_eq!(set_command_owned, deserialized_set_command);
         assert_eq!(remove_command_owned, deserialized_remove_command);
+        assert_eq!(append_command_owned, deserialized_append_command);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_deserialize_legacy_set_command() -> Result<()> {
+        let mut buffer = vec![SET_COMMAND_KEY];
+        buffer.extend_from_slice(&3u32.to_le_bytes());
+        buffer.extend_from_slice(b"key");
+        buffer.extend_from_slice(&5u32.to_le_bytes());
+        buffer.extend_from_slice(b"value");
+
+        let mut reader = BufReaderWithPos::new(Cursor::new(&mut buffer))?;
+        assert_eq!(
+            deserialize_command(&mut reader)?,
+            CommandOwned::set(b"key".to_vec(), b"value".to_vec(), 0, None)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_deserializer_skips_values() -> Result<()> {
+        let mut buffer = Vec::new();
+        {
+            let mut writer = BufWriterWithPos::new(Cursor::new(&mut buffer))?;
+            serialize_command(&CommandRef::set(b"a", &[7; 20_000], 1, None), &mut writer)?;
+            serialize_command(&CommandRef::set(b"b", b"value", 1, None), &mut writer)?;
+            writer.flush()?;
+        }
+
+        let end = buffer.len() as u64;
+        let mut reader = BufReaderWithPos::new(Cursor::new(&mut buffer))?;
+        let mut deserializer = CommandDeserializer::new(&mut reader, end).skip_values();
+        assert_eq!(
+            deserializer.next().transpose()?,
+            Some(CommandOwned::set(b"a".to_vec(), Vec::new(), 1, None))
+        );
+        assert_eq!(deserializer.value_len, 20_000);
+        assert_eq!(
+            deserializer.next().transpose()?,
+            Some(CommandOwned::set(b"b".to_vec(), Vec::new(), 1, None))
+        );
+        assert_eq!(deserializer.value_len, 5);
+        assert!(deserializer.next().is_none());
+
+        // A truncated value is still detected
+        buffer.truncate(end as usize - 1);
+        let mut reader = BufReaderWithPos::new(Cursor::new(&mut buffer))?;
+        let mut deserializer = CommandDeserializer::new(&mut reader, end - 1).skip_values();
+        assert!(deserializer.next().transpose().is_ok());
+        assert!(deserializer.next().transpose().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_deserializer_reads_batch() -> Result<()> {
+        let mut commands = Vec::new();
+        {
+            let mut writer = BufWriterWithPos::new(Cursor::new(&mut commands))?;
+            serialize_command(&CommandRef::set(b"b", b"value", 1, None), &mut writer)?;
+            serialize_command(&CommandRef::remove(b"a"), &mut writer)?;
+            writer.flush()?;
+        }
+        let mut buffer = Vec::new();
+        {
+            let mut writer = BufWriterWithPos::new(Cursor::new(&mut buffer))?;
+            serialize_command(&CommandRef::set(b"a", b"value", 1, None), &mut writer)?;
+            serialize_batch(&commands, &mut writer)?;
+            writer.flush()?;
+        }
+        let batch_pos = buffer.len() - commands.len() - BATCH_HEADER_LEN as usize;
+
+        let end = buffer.len() as u64;
+        let mut reader = BufReaderWithPos::new(Cursor::new(&mut buffer))?;
+        let mut deserializer = CommandDeserializer::new(&mut reader, end);
+        assert!(deserializer.next().transpose()?.is_some());
+        assert_eq!(deserializer.pos, batch_pos);
+        assert_eq!(
+            deserializer.next().transpose()?,
+            Some(CommandOwned::set(b"b".to_vec(), b"value".to_vec(), 1, None))
+        );
+        assert_eq!(deserializer.start, batch_pos + BATCH_HEADER_LEN as usize);
+        assert_eq!(
+            deserializer.next().transpose()?,
+            Some(CommandOwned::remove(b"a".to_vec()))
+        );
+        assert!(deserializer.next().is_none());
+
+        // A batch cut off is skipped whole
+        buffer.truncate(end as usize - 1);
+        let mut reader = BufReaderWithPos::new(Cursor::new(&mut buffer))?;
+        let mut deserializer = CommandDeserializer::new(&mut reader, end - 1);
+        assert!(deserializer.next().transpose()?.is_some());
+        assert!(deserializer.next().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_deserializer_skips_seq_mark() -> Result<()> {
+        let mut buffer = Vec::new();
+        {
+            let mut writer = BufWriterWithPos::new(Cursor::new(&mut buffer))?;
+            serialize_seq_mark(42, &mut writer)?;
+            serialize_command(&CommandRef::remove(b"a"), &mut writer)?;
+            writer.flush()?;
+        }
+
+        let end = buffer.len() as u64;
+        let mut reader = BufReaderWithPos::new(Cursor::new(&mut buffer))?;
+        let mut deserializer = CommandDeserializer::new(&mut reader, end);
+        assert_eq!(
+            deserializer.next().transpose()?,
+            Some(CommandOwned::remove(b"a".to_vec()))
+        );
+        assert_eq!(deserializer.start, SEQ_MARK_LEN as usize);
+        assert!(deserializer.next().is_none());
+
+        reader.seek(SeekFrom::Start(0))?;
+        assert_eq!(read_seq_mark(&mut reader)?, Some(42));
+        assert_eq!(read_seq_mark(&mut reader)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_deserialize_rejects_length_beyond_end() -> Result<()> {
+        let mut buffer = vec![REMOVE_COMMAND_KEY];
+        buffer.extend_from_slice(&u32::MAX.to_le_bytes());
+        buffer.extend_from_slice(b"key");
+
+        let mut reader = BufReaderWithPos::new(Cursor::new(&mut buffer))?;
+        assert!(matches!(
+            deserialize_command(&mut reader),
+            Err(GrausError::SerializationError(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_serde_footer() -> Result<()> {
+        let footer = LogFooter {
+            commands: 42,
+            checksum: 0xCAFE,
+        };
+        let mut buffer = Vec::new();
+        {
+            let mut writer = BufWriterWithPos::new(Cursor::new(&mut buffer))?;
+            serialize_footer(&footer, &mut writer)?;
+            writer.flush()?;
+        }
+        assert_eq!(buffer.len() as u64, LOG_FOOTER_LEN);
+        assert_eq!(deserialize_footer(&buffer), Some(footer));
 
+        buffer[1] = b'X';
+        assert_eq!(deserialize_footer(&buffer), None);
         Ok(())
     }
 }
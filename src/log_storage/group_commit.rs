@@ -0,0 +1,198 @@
+use super::log_reader::FlushedPos;
+use super::log_writer::LogWriter;
+use crate::io_types::{BufWriterWithPos, LogFile};
+use crate::Result;
+use std::io;
+use std::sync::{Condvar, Mutex};
+
+/// Coordinates the fsyncs of concurrent writers, so a single fsync makes the writes of all
+/// of them durable.
+///
+/// Writers append their commands under the writer lock, release it and wait until the logs
+/// are synced up to their position. The first waiter becomes the leader: it flushes the
+/// active log, value log and mirror, syncs them without holding the writer lock, and
+/// wakes up every writer whose commands were covered. Writes that arrive meanwhile are
+/// synced by the next leader.
+#[derive(Default)]
+pub struct GroupCommit {
+    state: Mutex<SyncState>,
+    synced: Condvar,
+}
+
+#[derive(Default)]
+struct SyncState {
+    // Position up to which the logs are durable.
+    synced_pos: Option<FlushedPos>,
+    // Sequence number of the last durable write.
+    synced_seq: u64,
+    // Whether a leader is syncing the active log.
+    syncing: bool,
+}
+
+impl GroupCommit {
+    /// Waits until the logs are durable up to `pos`, syncing them if no other writer is.
+    pub fn wait_durable(&self, pos: FlushedPos, writer: &Mutex<LogWriter>) -> Result<()> {
+        self.wait_until(writer, |state| {
+            state.synced_pos.is_some_and(|synced_pos| synced_pos >= pos)
+        })
+    }
+
+    /// Waits until the writes up to the sequence number `seq` are durable, syncing the logs
+    /// if no other writer is. The write with `seq` must have been written already.
+    pub fn wait_durable_seq(&self, seq: u64, writer: &Mutex<LogWriter>) -> Result<()> {
+        self.wait_until(writer, |state| state.synced_seq >= seq)
+    }
+
+    // Syncs the logs as the leader, or waits for the leader, until `durable` is true.
+    fn wait_until(
+        &self,
+        writer: &Mutex<LogWriter>,
+        durable: impl Fn(&SyncState) -> bool,
+    ) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if durable(&state) {
+                return Ok(());
+            }
+            if state.syncing {
+                state = self.synced.wait(state).unwrap();
+                continue;
+            }
+
+            state.syncing = true;
+            drop(state);
+            let result = sync_active_logs(writer);
+            state = self.state.lock().unwrap();
+            state.syncing = false;
+            if let Ok((synced_pos, synced_seq)) = result {
+                state.synced_pos = state.synced_pos.max(Some(synced_pos));
+                state.synced_seq = state.synced_seq.max(synced_seq);
+            }
+            self.synced.notify_all();
+            // Waiters retry as leaders if the sync failed
+            result?;
+        }
+    }
+
+    /// Marks the logs as durable up to `pos` and the write with `seq`, after they were
+    /// synced by the writer.
+    pub fn mark_durable(&self, pos: FlushedPos, seq: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.synced_pos = state.synced_pos.max(Some(pos));
+        state.synced_seq = state.synced_seq.max(seq);
+        self.synced.notify_all();
+    }
+}
+
+// New handles to the active logs, to fsync them without holding the writer lock.
+struct ActiveLogs {
+    value_log: Option<LogFile>,
+    log: Option<LogFile>,
+    mirror: Option<LogFile>,
+}
+
+impl ActiveLogs {
+    // Flushes the active logs and opens new handles to them. A log is `None` if it was not
+    // created yet, so nothing was written into it.
+    fn flush(writer: &mut LogWriter) -> Result<ActiveLogs> {
+        writer.flush()?;
+        Ok(ActiveLogs {
+            value_log: try_clone(writer.value_writer.as_ref())?,
+            log: try_clone(writer.writer.as_ref())?,
+            mirror: try_clone(writer.mirror.as_ref().map(|mirror| &mirror.writer))?,
+        })
+    }
+}
+
+fn try_clone(writer: Option<&BufWriterWithPos<LogFile>>) -> io::Result<Option<LogFile>> {
+    writer
+        .map(|writer| writer.get_ref().try_clone())
+        .transpose()
+}
+
+// Flushes the active logs under the writer lock and syncs them after releasing the lock,
+// so other writers can keep appending. Returns the position and the sequence number of
+// the last write that are durable.
+fn sync_active_logs(writer: &Mutex<LogWriter>) -> Result<(FlushedPos, u64)> {
+    let (active_logs, pos, seq) = {
+        let mut writer = writer.lock().unwrap();
+        let active_logs = ActiveLogs::flush(&mut writer)?;
+        (active_logs, writer.written_pos(), writer.seq)
+    };
+    // The values must be durable before the commands pointing to them
+    if let Some(value_log) = active_logs.value_log {
+        value_log.sync_data()?;
+    }
+    if let Some(log) = active_logs.log {
+        log.sync_data()?;
+    }
+    if let Some(mirror) = active_logs.mirror {
+        if let Err(e) = mirror.sync_data() {
+            writer.lock().unwrap().mirror_failed(e.into())?;
+        }
+    }
+    Ok((pos, seq))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GrausDb, GrausDbOptions, MirrorMode};
+    use std::fs;
+    use tempfile::TempDir;
+
+    // The value log is synced along with the active log, as the synced commands point to
+    // its values
+    #[cfg(unix)]
+    #[test]
+    fn test_value_log_is_synced() -> Result<()> {
+        use std::os::unix::fs::MetadataExt;
+
+        let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+        let options = GrausDbOptions::default()
+            .value_log_threshold(16)
+            .sync_each_write(true);
+        let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+        store.set(b"key".to_vec(), &[1; 64])?;
+
+        let active_logs = ActiveLogs::flush(&mut store.writer.lock().unwrap())?;
+        let Some(LogFile::Disk(value_log)) = active_logs.value_log else {
+            panic!("the value log is not synced");
+        };
+        let value_log_id = store.writer.lock().unwrap().current_log_id;
+        let value_log_path = temp_dir
+            .path()
+            .join("values")
+            .join(format!("{}.log", value_log_id));
+        assert_eq!(
+            value_log.metadata()?.ino(),
+            fs::metadata(value_log_path)?.ino()
+        );
+        Ok(())
+    }
+
+    // The mirror is synced along with the active log, so durable writes are durable in
+    // the mirror too
+    #[cfg(unix)]
+    #[test]
+    fn test_mirror_is_synced() -> Result<()> {
+        use std::os::unix::fs::MetadataExt;
+
+        let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+        let mirror_dir = TempDir::new().expect("unable to create temporary working directory");
+        let options = GrausDbOptions::default()
+            .mirror(mirror_dir.path(), MirrorMode::Required)
+            .sync_each_write(true);
+        let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+        store.set(b"key".to_vec(), b"value")?;
+
+        let active_logs = ActiveLogs::flush(&mut store.writer.lock().unwrap())?;
+        let Some(LogFile::Disk(mirror)) = active_logs.mirror else {
+            panic!("the mirror is not synced");
+        };
+        let log_id = store.writer.lock().unwrap().current_log_id;
+        let mirror_path = mirror_dir.path().join(format!("{}.log", log_id));
+        assert_eq!(mirror.metadata()?.ino(), fs::metadata(mirror_path)?.ino());
+        Ok(())
+    }
+}
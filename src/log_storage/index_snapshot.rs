@@ -0,0 +1,254 @@
+use super::db_command_serde::CommandDeserializer;
+use super::log_helpers::{get_log_ids, LogDir};
+use crate::checksum::Crc32;
+use crate::db_command::{CommandOwned, CommandPos};
+use crate::error::IoContext;
+use crate::io_types::{BufReaderWithPos, LogFile};
+use crate::key_index::{Index, KeyIndex};
+use crate::{GrausError, Result, SnapshotVerification};
+use log::error;
+use std::collections::hash_map::{Entry, HashMap};
+use std::fs::{self, File};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+
+// Snapshot of the index saved when the database is closed:
+// [magic][uncompacted u64][seq u64][log count u64]([log id u64][log len u64])*
+// [entry count u64]([key len u64][key][log id][pos][len][value len][appended len][version]
+// [value log id])*[checksum u32]
+// The logs are the ones the snapshot was taken from. The value log id is 0 if the value is
+// stored in the command. The checksum covers all the bytes before it. Snapshots saved
+// before the sequence numbers or the value logs were added have another magic, so they
+// are discarded.
+const SNAPSHOT_MAGIC: &[u8; 4] = b"GRS3";
+const SNAPSHOT_FILE: &str = "index.snapshot";
+
+/// Index loaded from a snapshot, instead of replaying the logs.
+pub struct IndexSnapshot {
+    /// Number of bytes that can be saved after a compaction.
+    pub uncompacted: u64,
+    /// Sequence number of the last write.
+    pub seq: u64,
+    pub entries: Vec<(Vec<u8>, CommandPos)>,
+}
+
+// Returns the path of the snapshot of the index
+fn snapshot_path(dir: &LogDir) -> PathBuf {
+    dir.root.join(SNAPSHOT_FILE)
+}
+
+/// Saves the index, along with the length of every log, so the snapshot is discarded if
+/// the logs change afterwards.
+///
+/// It is written to a temporary file first, so a crash never leaves a partial snapshot.
+pub fn save_index_snapshot(
+    dir: &LogDir,
+    index: &KeyIndex,
+    uncompacted: u64,
+    seq: u64,
+) -> Result<()> {
+    let mut logs = Vec::new();
+    for log_id in get_log_ids(dir)? {
+        let len = dir
+            .log_len(log_id)
+            .io_context("read metadata of", || dir.log_path(log_id))?;
+        // Empty logs are removed when the database is opened
+        if len > 0 {
+            logs.push((log_id, len));
+        }
+    }
+
+    let mut bytes = SNAPSHOT_MAGIC.to_vec();
+    bytes.extend_from_slice(&uncompacted.to_le_bytes());
+    bytes.extend_from_slice(&seq.to_le_bytes());
+    bytes.extend_from_slice(&(logs.len() as u64).to_le_bytes());
+    for (log_id, len) in logs {
+        bytes.extend_from_slice(&log_id.to_le_bytes());
+        bytes.extend_from_slice(&len.to_le_bytes());
+    }
+    let entries: Vec<(Vec<u8>, CommandPos)> = index.iter().collect();
+    bytes.extend_from_slice(&(entries.len() as u64).to_le_bytes());
+    for (key, cmd_pos) in entries {
+        bytes.extend_from_slice(&(key.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&key);
+        for field in [
+            cmd_pos.log_id,
+            cmd_pos.pos,
+            cmd_pos.len,
+            cmd_pos.value_len,
+            cmd_pos.appended_len,
+            cmd_pos.version,
+            cmd_pos.value_log_id.unwrap_or(0),
+        ] {
+            bytes.extend_from_slice(&field.to_le_bytes());
+        }
+    }
+    let mut checksum = Crc32::new();
+    checksum.update(&bytes);
+    bytes.extend_from_slice(&checksum.finalize().to_le_bytes());
+
+    let path = snapshot_path(dir);
+    let tmp_path = path.with_extension("snapshot.tmp");
+    let mut file = File::create(&tmp_path).io_context("create", || tmp_path.clone())?;
+    file.write_all(&bytes)
+        .io_context("write", || tmp_path.clone())?;
+    file.sync_all().io_context("sync", || tmp_path.clone())?;
+    fs::rename(&tmp_path, &path).io_context("move", || tmp_path)?;
+    Ok(())
+}
+
+/// Loads the snapshot of the index and removes it, so it is only used once.
+///
+/// Returns `None` if there is no snapshot, if it is corrupted, if the logs `log_ids`
+/// are not exactly the ones it was taken from, or if the entries checked according to
+/// `verification` don't match the logs.
+pub fn load_index_snapshot(
+    dir: &LogDir,
+    log_ids: &[u64],
+    verification: SnapshotVerification,
+) -> Result<Option<IndexSnapshot>> {
+    let path = snapshot_path(dir);
+    let bytes = match fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).io_context("read", || path),
+    };
+    fs::remove_file(&path).io_context("remove", || path.clone())?;
+
+    let snapshot = parse_snapshot(&bytes);
+    let Some((logs, snapshot)) = snapshot else {
+        error!("Index snapshot {:?} is corrupted, replaying the logs", path);
+        return Ok(None);
+    };
+    if logs
+        .iter()
+        .map(|&(log_id, _)| log_id)
+        .ne(log_ids.iter().copied())
+    {
+        return Ok(None);
+    }
+    for (log_id, len) in logs {
+        let current_len = dir
+            .log_len(log_id)
+            .io_context("read metadata of", || dir.log_path(log_id))?;
+        if current_len != len {
+            return Ok(None);
+        }
+    }
+
+    let sample_len = match verification {
+        SnapshotVerification::Off => 0,
+        SnapshotVerification::Sample(sample_len) => sample_len.min(snapshot.entries.len()),
+        SnapshotVerification::Full => snapshot.entries.len(),
+    };
+    let mut readers = HashMap::new();
+    for i in 0..sample_len {
+        // The checked entries are spread evenly across the index
+        let (key, cmd_pos) = &snapshot.entries[i * snapshot.entries.len() / sample_len];
+        if !entry_matches(dir, &mut readers, key, *cmd_pos) {
+            error!(
+                "Index snapshot {:?} does not match the logs, replaying them",
+                path
+            );
+            return Ok(None);
+        }
+    }
+    Ok(Some(snapshot))
+}
+
+// Returns whether the command at `cmd_pos` is a command of `key` with the same length.
+fn entry_matches(
+    dir: &LogDir,
+    readers: &mut HashMap<u64, BufReaderWithPos<LogFile>>,
+    key: &[u8],
+    cmd_pos: CommandPos,
+) -> bool {
+    let reader = match readers.entry(cmd_pos.log_id) {
+        Entry::Occupied(entry) => entry.into_mut(),
+        Entry::Vacant(entry) => {
+            let file = dir.open_log(cmd_pos.log_id);
+            let Ok(reader) = file
+                .map_err(GrausError::from)
+                .and_then(BufReaderWithPos::new)
+            else {
+                return false;
+            };
+            entry.insert(reader)
+        }
+    };
+    if reader.seek(SeekFrom::Start(cmd_pos.pos)).is_err() {
+        return false;
+    }
+    let end = cmd_pos.pos + cmd_pos.len;
+    let mut commands = CommandDeserializer::new(reader, end).skip_values();
+    let command_key = match commands.next() {
+        Some(Ok(
+            CommandOwned::Set { key, .. }
+            | CommandOwned::SetRef { key, .. }
+            | CommandOwned::Append { key, .. },
+        )) => key,
+        _ => return false,
+    };
+    command_key == key && commands.pos as u64 == end && commands.value_len == cmd_pos.value_len
+}
+
+// Parses a snapshot, returning the logs it was taken from. Returns `None` if it is corrupted.
+fn parse_snapshot(bytes: &[u8]) -> Option<(Vec<(u64, u64)>, IndexSnapshot)> {
+    let (content, checksum) = bytes.split_at_checked(bytes.len().checked_sub(4)?)?;
+    let mut crc = Crc32::new();
+    crc.update(content);
+    if crc.finalize() != u32::from_le_bytes(checksum.try_into().ok()?) {
+        return None;
+    }
+
+    let mut reader = SliceReader(content);
+    if reader.read(SNAPSHOT_MAGIC.len())? != SNAPSHOT_MAGIC {
+        return None;
+    }
+    let uncompacted = reader.read_u64()?;
+    let seq = reader.read_u64()?;
+    let log_count = reader.read_u64()?;
+    let logs = (0..log_count)
+        .map(|_| Some((reader.read_u64()?, reader.read_u64()?)))
+        .collect::<Option<Vec<_>>>()?;
+    let entry_count = reader.read_u64()?;
+    let entries = (0..entry_count)
+        .map(|_| {
+            let key_len = reader.read_u64()?;
+            let key = reader.read(usize::try_from(key_len).ok()?)?.to_vec();
+            let cmd_pos = CommandPos {
+                log_id: reader.read_u64()?,
+                pos: reader.read_u64()?,
+                len: reader.read_u64()?,
+                value_len: reader.read_u64()?,
+                appended_len: reader.read_u64()?,
+                version: reader.read_u64()?,
+                value_log_id: Some(reader.read_u64()?).filter(|&log_id| log_id != 0),
+            };
+            Some((key, cmd_pos))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some((
+        logs,
+        IndexSnapshot {
+            uncompacted,
+            seq,
+            entries,
+        },
+    ))
+}
+
+// Reads the fields of a snapshot, or of other files saved the same way, in order.
+pub(crate) struct SliceReader<'a>(pub &'a [u8]);
+
+impl<'a> SliceReader<'a> {
+    pub fn read(&mut self, len: usize) -> Option<&'a [u8]> {
+        let (bytes, rest) = self.0.split_at_checked(len)?;
+        self.0 = rest;
+        Some(bytes)
+    }
+
+    pub fn read_u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.read(8)?.try_into().ok()?))
+    }
+}
@@ -1,96 +1,578 @@
-use crate::Result;
+use crate::checksum::checksum_of;
+use crate::error::IoContext;
+use crate::key_index::{Index, KeyIndex};
 use crate::{
     db_command::{CommandOwned, CommandPos},
-    io_types::{BufReaderWithPos, BufWriterWithPos},
+    io_types::{BufReaderWithPos, BufWriterWithPos, LogFile},
+    storage::Storage,
 };
-use crossbeam_skiplist::SkipMap;
-use std::io::Seek;
+use crate::{GrausError, RecoveryMode, RecoverySummary, Result};
+use log::error;
+use std::io::{Read, Seek, Write};
 use std::{
     ffi::OsStr,
     fs::{self, File, OpenOptions},
-    io::SeekFrom,
+    io::{self, SeekFrom},
     path::{Path, PathBuf},
+    sync::Arc,
 };
 
-use super::db_command_serde::CommandDeserializer;
-
-// Returns sorted existing log ids in the given directory (path).
-pub fn get_log_ids(path: &Path) -> Result<Vec<u64>> {
-    let mut log_ids: Vec<u64> = fs::read_dir(&path)?
-        .flat_map(|res| -> Result<_> { Ok(res?.path()) })
-        .filter(|path| path.is_file() && path.extension() == Some("log".as_ref()))
-        .flat_map(|path| {
-            path.file_name()
-                .and_then(OsStr::to_str)
-                .map(|s| s.trim_end_matches(".log"))
-                .map(str::parse::<u64>)
+use super::db_command_serde::{
+    deserialize_footer, read_seq_mark, serialize_footer, CommandDeserializer, LogFooter,
+    LOG_FOOTER_LEN,
+};
+
+// Subdirectory where the value logs are stored.
+const VALUE_DIR: &str = "values";
+
+/// Directory where the log files of a database are stored.
+///
+/// Logs are stored as `<log_id>.log` in the root directory, or in subdirectories named
+/// `<log_id / logs_per_dir>` if `logs_per_dir` is set, so no directory holds too many files.
+/// New logs are preallocated to `preallocate_len` bytes if it is set, and the logs are
+/// read and written with buffers of the given capacities.
+///
+/// If `storage` is set, the logs are stored there instead, and only their ids matter.
+///
+/// Values stored apart from their commands are in the value logs, which are laid out the
+/// same way in the `values` subdirectory, see [`LogDir::value_dir`].
+#[derive(Clone)]
+pub struct LogDir {
+    pub root: PathBuf,
+    pub logs_per_dir: Option<u64>,
+    pub preallocate_len: Option<u64>,
+    pub read_buffer_capacity: usize,
+    pub write_buffer_capacity: usize,
+    pub storage: Option<Arc<dyn Storage>>,
+}
+
+impl LogDir {
+    // Returns the directory of the value logs, or `None` if the logs are in a storage, which
+    // has no value logs. They are only appended to, so they are not preallocated.
+    pub fn value_dir(&self) -> Option<LogDir> {
+        if self.storage.is_some() {
+            return None;
+        }
+        Some(LogDir {
+            root: self.root.join(VALUE_DIR),
+            preallocate_len: None,
+            ..self.clone()
         })
-        .flatten()
+    }
+
+    // Opens a log with log_id to read it
+    pub fn open_log(&self, log_id: u64) -> io::Result<LogFile> {
+        match &self.storage {
+            Some(storage) => {
+                // Fails if the log does not exist
+                storage.log_len(log_id)?;
+                Ok(LogFile::Storage {
+                    storage: Arc::clone(storage),
+                    log_id,
+                    pos: 0,
+                })
+            }
+            None => File::open(self.log_path(log_id)).map(LogFile::Disk),
+        }
+    }
+
+    // Removes a log with log_id. Open handles to it remain valid.
+    pub fn remove_log(&self, log_id: u64) -> io::Result<()> {
+        match &self.storage {
+            Some(storage) => storage.remove_log(log_id),
+            None => fs::remove_file(self.log_path(log_id)),
+        }
+    }
+
+    // Returns the length of a log with log_id
+    pub fn log_len(&self, log_id: u64) -> io::Result<u64> {
+        match &self.storage {
+            Some(storage) => storage.log_len(log_id),
+            None => Ok(fs::metadata(self.log_path(log_id))?.len()),
+        }
+    }
+
+    // Returns the path of a log with log_id
+    pub fn log_path(&self, log_id: u64) -> PathBuf {
+        let file_name = format!("{}.log", log_id);
+        match self.logs_per_dir {
+            Some(logs_per_dir) => self
+                .root
+                .join((log_id / logs_per_dir).to_string())
+                .join(file_name),
+            None => self.root.join(file_name),
+        }
+    }
+}
+
+// Returns sorted existing log ids in the given directory.
+// Files whose name is not a log id, like `tmp.log`, are ignored.
+pub fn get_log_ids(dir: &LogDir) -> Result<Vec<u64>> {
+    if let Some(storage) = &dir.storage {
+        let mut log_ids = storage
+            .log_ids()
+            .io_context("list the logs of", || dir.root.clone())?;
+        log_ids.sort_unstable();
+        return Ok(log_ids);
+    }
+    let mut log_ids: Vec<u64> = find_logs(&dir.root, dir.logs_per_dir.is_some())?
+        .into_iter()
+        .map(|(log_id, _)| log_id)
         .collect();
     log_ids.sort_unstable();
     Ok(log_ids)
 }
 
-// Creates a new log file
-pub fn new_log_file(path: &Path, log_id: u64) -> Result<BufWriterWithPos<File>> {
-    let path = log_path(&path, log_id);
-    let writer = BufWriterWithPos::new(
-        OpenOptions::new()
+// Returns sorted existing value log ids of the database in the given directory. The
+// directory of the value logs only exists once a value was written into a value log.
+pub fn get_value_log_ids(dir: &LogDir) -> Result<Vec<u64>> {
+    match existing_value_dir(dir) {
+        Some(value_dir) => get_log_ids(&value_dir),
+        None => Ok(Vec::new()),
+    }
+}
+
+// Returns the directory of the value logs of the database in the given directory, if it
+// was created.
+pub fn existing_value_dir(dir: &LogDir) -> Option<LogDir> {
+    dir.value_dir().filter(|value_dir| value_dir.root.exists())
+}
+
+// Removes the given value logs of the database in the given directory, which no command
+// points to anymore.
+pub fn remove_value_logs(dir: &LogDir, log_ids: &[u64]) {
+    let Some(value_dir) = dir.value_dir() else {
+        return;
+    };
+    for &log_id in log_ids {
+        let log_path = value_dir.log_path(log_id);
+        if let Err(e) = value_dir.remove_log(log_id) {
+            error!("{:?} cannot be deleted: {}", log_path, e);
+        }
+        if let (Some(_), Some(parent)) = (value_dir.logs_per_dir, log_path.parent()) {
+            remove_dir_if_empty(parent);
+        }
+    }
+}
+
+// Returns the id and path of the logs in the root directory and, if `nested` is true, in its
+// subdirectories whose name is a number.
+fn find_logs(root: &Path, nested: bool) -> Result<Vec<(u64, PathBuf)>> {
+    let mut logs = Vec::new();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let entries = fs::read_dir(&dir).io_context("read directory", || dir.clone())?;
+        for path in entries.flat_map(|res| -> Result<_> { Ok(res?.path()) }) {
+            let Some(name) = path.file_name().and_then(OsStr::to_str) else {
+                continue;
+            };
+            if path.is_file() && path.extension() == Some("log".as_ref()) {
+                if let Ok(log_id) = name.trim_end_matches(".log").parse::<u64>() {
+                    logs.push((log_id, path));
+                }
+            } else if nested && dir == root && path.is_dir() && name.parse::<u64>().is_ok() {
+                dirs.push(path);
+            }
+        }
+    }
+    Ok(logs)
+}
+
+// Moves the logs that are not where the layout of the directory expects them, so the
+// layout can change between opens. Empty subdirectories are removed.
+pub fn relocate_logs(dir: &LogDir) -> Result<()> {
+    if dir.storage.is_some() {
+        return Ok(());
+    }
+    for (log_id, path) in find_logs(&dir.root, true)? {
+        let expected_path = dir.log_path(log_id);
+        if path == expected_path {
+            continue;
+        }
+        if let Some(parent) = expected_path.parent() {
+            fs::create_dir_all(parent).io_context("create directory", || parent.to_path_buf())?;
+        }
+        fs::rename(&path, &expected_path).io_context("move", || path.clone())?;
+        if let Some(parent) = path.parent().filter(|parent| *parent != dir.root) {
+            remove_dir_if_empty(parent);
+        }
+    }
+    Ok(())
+}
+
+// Removes a subdirectory of logs if it doesn't contain any file.
+pub fn remove_dir_if_empty(path: &Path) {
+    // It fails if the directory is not empty
+    let _ = fs::remove_dir(path);
+}
+
+// Returns the sum of the sizes of all log files in the given directory.
+pub fn get_logs_size(dir: &LogDir) -> Result<u64> {
+    let mut size = 0;
+    for log_id in get_log_ids(dir)? {
+        match dir.log_len(log_id) {
+            Ok(len) => size += len,
+            // The log may have been removed by a compaction after listing it
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e).io_context("read metadata of", || dir.log_path(log_id)),
+        }
+    }
+    Ok(size)
+}
+
+// Returns the sum of the sizes of the value logs of the database in the given directory.
+pub fn get_value_logs_size(dir: &LogDir) -> Result<u64> {
+    match existing_value_dir(dir) {
+        Some(value_dir) => get_logs_size(&value_dir),
+        None => Ok(0),
+    }
+}
+
+// Removes the empty logs in the given directory and returns the ids of the remaining ones.
+// A log is empty when it was created but the process stopped before writing into it, so it
+// doesn't contain any command and it must not be used as the base of new log ids.
+// Preallocated logs that were never written only contain zeros.
+pub fn remove_empty_logs(dir: &LogDir) -> Result<Vec<u64>> {
+    let mut log_ids = get_log_ids(dir)?;
+    let mut empty_log_ids = Vec::new();
+    for &log_id in &log_ids {
+        let log_path = dir.log_path(log_id);
+        let file = dir
+            .open_log(log_id)
+            .io_context("open", || log_path.clone())?;
+        let log_len = file
+            .len()
+            .io_context("read metadata of", || log_path.clone())?;
+        let mut reader = BufReaderWithPos::new(file).io_context("seek", || log_path.clone())?;
+        if reader
+            .is_zeroed_until(log_len)
+            .io_context("read", || log_path)?
+        {
+            empty_log_ids.push(log_id);
+        }
+    }
+    for &log_id in &empty_log_ids {
+        dir.remove_log(log_id)
+            .io_context("remove", || dir.log_path(log_id))?;
+    }
+    log_ids.retain(|log_id| !empty_log_ids.contains(log_id));
+    Ok(log_ids)
+}
+
+// Removes the logs below `log_id`, which a compaction made stale.
+// Note that actually these files are not deleted immediately because `LogReader`s
+// still keep open file handles. When `LogReader` is used next time, it will clear
+// its stale file handles. On Unix, the files will be deleted after all the handles
+// are closed. On Windows, the deletions below will fail and stale files are expected
+// to be deleted in the next compaction.
+pub fn remove_logs_below(dir: &LogDir, log_id: u64) -> Result<()> {
+    for stale_log_id in get_log_ids(dir)? {
+        if stale_log_id >= log_id {
+            continue;
+        }
+        let log_path = dir.log_path(stale_log_id);
+        if let Err(e) = dir.remove_log(stale_log_id) {
+            error!("{:?} cannot be deleted: {}", log_path, e);
+        }
+        if let (Some(_), Some(parent)) = (dir.logs_per_dir, log_path.parent()) {
+            remove_dir_if_empty(parent);
+        }
+    }
+    Ok(())
+}
+
+// Creates a new log file, preallocated if the directory requires it. Commands are written
+// from the start of the preallocated space.
+pub fn new_log_file(dir: &LogDir, log_id: u64) -> Result<BufWriterWithPos<LogFile>> {
+    let path = dir.log_path(log_id);
+    // Logs in a storage are only appended to, so they are not preallocated
+    if let Some(storage) = &dir.storage {
+        storage
+            .create_log(log_id)
+            .io_context("create", || path.clone())?;
+        let file = LogFile::Storage {
+            storage: Arc::clone(storage),
+            log_id,
+            pos: 0,
+        };
+        return BufWriterWithPos::with_capacity(dir.write_buffer_capacity, file)
+            .io_context("seek", || path);
+    }
+    if let Some(parent) = path.parent().filter(|_| dir.logs_per_dir.is_some()) {
+        fs::create_dir_all(parent).io_context("create directory", || parent.to_path_buf())?;
+    }
+    let Some(preallocate_len) = dir.preallocate_len else {
+        let file = OpenOptions::new()
             .create(true)
-            .write(true)
             .append(true)
-            .open(&path)?,
-    )?;
+            .open(&path)
+            .io_context("create", || path.clone())?;
+        return BufWriterWithPos::with_capacity(dir.write_buffer_capacity, LogFile::Disk(file))
+            .io_context("seek", || path);
+    };
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(true)
+        .write(true)
+        .open(&path)
+        .io_context("create", || path.clone())?;
+    file.set_len(preallocate_len)
+        .io_context("preallocate", || path.clone())?;
+    let mut writer =
+        BufWriterWithPos::with_capacity(dir.write_buffer_capacity, LogFile::Disk(file))
+            .io_context("seek", || path.clone())?;
+    writer
+        .seek(SeekFrom::Start(0))
+        .io_context("seek", || path)?;
     Ok(writer)
 }
 
+/// Result of loading a log into the index.
+pub struct LoadedLog {
+    /// Number of bytes that can be saved after a compaction.
+    pub uncompacted: u64,
+    /// Number of commands in the log.
+    pub commands: u64,
+    /// Length of the commands in the log. An unsealed log may be longer if it was
+    /// preallocated, as the space after its commands is zeroed.
+    pub len: u64,
+    /// Whether the log ends with a footer, so it was already sealed.
+    pub sealed: bool,
+    /// Highest sequence number of the commands in the log, or 0 if they have none.
+    pub max_seq: u64,
+    /// Corrupted data skipped in `RecoveryMode::Salvage`.
+    pub dropped: RecoverySummary,
+}
+
 /// Load the whole log file and store value locations in the index map.
 ///
-/// Returns how many bytes can be saved after a compaction.
+/// If the log is sealed, its footer is checked first with a single sequential read. If it
+/// doesn't match, or the log has no footer, the log is scanned command by command to find
+/// where it ends.
+///
+/// Corrupted commands make it fail in `RecoveryMode::Strict`, and are skipped in
+/// `RecoveryMode::Salvage`.
+///
+/// Values are always skipped, whatever their size, so the memory used is bounded by the
+/// buffer of the reader and there is no size threshold to configure.
 pub fn load_log(
     log_id: u64,
-    reader: &mut BufReaderWithPos<File>,
-    index: &SkipMap<Vec<u8>, CommandPos>,
-) -> Result<u64> {
-    let mut pos = reader.seek(SeekFrom::Start(0))?;
+    reader: &mut BufReaderWithPos<LogFile>,
+    index: &KeyIndex,
+    recovery_mode: RecoveryMode,
+) -> Result<LoadedLog> {
+    let log_len = reader.seek(SeekFrom::End(0))?;
+    let footer = read_footer(reader, log_len)?;
+    let end = if footer.is_some() {
+        log_len - LOG_FOOTER_LEN
+    } else {
+        log_len
+    };
+
+    let mut verified_footer = None;
+    if let Some(footer) = footer {
+        reader.seek(SeekFrom::Start(0))?;
+        if checksum_of(reader, end)? == footer.checksum {
+            verified_footer = Some(footer);
+        } else {
+            error!("Footer of log {} does not match, scanning it", log_id);
+        }
+    }
+
+    reader.seek(SeekFrom::Start(0))?;
+    let mut dropped = RecoverySummary::default();
+    let (uncompacted, commands, len, max_seq) =
+        load_commands(log_id, reader, end, index, recovery_mode, &mut dropped)?;
+    if let Some(footer) = verified_footer {
+        if footer.commands != commands {
+            return Err(GrausError::SerializationError(format!(
+                "Log {} has {} commands but its footer expects {}",
+                log_id, commands, footer.commands
+            )));
+        }
+    }
+
+    Ok(LoadedLog {
+        uncompacted,
+        commands,
+        len,
+        sealed: footer.is_some(),
+        max_seq,
+        dropped,
+    })
+}
+
+// Returns the footer at the end of the log, if any.
+fn read_footer(reader: &mut BufReaderWithPos<LogFile>, log_len: u64) -> Result<Option<LogFooter>> {
+    if log_len < LOG_FOOTER_LEN {
+        return Ok(None);
+    }
+    let mut bytes = [0; LOG_FOOTER_LEN as usize];
+    reader.seek(SeekFrom::Start(log_len - LOG_FOOTER_LEN))?;
+    reader.read_exact(&mut bytes)?;
+    Ok(deserialize_footer(&bytes))
+}
+
+// Stores the value locations of the commands up to `end` in the index map.
+// Returns how many bytes can be saved after a compaction, the number of commands, where
+// they end and their highest sequence number, or the one of the sequence mark of the log.
+fn load_commands(
+    log_id: u64,
+    reader: &mut BufReaderWithPos<LogFile>,
+    end: u64,
+    index: &KeyIndex,
+    recovery_mode: RecoveryMode,
+    dropped: &mut RecoverySummary,
+) -> Result<(u64, u64, u64, u64)> {
+    // The sequence mark at the start of the log is replaced by the next compaction, so it
+    // can't be saved
+    let mut max_seq = read_seq_mark(reader)?.unwrap_or(0);
+    let mut pos = reader.pos;
     let mut uncompacted = 0; // number of bytes that can be saved after a compaction.
+    let mut commands = 0;
 
     // Create an iterator for deserializing commands.
-    let mut deserializer = CommandDeserializer::new(reader);
+    // Only the length of the values is needed, so they are not read into memory
+    let mut deserializer = CommandDeserializer::new(reader, end).skip_values();
 
     // Iterate over the deserialized commands.
     while let Some(command) = deserializer.next() {
+        let command = match (command, recovery_mode) {
+            (Ok(command), _) => command,
+            (Err(e), RecoveryMode::Salvage) => {
+                error!("Skipping corrupted command in log {}: {}", log_id, e);
+                dropped.dropped_records += 1;
+                dropped.dropped_bytes += deserializer.skip_corrupted()?;
+                pos = deserializer.pos as u64;
+                continue;
+            }
+            (Err(e), RecoveryMode::Strict) => return Err(e),
+        };
+        // The header of a batch, before its first command, is not copied by compactions
+        let start = deserializer.start as u64;
+        uncompacted += start - pos;
+        pos = start;
         let new_pos = deserializer.pos as u64;
-        let command = command?;
+        let value_len = deserializer.value_len;
+        commands += 1;
+        max_seq = max_seq.max(command.seq());
+        let value_log_id = match &command {
+            CommandOwned::SetRef { value_ref, .. } => Some(value_ref.log_id),
+            _ => None,
+        };
         match command {
-            CommandOwned::Set { key, .. } => {
-                let old_cmd = index.insert(
+            CommandOwned::Set { key, version, .. } | CommandOwned::SetRef { key, version, .. } => {
+                let old_cmd = index.get(&key);
+                if let Some(old_cmd) = old_cmd {
+                    uncompacted += old_cmd.stale_len();
+                }
+                // Legacy commands have no version, so they follow the previous one
+                let version = match version {
+                    0 => old_cmd.map_or(1, |old_cmd| old_cmd.version + 1),
+                    version => version,
+                };
+                index.insert(
                     key,
                     CommandPos {
                         log_id,
                         pos,
                         len: new_pos - pos,
+                        value_len,
+                        appended_len: 0,
+                        version,
+                        value_log_id,
                     },
                 );
-                uncompacted += old_cmd.value().len;
             }
-            CommandOwned::Remove { key } => {
+            CommandOwned::Remove { key, .. } => {
                 if let Some(old_cmd) = index.remove(&key) {
-                    uncompacted += old_cmd.value().len;
+                    uncompacted += old_cmd.stale_len();
                 }
 
                 // the new "remove" command itself can be deleted in the next compaction.
                 // so we add its length to `uncompacted`.
                 uncompacted += new_pos - pos;
             }
+            CommandOwned::Append { key, version, .. } => {
+                // The chunk can't be read without the value it is appended to
+                let Some(old_cmd) = index.get(&key) else {
+                    let e = GrausError::SerializationError(String::from("Append to a missing key"));
+                    if recovery_mode == RecoveryMode::Strict {
+                        return Err(e);
+                    }
+                    error!("Skipping corrupted command in log {}: {}", log_id, e);
+                    dropped.dropped_records += 1;
+                    dropped.dropped_bytes += new_pos - pos;
+                    pos = new_pos;
+                    continue;
+                };
+                // Folding the chunk in the next compaction only saves the rest of the command
+                uncompacted += new_pos - pos - value_len;
+                index.insert(
+                    key,
+                    CommandPos {
+                        log_id,
+                        pos,
+                        len: new_pos - pos,
+                        value_len,
+                        appended_len: old_cmd.total_value_len(),
+                        version,
+                        value_log_id: old_cmd.value_log_id,
+                    },
+                );
+            }
         }
 
         pos = new_pos;
     }
-    Ok(uncompacted)
+    Ok((uncompacted, commands, pos, max_seq))
 }
 
-// Returns the path of a log with log_id
-pub fn log_path(dir: &Path, log_id: u64) -> PathBuf {
-    dir.join(format!("{}.log", log_id))
+/// Calls `f` with every command of a log, in order, stopping at `max_end` if given or at
+/// the first error.
+pub fn for_each_command<F>(
+    reader: &mut BufReaderWithPos<LogFile>,
+    max_end: Option<u64>,
+    mut f: F,
+) -> Result<()>
+where
+    F: FnMut(CommandOwned) -> Result<()>,
+{
+    let log_len = reader.seek(SeekFrom::End(0))?;
+    let mut end = match read_footer(reader, log_len)? {
+        Some(_) => log_len - LOG_FOOTER_LEN,
+        None => log_len,
+    };
+    if let Some(max_end) = max_end {
+        end = end.min(max_end);
+    }
+    reader.seek(SeekFrom::Start(0))?;
+    for command in CommandDeserializer::new(reader, end) {
+        f(command?)?;
+    }
+    Ok(())
+}
+
+// Seals a log that will not receive more commands, appending a footer with its checksum
+// after its first `len` bytes. The rest of the log, like preallocated space, is dropped.
+pub fn seal_log(dir: &LogDir, log_id: u64, len: u64, commands: u64) -> Result<()> {
+    let log_path = dir.log_path(log_id);
+    let mut file = match dir.storage {
+        Some(_) => dir.open_log(log_id),
+        None => OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&log_path)
+            .map(LogFile::Disk),
+    }
+    .io_context("open", || log_path.clone())?;
+    file.set_len(len)
+        .io_context("truncate", || log_path.clone())?;
+    let checksum = checksum_of(&mut file, len).io_context("read", || log_path.clone())?;
+
+    let mut writer = BufWriterWithPos::new(file).io_context("seek", || log_path.clone())?;
+    serialize_footer(&LogFooter { commands, checksum }, &mut writer)
+        .io_context("write", || log_path.clone())?;
+    writer.flush().io_context("write", || log_path)?;
+    Ok(())
 }
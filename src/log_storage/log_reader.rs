@@ -1,31 +1,57 @@
-use super::db_command_serde::deserialize_command;
-use super::log_helpers::log_path;
+use super::db_command_serde::{deserialize_command, verify_value};
+use super::log_helpers::LogDir;
+use crate::cipher::{Nonce, Sealer};
 use crate::db_command::CommandOwned;
-use crate::Result;
-use crate::{db_command::CommandPos, io_types::BufReaderWithPos};
-use std::io::Seek;
+use crate::db_command::{CommandPos, ValueRef};
+use crate::error::IoContext;
+use crate::io_types::{BufReaderWithPos, LogFile};
+use crate::{GrausError, Result};
+use crossbeam_utils::atomic::AtomicCell;
+use std::io::{self, BufReader, Read, Seek, Take};
 use std::{
+    borrow::Cow,
     cell::RefCell,
-    collections::HashMap,
-    fs::File,
+    collections::{btree_map::Entry, BTreeMap, VecDeque},
     io::SeekFrom,
-    path::PathBuf,
     sync::{
         atomic::{AtomicU64, Ordering},
         Arc,
     },
 };
 
+/// Position up to which the logs have been flushed to the file system.
+///
+/// Logs older than `log_id` are completely flushed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FlushedPos {
+    pub log_id: u64,
+    pub pos: u64,
+}
+
 /// A single thread reader.
 ///
 /// Each `GrausDb` instance has its own `LogReader` and
 /// `LogReader`s open the same files separately. So the user
 /// can read concurrently through multiple `GrausDb`s in different
 /// threads.
+///
+/// If `max_open_readers` is set, the least recently used readers are closed when there
+/// are more open, and they are opened again when needed.
 pub struct LogReader {
-    pub path: Arc<PathBuf>,
+    pub dir: Arc<LogDir>,
     pub safe_point: Arc<AtomicU64>,
-    pub readers: RefCell<HashMap<u64, BufReaderWithPos<File>>>,
+    pub flushed: Arc<AtomicCell<FlushedPos>>,
+    pub readers: RefCell<BTreeMap<u64, BufReaderWithPos<LogFile>>>,
+    pub max_open_readers: Option<usize>,
+    // Log ids of the readers, from the least to the most recently used. Only tracked when
+    // `max_open_readers` is set.
+    pub recently_used: RefCell<VecDeque<u64>>,
+    // Seals the values written and opens the values read, when a cipher is set.
+    pub sealer: Option<Arc<Sealer>>,
+    // Directory of the value logs, `None` if the logs are in a storage, and their readers,
+    // opened when a value is read.
+    pub value_dir: Option<Arc<LogDir>>,
+    pub value_readers: RefCell<BTreeMap<u64, BufReaderWithPos<LogFile>>>,
 }
 
 impl LogReader {
@@ -45,39 +71,291 @@ impl LogReader {
             }
             readers.remove(&first_log_id);
         }
+        // Value logs below the safe point may have been deleted too. The ones that were kept
+        // are opened again when needed.
+        let safe_point = self.safe_point.load(Ordering::SeqCst);
+        self.value_readers
+            .borrow_mut()
+            .retain(|&log_id, _| log_id >= safe_point);
+    }
+
+    /// Returns whether the given log is older than the last compaction, so it may have
+    /// been deleted already.
+    pub fn is_stale(&self, log_id: u64) -> bool {
+        log_id < self.safe_point.load(Ordering::SeqCst)
+    }
+
+    /// Returns whether the command at the given position has been flushed, so it can be read.
+    pub fn is_flushed(&self, cmd_pos: CommandPos) -> bool {
+        let flushed = self.flushed.load();
+        cmd_pos.log_id < flushed.log_id || cmd_pos.pos + cmd_pos.len <= flushed.pos
     }
 
     /// Read the log file at the given `CommandPos` and execute a callback.
     pub fn read_and<F, R>(&self, cmd_pos: CommandPos, f: F) -> Result<R>
     where
-        F: FnOnce(&mut BufReaderWithPos<File>) -> Result<R>,
+        F: FnOnce(&mut BufReaderWithPos<LogFile>) -> Result<R>,
+    {
+        self.read_at(cmd_pos.log_id, cmd_pos.pos, f)
+    }
+
+    // Reads the log file `log_id` from `pos` and executes a callback.
+    fn read_at<F, R>(&self, log_id: u64, pos: u64, f: F) -> Result<R>
+    where
+        F: FnOnce(&mut BufReaderWithPos<LogFile>) -> Result<R>,
     {
         self.close_stale_readers();
 
         let mut readers = self.readers.borrow_mut();
         // Since each clone uses its own Map, maybe this log file was not opened in this instance
-        if !readers.contains_key(&cmd_pos.log_id) {
-            let log_path = log_path(&self.path, cmd_pos.log_id);
-            let reader = BufReaderWithPos::new(File::open(log_path)?)?;
-            readers.insert(cmd_pos.log_id, reader);
+        if let Entry::Vacant(entry) = readers.entry(log_id) {
+            let file = self.open_log(log_id)?;
+            let reader = BufReaderWithPos::with_capacity(self.dir.read_buffer_capacity, file)
+                .io_context("seek", || self.dir.log_path(log_id))?;
+            entry.insert(reader);
+        }
+        self.evict_readers(&mut readers, log_id);
+
+        let reader = readers.get_mut(&log_id).unwrap();
+        reader
+            .seek(SeekFrom::Start(pos))
+            .io_context("seek", || self.dir.log_path(log_id))?;
+        f(reader).io_context("read", || self.dir.log_path(log_id))
+    }
+
+    // Marks the reader of `used_log_id` as the most recently used, and closes the least
+    // recently used ones while there are more than `max_open_readers`.
+    fn evict_readers(
+        &self,
+        readers: &mut BTreeMap<u64, BufReaderWithPos<LogFile>>,
+        used_log_id: u64,
+    ) {
+        let Some(max_open_readers) = self.max_open_readers else {
+            return;
+        };
+        let mut recently_used = self.recently_used.borrow_mut();
+        recently_used.retain(|&log_id| log_id != used_log_id);
+        recently_used.push_back(used_log_id);
+
+        while readers.len() > max_open_readers {
+            // Readers that were never used, like the ones opened with the database, go first
+            let never_used = readers
+                .keys()
+                .find(|log_id| !recently_used.contains(log_id))
+                .copied();
+            let log_id = match never_used {
+                Some(log_id) => log_id,
+                None => recently_used.pop_front().unwrap(),
+            };
+            readers.remove(&log_id);
+        }
+        // Closed by `close_stale_readers` or evicted above
+        recently_used.retain(|log_id| readers.contains_key(log_id));
+    }
+
+    // Opens the log `log_id`. Logs of the index are only deleted by compactions once they
+    // are stale, so the others are missing because they were deleted from outside.
+    fn open_log(&self, log_id: u64) -> Result<LogFile> {
+        match self.dir.open_log(log_id) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                Err(GrausError::LogFileMissing { log_id })
+            }
+            file => file.io_context("open", || self.dir.log_path(log_id)),
+        }
+    }
+
+    /// Opens a new handle to the log at the given position, limited to `len` bytes.
+    ///
+    /// The handle is independent from the cached readers, so it can outlive this reader.
+    pub fn open_at(&self, log_id: u64, pos: u64, len: u64) -> Result<Take<BufReader<LogFile>>> {
+        let mut file = self.open_log(log_id)?;
+        file.seek(SeekFrom::Start(pos))
+            .io_context("seek", || self.dir.log_path(log_id))?;
+        Ok(BufReader::with_capacity(self.dir.read_buffer_capacity, file).take(len))
+    }
+
+    /// Encrypts a value to be written to a log, if a cipher is set. Returns the nonce it
+    /// is sealed with along with the value to store.
+    pub fn seal_value<'a>(&self, value: &'a [u8]) -> (Option<Nonce>, Cow<'a, [u8]>) {
+        match &self.sealer {
+            Some(sealer) => {
+                let (nonce, ciphertext) = sealer.seal(value);
+                (Some(nonce), Cow::Owned(ciphertext))
+            }
+            None => (None, Cow::Borrowed(value)),
+        }
+    }
+
+    /// Decrypts a value read from a log, if it was sealed with `nonce`.
+    pub fn open_value(&self, value: Vec<u8>, nonce: Option<Nonce>) -> Result<Vec<u8>> {
+        match (nonce, &self.sealer) {
+            (None, _) => Ok(value),
+            (Some(nonce), Some(sealer)) => sealer.open(&nonce, &value),
+            (Some(_), None) => Err(GrausError::DecryptionFailed),
+        }
+    }
+
+    /// Reads a value stored in a value log, verifying it against its checksum if any.
+    pub fn read_value(&self, value_ref: ValueRef, checksum: Option<u32>) -> Result<Vec<u8>> {
+        let value_dir = self.value_dir()?;
+        let log_path = value_dir.log_path(value_ref.log_id);
+        let mut value_readers = self.value_readers.borrow_mut();
+        if let Entry::Vacant(entry) = value_readers.entry(value_ref.log_id) {
+            let file = value_dir
+                .open_log(value_ref.log_id)
+                .io_context("open", || log_path.clone())?;
+            let reader = BufReaderWithPos::with_capacity(self.dir.read_buffer_capacity, file)
+                .io_context("seek", || log_path.clone())?;
+            entry.insert(reader);
         }
-        let reader = readers.get_mut(&cmd_pos.log_id).unwrap();
-        reader.seek(SeekFrom::Start(cmd_pos.pos))?;
-        f(reader)
+        let reader = value_readers.get_mut(&value_ref.log_id).unwrap();
+        reader
+            .seek(SeekFrom::Start(value_ref.pos))
+            .io_context("seek", || log_path.clone())?;
+        let mut value = vec![0; value_ref.len as usize];
+        reader
+            .read_exact(&mut value)
+            .io_context("read", || log_path)?;
+        verify_value(&value, checksum, false)?;
+        Ok(value)
+    }
+
+    /// Opens a new handle to a value stored in a value log, like [`LogReader::open_at`].
+    pub fn open_value_at(&self, value_ref: ValueRef) -> Result<Take<BufReader<LogFile>>> {
+        let value_dir = self.value_dir()?;
+        let log_path = value_dir.log_path(value_ref.log_id);
+        let mut file = value_dir
+            .open_log(value_ref.log_id)
+            .io_context("open", || log_path.clone())?;
+        file.seek(SeekFrom::Start(value_ref.pos))
+            .io_context("seek", || log_path)?;
+        Ok(BufReader::with_capacity(self.dir.read_buffer_capacity, file).take(value_ref.len))
     }
 
+    /// Returns the directory of the value logs. Logs in a storage never point to a value
+    /// log, so they are corrupted if they do.
+    pub fn value_dir(&self) -> Result<&LogDir> {
+        self.value_dir
+            .as_deref()
+            .ok_or(GrausError::UnexpectedCommandType)
+    }
+
+    /// Reads the command at the given position.
+    ///
+    /// An "append" command is folded with the commands it appends to, following their
+    /// positions back to the "set" command of the key, so a "set" command with the whole
+    /// value is returned. Values stored in a value log are read from it, so a "set" command
+    /// is returned for them too. Encrypted values are decrypted.
     pub fn read_command(&self, cmd_pos: CommandPos) -> Result<CommandOwned> {
-        self.read_and(cmd_pos, |reader| deserialize_command(reader))
+        let command = self.resolve_value(self.read_and(cmd_pos, deserialize_command)?)?;
+        let CommandOwned::Append {
+            key,
+            chunk,
+            version,
+            timestamp,
+            mut prev_log_id,
+            mut prev_pos,
+            nonce,
+            seq,
+        } = command
+        else {
+            return match command {
+                CommandOwned::Set {
+                    key,
+                    value,
+                    version,
+                    timestamp,
+                    nonce,
+                    seq,
+                } => Ok(CommandOwned::Set {
+                    key,
+                    value: self.open_value(value, nonce)?,
+                    version,
+                    timestamp,
+                    nonce: None,
+                    seq,
+                }),
+                command => Ok(command),
+            };
+        };
+
+        let mut chunks = vec![self.open_value(chunk, nonce)?];
+        let mut value = loop {
+            match self.read_at(prev_log_id, prev_pos, deserialize_command)? {
+                CommandOwned::Append {
+                    chunk,
+                    prev_log_id: log_id,
+                    prev_pos: pos,
+                    nonce,
+                    ..
+                } => {
+                    chunks.push(self.open_value(chunk, nonce)?);
+                    prev_log_id = log_id;
+                    prev_pos = pos;
+                }
+                CommandOwned::Set { value, nonce, .. } => break self.open_value(value, nonce)?,
+                CommandOwned::SetRef {
+                    value_ref,
+                    nonce,
+                    checksum,
+                    ..
+                } => break self.open_value(self.read_value(value_ref, checksum)?, nonce)?,
+                CommandOwned::Remove { .. } => return Err(GrausError::UnexpectedCommandType),
+            }
+        };
+        value.reserve(chunks.iter().map(Vec::len).sum());
+        for chunk in chunks.iter().rev() {
+            value.extend_from_slice(chunk);
+        }
+        Ok(CommandOwned::Set {
+            key,
+            value,
+            version,
+            timestamp,
+            nonce: None,
+            seq,
+        })
+    }
+
+    /// Turns a "set" command whose value is in a value log into a "set" command with the
+    /// value, still encrypted if it was. Other commands are returned as they are.
+    pub fn resolve_value(&self, command: CommandOwned) -> Result<CommandOwned> {
+        let CommandOwned::SetRef {
+            key,
+            value_ref,
+            version,
+            timestamp,
+            nonce,
+            checksum,
+            seq,
+        } = command
+        else {
+            return Ok(command);
+        };
+        Ok(CommandOwned::Set {
+            key,
+            value: self.read_value(value_ref, checksum)?,
+            version,
+            timestamp,
+            nonce,
+            seq,
+        })
     }
 }
 
 impl Clone for LogReader {
     fn clone(&self) -> LogReader {
         LogReader {
-            path: Arc::clone(&self.path),
+            dir: Arc::clone(&self.dir),
             safe_point: Arc::clone(&self.safe_point),
+            flushed: Arc::clone(&self.flushed),
             // use a new map
-            readers: RefCell::new(HashMap::new()),
+            readers: RefCell::new(BTreeMap::new()),
+            max_open_readers: self.max_open_readers,
+            recently_used: RefCell::new(VecDeque::new()),
+            sealer: self.sealer.clone(),
+            value_dir: self.value_dir.clone(),
+            value_readers: RefCell::new(BTreeMap::new()),
         }
     }
 }
@@ -1,25 +1,38 @@
 use super::{
-    db_command_serde::serialize_command,
-    log_helpers::{get_log_ids, log_path, new_log_file},
-    log_reader::LogReader,
+    db_command_serde::{
+        crc_of, serialize_batch, serialize_command, serialize_footer, serialize_seq_mark,
+        LogFooter, BATCH_HEADER_LEN,
+    },
+    group_commit::GroupCommit,
+    log_helpers::{get_log_ids, get_value_log_ids, load_log, new_log_file, LogDir},
+    log_reader::{FlushedPos, LogReader},
+    mirror::Mirror,
 };
+use crate::error::IoContext;
 use crate::{
-    db_command::{CommandPos, CommandRef},
-    io_types::BufWriterWithPos,
+    checksum::Crc32,
+    cipher::Nonce,
+    compaction::{CompactionStrategy, ValueTransform},
+    db_command::{CommandOwned, CommandPos, CommandRef, ValueRef},
+    executor::Executor,
+    io_types::{BufReaderWithPos, BufWriterWithPos, LogFile},
+    key_index::{Index, KeyIndex},
+    secondary_index::SecondaryIndexes,
+    snapshot::SnapshotPins,
+    stats::CompactionStats,
+    value_cache::ValueCache,
 };
-use crate::{GrausError, Result};
-use crossbeam_skiplist::SkipMap;
+use crate::{GrausError, MirrorMode, RecoveryMode, Result};
 use log::error;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{SystemTime, UNIX_EPOCH};
 use std::{
-    collections::HashMap,
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet, HashMap},
     fs,
-    io::{self, Write},
+    io::{Cursor, Read, Write},
     sync::atomic::Ordering,
 };
-use std::{fs::File, path::PathBuf, sync::Arc};
-
-// If the log reaches 1 MB, trigger a compaction.
-const COMPACTION_THRESHOLD: u64 = 1024 * 1024;
 
 /// A log writer that is used by GrausDb to store new commands on the log.
 ///
@@ -27,34 +40,329 @@ const COMPACTION_THRESHOLD: u64 = 1024 * 1024;
 /// Since GrausDB is lock-free, multiple reads can happen at the same time, even if
 /// there is a write.
 pub struct LogWriter {
-    pub writer: BufWriterWithPos<File>,
-    pub index: Arc<SkipMap<Vec<u8>, CommandPos>>,
+    // The active log, `None` until the first write if it was not created on open.
+    pub writer: Option<BufWriterWithPos<LogFile>>,
+    pub index: Arc<KeyIndex>,
     pub reader: LogReader,
-    pub path: Arc<PathBuf>,
+    pub dir: Arc<LogDir>,
     pub current_log_id: u64,
     pub uncompacted: u64,
+    // Sequence number of the last write, increased on every write.
+    pub seq: u64,
+    pub total_bytes: u64,
+    pub num_logs: usize,
+    pub compaction_strategy: Arc<dyn CompactionStrategy>,
+    pub flush_each_write: bool,
+    // Whether every command is fsynced before it is added to the index.
+    pub sync_before_visible: bool,
+    pub secondary_indexes: Arc<SecondaryIndexes>,
+    pub group_commit: Arc<GroupCommit>,
+    // Runs the compactions in the background if set, instead of during the writes.
+    pub executor: Option<Arc<dyn Executor>>,
+    // The writer itself, locked by the background compactions.
+    pub this: Weak<Mutex<LogWriter>>,
+    pub compaction_scheduled: bool,
+    // Id of the log being written by a compaction, while the writer is unlocked.
+    pub compacting: Option<u64>,
+    pub compaction_stats: Arc<CompactionStats>,
+    // Copy of the logs in a second directory, if set.
+    pub mirror: Option<Mirror>,
+    // High-water marks above which writes are stalled.
+    pub max_uncompacted: Option<u64>,
+    pub max_total_bytes: Option<u64>,
+    // Whether a checksum of the values is stored with them.
+    pub value_checksums: bool,
+    pub snapshot_pins: Arc<SnapshotPins>,
+    // Values at least this long are written into the value log, if set.
+    pub value_log_threshold: Option<u64>,
+    // The active value log, with the same id as the active log, created on its first value.
+    pub value_writer: Option<BufWriterWithPos<LogFile>>,
+    // Whether every "set" command is read back after it is written.
+    pub verify_writes: bool,
+    // Applied to every value copied by the compactions, if set.
+    pub value_transform: Option<ValueTransform>,
+    // Whether writes are rejected, as only some of the logs were loaded.
+    pub read_only: bool,
+    // Values read by `get`, dropped when their keys are written.
+    pub value_cache: Arc<ValueCache>,
 }
 
 impl LogWriter {
     pub fn set(&mut self, key: Vec<u8>, value: &[u8]) -> Result<()> {
-        let command_ref = CommandRef::set(&key, value);
-        let pos = self.writer.pos;
+        self.check_stalled()?;
+        let version = self.next_version();
+        self.write_set(key, value, version, Some(now_micros()))?;
+        self.compact_if_needed();
+        Ok(())
+    }
+
+    /// Sets every entry without flushing or checking compaction after each one, then syncs
+    /// the active log once. Compaction is only checked after the last entry.
+    ///
+    /// Writes are not stalled by the high-water marks during the load.
+    pub fn bulk_load(
+        &mut self,
+        entries: impl IntoIterator<Item = (Vec<u8>, Vec<u8>)>,
+    ) -> Result<()> {
+        let flush_each_write = std::mem::replace(&mut self.flush_each_write, false);
+        let sync_before_visible = std::mem::replace(&mut self.sync_before_visible, false);
+        let result = entries.into_iter().try_for_each(|(key, value)| {
+            let version = self.next_version();
+            self.write_set(key, &value, version, Some(now_micros()))
+        });
+        self.flush_each_write = flush_each_write;
+        self.sync_before_visible = sync_before_visible;
+        result?;
+        self.sync()?;
+
+        self.compact_if_needed();
+        Ok(())
+    }
+
+    /// Rewrites the current value of a key into the active log, with the same version and
+    /// timestamp, so its previous commands, including appended chunks, become stale.
+    pub fn rewrite(&mut self, key: Vec<u8>) -> Result<()> {
+        let Some(cmd_pos) = self.index.get(&key) else {
+            return Err(GrausError::KeyNotFound);
+        };
+        // The value may not be flushed yet
+        self.flush()?;
+        let CommandOwned::Set {
+            value, timestamp, ..
+        } = self.reader.read_command(cmd_pos)?
+        else {
+            return Err(GrausError::UnexpectedCommandType);
+        };
+        // Legacy commands have no version in the log, only in the index
+        self.write_set(key, &value, cmd_pos.version, timestamp)?;
+        self.compact_if_needed();
+        Ok(())
+    }
 
-        serialize_command(&command_ref, &mut self.writer)?;
+    // Writes a "set" command and points the index to it.
+    fn write_set(
+        &mut self,
+        key: Vec<u8>,
+        value: &[u8],
+        version: u64,
+        timestamp: Option<u64>,
+    ) -> Result<()> {
+        let stored_value = self.store_value(value)?;
+        let command_ref = self.set_command(&key, &stored_value, version, timestamp);
+        let (pos, len) = self.write_command(command_ref)?;
 
+        let command_pos = stored_value.command_pos(self.current_log_id, pos, len, version);
+        if self.verify_writes {
+            self.verify_write(&key, value, command_pos)?;
+        }
+        self.publish_set(key, value, command_pos);
+        Ok(())
+    }
+
+    // Returns the version of the next write, which is its sequence number, so the version
+    // of a key keeps increasing even if it is removed and written again.
+    fn next_version(&self) -> u64 {
+        self.seq + 1
+    }
+
+    // Seals a value, and writes it into the value log if it reaches the threshold.
+    fn store_value<'a>(&mut self, value: &'a [u8]) -> Result<StoredValue<'a>> {
+        let (nonce, bytes) = self.reader.seal_value(value);
+        let value_ref = match self.value_log_threshold {
+            Some(threshold) if bytes.len() as u64 >= threshold => Some(self.write_value(&bytes)?),
+            _ => None,
+        };
+        Ok(StoredValue {
+            nonce,
+            bytes,
+            value_ref,
+        })
+    }
+
+    // Returns the "set" command of a stored value.
+    fn set_command<'a>(
+        &self,
+        key: &'a [u8],
+        stored_value: &'a StoredValue<'_>,
+        version: u64,
+        timestamp: Option<u64>,
+    ) -> CommandRef<'a> {
+        match stored_value.value_ref {
+            Some(value_ref) => {
+                let checksum = self.value_checksums.then(|| crc_of(&stored_value.bytes));
+                CommandRef::set_ref(key, value_ref, version, timestamp, checksum)
+            }
+            None => CommandRef::set(key, &stored_value.bytes, version, timestamp)
+                .with_checksum(self.value_checksums),
+        }
+        .with_nonce(stored_value.nonce)
+    }
+
+    // Points the index to a written "set" command.
+    fn publish_set(&mut self, key: Vec<u8>, value: &[u8], command_pos: CommandPos) {
         if let Some(old_cmd) = self.index.get(&key) {
-            self.uncompacted += old_cmd.value().len;
+            self.uncompacted += old_cmd.stale_len();
+        }
+        self.total_bytes += command_pos.len;
+        if command_pos.value_log_id.is_some() {
+            self.total_bytes += command_pos.value_len;
+        }
+        self.secondary_indexes.on_set(&key, value);
+        self.value_cache.remove(&key);
+        self.index.insert(key, command_pos);
+    }
+
+    /// Writes the writes of a transaction as a single batch: a "set" command for every
+    /// `Some` value and a "remove" command for every `None`, skipping missing keys.
+    ///
+    /// The index is only updated once the whole batch is written. A batch cut off by a
+    /// crash is ignored when the logs are loaded, so none of its writes are kept.
+    pub fn write_batch(&mut self, writes: BTreeMap<Vec<u8>, Option<Vec<u8>>>) -> Result<()> {
+        let writes: Vec<(Vec<u8>, Option<Vec<u8>>)> = writes
+            .into_iter()
+            .filter(|(key, value)| value.is_some() || self.index.contains_key(key))
+            .collect();
+        if writes.is_empty() {
+            return Ok(());
+        }
+        let mut stored_values = Vec::with_capacity(writes.len());
+        for (_, value) in &writes {
+            let stored_value = match value {
+                Some(value) => Some(self.store_value(value)?),
+                None => None,
+            };
+            stored_values.push(stored_value);
+        }
+
+        let log_id = self.current_log_id;
+        let commands_pos = self.active_log()?.pos + BATCH_HEADER_LEN;
+        let timestamp = Some(now_micros());
+        let mut commands = Vec::new();
+        let mut batched = Vec::with_capacity(writes.len());
+        {
+            let mut batch_writer = BufWriterWithPos::new(Cursor::new(&mut commands))?;
+            let stored_writes = writes.iter().zip(&stored_values);
+            for (seq, ((key, _), stored_value)) in (self.seq + 1..).zip(stored_writes) {
+                // The version of a write is its sequence number, see `next_version`
+                let command_ref = match stored_value {
+                    Some(stored_value) => self.set_command(key, stored_value, seq, timestamp),
+                    None => CommandRef::remove(key),
+                };
+                let offset = batch_writer.pos;
+                serialize_command(&command_ref.with_seq(seq), &mut batch_writer)?;
+                let len = batch_writer.pos - offset;
+                batched.push(match stored_value {
+                    Some(stored_value) => BatchedCommand::Set(stored_value.command_pos(
+                        log_id,
+                        commands_pos + offset,
+                        len,
+                        seq,
+                    )),
+                    None => BatchedCommand::Remove(len),
+                });
+            }
+            batch_writer.flush()?;
+        }
+
+        let writer = self.active_log()?;
+        serialize_batch(&commands, writer)
+            .io_context("write", || self.dir.log_path(self.current_log_id))?;
+        self.seq += writes.len() as u64;
+        // The header of the batch is not copied by the compactions
+        self.uncompacted += BATCH_HEADER_LEN;
+        self.total_bytes += BATCH_HEADER_LEN;
+        self.with_mirror(|mirror| mirror.write_batch(&commands))?;
+        self.persist_write()?;
+
+        if self.verify_writes {
+            for ((key, value), command) in writes.iter().zip(&batched) {
+                if let (Some(value), BatchedCommand::Set(command_pos)) = (value, command) {
+                    self.verify_write(key, value, *command_pos)?;
+                }
+            }
+        }
+        for ((key, value), command) in writes.into_iter().zip(batched) {
+            match (value, command) {
+                (Some(value), BatchedCommand::Set(command_pos)) => {
+                    self.publish_set(key, &value, command_pos)
+                }
+                (_, BatchedCommand::Remove(len)) => self.publish_remove(&key, len),
+                (None, BatchedCommand::Set(_)) => unreachable!("removals are not sets"),
+            }
+        }
+
+        self.compact_if_needed();
+        Ok(())
+    }
+
+    // Reads back the "set" command at `command_pos` and checks that it holds the key and
+    // the value written.
+    fn verify_write(&mut self, key: &[u8], value: &[u8], command_pos: CommandPos) -> Result<()> {
+        self.flush()?;
+        match self.reader.read_command(command_pos)? {
+            CommandOwned::Set {
+                key: read_key,
+                value: read_value,
+                version,
+                ..
+            } if read_key == key && read_value == value && version == command_pos.version => Ok(()),
+            _ => Err(GrausError::WriteVerificationFailed {
+                log_id: command_pos.log_id,
+                pos: command_pos.pos,
+            }),
         }
+    }
+
+    /// Appends `chunk` to the value of a key, or sets it if the key does not exist.
+    ///
+    /// Only the chunk is written, along with the position of the previous command of the
+    /// key, so the value is folded when it is read. Compactions store it as a single value.
+    pub fn append(&mut self, key: Vec<u8>, chunk: &[u8]) -> Result<()> {
+        self.check_stalled()?;
+        let Some(old_cmd) = self.index.get(&key) else {
+            return self.set(key, chunk);
+        };
+        if self.compacting.is_some() && old_cmd.log_id < self.current_log_id {
+            // The previous command may be deleted by the running compaction, so the
+            // appended value can't point to it
+            self.flush()?;
+            let CommandOwned::Set { mut value, .. } = self.reader.read_command(old_cmd)? else {
+                return Err(GrausError::UnexpectedCommandType);
+            };
+            value.extend_from_slice(chunk);
+            return self.set(key, &value);
+        }
+        let version = self.next_version();
+        let (nonce, stored_chunk) = self.reader.seal_value(chunk);
+        let command_ref =
+            CommandRef::append(&key, &stored_chunk, version, Some(now_micros()), old_cmd)
+                .with_nonce(nonce)
+                .with_checksum(self.value_checksums);
+        let (pos, len) = self.write_command(command_ref)?;
+
+        // Folding the chunk in the next compaction only saves the rest of the command
+        self.uncompacted += len - stored_chunk.len() as u64;
+        self.total_bytes += len;
         let command_pos = CommandPos {
             log_id: self.current_log_id,
             pos,
-            len: self.writer.pos - pos,
+            len,
+            value_len: stored_chunk.len() as u64,
+            appended_len: old_cmd.total_value_len(),
+            version,
+            value_log_id: old_cmd.value_log_id,
         };
+        if !self.secondary_indexes.is_empty() {
+            // The whole value is needed to update the indexes
+            self.flush()?;
+            if let CommandOwned::Set { value, .. } = self.reader.read_command(command_pos)? {
+                self.secondary_indexes.on_set(&key, &value);
+            }
+        }
+        self.value_cache.remove(&key);
         self.index.insert(key, command_pos);
 
-        if self.uncompacted > COMPACTION_THRESHOLD {
-            self.compact()?;
-        }
+        self.compact_if_needed();
         Ok(())
     }
 
@@ -63,78 +371,757 @@ impl LogWriter {
             return Err(GrausError::KeyNotFound);
         }
 
+        self.write_remove(key)?;
+
+        self.compact_if_needed();
+
+        Ok(())
+    }
+
+    /// Removes every existing key, returning for each one whether it existed.
+    ///
+    /// Compaction is only checked once, after all keys have been removed.
+    pub fn remove_many(&mut self, keys: Vec<Vec<u8>>) -> Result<Vec<bool>> {
+        let mut removed = Vec::with_capacity(keys.len());
+        for key in keys {
+            let exists = self.index.contains_key(&key);
+            if exists {
+                self.write_remove(&key)?;
+            }
+            removed.push(exists);
+        }
+
+        self.compact_if_needed();
+
+        Ok(removed)
+    }
+
+    pub fn remove_prefix(&mut self, prefix: &[u8]) -> Result<u64> {
+        let keys: Vec<Vec<u8>> = self.index.prefix_iter(prefix).map(|(key, _)| key).collect();
+        for key in &keys {
+            self.write_remove(key)?;
+        }
+
+        self.compact_if_needed();
+
+        Ok(keys.len() as u64)
+    }
+
+    // Writes the "remove" command of an existing key and removes it from the index.
+    fn write_remove(&mut self, key: &[u8]) -> Result<()> {
         let command_ref = CommandRef::remove(key);
-        let pos = self.writer.pos;
+        let (_, len) = self.write_command(command_ref)?;
+        self.publish_remove(key, len);
+        Ok(())
+    }
+
+    // Removes an existing key from the index once its "remove" command of length `len` is
+    // written.
+    fn publish_remove(&mut self, key: &[u8], len: u64) {
+        self.secondary_indexes.on_remove(key);
+        let old_cmd = self.index.remove(key).expect("key not found");
+        self.value_cache.remove(key);
+        self.uncompacted += old_cmd.stale_len();
+        // the "remove" command itself can be deleted in the next compaction
+        // so we add its length to `uncompacted`
+        self.uncompacted += len;
+        self.total_bytes += len;
+    }
 
-        serialize_command(&command_ref, &mut self.writer)?;
+    // Writes a command into the active log and the mirror with the next sequence number.
+    // Returns its position and length.
+    fn write_command(&mut self, command_ref: CommandRef<'_>) -> Result<(u64, u64)> {
+        let command_ref = command_ref.with_seq(self.seq + 1);
+        let writer = self.active_log()?;
+        let pos = writer.pos;
+        serialize_command(&command_ref, writer)
+            .io_context("write", || self.dir.log_path(self.current_log_id))?;
+        self.seq += 1;
+        let len = self.written_pos().pos - pos;
+        self.with_mirror(|mirror| mirror.write_command(&command_ref))?;
+        self.persist_write()?;
+        Ok((pos, len))
+    }
 
-        {
-            let old_cmd = self.index.remove(key).expect("key not found");
-            self.uncompacted += old_cmd.value().len;
-            // the "remove" command itself can be deleted in the next compaction
-            // so we add its length to `uncompacted`
-            self.uncompacted += self.writer.pos - pos;
+    // Syncs or flushes a write, as configured, before the index is updated.
+    fn persist_write(&mut self) -> Result<()> {
+        if self.sync_before_visible {
+            // The callers only update the index after this returns
+            self.sync()
+        } else if self.flush_each_write {
+            self.flush()
+        } else {
+            Ok(())
+        }
+    }
+
+    // Appends a value to the active value log and flushes it right away, so the command
+    // pointing to it never reaches the file system before the value.
+    fn write_value(&mut self, value: &[u8]) -> Result<ValueRef> {
+        let value_dir = self.reader.value_dir()?.clone();
+        let log_id = self.current_log_id;
+        let value_writer = match self.value_writer.take() {
+            Some(value_writer) => value_writer,
+            None => {
+                fs::create_dir_all(&value_dir.root)
+                    .io_context("create directory", || value_dir.root.clone())?;
+                new_log_file(&value_dir, log_id)?
+            }
+        };
+        let value_writer = self.value_writer.insert(value_writer);
+        let pos = value_writer.pos;
+        value_writer
+            .write_all(value)
+            .and_then(|()| value_writer.flush())
+            .io_context("write", || value_dir.log_path(log_id))?;
+        Ok(ValueRef {
+            log_id,
+            pos,
+            len: value.len() as u64,
+        })
+    }
+
+    // Returns the active log, creating it if this is the first write since it was skipped
+    // on open.
+    fn active_log(&mut self) -> Result<&mut BufWriterWithPos<LogFile>> {
+        let writer = match self.writer.take() {
+            Some(writer) => writer,
+            None => {
+                self.num_logs += 1;
+                new_log_file(&self.dir, self.current_log_id)?
+            }
+        };
+        Ok(self.writer.insert(writer))
+    }
+
+    // Stalled writes compact the logs whatever the strategy decides, as long as a
+    // compaction can reclaim something.
+    fn should_compact(&self) -> bool {
+        self.compaction_strategy
+            .should_compact(self.uncompacted, self.total_bytes, self.num_logs)
+            || (self.exceeds_high_water_mark() && self.uncompacted > 0)
+    }
+
+    fn exceeds_high_water_mark(&self) -> bool {
+        self.max_uncompacted
+            .is_some_and(|max_uncompacted| self.uncompacted > max_uncompacted)
+            || self
+                .max_total_bytes
+                .is_some_and(|max_total_bytes| self.total_bytes > max_total_bytes)
+    }
+
+    /// Returns whether writes are stalled because the logs exceed a high-water mark.
+    pub fn is_stalled(&self) -> bool {
+        self.exceeds_high_water_mark()
+    }
+
+    /// Schedules a compaction to catch up with the high-water marks if writes are stalled.
+    pub fn compact_if_stalled(&mut self) {
+        if self.is_stalled() {
+            self.compact_if_needed();
         }
+    }
 
-        if self.uncompacted >= COMPACTION_THRESHOLD {
-            self.compact()?;
+    /// Fails with `GrausError::WriteStalled` if writes are stalled, scheduling a compaction
+    /// to catch up with the high-water marks.
+    pub fn check_stalled(&mut self) -> Result<()> {
+        self.compact_if_stalled();
+        if self.is_stalled() {
+            return Err(GrausError::WriteStalled);
         }
+        Ok(())
+    }
 
+    // Schedules a compaction if the compaction strategy decides so, once until it runs.
+    // With an executor the compaction runs in the background, otherwise `GrausDb` runs it
+    // after releasing the writer lock.
+    fn compact_if_needed(&mut self) {
+        if self.compaction_scheduled || self.compacting.is_some() || !self.should_compact() {
+            return;
+        }
+        self.compaction_scheduled = true;
+        let Some(executor) = &self.executor else {
+            return;
+        };
+        let this = Weak::clone(&self.this);
+        executor.execute(Box::new(move || {
+            // The database may have been dropped meanwhile
+            let Some(writer) = this.upgrade() else {
+                return;
+            };
+            if let Err(e) = compact(&writer) {
+                error!("Background compaction failed: {}", e);
+            }
+        }));
+    }
+
+    /// Returns whether a compaction was scheduled and must be run by the caller, as there
+    /// is no executor to run it.
+    pub fn compaction_pending(&self) -> bool {
+        self.compaction_scheduled && self.executor.is_none()
+    }
+
+    // Runs an operation on the mirror, if any, and handles its failure.
+    fn with_mirror(&mut self, op: impl FnOnce(&mut Mirror) -> Result<()>) -> Result<()> {
+        let Some(mirror) = &mut self.mirror else {
+            return Ok(());
+        };
+        match op(mirror) {
+            Ok(()) => Ok(()),
+            Err(e) => self.mirror_failed(e),
+        }
+    }
+
+    /// Handles a failure of the mirror: it is returned in `MirrorMode::Required`,
+    /// otherwise it is logged and the mirror is dropped.
+    pub fn mirror_failed(&mut self, e: GrausError) -> Result<()> {
+        let Some(mirror) = &self.mirror else {
+            return Ok(());
+        };
+        if mirror.mode == MirrorMode::Required {
+            return Err(e);
+        }
+        error!(
+            "Mirror {:?} is disabled after failing: {}",
+            mirror.dir.root, e
+        );
+        self.mirror = None;
+        Ok(())
+    }
+
+    /// Flushes the buffered commands of the active log, so readers can see them.
+    pub fn flush(&mut self) -> Result<()> {
+        if let Some(writer) = &mut self.writer {
+            writer
+                .flush()
+                .io_context("flush", || self.dir.log_path(self.current_log_id))?;
+        }
+        self.with_mirror(Mirror::flush)?;
+        self.reader.flushed.store(self.written_pos());
+        Ok(())
+    }
+
+    /// Flushes and fsyncs the active log, and the active value log before it.
+    pub fn sync(&mut self) -> Result<()> {
+        self.flush()?;
+        self.sync_values()?;
+        if let Some(writer) = &mut self.writer {
+            writer
+                .sync_all()
+                .io_context("sync", || self.dir.log_path(self.current_log_id))?;
+        }
+        self.with_mirror(Mirror::sync)?;
+        self.group_commit.mark_durable(self.written_pos(), self.seq);
         Ok(())
     }
 
-    fn compact(&mut self) -> Result<()> {
+    // Fsyncs the active value log, whose values are already flushed.
+    fn sync_values(&mut self) -> Result<()> {
+        if let Some(value_writer) = &mut self.value_writer {
+            let value_dir = self.reader.value_dir()?;
+            value_writer
+                .sync_all()
+                .io_context("sync", || value_dir.log_path(self.current_log_id))?;
+        }
+        Ok(())
+    }
+
+    /// Drops the preallocated space after the commands of the active log, so its length
+    /// is the length of the commands.
+    pub fn truncate(&mut self) -> Result<()> {
+        self.flush()?;
+        let Some(writer) = &mut self.writer else {
+            return Ok(());
+        };
+        writer
+            .truncate()
+            .io_context("truncate", || self.dir.log_path(self.current_log_id))
+    }
+
+    /// Replays the logs into a new index, then replaces the entries of the index that
+    /// differ and removes the keys missing from the logs.
+    ///
+    /// The logs made stale by a compaction are skipped, as the removals before it are not
+    /// in the compaction log, along with the log being written by a running compaction.
+    pub fn rebuild_index(&mut self) -> Result<()> {
+        self.flush()?;
+        let index = self.index.empty_like();
+        let mut uncompacted = 0;
+        for log_id in get_log_ids(&self.dir)? {
+            if self.reader.is_stale(log_id) || self.compacting == Some(log_id) {
+                continue;
+            }
+            let log_path = self.dir.log_path(log_id);
+            let file = self
+                .dir
+                .open_log(log_id)
+                .io_context("open", || log_path.clone())?;
+            let mut reader = BufReaderWithPos::with_capacity(self.dir.read_buffer_capacity, file)
+                .io_context("seek", || log_path.clone())?;
+            let loaded_log = load_log(log_id, &mut reader, &index, RecoveryMode::Strict)
+                .io_context("read", || log_path)?;
+            uncompacted += loaded_log.uncompacted;
+        }
+
+        let removed: Vec<Vec<u8>> = self
+            .index
+            .iter()
+            .filter(|(key, _)| !index.contains_key(key))
+            .map(|(key, _)| key)
+            .collect();
+        for key in &removed {
+            self.index.remove(key);
+            self.secondary_indexes.on_remove(key);
+            self.value_cache.remove(key);
+        }
+        for (key, cmd_pos) in index.iter() {
+            if self.index.get(&key) == Some(cmd_pos) {
+                continue;
+            }
+            self.index.insert(key.clone(), cmd_pos);
+            self.value_cache.remove(&key);
+            if !self.secondary_indexes.is_empty() {
+                if let CommandOwned::Set { value, .. } = self.reader.read_command(cmd_pos)? {
+                    self.secondary_indexes.on_set(&key, &value);
+                }
+            }
+        }
+        self.uncompacted = uncompacted;
+        Ok(())
+    }
+
+    /// Returns the position of the end of the last command written.
+    pub fn written_pos(&self) -> FlushedPos {
+        FlushedPos {
+            log_id: self.current_log_id,
+            pos: self.writer.as_ref().map_or(0, |writer| writer.pos),
+        }
+    }
+
+    // Starts the scheduled compaction: the active log is replaced by a new one, so the
+    // commands to copy are not written anymore, and the index is snapshotted.
+    fn start_compaction(&mut self) -> Result<Option<Compaction>> {
+        if !self.compaction_scheduled {
+            return Ok(None);
+        }
+        self.compaction_scheduled = false;
+        if !self.should_compact() {
+            return Ok(None);
+        }
+        // Commands in the active log must be readable to be copied into the compacted log
+        self.flush()?;
+        // The values that are not moved by the compaction must be as durable as their copied
+        // commands. The new active log gets a new value log.
+        self.sync_values()?;
+        self.value_writer = None;
+
         let compaction_log_id = self.current_log_id + 1;
         self.current_log_id += 2; // Increase current log by 2, as current_log+1 will be used for the compacted file.
-        self.writer = new_log_file(&self.path, self.current_log_id)?;
+        self.writer = Some(new_log_file(&self.dir, self.current_log_id)?);
+        let current_log_id = self.current_log_id;
+        self.with_mirror(|mirror| mirror.rotate(current_log_id))?;
+        self.write_seq_mark()?;
+        self.flush()?;
+        self.compacting = Some(compaction_log_id);
 
-        let mut compaction_writer = new_log_file(&self.path, compaction_log_id)?;
+        Ok(Some(Compaction {
+            log_id: compaction_log_id,
+            entries: self.index.iter().collect(),
+            reader: self.reader.clone(),
+            dir: Arc::clone(&self.dir),
+            uncompacted: self.uncompacted,
+            total_bytes: self.total_bytes,
+            seq: self.seq,
+            value_checksums: self.value_checksums,
+            value_log_threshold: self.value_log_threshold,
+            relocate_values: false,
+            value_transform: self.value_transform.clone(),
+        }))
+    }
 
-        let mut index_with_updated_positions: HashMap<Vec<u8>, CommandPos> = HashMap::new();
-        // Write compacted entries in compaction log
-        let mut new_pos = 0;
-        for cmd_pos in self.index.iter() {
-            // Removed values are not present in the index so they are not copied into the new log
-            let len = self.reader.read_and(*cmd_pos.value(), |mut cmd_reader| {
-                Ok(io::copy(&mut cmd_reader, &mut compaction_writer)?)
-            })?;
-            index_with_updated_positions.insert(
-                cmd_pos.key().clone(),
-                (compaction_log_id, new_pos..new_pos + len).into(),
-            );
-            new_pos += len;
+    // Writes the sequence number of the last write at the start of the new active log and
+    // syncs it, so it is kept once the compaction deletes the logs, even if the commands
+    // with the highest sequence numbers are not copied.
+    fn write_seq_mark(&mut self) -> Result<()> {
+        if self.seq == 0 {
+            return Ok(());
         }
-        compaction_writer.flush()?;
+        let seq = self.seq;
+        let writer = self.active_log()?;
+        let pos = writer.pos;
+        serialize_seq_mark(seq, writer)
+            .and_then(|()| Ok(writer.sync_all()?))
+            .io_context("write", || self.dir.log_path(self.current_log_id))?;
+        // The next compaction replaces it, so it is never reclaimed
+        self.total_bytes += self.written_pos().pos - pos;
+        self.with_mirror(|mirror| {
+            mirror.write_seq_mark(seq)?;
+            mirror.sync()
+        })
+    }
 
+    // Installs the copied commands in the index, unless their keys were written during the
+    // compaction, and deletes the compacted logs.
+    fn finish_compaction(&mut self, compaction: Compaction, copied: CopiedLog) -> Result<()> {
+        self.compacting = None;
+        let compaction_log_id = compaction.log_id;
+        let dir = Arc::clone(&self.dir);
+        self.with_mirror(|mirror| mirror.compacted(&dir, compaction_log_id))?;
+
+        // Only the stale bytes written during the compaction remain
+        let mut uncompacted = self.uncompacted - compaction.uncompacted;
         // Now that all data is written into the new compacted log, we can update the lock-free index
-        for (key, value) in index_with_updated_positions.iter() {
-            self.index.insert(key.clone(), value.clone());
+        for ((key, old_pos), new_pos) in compaction.entries.into_iter().zip(copied.positions) {
+            if self.index.get(&key) == Some(old_pos) {
+                if compaction.value_transform.is_some() && !self.secondary_indexes.is_empty() {
+                    // The secondary indexes are derived from the transformed value
+                    if let CommandOwned::Set { value, .. } = self.reader.read_command(new_pos)? {
+                        self.secondary_indexes.on_set(&key, &value);
+                    }
+                }
+                self.value_cache.moved(&key, old_pos, new_pos);
+                self.index.insert(key, new_pos);
+            } else {
+                // Overwriting or removing the key counted the old command as stale, but
+                // the copy is what remains after the compaction
+                uncompacted = uncompacted - old_pos.stale_len() + new_pos.stale_len();
+            }
         }
 
         self.reader
             .safe_point
             .store(compaction_log_id, Ordering::SeqCst);
         self.reader.close_stale_readers();
+        // Everything written before the new active log is now in the synced compaction log
+        self.group_commit.mark_durable(
+            FlushedPos {
+                log_id: compaction_log_id + 1,
+                pos: 0,
+            },
+            compaction.seq,
+        );
 
-        // remove stale log files
-        // Note that actually these files are not deleted immediately because `LogReader`s
-        // still keep open file handles. When `LogReader` is used next time, it will clear
-        // its stale file handles. On Unix, the files will be deleted after all the handles
-        // are closed. On Windows, the deletions below will fail and stale files are expected
-        // to be deleted in the next compaction.
-        let log_ids_to_remove: Vec<u64> = get_log_ids(&self.path)?
+        // The value logs older than the compaction are stale unless it kept values in them
+        let stale_value_logs = get_value_log_ids(&self.dir)?
             .into_iter()
-            .filter(|&log_id| log_id < compaction_log_id)
+            .filter(|log_id| {
+                *log_id < compaction_log_id && !copied.kept_value_logs.contains(log_id)
+            })
             .collect();
+        // Live snapshots may still read the stale logs, so they may be deleted later
+        self.snapshot_pins
+            .remove_stale_logs(&self.dir, compaction_log_id, stale_value_logs)?;
+        self.uncompacted = uncompacted;
+        // The compacted log, if any, the value logs kept and the active log
+        self.total_bytes =
+            copied.len + copied.kept_value_len + (self.total_bytes - compaction.total_bytes);
+        self.num_logs = 1 + (copied.len > 0) as usize;
+        self.compaction_stats.record();
+
+        Ok(())
+    }
+}
+
+/// Runs the scheduled compaction of the writer, if it is still needed.
+///
+/// The writer is only locked to start and finish the compaction. Meanwhile, the commands
+/// are copied into the compaction log and writes proceed in a new active log. The newest
+/// write always wins: keys written during the compaction keep their new command instead of
+/// the copy, so no update is lost.
+pub fn compact(writer: &Mutex<LogWriter>) -> Result<()> {
+    let Some(compaction) = writer.lock().unwrap().start_compaction()? else {
+        return Ok(());
+    };
+    match compaction.copy() {
+        Ok(copied) => writer.lock().unwrap().finish_compaction(compaction, copied),
+        Err(e) => {
+            // The compacted logs are kept, the next compaction will copy them again
+            let mut writer = writer.lock().unwrap();
+            writer.compacting = None;
+            Err(e)
+        }
+    }
+}
+
+/// Copies the live `entries`, read through `reader`, into a new sealed log `log_id` of
+/// `dir`, the same way a compaction does. Values at least `value_log_threshold` long are
+/// copied into the value log `log_id` of `dir`. Returns the length of the logs, 0 if there
+/// was nothing to copy.
+pub fn copy_entries(
+    entries: Vec<(Vec<u8>, CommandPos)>,
+    reader: LogReader,
+    dir: Arc<LogDir>,
+    log_id: u64,
+    value_checksums: bool,
+    value_log_threshold: Option<u64>,
+) -> Result<u64> {
+    let compaction = Compaction {
+        log_id,
+        entries,
+        reader,
+        dir,
+        uncompacted: 0,
+        total_bytes: 0,
+        seq: 0,
+        value_checksums,
+        value_log_threshold,
+        // The value logs of the entries are not in `dir`
+        relocate_values: true,
+        value_transform: None,
+    };
+    Ok(compaction.copy()?.len)
+}
+
+// A compaction copying the live commands into a new log, without the writer lock.
+struct Compaction {
+    log_id: u64,
+    // Snapshot of the index when the compaction started.
+    entries: Vec<(Vec<u8>, CommandPos)>,
+    reader: LogReader,
+    dir: Arc<LogDir>,
+    // Counters of the writer when the compaction started.
+    uncompacted: u64,
+    total_bytes: u64,
+    seq: u64,
+    // Whether folded values are written with a checksum.
+    value_checksums: bool,
+    // Rewritten values at least this long are written into the value log of the compaction.
+    value_log_threshold: Option<u64>,
+    // Whether every value in a value log is rewritten, instead of only the ones in value
+    // logs that are mostly stale.
+    relocate_values: bool,
+    // Applied to every copied value, which are all rewritten, if set.
+    value_transform: Option<ValueTransform>,
+}
+
+// Commands copied by a compaction.
+struct CopiedLog {
+    // New position of every entry of the snapshot, in the same order.
+    positions: Vec<CommandPos>,
+    // Length of the compaction log and its value log, 0 if they were not created.
+    len: u64,
+    // Older value logs that still hold values of the copied commands, and their length.
+    kept_value_logs: BTreeSet<u64>,
+    kept_value_len: u64,
+}
+
+impl Compaction {
+    // Copies the commands of the snapshot into the compaction log and syncs it.
+    //
+    // Values in value logs are only rewritten if their value log is mostly stale, so it can
+    // be deleted. Otherwise the copied commands keep pointing to them.
+    fn copy(&self) -> Result<CopiedLog> {
+        let compaction_log_id = self.log_id;
+        let (relocated, kept_value_logs, kept_value_len) = self.value_logs_to_relocate()?;
+        // The compaction logs are only created if there is something to copy into them
+        let mut compaction_writer = None;
+        let mut value_writer = None;
+        // Logs in a storage keep the values in their commands
+        let value_dir = self.dir.value_dir();
+        let value_log_threshold = self.value_log_threshold.filter(|_| value_dir.is_some());
+        let mut checksum = Crc32::new();
 
-        for log_id_to_remove in log_ids_to_remove {
-            let log_path = log_path(&self.path, log_id_to_remove);
-            if let Err(e) = fs::remove_file(&log_path) {
-                error!("{:?} cannot be deleted: {}", log_path, e);
+        let mut positions = Vec::with_capacity(self.entries.len());
+        // Write compacted entries in compaction log
+        let mut new_pos = 0;
+        for &(_, cmd_pos) in &self.entries {
+            // Removed values are not present in the index so they are not copied into the new log
+            let compaction_writer = match &mut compaction_writer {
+                Some(compaction_writer) => compaction_writer,
+                None => compaction_writer.insert(new_log_file(&self.dir, compaction_log_id)?),
+            };
+            let relocate = cmd_pos.is_separated()
+                && cmd_pos
+                    .value_log_id
+                    .is_some_and(|log_id| relocated.contains(&log_id));
+            let (command, value_len, value_log_id) = if cmd_pos.appended_len > 0
+                || relocate
+                || self.value_transform.is_some()
+            {
+                // Appended chunks are folded into a single "set" command, and the values of
+                // the relocated value logs and the transformed values are rewritten
+                let CommandOwned::Set {
+                    key,
+                    mut value,
+                    version,
+                    timestamp,
+                    seq,
+                    ..
+                } = self.reader.read_command(cmd_pos)?
+                else {
+                    return Err(GrausError::UnexpectedCommandType);
+                };
+                if let Some(value_transform) = &self.value_transform {
+                    value = value_transform(&value);
+                }
+                // The rewritten value is encrypted again, as a whole
+                let (nonce, stored_value) = self.reader.seal_value(&value);
+                let value_ref = match (value_log_threshold, &value_dir) {
+                    (Some(threshold), Some(value_dir))
+                        if stored_value.len() as u64 >= threshold =>
+                    {
+                        let value_writer = match &mut value_writer {
+                            Some(value_writer) => value_writer,
+                            None => {
+                                fs::create_dir_all(&value_dir.root)
+                                    .io_context("create directory", || value_dir.root.clone())?;
+                                value_writer.insert(new_log_file(value_dir, compaction_log_id)?)
+                            }
+                        };
+                        let pos = value_writer.pos;
+                        value_writer
+                            .write_all(&stored_value)
+                            .io_context("write", || value_dir.log_path(compaction_log_id))?;
+                        Some(ValueRef {
+                            log_id: compaction_log_id,
+                            pos,
+                            len: stored_value.len() as u64,
+                        })
+                    }
+                    _ => None,
+                };
+                let command_ref = match value_ref {
+                    Some(value_ref) => {
+                        let checksum = self.value_checksums.then(|| crc_of(&stored_value));
+                        CommandRef::set_ref(&key, value_ref, version, timestamp, checksum)
+                    }
+                    None => CommandRef::set(&key, &stored_value, version, timestamp)
+                        .with_checksum(self.value_checksums),
+                }
+                .with_nonce(nonce)
+                .with_seq(seq);
+                let mut command = Vec::new();
+                let mut command_writer = BufWriterWithPos::new(Cursor::new(&mut command))?;
+                serialize_command(&command_ref, &mut command_writer)?;
+                command_writer.flush()?;
+                drop(command_writer);
+                let value_log_id = value_ref.map(|value_ref| value_ref.log_id);
+                (command, stored_value.len() as u64, value_log_id)
+            } else {
+                let command = self.reader.read_and(cmd_pos, |cmd_reader| {
+                    // Only copy this command, not the rest of the log
+                    let mut command = vec![0; cmd_pos.len as usize];
+                    cmd_reader.read_exact(&mut command)?;
+                    Ok(command)
+                })?;
+                (command, cmd_pos.value_len, cmd_pos.value_log_id)
+            };
+            compaction_writer
+                .write_all(&command)
+                .io_context("write", || self.dir.log_path(compaction_log_id))?;
+            checksum.update(&command);
+            let len = command.len() as u64;
+            positions.push(CommandPos {
+                log_id: compaction_log_id,
+                pos: new_pos,
+                len,
+                value_len,
+                appended_len: 0,
+                value_log_id,
+                ..cmd_pos
+            });
+            new_pos += len;
+        }
+        // The commands of the compaction log point to its value log, so it is synced first
+        if let (Some(value_writer), Some(value_dir)) = (&mut value_writer, &value_dir) {
+            value_writer
+                .sync_all()
+                .io_context("sync", || value_dir.log_path(compaction_log_id))?;
+        }
+        // The compaction log will not receive more commands, so it is sealed
+        if let Some(compaction_writer) = &mut compaction_writer {
+            let footer = LogFooter {
+                commands: positions.len() as u64,
+                checksum: checksum.finalize(),
+            };
+            serialize_footer(&footer, compaction_writer)
+                .io_context("write", || self.dir.log_path(compaction_log_id))?;
+            compaction_writer
+                .truncate()
+                .io_context("truncate", || self.dir.log_path(compaction_log_id))?;
+            // The compaction log must be durable before the old logs are deleted
+            compaction_writer
+                .sync_all()
+                .io_context("sync", || self.dir.log_path(compaction_log_id))?;
+        }
+
+        let len = [compaction_writer, value_writer]
+            .into_iter()
+            .flatten()
+            .map(|writer| writer.pos)
+            .sum();
+        Ok(CopiedLog {
+            positions,
+            len,
+            kept_value_logs,
+            kept_value_len,
+        })
+    }
+
+    // Returns the value logs of the entries whose values are rewritten, because less than
+    // half of their bytes are live, and the ones that are kept, along with their length.
+    fn value_logs_to_relocate(&self) -> Result<(BTreeSet<u64>, BTreeSet<u64>, u64)> {
+        let mut live_lens: HashMap<u64, u64> = HashMap::new();
+        for (_, cmd_pos) in &self.entries {
+            match cmd_pos.value_log_id {
+                Some(log_id) if cmd_pos.is_separated() => {
+                    *live_lens.entry(log_id).or_insert(0) += cmd_pos.value_len
+                }
+                _ => {}
+            }
+        }
+        let mut relocated = BTreeSet::new();
+        let mut kept = BTreeSet::new();
+        let mut kept_len = 0;
+        for (log_id, live_len) in live_lens {
+            let value_dir = self.reader.value_dir()?;
+            let len = value_dir
+                .log_len(log_id)
+                .io_context("read metadata of", || value_dir.log_path(log_id))?;
+            if self.relocate_values || self.value_transform.is_some() || live_len * 2 < len {
+                relocated.insert(log_id);
+            } else {
+                kept.insert(log_id);
+                kept_len += len;
             }
         }
-        self.uncompacted = 0;
+        Ok((relocated, kept, kept_len))
+    }
+}
 
-        Ok(())
+// A value ready to be written in a "set" command: sealed if there is a cipher, and already
+// written into the value log if it is long enough.
+struct StoredValue<'a> {
+    nonce: Option<Nonce>,
+    bytes: Cow<'a, [u8]>,
+    value_ref: Option<ValueRef>,
+}
+
+impl StoredValue<'_> {
+    // Returns the index entry of the "set" command of the value written at `pos`.
+    fn command_pos(&self, log_id: u64, pos: u64, len: u64, version: u64) -> CommandPos {
+        CommandPos {
+            log_id,
+            pos,
+            len,
+            value_len: self.bytes.len() as u64,
+            appended_len: 0,
+            version,
+            value_log_id: self.value_ref.map(|value_ref| value_ref.log_id),
+        }
     }
 }
+
+// A command of a batch, to publish in the index once the whole batch is written.
+enum BatchedCommand {
+    Set(CommandPos),
+    // Length of the "remove" command.
+    Remove(u64),
+}
+
+// Returns the current wall clock time in microseconds since the Unix epoch.
+fn now_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_micros() as u64)
+}
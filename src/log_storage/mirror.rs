@@ -0,0 +1,163 @@
+use super::db_command_serde::{serialize_batch, serialize_command, serialize_seq_mark};
+use super::log_helpers::{
+    get_log_ids, new_log_file, relocate_logs, remove_dir_if_empty, remove_empty_logs, LogDir,
+};
+use crate::db_command::CommandRef;
+use crate::error::IoContext;
+use crate::io_types::{BufWriterWithPos, LogFile};
+use crate::{MirrorMode, Result};
+use std::fs;
+use std::io::{self, Write};
+
+/// Copy of the logs in a second directory, written along with the primary logs.
+///
+/// The logs of the mirror have the same ids and content as the primary ones, so the
+/// primary directory can be restored from it.
+pub struct Mirror {
+    pub dir: LogDir,
+    pub writer: BufWriterWithPos<LogFile>,
+    pub current_log_id: u64,
+    pub mode: MirrorMode,
+}
+
+impl Mirror {
+    /// Opens the mirror of the primary logs `log_ids`, copying the logs that differ from
+    /// the primary ones and removing the rest, and creates the active log `active_log_id`.
+    pub fn open(
+        dir: LogDir,
+        primary: &LogDir,
+        log_ids: &[u64],
+        active_log_id: u64,
+        mode: MirrorMode,
+    ) -> Result<Mirror> {
+        fs::create_dir_all(&dir.root).io_context("create directory", || dir.root.clone())?;
+        relocate_logs(&dir)?;
+        for log_id in get_log_ids(&dir)? {
+            if !log_ids.contains(&log_id) {
+                remove_log(&dir, log_id)?;
+            }
+        }
+        for &log_id in log_ids {
+            let primary_len = primary
+                .log_len(log_id)
+                .io_context("read metadata of", || primary.log_path(log_id))?;
+            // Logs are only appended, so logs of the same length are equal
+            if dir.log_len(log_id).ok() != Some(primary_len) {
+                copy_log(primary, &dir, log_id)?;
+            }
+        }
+        let writer = new_log_file(&dir, active_log_id)?;
+        Ok(Mirror {
+            dir,
+            writer,
+            current_log_id: active_log_id,
+            mode,
+        })
+    }
+
+    /// Writes a command into the active log of the mirror.
+    pub fn write_command(&mut self, command: &CommandRef) -> Result<()> {
+        serialize_command(command, &mut self.writer)
+            .io_context("write", || self.dir.log_path(self.current_log_id))
+    }
+
+    /// Writes a batch of serialized commands into the active log of the mirror.
+    pub fn write_batch(&mut self, commands: &[u8]) -> Result<()> {
+        serialize_batch(commands, &mut self.writer)
+            .io_context("write", || self.dir.log_path(self.current_log_id))
+    }
+
+    /// Writes a sequence mark into the active log of the mirror.
+    pub fn write_seq_mark(&mut self, seq: u64) -> Result<()> {
+        serialize_seq_mark(seq, &mut self.writer)
+            .io_context("write", || self.dir.log_path(self.current_log_id))
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer
+            .flush()
+            .io_context("flush", || self.dir.log_path(self.current_log_id))
+    }
+
+    pub fn sync(&mut self) -> Result<()> {
+        self.flush()?;
+        self.writer
+            .sync_all()
+            .io_context("sync", || self.dir.log_path(self.current_log_id))
+    }
+
+    /// Replaces the active log of the mirror by a new one.
+    pub fn rotate(&mut self, log_id: u64) -> Result<()> {
+        self.flush()?;
+        self.writer = new_log_file(&self.dir, log_id)?;
+        self.current_log_id = log_id;
+        Ok(())
+    }
+
+    /// Copies a compaction log into the mirror and removes the logs it replaces.
+    pub fn compacted(&mut self, primary: &LogDir, compaction_log_id: u64) -> Result<()> {
+        if primary.log_len(compaction_log_id).is_ok() {
+            copy_log(primary, &self.dir, compaction_log_id)?;
+        }
+        for log_id in get_log_ids(&self.dir)? {
+            if log_id < compaction_log_id {
+                remove_log(&self.dir, log_id)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Replaces the logs of the primary directory by the ones of the mirror, and returns
+/// whether the mirror had any log.
+pub fn restore_from_mirror(primary: &LogDir, mirror: &LogDir) -> Result<bool> {
+    if !mirror.root.exists() {
+        return Ok(false);
+    }
+    relocate_logs(mirror)?;
+    let log_ids = remove_empty_logs(mirror)?;
+    if log_ids.is_empty() {
+        return Ok(false);
+    }
+    for log_id in get_log_ids(primary)? {
+        remove_log(primary, log_id)?;
+    }
+    for log_id in log_ids {
+        copy_log(mirror, primary, log_id)?;
+    }
+    Ok(true)
+}
+
+// Copies the log `log_id` from one directory to another, replacing it if it exists.
+fn copy_log(from: &LogDir, to: &LogDir, log_id: u64) -> Result<()> {
+    let from_path = from.log_path(log_id);
+    let mut reader = from
+        .open_log(log_id)
+        .io_context("open", || from_path.clone())?;
+    let to_path = to.log_path(log_id);
+    match to.remove_log(log_id) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => {
+            return Err(e).io_context("remove", || to_path);
+        }
+        _ => {}
+    }
+    let mut writer = new_log_file(to, log_id)?;
+    io::copy(&mut reader, &mut writer).io_context("copy", || from_path)?;
+    // The copy is shorter than the log if it was preallocated
+    writer
+        .truncate()
+        .io_context("truncate", || to_path.clone())?;
+    writer.sync_all().io_context("sync", || to_path)?;
+    Ok(())
+}
+
+// Removes the log `log_id` and its subdirectory if it is left empty.
+fn remove_log(dir: &LogDir, log_id: u64) -> Result<()> {
+    let log_path = dir.log_path(log_id);
+    dir.remove_log(log_id)
+        .io_context("remove", || log_path.clone())?;
+    if let (Some(_), Some(parent)) = (dir.logs_per_dir, log_path.parent()) {
+        remove_dir_if_empty(parent);
+    }
+    Ok(())
+}
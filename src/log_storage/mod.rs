@@ -1,4 +1,8 @@
 pub mod db_command_serde;
+pub mod group_commit;
+pub mod index_snapshot;
 pub mod log_helpers;
 pub mod log_reader;
 pub mod log_writer;
+pub mod mirror;
+pub mod periodic_flush;
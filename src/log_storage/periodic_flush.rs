@@ -0,0 +1,54 @@
+use super::group_commit::GroupCommit;
+use super::log_writer::LogWriter;
+use log::error;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Background thread that flushes and fsyncs the active log periodically, so a crash loses
+/// at most the writes of the last interval.
+///
+/// The thread is stopped when it is dropped, along with the last handle to the database.
+pub struct PeriodicFlush {
+    // Dropping the sender wakes up the thread to stop it.
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl PeriodicFlush {
+    /// Starts syncing the logs of `writer` every `interval`.
+    pub fn start(
+        interval: Duration,
+        writer: Weak<Mutex<LogWriter>>,
+        group_commit: Arc<GroupCommit>,
+    ) -> PeriodicFlush {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let thread = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                let Some(writer) = writer.upgrade() else {
+                    return;
+                };
+                // Syncing through the group commit doesn't hold the writer lock during the
+                // fsync, and does nothing if the writes are already durable
+                let written_pos = writer.lock().unwrap().written_pos();
+                if let Err(e) = group_commit.wait_durable(written_pos, &writer) {
+                    error!("Periodic flush failed: {}", e);
+                }
+            }
+        });
+        PeriodicFlush {
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for PeriodicFlush {
+    fn drop(&mut self) {
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use crate::checksum::Crc32;
+use crate::error::IoContext;
+use crate::log_storage::index_snapshot::SliceReader;
+use crate::{GrausError, Result};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Manifest of a database, stored apart from the logs:
+// [magic][format version u64][schema version u64][created at u64]
+// [tag count u64]([key len u64][key][value len u64][value])*[checksum u32]
+// The format version is read before anything else, as newer versions may store the rest
+// differently. The creation time is in microseconds since the Unix epoch, or 0 if it is
+// not set. The checksum covers all the bytes before it.
+const MANIFEST_MAGIC: &[u8; 4] = b"GRSM";
+const MANIFEST_FILE: &str = "manifest";
+
+/// Version of the on-disk format written by this version of GrausDb. Databases written
+/// with older versions are still read, but newer ones are rejected.
+pub(crate) const FORMAT_VERSION: u64 = 2;
+
+// Version of the format of databases without value logs, which is still written to their
+// manifest so older versions of GrausDb keep opening them.
+const BASE_FORMAT_VERSION: u64 = 1;
+
+/// Metadata of a whole database, set by the application to identify and version it.
+///
+/// It is stored in a `manifest` file in the database directory, apart from the logs.
+/// Databases without a manifest have the default metadata.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DbMetadata {
+    /// Version of the schema of the stored keys and values.
+    pub schema_version: u64,
+    /// When the database was created.
+    pub created_at: Option<SystemTime>,
+    /// Tags defined by the application.
+    pub tags: BTreeMap<String, String>,
+}
+
+// Returns the path of the manifest of the database in `root`
+pub fn manifest_path(root: &Path) -> PathBuf {
+    root.join(MANIFEST_FILE)
+}
+
+/// Saves the manifest, writing it to a temporary file first so a crash never leaves a
+/// partial manifest.
+///
+/// It is stamped with the current format version if the database has value logs, and with
+/// the base one otherwise.
+pub fn save_manifest(path: &Path, metadata: &DbMetadata, value_logs: bool) -> Result<()> {
+    let format_version = if value_logs {
+        FORMAT_VERSION
+    } else {
+        BASE_FORMAT_VERSION
+    };
+    let created_at = match metadata.created_at {
+        Some(created_at) => created_at
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_micros() as u64),
+        None => 0,
+    };
+    let mut bytes = MANIFEST_MAGIC.to_vec();
+    bytes.extend_from_slice(&format_version.to_le_bytes());
+    bytes.extend_from_slice(&metadata.schema_version.to_le_bytes());
+    bytes.extend_from_slice(&created_at.to_le_bytes());
+    bytes.extend_from_slice(&(metadata.tags.len() as u64).to_le_bytes());
+    for (key, value) in &metadata.tags {
+        for field in [key, value] {
+            bytes.extend_from_slice(&(field.len() as u64).to_le_bytes());
+            bytes.extend_from_slice(field.as_bytes());
+        }
+    }
+    let mut checksum = Crc32::new();
+    checksum.update(&bytes);
+    bytes.extend_from_slice(&checksum.finalize().to_le_bytes());
+
+    let tmp_path = path.with_extension("tmp");
+    let mut file = File::create(&tmp_path).io_context("create", || tmp_path.clone())?;
+    file.write_all(&bytes)
+        .io_context("write", || tmp_path.clone())?;
+    file.sync_all().io_context("sync", || tmp_path.clone())?;
+    fs::rename(&tmp_path, path).io_context("move", || tmp_path)?;
+    Ok(())
+}
+
+/// Loads the manifest and checks that its format version is supported, returning the
+/// default metadata if there is none.
+///
+/// Databases without a manifest are read with the current format version: versions of
+/// GrausDb that write an incompatible format always write a manifest, so older versions
+/// refuse to open their databases.
+///
+/// Unlike the index snapshot, a corrupted manifest can't be rebuilt from the logs, so it
+/// is an error.
+pub fn load_manifest(path: &Path) -> Result<DbMetadata> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(DbMetadata::default()),
+        Err(e) => return Err(e).io_context("read", || path.to_path_buf()),
+    };
+    let corrupted = || GrausError::SerializationError(format!("Manifest {:?} is corrupted", path));
+    let mut reader = SliceReader(&bytes);
+    if reader.read(MANIFEST_MAGIC.len()) != Some(MANIFEST_MAGIC) {
+        return Err(corrupted());
+    }
+    let format_version = reader.read_u64().ok_or_else(corrupted)?;
+    if format_version > FORMAT_VERSION {
+        return Err(GrausError::UnsupportedFormatVersion {
+            found: format_version,
+            supported: FORMAT_VERSION,
+        });
+    }
+    parse_manifest(&bytes).ok_or_else(corrupted)
+}
+
+// Parses a manifest whose format version is supported. Returns `None` if it is corrupted.
+fn parse_manifest(bytes: &[u8]) -> Option<DbMetadata> {
+    let (content, checksum) = bytes.split_at_checked(bytes.len().checked_sub(4)?)?;
+    let mut crc = Crc32::new();
+    crc.update(content);
+    if crc.finalize() != u32::from_le_bytes(checksum.try_into().ok()?) {
+        return None;
+    }
+
+    let mut reader = SliceReader(content);
+    // The magic and the format version were already checked
+    reader.read(MANIFEST_MAGIC.len() + 8)?;
+    let schema_version = reader.read_u64()?;
+    let created_at = match reader.read_u64()? {
+        0 => None,
+        micros => Some(UNIX_EPOCH + Duration::from_micros(micros)),
+    };
+    let tag_count = reader.read_u64()?;
+    let mut read_string = || {
+        let len = usize::try_from(reader.read_u64()?).ok()?;
+        String::from_utf8(reader.read(len)?.to_vec()).ok()
+    };
+    let tags = (0..tag_count)
+        .map(|_| Some((read_string()?, read_string()?)))
+        .collect::<Option<BTreeMap<_, _>>>()?;
+    Some(DbMetadata {
+        schema_version,
+        created_at,
+        tags,
+    })
+}
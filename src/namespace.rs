@@ -0,0 +1,68 @@
+use crate::{GrausDb, Result};
+
+/// A view of the keys of a `GrausDb` under a namespace, created by [`GrausDb::namespace`].
+///
+/// Keys are stored with a prefix made of the length of the namespace name as 4 big-endian
+/// bytes followed by the name, and the prefix is stripped from the keys returned. As the
+/// length comes first, the prefix of a namespace never starts with the prefix of another
+/// one, so the same key in different namespaces never collides.
+///
+/// ```rust
+/// # use graus_db::{GrausDb, Result};
+/// # fn try_main() -> Result<()> {
+/// use std::env::current_dir;
+///
+/// let store = GrausDb::open(current_dir()?)?;
+/// let users = store.namespace(b"users");
+/// users.set(b"alice".to_vec(), b"admin")?;
+/// assert_eq!(store.namespace(b"groups").get(b"alice")?, None);
+/// # Ok(())
+/// # }
+/// ```
+pub struct Namespace<'a> {
+    db: &'a GrausDb,
+    prefix: Vec<u8>,
+}
+
+impl<'a> Namespace<'a> {
+    pub(crate) fn new(db: &'a GrausDb, name: &[u8]) -> Namespace<'a> {
+        let mut prefix = (name.len() as u32).to_be_bytes().to_vec();
+        prefix.extend_from_slice(name);
+        Namespace { db, prefix }
+    }
+
+    /// Sets the value of a key of the namespace.
+    pub fn set(&self, key: Vec<u8>, value: &[u8]) -> Result<()> {
+        self.db.set(self.prefixed(&key), value)
+    }
+
+    /// Gets the value of a key of the namespace.
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.db.get(&self.prefixed(key))
+    }
+
+    /// Removes a key of the namespace.
+    ///
+    /// Returns GrausError::KeyNotFound if the key does not exist.
+    pub fn remove(&self, key: &[u8]) -> Result<()> {
+        self.db.remove(&self.prefixed(key))
+    }
+
+    /// Returns the entries of the namespace, without the prefix of their keys, in the order
+    /// of [`GrausDb::scan_prefixes`].
+    pub fn scan(&self) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let mut groups = self.db.scan_prefixes(&[&self.prefix])?;
+        let entries = groups.remove(&self.prefix).unwrap_or_default();
+        Ok(entries
+            .into_iter()
+            .map(|(key, value)| (key[self.prefix.len()..].to_vec(), value))
+            .collect())
+    }
+
+    fn prefixed(&self, key: &[u8]) -> Vec<u8> {
+        let mut prefixed = Vec::with_capacity(self.prefix.len() + key.len());
+        prefixed.extend_from_slice(&self.prefix);
+        prefixed.extend_from_slice(key);
+        prefixed
+    }
+}
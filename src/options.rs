@@ -0,0 +1,537 @@
+use crate::cipher::ValueCipher;
+use crate::compaction::{CompactionStrategy, ThresholdStrategy, ValueTransform};
+use crate::executor::Executor;
+use crate::io_types::DEFAULT_BUF_CAPACITY;
+use crate::key_index::{lexicographic, KeyComparator, KeyTransform};
+use crate::storage::Storage;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Options used to configure a `GrausDb` when it is opened.
+///
+/// ```rust
+/// # use graus_db::{GrausDb, GrausDbOptions, Result};
+/// # fn try_main() -> Result<()> {
+/// use std::env::current_dir;
+///
+/// let options = GrausDbOptions::default().key_comparator(|a, b| b.cmp(a));
+/// let store = GrausDb::open_with_options(current_dir()?, options)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct GrausDbOptions {
+    pub(crate) key_comparator: KeyComparator,
+    pub(crate) key_transform: Option<KeyTransform>,
+    pub(crate) flush_each_write: bool,
+    pub(crate) sync_each_write: bool,
+    pub(crate) sync_before_visible: bool,
+    pub(crate) flush_interval: Option<Duration>,
+    pub(crate) compaction_strategy: Arc<dyn CompactionStrategy>,
+    pub(crate) bloom_false_positive_rate: Option<f64>,
+    pub(crate) recovery_mode: RecoveryMode,
+    pub(crate) value_cipher: Option<Arc<dyn ValueCipher>>,
+    pub(crate) max_open_readers: Option<usize>,
+    pub(crate) executor: Option<Arc<dyn Executor>>,
+    pub(crate) logs_per_dir: Option<u64>,
+    pub(crate) index_snapshot: bool,
+    pub(crate) snapshot_verification: SnapshotVerification,
+    pub(crate) mirror_dir: Option<PathBuf>,
+    pub(crate) mirror_mode: MirrorMode,
+    pub(crate) preallocate_len: Option<u64>,
+    pub(crate) max_uncompacted: Option<u64>,
+    pub(crate) max_total_bytes: Option<u64>,
+    pub(crate) value_checksums: bool,
+    pub(crate) read_buffer_capacity: usize,
+    pub(crate) write_buffer_capacity: usize,
+    pub(crate) create_active_log: bool,
+    pub(crate) storage: Option<Arc<dyn Storage>>,
+    pub(crate) value_log_threshold: Option<u64>,
+    pub(crate) record_latencies: bool,
+    pub(crate) verify_writes: bool,
+    pub(crate) compaction_value_transform: Option<ValueTransform>,
+    pub(crate) value_cache_bytes: u64,
+    // Only set by `GrausDb::open_up_to`, which opens the database read-only.
+    pub(crate) max_log_id: Option<u64>,
+}
+
+/// How corrupted records found in the logs are handled when the database is opened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RecoveryMode {
+    /// Fails to open the database on any corrupted record.
+    #[default]
+    Strict,
+    /// Skips corrupted records, advancing to the next valid one, so the readable data can be
+    /// salvaged. The data lost is reported by
+    /// [`GrausDb::recovery_summary`](crate::GrausDb::recovery_summary).
+    Salvage,
+}
+
+/// How many entries of an index snapshot are checked against the logs before using it.
+/// See [`GrausDbOptions::snapshot_verification`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotVerification {
+    /// The snapshot is used if the logs have the same length as when it was saved.
+    Off,
+    /// Up to this number of entries, spread across the index, are checked.
+    Sample(usize),
+    /// Every entry is checked.
+    Full,
+}
+
+impl Default for SnapshotVerification {
+    fn default() -> Self {
+        SnapshotVerification::Sample(64)
+    }
+}
+
+/// How failures to write into the mirror directory are handled. See
+/// [`GrausDbOptions::mirror`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MirrorMode {
+    /// Writes fail if they can't be written into the mirror.
+    #[default]
+    Required,
+    /// Failures are logged and the mirror is not written anymore until the database is
+    /// opened again, which brings it up to date.
+    BestEffort,
+}
+
+impl Default for GrausDbOptions {
+    fn default() -> Self {
+        GrausDbOptions {
+            key_comparator: lexicographic,
+            key_transform: None,
+            flush_each_write: true,
+            sync_each_write: false,
+            sync_before_visible: false,
+            flush_interval: None,
+            compaction_strategy: Arc::new(ThresholdStrategy::default()),
+            bloom_false_positive_rate: None,
+            recovery_mode: RecoveryMode::Strict,
+            value_cipher: None,
+            max_open_readers: None,
+            executor: None,
+            logs_per_dir: None,
+            index_snapshot: false,
+            snapshot_verification: SnapshotVerification::default(),
+            mirror_dir: None,
+            mirror_mode: MirrorMode::Required,
+            preallocate_len: None,
+            max_uncompacted: None,
+            max_total_bytes: None,
+            value_checksums: false,
+            read_buffer_capacity: DEFAULT_BUF_CAPACITY,
+            write_buffer_capacity: DEFAULT_BUF_CAPACITY,
+            create_active_log: true,
+            storage: None,
+            value_log_threshold: None,
+            record_latencies: false,
+            verify_writes: false,
+            compaction_value_transform: None,
+            value_cache_bytes: 0,
+            max_log_id: None,
+        }
+    }
+}
+
+impl GrausDbOptions {
+    /// Sets the comparator used to order keys in the in-memory index.
+    ///
+    /// Keys are ordered lexicographically by default. The comparator only affects
+    /// iteration order, not the on-disk layout, but it must be the same every time the
+    /// database is opened so ordered scans stay stable across restarts.
+    ///
+    /// With the `hash-index` feature, keys are not ordered, so opening the database fails
+    /// with [`GrausError::IncompatibleOptions`](crate::GrausError::IncompatibleOptions) if
+    /// a comparator is set.
+    pub fn key_comparator(mut self, comparator: KeyComparator) -> Self {
+        self.key_comparator = comparator;
+        self
+    }
+
+    /// Sets a function applied to every key before it is stored, e.g. to hash or encrypt
+    /// the keys, so the keys given to `GrausDb` never land in the log files.
+    ///
+    /// The index is keyed by the transformed keys, and every operation that takes a key
+    /// transforms it first. The transformed keys can't be turned back into the original
+    /// ones nor kept in their order, so the operations that return or scan keys fail with
+    /// [`GrausError::IncompatibleOptions`](crate::GrausError::IncompatibleOptions): the
+    /// scans, the prefix operations, `list_keys`, `value_counts` and `lookup_by_index`. The
+    /// diagnostics that return the positions of the keys in the logs, like
+    /// `index_snapshot`, return the keys as they are stored.
+    ///
+    /// The function must be deterministic, map different keys to different ones, and be
+    /// the same every time the database is opened. It is not set by default.
+    pub fn key_transform(mut self, transform: KeyTransform) -> Self {
+        self.key_transform = Some(transform);
+        self
+    }
+
+    /// Sets whether every write is flushed to the file system as soon as it happens.
+    ///
+    /// It is enabled by default. When disabled, writes are kept in the log buffer until it
+    /// is full or [`GrausDb::flush`](crate::GrausDb::flush) is called, which is much faster
+    /// for bulk writes. Values written are always visible to `get`, as reading a value that
+    /// has not been flushed yet flushes the log first.
+    pub fn flush_each_write(mut self, flush_each_write: bool) -> Self {
+        self.flush_each_write = flush_each_write;
+        self
+    }
+
+    /// Sets whether every write waits until it is durable on disk (fsynced) before returning.
+    ///
+    /// It is disabled by default, so writes survive a crash of the process but not of the
+    /// operating system. When enabled, concurrent writes share their fsyncs (group commit):
+    /// a single fsync makes the writes of all the waiting writers durable.
+    pub fn sync_each_write(mut self, sync_each_write: bool) -> Self {
+        self.sync_each_write = sync_each_write;
+        self
+    }
+
+    /// Sets whether every write is fsynced before it becomes visible to readers.
+    ///
+    /// It is disabled by default, so a write is visible as soon as it is in the log buffer
+    /// or flushed, and a key that was read may still be lost if the operating system
+    /// crashes. When enabled, a write is only added to the index once it is durable, so
+    /// any key that can be read survives a crash.
+    ///
+    /// The fsync happens while the writer is locked, so concurrent writes can't share it
+    /// like with `sync_each_write`: every write pays the full latency of an fsync, which
+    /// is usually milliseconds on disks.
+    pub fn sync_before_visible(mut self, enabled: bool) -> Self {
+        self.sync_before_visible = enabled;
+        self
+    }
+
+    /// Flushes and fsyncs the logs every `interval` in a background thread, so a crash of
+    /// the operating system loses at most the writes of the last interval.
+    ///
+    /// It is a middle ground between `sync_each_write` and leaving the writes buffered,
+    /// usually combined with disabling `flush_each_write`. The thread stops when the last
+    /// handle to the database is dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `interval` is zero.
+    pub fn flush_interval(mut self, interval: Duration) -> Self {
+        assert!(!interval.is_zero(), "flush interval must be greater than 0");
+        self.flush_interval = Some(interval);
+        self
+    }
+
+    /// Sets the strategy that decides when the logs are compacted.
+    ///
+    /// Defaults to a `ThresholdStrategy` of 1 MB of stale data.
+    pub fn compaction_strategy(mut self, strategy: Arc<dyn CompactionStrategy>) -> Self {
+        self.compaction_strategy = strategy;
+        self
+    }
+
+    /// Enables an in-memory bloom filter in front of the index, so lookups of absent keys
+    /// return early. It never misses an existing key.
+    ///
+    /// `false_positive_rate` (between 0 and 1, exclusive) is the expected ratio of absent
+    /// keys that still need to be looked up in the index. It is disabled by default.
+    ///
+    /// With the `hash-index` feature, the index has no bloom filter, so opening the database
+    /// fails with [`GrausError::IncompatibleOptions`](crate::GrausError::IncompatibleOptions)
+    /// if it is enabled.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `false_positive_rate` is not between 0 and 1.
+    pub fn enable_bloom_filter(mut self, false_positive_rate: f64) -> Self {
+        assert!(
+            false_positive_rate > 0.0 && false_positive_rate < 1.0,
+            "false positive rate must be between 0 and 1"
+        );
+        self.bloom_false_positive_rate = Some(false_positive_rate);
+        self
+    }
+
+    /// Sets how corrupted records are handled when the database is opened.
+    ///
+    /// Defaults to `RecoveryMode::Strict`. Logs with skipped records are not sealed, so
+    /// they are scanned again on the next open.
+    pub fn recovery_mode(mut self, recovery_mode: RecoveryMode) -> Self {
+        self.recovery_mode = recovery_mode;
+        self
+    }
+
+    /// Encrypts the values written from now on with `cipher`.
+    ///
+    /// Keys stay in plaintext, so they can still be scanned. Every record is marked as
+    /// encrypted or not, so logs written without a cipher can still be read. The same
+    /// cipher is needed to reopen the database, values that can't be decrypted return
+    /// `GrausError::DecryptionFailed`. The value lengths reported by the stats are the
+    /// encrypted ones, and `get_stream` reads encrypted values in memory.
+    pub fn value_cipher(mut self, cipher: Arc<dyn ValueCipher>) -> Self {
+        self.value_cipher = Some(cipher);
+        self
+    }
+
+    /// Sets the maximum number of log files kept open for reading by every clone of the
+    /// database.
+    ///
+    /// The least recently used files are closed when the limit is exceeded, and opened
+    /// again when they are read. It is unlimited by default, which can exhaust the file
+    /// descriptors of the process when there are many logs.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_open_readers` is 0.
+    pub fn max_open_readers(mut self, max_open_readers: usize) -> Self {
+        assert!(
+            max_open_readers > 0,
+            "max open readers must be greater than 0"
+        );
+        self.max_open_readers = Some(max_open_readers);
+        self
+    }
+
+    /// Stores the log files in subdirectories of `logs_per_dir` logs each, named
+    /// `<log_id / logs_per_dir>`, instead of directly in the database directory.
+    ///
+    /// It keeps directory listings fast on file systems that slow down with thousands of
+    /// files in a directory. The layout can change between opens: the existing logs are
+    /// moved to where the current layout expects them when the database is opened.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `logs_per_dir` is 0.
+    pub fn logs_per_directory(mut self, logs_per_dir: u64) -> Self {
+        assert!(
+            logs_per_dir > 0,
+            "logs per directory must be greater than 0"
+        );
+        self.logs_per_dir = Some(logs_per_dir);
+        self
+    }
+
+    /// Sets the executor that runs the compactions in the background.
+    ///
+    /// By default, compactions run during the write that triggers them, which delays that
+    /// write (other writes only wait while the compaction starts and finishes). With an
+    /// executor they are scheduled on it instead, so the
+    /// same executor, like a [`ThreadPool`](crate::ThreadPool), can be shared by many
+    /// databases without a thread for every one.
+    pub fn executor(mut self, executor: Arc<dyn Executor>) -> Self {
+        self.executor = Some(executor);
+        self
+    }
+
+    /// Sets whether the index is saved to an `index.snapshot` file when the database is
+    /// closed, and loaded from it when it is opened, instead of replaying all the logs.
+    ///
+    /// It is disabled by default. The snapshot is only used if the logs are exactly as they
+    /// were when it was saved, otherwise, like after a crash or a write through another
+    /// clone after closing, the logs are replayed. It is only saved by
+    /// [`GrausDb::close`](crate::GrausDb::close).
+    pub fn index_snapshot(mut self, index_snapshot: bool) -> Self {
+        self.index_snapshot = index_snapshot;
+        self
+    }
+
+    /// Sets how many entries of the index snapshot are checked before using it.
+    ///
+    /// An entry is valid if the command at its position in the logs is a command of its
+    /// key with the same length. If any checked entry is not valid, the snapshot is
+    /// discarded and the logs are replayed. Defaults to `SnapshotVerification::Sample(64)`.
+    pub fn snapshot_verification(mut self, verification: SnapshotVerification) -> Self {
+        self.snapshot_verification = verification;
+        self
+    }
+
+    /// Sets a second directory, ideally on a different disk, where every write is copied
+    /// synchronously along with the logs.
+    ///
+    /// When the database is opened, the mirror is brought up to date with the logs. If the
+    /// logs can't be loaded because they are corrupted, they are restored from the mirror
+    /// instead. `mode` tells whether writes fail when the mirror can't be written; a
+    /// write that fails this way may still be in the logs after opening them again.
+    pub fn mirror(mut self, path: impl Into<PathBuf>, mode: MirrorMode) -> Self {
+        self.mirror_dir = Some(path.into());
+        self.mirror_mode = mode;
+        self
+    }
+
+    /// Preallocates every new log file to `len` bytes when it is created, instead of
+    /// growing it with every write, which reduces fragmentation on some file systems.
+    ///
+    /// The file is extended with `set_len`, and the unused space at its end is zeroed.
+    /// It is dropped when the log is sealed, or when the database is closed for the
+    /// active log, so [`GrausDb::disk_size`](crate::GrausDb::disk_size) includes it only
+    /// while a log is being written.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `len` is 0.
+    pub fn preallocate_logs(mut self, len: u64) -> Self {
+        assert!(len > 0, "preallocated length must be greater than 0");
+        self.preallocate_len = Some(len);
+        self
+    }
+
+    /// Sets the capacity in bytes of the buffers used to read the logs. It is 8 KiB by
+    /// default.
+    ///
+    /// Larger buffers need fewer syscalls for sequential reads, like loading the logs when
+    /// the database is opened or streaming large values, but every read of a small value
+    /// fills the whole buffer, and every clone keeps a buffer per open log.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0.
+    pub fn read_buffer_capacity(mut self, capacity: usize) -> Self {
+        assert!(capacity > 0, "read buffer capacity must be greater than 0");
+        self.read_buffer_capacity = capacity;
+        self
+    }
+
+    /// Sets the capacity in bytes of the buffer of the active log. It is 8 KiB by default.
+    ///
+    /// Larger buffers need fewer syscalls for bulk writes when `flush_each_write` is
+    /// disabled, as writes are only flushed when the buffer is full.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0.
+    pub fn write_buffer_capacity(mut self, capacity: usize) -> Self {
+        assert!(capacity > 0, "write buffer capacity must be greater than 0");
+        self.write_buffer_capacity = capacity;
+        self
+    }
+
+    /// Sets whether a checksum of every value written is stored with it, and verified
+    /// when the value is read.
+    ///
+    /// It is disabled by default. A value whose bytes don't match its checksum makes
+    /// reads like `get` fail with
+    /// [`GrausError::ChecksumMismatch`](crate::GrausError::ChecksumMismatch), which
+    /// catches silent disk corruption. Streamed reads (`get_stream`) are not verified.
+    /// Values written before enabling it, or while it was disabled, are not verified.
+    pub fn value_checksums(mut self, enabled: bool) -> Self {
+        self.value_checksums = enabled;
+        self
+    }
+
+    /// Stalls the writes while the bytes that a compaction can reclaim exceed `bytes`.
+    ///
+    /// A stalled `set` or `append` schedules a compaction, whatever the compaction
+    /// strategy decides, and fails with [`GrausError::WriteStalled`](crate::GrausError::WriteStalled)
+    /// until the compaction catches up. Without an executor the compaction runs during the
+    /// stalled write, which is only rejected if the logs still exceed the mark afterwards.
+    /// Removes are never stalled, as they are needed to free space.
+    pub fn uncompacted_high_water_mark(mut self, bytes: u64) -> Self {
+        self.max_uncompacted = Some(bytes);
+        self
+    }
+
+    /// Stalls the writes while the logs take more than `bytes`, like
+    /// [`uncompacted_high_water_mark`](GrausDbOptions::uncompacted_high_water_mark).
+    ///
+    /// If the live data alone exceeds the mark, writes fail until enough keys are removed
+    /// and compacted, which protects the disk from filling up.
+    pub fn disk_size_high_water_mark(mut self, bytes: u64) -> Self {
+        self.max_total_bytes = Some(bytes);
+        self
+    }
+
+    /// Sets whether the log that receives the writes is created when the database is
+    /// opened.
+    ///
+    /// It is enabled by default. When disabled, the log is only created by the first
+    /// write, so opening a database just to read it, e.g. from inspection tools, leaves no
+    /// empty log behind. Opening still seals the logs that were left unsealed.
+    pub fn create_active_log(mut self, enabled: bool) -> Self {
+        self.create_active_log = enabled;
+        self
+    }
+
+    /// Stores the logs in `storage` instead of in files on disk.
+    ///
+    /// The directory the database is opened with is still created, and it keeps the files
+    /// other than the logs, like the index snapshot. `logs_per_directory` and
+    /// `preallocate_logs` only apply to logs on disk, so they are ignored.
+    pub fn storage(mut self, storage: Arc<dyn Storage>) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    /// Stores the values at least `bytes` long in value logs, apart from the commands of
+    /// their keys, which only point to them.
+    ///
+    /// It is disabled by default. Compactions copy the small commands instead of the large
+    /// values: a value log is only rewritten once less than half of its bytes are live, and
+    /// deleted once none are. Reading a separated value takes one more read. The value logs
+    /// are kept in the `values` subdirectory, and databases with value logs can't be
+    /// opened by older versions of GrausDb.
+    ///
+    /// Opening the database fails with
+    /// [`GrausError::IncompatibleOptions`](crate::GrausError::IncompatibleOptions) if it
+    /// is combined with a [`storage`](GrausDbOptions::storage), or with a
+    /// [`mirror`](GrausDbOptions::mirror), which doesn't copy the value logs.
+    pub fn value_log_threshold(mut self, bytes: u64) -> Self {
+        self.value_log_threshold = Some(bytes);
+        self
+    }
+
+    /// Sets whether the latencies of `set` and `get` are recorded, to be reported by
+    /// [`GrausDb::latency_report`](crate::GrausDb::latency_report).
+    ///
+    /// It is disabled by default, as every recorded operation reads the clock twice.
+    pub fn record_latencies(mut self, enabled: bool) -> Self {
+        self.record_latencies = enabled;
+        self
+    }
+
+    /// Sets whether every value set is read back from the logs right after it is written,
+    /// failing the write with
+    /// [`GrausError::WriteVerificationFailed`](crate::GrausError::WriteVerificationFailed)
+    /// if the key or the value don't match.
+    ///
+    /// It is disabled by default, and meant for tests and staging environments, where it
+    /// catches file system or serialization bugs early: every write flushes the active log
+    /// and reads the command back, which roughly halves the write throughput. The command
+    /// stays in the log when it doesn't match, but the index is not updated.
+    pub fn verify_writes(mut self, enabled: bool) -> Self {
+        self.verify_writes = enabled;
+        self
+    }
+
+    /// Sets a function applied by the compactions to every live value they copy, to
+    /// migrate the values to a new format in the background.
+    ///
+    /// The transformed value replaces the value in the compacted log, keeping its version
+    /// and timestamp, so a value is only seen transformed once a compaction copied it:
+    /// `get` may return values in both formats in the meantime, and snapshots keep reading
+    /// the old ones. The function must accept its own output, as values already copied are
+    /// transformed again by the next compactions. Every value is rewritten, including the
+    /// ones in value logs, and the secondary indexes are updated with the new values.
+    ///
+    /// It is not set by default.
+    pub fn compaction_value_transform(mut self, transform: ValueTransform) -> Self {
+        self.compaction_value_transform = Some(transform);
+        self
+    }
+
+    /// Sets the size of the cache of the values read by `get`, in bytes of keys and values.
+    ///
+    /// Repeated reads of a cached key skip the logs, which speeds up skewed workloads
+    /// where a few hot keys are read constantly. The least recently read values are
+    /// evicted when the cache is full, and a cached value is no longer used once its key
+    /// is written, removed or moved by a compaction. Keys can also be kept in the cache with
+    /// [`GrausDb::pin`](crate::GrausDb::pin).
+    ///
+    /// Caches of 128 KiB or more are split by key hash in up to 16 shards of 64 KiB or more,
+    /// each with its own lock, so concurrent gets rarely contend. Every shard evicts its own
+    /// least recently read values, and values larger than a shard are never cached.
+    ///
+    /// The cache is shared by the clones of the `GrausDb`. It is disabled by default.
+    pub fn value_cache_bytes(mut self, bytes: u64) -> Self {
+        self.value_cache_bytes = bytes;
+        self
+    }
+}
@@ -0,0 +1,106 @@
+use crate::{GrausError, Result};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::{Arc, RwLock};
+
+/// Function that derives the secondary index key of a value.
+///
+/// Values for which it returns `None` are not indexed.
+pub type IndexExtractor = Arc<dyn Fn(&[u8]) -> Option<Vec<u8>> + Send + Sync>;
+
+/// An in-memory secondary index that maps derived index keys to primary keys.
+struct SecondaryIndex {
+    extractor: IndexExtractor,
+    // Primary keys of every index key.
+    primary_keys: BTreeMap<Vec<u8>, BTreeSet<Vec<u8>>>,
+    // Index key of every indexed primary key, used to clean the old entry on updates.
+    index_keys: HashMap<Vec<u8>, Vec<u8>>,
+}
+
+impl SecondaryIndex {
+    fn insert(&mut self, key: &[u8], value: &[u8]) {
+        self.remove(key);
+        if let Some(index_key) = (self.extractor)(value) {
+            self.primary_keys
+                .entry(index_key.clone())
+                .or_default()
+                .insert(key.to_vec());
+            self.index_keys.insert(key.to_vec(), index_key);
+        }
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        let Some(index_key) = self.index_keys.remove(key) else {
+            return;
+        };
+        if let Some(primary_keys) = self.primary_keys.get_mut(&index_key) {
+            primary_keys.remove(key);
+            if primary_keys.is_empty() {
+                self.primary_keys.remove(&index_key);
+            }
+        }
+    }
+}
+
+/// Secondary indexes registered in a `GrausDb`.
+///
+/// They are only kept in memory and updated by the `LogWriter`, so they must be mutated
+/// under the writer lock.
+#[derive(Default)]
+pub(crate) struct SecondaryIndexes {
+    indexes: RwLock<HashMap<String, SecondaryIndex>>,
+}
+
+impl SecondaryIndexes {
+    /// Registers an index built from the given existing entries, replacing any index with
+    /// the same name.
+    pub fn register<I>(&self, name: &str, extractor: IndexExtractor, entries: I) -> Result<()>
+    where
+        I: Iterator<Item = Result<(Vec<u8>, Vec<u8>)>>,
+    {
+        let mut index = SecondaryIndex {
+            extractor,
+            primary_keys: BTreeMap::new(),
+            index_keys: HashMap::new(),
+        };
+        for entry in entries {
+            let (key, value) = entry?;
+            index.insert(&key, &value);
+        }
+        self.indexes.write().unwrap().insert(name.to_owned(), index);
+        Ok(())
+    }
+
+    /// Returns whether no index is registered.
+    pub fn is_empty(&self) -> bool {
+        self.indexes.read().unwrap().is_empty()
+    }
+
+    /// Updates every index after a key is set.
+    pub fn on_set(&self, key: &[u8], value: &[u8]) {
+        let mut indexes = self.indexes.write().unwrap();
+        for index in indexes.values_mut() {
+            index.insert(key, value);
+        }
+    }
+
+    /// Updates every index after a key is removed.
+    pub fn on_remove(&self, key: &[u8]) {
+        let mut indexes = self.indexes.write().unwrap();
+        for index in indexes.values_mut() {
+            index.remove(key);
+        }
+    }
+
+    /// Returns the primary keys whose values are indexed under `index_key`.
+    pub fn lookup(&self, name: &str, index_key: &[u8]) -> Result<Vec<Vec<u8>>> {
+        let indexes = self.indexes.read().unwrap();
+        let index = indexes
+            .get(name)
+            .ok_or_else(|| GrausError::IndexNotFound(name.to_owned()))?;
+        Ok(index
+            .primary_keys
+            .get(index_key)
+            .map(|primary_keys| primary_keys.iter().cloned().collect())
+            .unwrap_or_default())
+    }
+}
@@ -0,0 +1,230 @@
+use crate::checksum::Crc32;
+use crate::error::IoContext;
+use crate::{GrausDb, GrausDbOptions, GrausError, KeyComparator, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// File in the root directory that stores the number of shards.
+const SHARDS_FILE: &str = "SHARDS";
+
+/// A `GrausDb` split into independent shards, to scale concurrent writes.
+///
+/// Keys are hashed to one of the shards, and every shard is a `GrausDb` with its own logs,
+/// writer lock and index, stored in the `shard-<n>` subdirectory. Writes to different
+/// shards don't wait for each other.
+///
+/// The number of shards is persisted when the database is created, and it must be the
+/// same every time it is opened. Operations over several shards, like `drain_prefix`, are
+/// only atomic within each shard.
+///
+/// ```rust
+/// # use graus_db::{Result, ShardedGrausDb};
+/// # fn try_main() -> Result<()> {
+/// use std::env::current_dir;
+///
+/// let store = ShardedGrausDb::open(current_dir()?, 4)?;
+/// store.set(b"key".to_vec(), b"value")?;
+/// assert_eq!(store.get(b"key")?, Some(b"value".to_vec()));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct ShardedGrausDb {
+    shards: Vec<GrausDb>,
+    key_comparator: KeyComparator,
+}
+
+impl ShardedGrausDb {
+    /// Opens a `ShardedGrausDb` with the given path and number of shards.
+    ///
+    /// This will create a new directory if the given one does not exist.
+    ///
+    /// # Errors
+    ///
+    /// It propagates I/O or deserialization errors during the log replay, and returns
+    /// `GrausError::ShardCountMismatch` if the database was created with another number
+    /// of shards.
+    pub fn open(path: impl Into<PathBuf>, num_shards: usize) -> Result<ShardedGrausDb> {
+        ShardedGrausDb::open_with_options(path, num_shards, GrausDbOptions::default())
+    }
+
+    /// Opens a `ShardedGrausDb` with the given path, number of shards and options.
+    ///
+    /// The options are used to open every shard. A mirror directory is split the same
+    /// way as `path`, each shard mirroring its logs in its `shard-<n>` subdirectory. The
+    /// limits of the whole database, `value_cache_bytes`, `max_open_readers` and
+    /// `disk_size_high_water_mark`, are split evenly across the shards, every shard
+    /// keeping at least one open reader. The other options apply to every shard.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `num_shards` is 0.
+    pub fn open_with_options(
+        path: impl Into<PathBuf>,
+        num_shards: usize,
+        options: GrausDbOptions,
+    ) -> Result<ShardedGrausDb> {
+        assert!(num_shards > 0, "number of shards must be greater than 0");
+        let path = path.into();
+        fs::create_dir_all(&path).io_context("create directory", || path.clone())?;
+        check_num_shards(&path, num_shards)?;
+
+        let key_comparator = options.key_comparator;
+        let shards = (0..num_shards)
+            .map(|shard| {
+                let shard_dir = format!("shard-{}", shard);
+                GrausDb::open_with_options(
+                    path.join(&shard_dir),
+                    shard_options(&options, &shard_dir, num_shards),
+                )
+            })
+            .collect::<Result<_>>()?;
+        Ok(ShardedGrausDb {
+            shards,
+            key_comparator,
+        })
+    }
+
+    /// Sets the value of a key in its shard.
+    ///
+    /// If the key already exists, the previous value will be overwritten.
+    pub fn set(&self, key: Vec<u8>, value: &[u8]) -> Result<()> {
+        self.shard(&key).set(key, value)
+    }
+
+    /// Gets the value of a given key from its shard.
+    ///
+    /// Returns `None` if the given key does not exist.
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.shard(key).get(key)
+    }
+
+    /// Returns whether the given key exists, without reading its value.
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.shard(key).contains_key(key)
+    }
+
+    /// Removes a given key from its shard.
+    ///
+    /// # Errors
+    ///
+    /// Returns GrausError::KeyNotFound if the key does not exist.
+    pub fn remove(&self, key: &[u8]) -> Result<()> {
+        self.shard(key).remove(key)
+    }
+
+    /// Returns up to `limit` keys of all the shards that come strictly after `after`,
+    /// ordered by the key comparator, like [`GrausDb::list_keys`].
+    pub fn list_keys(&self, after: Option<&[u8]>, limit: usize) -> Result<Vec<Vec<u8>>> {
+        let mut keys = Vec::new();
+        for shard in &self.shards {
+            keys.extend(shard.list_keys(after, limit)?);
+        }
+        keys.sort_by(|a, b| (self.key_comparator)(a, b));
+        keys.truncate(limit);
+        Ok(keys)
+    }
+
+    /// Returns the entries of all the shards whose keys start with the given prefix,
+    /// ordered by the key comparator.
+    ///
+    /// Every shard is scanned like [`GrausDb::scan_prefixes`], one after another, so
+    /// writes that happen meanwhile may be seen in some shards and not in others.
+    pub fn scan_prefix(&self, prefix: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let mut entries = Vec::new();
+        for shard in &self.shards {
+            let mut groups = shard.scan_prefixes(&[prefix])?;
+            entries.extend(groups.remove(prefix).unwrap_or_default());
+        }
+        entries.sort_by(|(a, _), (b, _)| (self.key_comparator)(a, b));
+        Ok(entries)
+    }
+
+    /// Removes all the keys that start with the given prefix and returns them with their
+    /// values, ordered by the key comparator.
+    ///
+    /// Every shard is drained atomically, but the shards are drained one after another.
+    pub fn drain_prefix(&self, prefix: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let mut entries = Vec::new();
+        for shard in &self.shards {
+            entries.extend(shard.drain_prefix(prefix)?);
+        }
+        entries.sort_by(|(a, _), (b, _)| (self.key_comparator)(a, b));
+        Ok(entries)
+    }
+
+    /// Returns the current size in bytes of all the log files of all the shards.
+    pub fn disk_size(&self) -> Result<u64> {
+        self.shards.iter().map(GrausDb::disk_size).sum()
+    }
+
+    /// Flushes the buffered writes of every shard to the file system.
+    pub fn flush(&self) -> Result<()> {
+        self.shards.iter().try_for_each(GrausDb::flush)
+    }
+
+    /// Flushes and fsyncs the active log of every shard.
+    ///
+    /// See [`GrausDb::close`].
+    pub fn close(self) -> Result<()> {
+        self.shards.into_iter().try_for_each(GrausDb::close)
+    }
+
+    /// Returns the number of shards.
+    pub fn num_shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Returns the shard a key belongs to.
+    ///
+    /// It uses a CRC-32 of the key, which is stable across versions and platforms.
+    pub fn shard_of(&self, key: &[u8]) -> usize {
+        let mut hash = Crc32::new();
+        hash.update(key);
+        hash.finalize() as usize % self.shards.len()
+    }
+
+    fn shard(&self, key: &[u8]) -> &GrausDb {
+        &self.shards[self.shard_of(key)]
+    }
+}
+
+// Returns the options of the shard stored in the subdirectory `shard_dir`, one of
+// `num_shards`.
+fn shard_options(options: &GrausDbOptions, shard_dir: &str, num_shards: usize) -> GrausDbOptions {
+    let mut options = options.clone();
+    // The logs of the shards have the same ids, so they can't share a mirror directory
+    options.mirror_dir = options
+        .mirror_dir
+        .map(|mirror_dir| mirror_dir.join(shard_dir));
+    options.value_cache_bytes /= num_shards as u64;
+    options.max_open_readers = options
+        .max_open_readers
+        .map(|max_open_readers| (max_open_readers / num_shards).max(1));
+    options.max_total_bytes = options
+        .max_total_bytes
+        .map(|max_total_bytes| max_total_bytes / num_shards as u64);
+    options
+}
+
+// Stores the number of shards of a new database, or checks it matches the stored one.
+fn check_num_shards(path: &Path, num_shards: usize) -> Result<()> {
+    let shards_path = path.join(SHARDS_FILE);
+    if !shards_path.exists() {
+        fs::write(&shards_path, num_shards.to_string())
+            .io_context("write", || shards_path.clone())?;
+        return Ok(());
+    }
+    let found = fs::read_to_string(&shards_path)
+        .io_context("read", || shards_path.clone())?
+        .trim()
+        .parse::<usize>()
+        .map_err(|e| GrausError::SerializationError(e.to_string()))?;
+    if found != num_shards {
+        return Err(GrausError::ShardCountMismatch {
+            found,
+            expected: num_shards,
+        });
+    }
+    Ok(())
+}
@@ -0,0 +1,161 @@
+use crate::db_command::{CommandOwned, CommandPos};
+use crate::key_index::{stored_key, KeyTransform};
+use crate::log_storage::log_helpers::{remove_logs_below, remove_value_logs, LogDir};
+use crate::log_storage::log_reader::LogReader;
+use crate::log_storage::log_writer::copy_entries;
+use crate::{GrausError, Result};
+use log::error;
+use std::collections::HashMap;
+use std::sync::atomic::AtomicU64;
+use std::sync::{Arc, Mutex};
+
+/// A consistent view of a `GrausDb` at the moment it was created by
+/// [`GrausDb::snapshot`](crate::GrausDb::snapshot).
+///
+/// Reads through it return the values as they were then, even while writes and
+/// compactions continue. Compactions don't delete the logs while any snapshot is alive,
+/// they are deleted when the last one is dropped, so long-lived snapshots keep the disk
+/// space of the compacted logs.
+pub struct Snapshot {
+    entries: HashMap<Vec<u8>, CommandPos>,
+    reader: LogReader,
+    pins: Arc<SnapshotPins>,
+    key_transform: Option<KeyTransform>,
+}
+
+impl Snapshot {
+    pub(crate) fn new(
+        entries: HashMap<Vec<u8>, CommandPos>,
+        reader: &LogReader,
+        pins: Arc<SnapshotPins>,
+        key_transform: Option<KeyTransform>,
+    ) -> Snapshot {
+        pins.pin();
+        let mut reader = reader.clone();
+        // The logs of the snapshot are never stale for it
+        reader.safe_point = Arc::new(AtomicU64::new(0));
+        Snapshot {
+            entries,
+            reader,
+            pins,
+            key_transform,
+        }
+    }
+
+    /// Gets the value of a key when the snapshot was created.
+    ///
+    /// Returns `None` if the key did not exist.
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let key = stored_key(self.key_transform.as_ref(), key);
+        let Some(&cmd_pos) = self.entries.get(&*key) else {
+            return Ok(None);
+        };
+        match self.reader.read_command(cmd_pos)? {
+            CommandOwned::Set { value, .. } => Ok(Some(value)),
+            _ => Err(GrausError::UnexpectedCommandType),
+        }
+    }
+
+    /// Returns whether the key existed when the snapshot was created.
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.entries
+            .contains_key(&*stored_key(self.key_transform.as_ref(), key))
+    }
+
+    /// Returns the number of keys when the snapshot was created.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns whether there were no keys when the snapshot was created.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Copies the entries into a new sealed log `log_id` of `dir`, and their values at least
+    // `value_log_threshold` long into its value log.
+    pub(crate) fn copy_into(
+        &self,
+        dir: Arc<LogDir>,
+        log_id: u64,
+        value_checksums: bool,
+        value_log_threshold: Option<u64>,
+    ) -> Result<()> {
+        let entries = self
+            .entries
+            .iter()
+            .map(|(key, &cmd_pos)| (key.clone(), cmd_pos))
+            .collect();
+        copy_entries(
+            entries,
+            self.reader.clone(),
+            dir,
+            log_id,
+            value_checksums,
+            value_log_threshold,
+        )?;
+        Ok(())
+    }
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        self.pins.unpin(&self.reader.dir);
+    }
+}
+
+/// Tracks the live snapshots of a database, so the logs they read are not deleted by the
+/// compactions until they are dropped.
+#[derive(Default)]
+pub(crate) struct SnapshotPins {
+    state: Mutex<PinState>,
+}
+
+#[derive(Default)]
+struct PinState {
+    live: usize,
+    // The logs below this id are stale, but a snapshot may still read them.
+    deferred_below: Option<u64>,
+    // Stale value logs that a snapshot may still read.
+    deferred_value_logs: Vec<u64>,
+}
+
+impl SnapshotPins {
+    fn pin(&self) {
+        self.state.lock().unwrap().live += 1;
+    }
+
+    // Deletes the deferred logs when the last snapshot is dropped.
+    fn unpin(&self, dir: &LogDir) {
+        let mut state = self.state.lock().unwrap();
+        state.live -= 1;
+        if state.live > 0 {
+            return;
+        }
+        if let Some(log_id) = state.deferred_below.take() {
+            if let Err(e) = remove_logs_below(dir, log_id) {
+                error!("Stale logs cannot be deleted: {}", e);
+            }
+        }
+        remove_value_logs(dir, &state.deferred_value_logs);
+        state.deferred_value_logs.clear();
+    }
+
+    /// Deletes the logs below `log_id` and the value logs `value_log_ids`, which a
+    /// compaction made stale, or defers it until the last live snapshot is dropped.
+    pub(crate) fn remove_stale_logs(
+        &self,
+        dir: &LogDir,
+        log_id: u64,
+        value_log_ids: Vec<u64>,
+    ) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.live > 0 {
+            state.deferred_below = Some(log_id);
+            state.deferred_value_logs.extend(value_log_ids);
+            return Ok(());
+        }
+        remove_value_logs(dir, &value_log_ids);
+        remove_logs_below(dir, log_id)
+    }
+}
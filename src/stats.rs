@@ -0,0 +1,206 @@
+use crossbeam_utils::atomic::AtomicCell;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Metadata of a stored key, obtained without reading its value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyStat {
+    /// Log file where the value is stored.
+    pub log_id: u64,
+    /// Length of the value in bytes.
+    pub value_len: u64,
+}
+
+/// Data dropped because of corrupted records when the database was opened in
+/// `RecoveryMode::Salvage`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RecoverySummary {
+    /// Number of corrupted regions skipped. Each one may contain several records.
+    pub dropped_records: u64,
+    /// Number of bytes skipped.
+    pub dropped_bytes: u64,
+}
+
+/// Estimate of the space a compaction would reclaim, obtained without reading the logs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CompactionEstimate {
+    /// Bytes of the logs used by the last command of every key.
+    pub live_bytes: u64,
+    /// Bytes of the logs used by overwritten values and removals, which a compaction
+    /// deletes.
+    pub dead_bytes: u64,
+    /// Size of the logs after a compaction. It differs from `live_bytes` as the compacted
+    /// log is sealed with a footer and appended values are folded.
+    pub projected_size: u64,
+}
+
+impl CompactionEstimate {
+    /// Returns the ratio of dead bytes in the logs, between 0 and 1.
+    pub fn dead_ratio(&self) -> f64 {
+        let total = self.live_bytes + self.dead_bytes;
+        if total == 0 {
+            return 0.0;
+        }
+        self.dead_bytes as f64 / total as f64
+    }
+}
+
+/// Logs of the database, along with the role of some of them.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct LogLayout {
+    /// Ids of the logs on disk in ascending order, including the ones made stale by a
+    /// compaction that are not deleted yet.
+    pub log_ids: Vec<u64>,
+    /// Id of the active log, where new commands are written.
+    pub active: u64,
+    /// Id of the log written by the last compaction, if there was one since the database
+    /// was opened and it is still on disk.
+    pub last_compaction: Option<u64>,
+}
+
+/// Distribution of the key and value lengths of the live entries.
+///
+/// Lengths are grouped in power-of-two buckets: bucket `0` counts empty keys or values,
+/// and bucket `i` counts lengths in the range `[2^(i-1), 2^i)`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SizeHistogram {
+    /// Number of keys in every bucket.
+    pub key_lengths: Vec<u64>,
+    /// Number of values in every bucket.
+    pub value_lengths: Vec<u64>,
+}
+
+impl SizeHistogram {
+    /// Returns the bucket a length belongs to.
+    pub fn bucket(len: u64) -> usize {
+        (u64::BITS - len.leading_zeros()) as usize
+    }
+
+    pub(crate) fn record(&mut self, key_len: u64, value_len: u64) {
+        increment_bucket(&mut self.key_lengths, Self::bucket(key_len));
+        increment_bucket(&mut self.value_lengths, Self::bucket(value_len));
+    }
+}
+
+fn increment_bucket(buckets: &mut Vec<u64>, bucket: usize) {
+    if buckets.len() <= bucket {
+        buckets.resize(bucket + 1, 0);
+    }
+    buckets[bucket] += 1;
+}
+
+/// Percentiles of the latency of an operation, see
+/// [`GrausDb::latency_report`](crate::GrausDb::latency_report).
+///
+/// The latencies are recorded in power-of-two buckets of nanoseconds, and the percentiles
+/// are the upper bound of their bucket, so they may be up to twice the actual latency.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LatencyPercentiles {
+    /// Number of operations recorded.
+    pub count: u64,
+    /// Median latency.
+    pub p50: Duration,
+    /// 99th percentile of the latency.
+    pub p99: Duration,
+}
+
+/// Latencies of the operations since the database was opened, see
+/// [`GrausDb::latency_report`](crate::GrausDb::latency_report).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LatencyReport {
+    /// Latencies of `set`.
+    pub set: LatencyPercentiles,
+    /// Latencies of `get`.
+    pub get: LatencyPercentiles,
+}
+
+/// Latencies of the operations, recorded and read without locking.
+#[derive(Debug, Default)]
+pub(crate) struct Latencies {
+    pub(crate) set: LatencyHistogram,
+    pub(crate) get: LatencyHistogram,
+}
+
+impl Latencies {
+    pub(crate) fn report(&self) -> LatencyReport {
+        LatencyReport {
+            set: self.set.percentiles(),
+            get: self.get.percentiles(),
+        }
+    }
+}
+
+/// Latencies of an operation in power-of-two buckets of nanoseconds, as in
+/// `SizeHistogram`.
+#[derive(Debug)]
+pub(crate) struct LatencyHistogram {
+    buckets: [AtomicU64; 65],
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        LatencyHistogram {
+            buckets: std::array::from_fn(|_| AtomicU64::new(0)),
+        }
+    }
+}
+
+impl LatencyHistogram {
+    pub(crate) fn record(&self, latency: Duration) {
+        let nanos = u64::try_from(latency.as_nanos()).unwrap_or(u64::MAX);
+        self.buckets[SizeHistogram::bucket(nanos)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn percentiles(&self) -> LatencyPercentiles {
+        let counts: Vec<u64> = self
+            .buckets
+            .iter()
+            .map(|bucket| bucket.load(Ordering::Relaxed))
+            .collect();
+        let count = counts.iter().sum();
+        let percentile = |quantile: f64| {
+            let rank = ((count as f64 * quantile).ceil() as u64).max(1);
+            let mut seen = 0;
+            for (bucket, bucket_count) in counts.iter().enumerate() {
+                seen += bucket_count;
+                if seen >= rank {
+                    // Latencies in bucket `i` are below 2^i
+                    return Duration::from_nanos(
+                        1u64.checked_shl(bucket as u32).unwrap_or(u64::MAX),
+                    );
+                }
+            }
+            Duration::ZERO
+        };
+        if count == 0 {
+            return LatencyPercentiles::default();
+        }
+        LatencyPercentiles {
+            count,
+            p50: percentile(0.5),
+            p99: percentile(0.99),
+        }
+    }
+}
+
+/// Completed compactions, updated by the writer and read without locking it.
+#[derive(Debug, Default)]
+pub(crate) struct CompactionStats {
+    count: AtomicU64,
+    last: AtomicCell<Option<Instant>>,
+}
+
+impl CompactionStats {
+    pub(crate) fn record(&self) {
+        self.last.store(Some(Instant::now()));
+        self.count.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub(crate) fn count(&self) -> u64 {
+        self.count.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn last(&self) -> Option<Instant> {
+        self.last.load()
+    }
+}
@@ -0,0 +1,107 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::sync::{Arc, Mutex, RwLock};
+
+/// Backend that stores the logs of a database, identified by their log id.
+///
+/// Logs are files on disk by default. A storage set through
+/// [`GrausDbOptions::storage`](crate::GrausDbOptions::storage) replaces them, e.g. to keep
+/// the logs in memory or in an object store.
+///
+/// Logs are only appended to and read at any position, except when a log is truncated
+/// to drop its unused end. A log is removed once a compaction makes it stale, so reads of
+/// a removed log must fail with [`io::ErrorKind::NotFound`]: readers retry from the
+/// compacted log.
+pub trait Storage: Send + Sync {
+    /// Creates an empty log, replacing the existing one if any.
+    fn create_log(&self, log_id: u64) -> io::Result<()>;
+
+    /// Reads the bytes of a log from `pos` into `buf`, returning how many were read. It
+    /// returns 0 if `pos` is at or after the end of the log.
+    fn read_at(&self, log_id: u64, pos: u64, buf: &mut [u8]) -> io::Result<usize>;
+
+    /// Appends `bytes` to the end of a log.
+    fn append(&self, log_id: u64, bytes: &[u8]) -> io::Result<()>;
+
+    /// Returns the length of a log.
+    fn log_len(&self, log_id: u64) -> io::Result<u64>;
+
+    /// Drops the bytes of a log after the first `len`.
+    fn truncate(&self, log_id: u64, len: u64) -> io::Result<()>;
+
+    /// Waits until the bytes appended to a log are durable.
+    fn sync(&self, log_id: u64) -> io::Result<()>;
+
+    /// Removes a log.
+    fn remove_log(&self, log_id: u64) -> io::Result<()>;
+
+    /// Returns the ids of the existing logs, in any order.
+    fn log_ids(&self) -> io::Result<Vec<u64>>;
+}
+
+/// Storage that keeps the logs in memory, so nothing is written to disk.
+///
+/// It is used by [`GrausDb::open_in_memory`](crate::GrausDb::open_in_memory).
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    logs: Mutex<BTreeMap<u64, Arc<RwLock<Vec<u8>>>>>,
+}
+
+impl MemoryStorage {
+    // Returns the content of a log. It is shared, so the map is only locked to find it.
+    fn log(&self, log_id: u64) -> io::Result<Arc<RwLock<Vec<u8>>>> {
+        self.logs
+            .lock()
+            .unwrap()
+            .get(&log_id)
+            .cloned()
+            .ok_or_else(|| io::ErrorKind::NotFound.into())
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn create_log(&self, log_id: u64) -> io::Result<()> {
+        self.logs.lock().unwrap().insert(log_id, Arc::default());
+        Ok(())
+    }
+
+    fn read_at(&self, log_id: u64, pos: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let log = self.log(log_id)?;
+        let log = log.read().unwrap();
+        let start = pos.min(log.len() as u64) as usize;
+        let len = buf.len().min(log.len() - start);
+        buf[..len].copy_from_slice(&log[start..start + len]);
+        Ok(len)
+    }
+
+    fn append(&self, log_id: u64, bytes: &[u8]) -> io::Result<()> {
+        self.log(log_id)?.write().unwrap().extend_from_slice(bytes);
+        Ok(())
+    }
+
+    fn log_len(&self, log_id: u64) -> io::Result<u64> {
+        Ok(self.log(log_id)?.read().unwrap().len() as u64)
+    }
+
+    fn truncate(&self, log_id: u64, len: u64) -> io::Result<()> {
+        self.log(log_id)?.write().unwrap().truncate(len as usize);
+        Ok(())
+    }
+
+    fn sync(&self, log_id: u64) -> io::Result<()> {
+        self.log(log_id).map(|_| ())
+    }
+
+    fn remove_log(&self, log_id: u64) -> io::Result<()> {
+        self.logs
+            .lock()
+            .unwrap()
+            .remove(&log_id)
+            .map(|_| ())
+            .ok_or_else(|| io::ErrorKind::NotFound.into())
+    }
+
+    fn log_ids(&self) -> io::Result<Vec<u64>> {
+        Ok(self.logs.lock().unwrap().keys().copied().collect())
+    }
+}
@@ -0,0 +1,72 @@
+use crate::{GrausDb, Result};
+use std::collections::BTreeMap;
+
+/// A set of writes applied together, created by [`GrausDb::transaction`].
+///
+/// Writes are buffered in memory and only applied when the transaction commits, under a
+/// single acquisition of the writer lock, so no other write is interleaved with them.
+/// Reads through the transaction see its own buffered writes first.
+///
+/// Committing is atomic: the writes are stored as a single batch in the log, which is only
+/// replayed if it was written whole, so after an I/O error or a crash while committing
+/// either all of them or none are in the database once it is reopened. The index is only
+/// updated after the whole batch is written.
+///
+/// The isolation level is read committed: reads of keys not written by the transaction
+/// see the latest committed value, and keys read are not checked for conflicts when it
+/// commits, so the last writer wins. The index is updated key by key, so concurrent reads
+/// of several keys may see some of the writes before the others; snapshots, taken under
+/// the writer lock, see all of them or none.
+///
+/// ```rust
+/// # use graus_db::{GrausDb, Result};
+/// # fn try_main() -> Result<()> {
+/// use std::env::current_dir;
+///
+/// let store = GrausDb::open(current_dir()?)?;
+/// store.transaction(|tx| {
+///     let balance = tx.get(b"alice")?.unwrap_or_default();
+///     tx.set(b"bob".to_vec(), &balance);
+///     tx.remove(b"alice");
+///     Ok(())
+/// })?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Transaction<'a> {
+    db: &'a GrausDb,
+    // Last value written for every key, `None` for removals.
+    writes: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+}
+
+impl<'a> Transaction<'a> {
+    pub(crate) fn new(db: &'a GrausDb) -> Transaction<'a> {
+        Transaction {
+            db,
+            writes: BTreeMap::new(),
+        }
+    }
+
+    /// Sets the value of a key when the transaction commits.
+    pub fn set(&mut self, key: Vec<u8>, value: &[u8]) {
+        self.writes.insert(key, Some(value.to_vec()));
+    }
+
+    /// Removes a key when the transaction commits. Removing a missing key does nothing.
+    pub fn remove(&mut self, key: &[u8]) {
+        self.writes.insert(key.to_vec(), None);
+    }
+
+    /// Gets the value of a key, as written by this transaction or, if it didn't write it,
+    /// as stored in the database.
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        match self.writes.get(key) {
+            Some(value) => Ok(value.clone()),
+            None => self.db.get(key),
+        }
+    }
+
+    pub(crate) fn into_writes(self) -> BTreeMap<Vec<u8>, Option<Vec<u8>>> {
+        self.writes
+    }
+}
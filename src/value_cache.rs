@@ -0,0 +1,251 @@
+use crate::db_command::CommandPos;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+// Maximum number of independent shards, so concurrent gets rarely wait for each other.
+const MAX_SHARDS: u64 = 16;
+// Minimum capacity of a shard. Smaller caches have fewer shards, so a shard can still
+// hold values of a useful size.
+const MIN_SHARD_CAPACITY: u64 = 64 * 1024;
+
+/// Size-bounded LRU cache of the values read by `get`, shared by every handle of a
+/// database, along with the keys pinned in it.
+///
+/// Every value is cached with the position of the command it was read from. The writer
+/// drops the values of the keys it writes or removes, and moves the ones of the commands
+/// copied by a compaction. A value is only returned while its key still points to its
+/// command, so a value cached by a `get` racing with a write is never returned.
+///
+/// Keys are split in shards by their hash, each with its own lock, its own share of the
+/// capacity and its own LRU order.
+#[derive(Debug)]
+pub(crate) struct ValueCache {
+    // Bytes of the keys and values cached, excluding the pinned ones.
+    capacity: u64,
+    // Number of keys pinned, so lookups skip the locks of a disabled cache.
+    pins: AtomicUsize,
+    shards: Vec<Mutex<CacheShard>>,
+}
+
+#[derive(Debug, Default)]
+struct CacheShard {
+    capacity: u64,
+    entries: HashMap<Vec<u8>, CachedValue>,
+    // Keys of the unpinned entries, from the least recently used.
+    lru: BTreeMap<u64, Vec<u8>>,
+    pinned: HashSet<Vec<u8>>,
+    // Bytes of the unpinned entries.
+    len: u64,
+    tick: u64,
+}
+
+#[derive(Debug)]
+struct CachedValue {
+    cmd_pos: CommandPos,
+    value: Vec<u8>,
+    // Position in the LRU list, `None` for pinned keys.
+    tick: Option<u64>,
+}
+
+impl ValueCache {
+    pub(crate) fn new(capacity: u64) -> ValueCache {
+        let shard_count = (capacity / MIN_SHARD_CAPACITY).clamp(1, MAX_SHARDS);
+        let shards = (0..shard_count)
+            .map(|_| {
+                Mutex::new(CacheShard {
+                    capacity: capacity / shard_count,
+                    ..CacheShard::default()
+                })
+            })
+            .collect();
+        ValueCache {
+            capacity,
+            pins: AtomicUsize::new(0),
+            shards,
+        }
+    }
+
+    /// Returns whether values can be cached.
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.capacity > 0 || self.pins.load(Ordering::Relaxed) > 0
+    }
+
+    /// Returns the value of `key` if it was cached from the command at `cmd_pos`.
+    pub(crate) fn get(&self, key: &[u8], cmd_pos: CommandPos) -> Option<Vec<u8>> {
+        let mut shard = self.shard(key).lock().unwrap();
+        let cached = shard.entries.get(key)?;
+        if cached.cmd_pos != cmd_pos {
+            // The key was written or moved since it was cached
+            shard.remove(key);
+            return None;
+        }
+        let value = cached.value.clone();
+        shard.touch(key);
+        Some(value)
+    }
+
+    /// Caches the value of `key` read from the command at `cmd_pos`, evicting the least
+    /// recently used values of its shard that don't fit. Values larger than a shard are
+    /// not cached, unless their key is pinned.
+    pub(crate) fn insert(&self, key: &[u8], cmd_pos: CommandPos, value: Vec<u8>) {
+        self.shard(key).lock().unwrap().insert(key, cmd_pos, value);
+    }
+
+    /// Drops the cached value of `key`, which was written or removed. It stays pinned.
+    pub(crate) fn remove(&self, key: &[u8]) {
+        if self.is_enabled() {
+            self.shard(key).lock().unwrap().remove(key);
+        }
+    }
+
+    /// Moves the value of `key` cached from the command at `old_pos` to `new_pos`, where a
+    /// compaction copied the command.
+    pub(crate) fn moved(&self, key: &[u8], old_pos: CommandPos, new_pos: CommandPos) {
+        if !self.is_enabled() {
+            return;
+        }
+        let mut shard = self.shard(key).lock().unwrap();
+        if let Some(cached) = shard.entries.get_mut(key) {
+            if cached.cmd_pos == old_pos {
+                cached.cmd_pos = new_pos;
+            }
+        }
+    }
+
+    /// Pins `key`, so its value is kept once cached.
+    pub(crate) fn pin(&self, key: &[u8]) {
+        let mut shard = self.shard(key).lock().unwrap();
+        shard.remove(key);
+        if shard.pinned.insert(key.to_vec()) {
+            self.pins.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Unpins `key`, dropping its value from the cache.
+    pub(crate) fn unpin(&self, key: &[u8]) {
+        let mut shard = self.shard(key).lock().unwrap();
+        if shard.pinned.remove(key) {
+            shard.remove(key);
+            self.pins.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    fn shard(&self, key: &[u8]) -> &Mutex<CacheShard> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        &self.shards[hasher.finish() as usize % self.shards.len()]
+    }
+}
+
+impl CacheShard {
+    fn insert(&mut self, key: &[u8], cmd_pos: CommandPos, value: Vec<u8>) {
+        self.remove(key);
+        if self.pinned.contains(key) {
+            self.entries.insert(
+                key.to_vec(),
+                CachedValue {
+                    cmd_pos,
+                    value,
+                    tick: None,
+                },
+            );
+            return;
+        }
+        let len = (key.len() + value.len()) as u64;
+        if len > self.capacity {
+            return;
+        }
+        while self.len + len > self.capacity {
+            self.evict();
+        }
+        self.tick += 1;
+        let tick = self.tick;
+        self.lru.insert(tick, key.to_vec());
+        self.len += len;
+        self.entries.insert(
+            key.to_vec(),
+            CachedValue {
+                cmd_pos,
+                value,
+                tick: Some(tick),
+            },
+        );
+    }
+
+    // Marks the entry of `key` as the most recently used.
+    fn touch(&mut self, key: &[u8]) {
+        let Some(cached) = self.entries.get_mut(key) else {
+            return;
+        };
+        let Some(tick) = cached.tick.as_mut() else {
+            return;
+        };
+        self.tick += 1;
+        let key = self.lru.remove(tick).unwrap_or_else(|| key.to_vec());
+        *tick = self.tick;
+        self.lru.insert(self.tick, key);
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        if let Some(cached) = self.entries.remove(key) {
+            if let Some(tick) = cached.tick {
+                self.lru.remove(&tick);
+                self.len -= (key.len() + cached.value.len()) as u64;
+            }
+        }
+    }
+
+    // Removes the least recently used entry.
+    fn evict(&mut self) {
+        if let Some((_, key)) = self.lru.pop_first() {
+            if let Some(cached) = self.entries.remove(&key) {
+                self.len -= (key.len() + cached.value.len()) as u64;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{GrausDb, Result};
+    use tempfile::TempDir;
+
+    // Returns the bytes of the keys and values cached, including the pinned ones.
+    fn cached_len(store: &GrausDb) -> usize {
+        let value_cache = &store.writer.lock().unwrap().value_cache;
+        value_cache
+            .shards
+            .iter()
+            .flat_map(|shard| {
+                let shard = shard.lock().unwrap();
+                shard
+                    .entries
+                    .iter()
+                    .map(|(key, cached)| key.len() + cached.value.len())
+                    .collect::<Vec<_>>()
+            })
+            .sum()
+    }
+
+    #[test]
+    fn test_removed_pinned_key_is_dropped() -> Result<()> {
+        let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+        let store = GrausDb::open(temp_dir.path())?;
+        store.set(b"key1".to_vec(), b"value1")?;
+        store.pin(b"key1")?;
+        assert_eq!(cached_len(&store), 10);
+
+        store.remove(b"key1")?;
+        assert_eq!(cached_len(&store), 0);
+
+        // The key stays pinned
+        store.set(b"key1".to_vec(), b"value2")?;
+        assert_eq!(cached_len(&store), 0);
+        store.get(b"key1")?;
+        assert_eq!(cached_len(&store), 10);
+        Ok(())
+    }
+}
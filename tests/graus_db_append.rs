@@ -0,0 +1,132 @@
+use graus_db::{GrausDb, GrausDbOptions, Result, ThresholdStrategy};
+use std::io::Read;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+// Should set the value of a missing key and concatenate the next chunks
+#[test]
+fn append_concatenates_chunks() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+
+    store.append(b"events".to_vec(), b"a")?;
+    store.append(b"events".to_vec(), b"bc")?;
+    store.append(b"events".to_vec(), b"def")?;
+    assert_eq!(store.get(b"events")?, Some(b"abcdef".to_vec()));
+    assert_eq!(
+        store.get_versioned(b"events")?.map(|(_, version)| version),
+        Some(3)
+    );
+    assert_eq!(
+        store.stat_key(b"events").map(|stat| stat.value_len),
+        Some(6)
+    );
+
+    // Appends to an existing value
+    store.set(b"key".to_vec(), b"value")?;
+    store.append(b"key".to_vec(), b"1")?;
+    assert_eq!(store.get(b"key")?, Some(b"value1".to_vec()));
+
+    // Open from disk again and check persistent data
+    drop(store);
+    let store = GrausDb::open(temp_dir.path())?;
+    assert_eq!(store.get(b"events")?, Some(b"abcdef".to_vec()));
+    assert_eq!(store.get(b"key")?, Some(b"value1".to_vec()));
+    store.append(b"events".to_vec(), b"g")?;
+    assert_eq!(store.get(b"events")?, Some(b"abcdefg".to_vec()));
+    Ok(())
+}
+
+// Should stream the whole appended value
+#[test]
+fn get_stream_reads_appended_value() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.append(b"key".to_vec(), b"first ")?;
+    store.append(b"key".to_vec(), b"second")?;
+
+    let mut value = Vec::new();
+    store
+        .get_stream(b"key")?
+        .expect("key not found")
+        .read_to_end(&mut value)?;
+    assert_eq!(value, b"first second");
+    Ok(())
+}
+
+// Setting or removing an appended key should replace all its chunks
+#[test]
+fn set_and_remove_replace_appended_value() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.append(b"key".to_vec(), b"a")?;
+    store.append(b"key".to_vec(), b"b")?;
+
+    store.set(b"key".to_vec(), b"new")?;
+    store.append(b"key".to_vec(), b"!")?;
+    assert_eq!(store.get(b"key")?, Some(b"new!".to_vec()));
+
+    store.remove(b"key")?;
+    store.append(b"key".to_vec(), b"c")?;
+    assert_eq!(store.get(b"key")?, Some(b"c".to_vec()));
+
+    drop(store);
+    let store = GrausDb::open(temp_dir.path())?;
+    assert_eq!(store.get(b"key")?, Some(b"c".to_vec()));
+    Ok(())
+}
+
+// Compactions should fold the chunks into a single value
+#[test]
+fn compaction_folds_appended_chunks() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options =
+        GrausDbOptions::default().compaction_strategy(Arc::new(ThresholdStrategy { threshold: 0 }));
+    let store = GrausDb::open(temp_dir.path())?;
+    let mut expected = Vec::new();
+    for i in 0..100 {
+        let chunk = format!("{},", i).into_bytes();
+        store.append(b"key".to_vec(), &chunk)?;
+        expected.extend_from_slice(&chunk);
+    }
+    drop(store);
+
+    // Every write compacts the logs
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    store.set(b"other".to_vec(), b"value")?;
+    let (_, cmd_pos) = store
+        .index_snapshot()
+        .into_iter()
+        .find(|(key, _)| key == b"key")
+        .expect("key not found");
+    assert_eq!(cmd_pos.appended_len, 0);
+    assert_eq!(cmd_pos.value_len, expected.len() as u64);
+    assert_eq!(store.get(b"key")?, Some(expected.clone()));
+    assert_eq!(
+        store.get_versioned(b"key")?.map(|(_, version)| version),
+        Some(100)
+    );
+
+    // Appends after the compaction go on top of the folded value
+    store.append(b"key".to_vec(), b"end")?;
+    expected.extend_from_slice(b"end");
+    assert_eq!(store.get(b"key")?, Some(expected));
+    Ok(())
+}
+
+// History should contain the value after every append
+#[test]
+fn get_history_folds_appends() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.append(b"key".to_vec(), b"a")?;
+    store.append(b"key".to_vec(), b"b")?;
+
+    let values: Vec<Option<Vec<u8>>> = store
+        .get_history(b"key")?
+        .into_iter()
+        .map(|(_, value)| value)
+        .collect();
+    assert_eq!(values, vec![Some(b"a".to_vec()), Some(b"ab".to_vec())]);
+    Ok(())
+}
@@ -0,0 +1,34 @@
+use graus_db::{GrausDb, GrausDbOptions, Result};
+use tempfile::TempDir;
+
+// The bloom filter should never hide an existing key, even after it is resized or rebuilt
+#[test]
+#[cfg_attr(feature = "hash-index", ignore = "the hash index has no bloom filter")]
+fn bloom_filter_has_no_false_negatives() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = GrausDbOptions::default().enable_bloom_filter(0.01);
+    let store = GrausDb::open_with_options(temp_dir.path(), options.clone())?;
+
+    for i in 0..5000 {
+        store.set(format!("key{}", i).into_bytes(), b"value")?;
+    }
+    for i in (0..5000).step_by(2) {
+        store.remove(format!("key{}", i).as_bytes())?;
+    }
+
+    let check = |store: &GrausDb| -> Result<()> {
+        for i in 0..5000 {
+            let key = format!("key{}", i).into_bytes();
+            let exists = i % 2 == 1;
+            assert_eq!(store.contains_key(&key), exists);
+            assert_eq!(store.get(&key)?.is_some(), exists);
+        }
+        Ok(())
+    };
+    check(&store)?;
+
+    // Open from disk again, the filter is rebuilt from the logs
+    drop(store);
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    check(&store)
+}
@@ -1,4 +1,10 @@
-use graus_db::{GrausDb, Result};
+use graus_db::{
+    CompactionStrategy, GrausDb, GrausDbOptions, GrausError, RatioStrategy, Result,
+    ThresholdStrategy,
+};
+use std::fs::{self, File};
+use std::sync::Arc;
+use std::thread;
 use tempfile::TempDir;
 use walkdir::WalkDir;
 
@@ -57,3 +63,341 @@ fn data_is_compacted_when_limit_reached() -> Result<()> {
 
     panic!("No compaction detected");
 }
+
+// Disk size should grow with writes and shrink after a compaction.
+#[test]
+fn disk_size_matches_log_files() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    assert_eq!(store.disk_size()?, 0);
+
+    store.set(b"key1".to_vec(), b"value1")?;
+    let size_after_set = store.disk_size()?;
+    assert!(size_after_set > 0);
+
+    let dir_size: u64 = WalkDir::new(temp_dir.path())
+        .into_iter()
+        .map(|entry| entry.unwrap().metadata().unwrap())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .sum();
+    assert_eq!(store.disk_size()?, dir_size);
+
+    let mut max_size = size_after_set;
+    for iter in 0..10000 {
+        store.set(b"key1".to_vec(), format!("{:0>500}", iter).as_bytes())?;
+        let size = store.disk_size()?;
+        if size < max_size {
+            return Ok(());
+        }
+        max_size = size;
+    }
+    panic!("No compaction detected");
+}
+
+struct NeverCompact;
+
+impl CompactionStrategy for NeverCompact {
+    fn should_compact(&self, _uncompacted: u64, _total: u64, _num_logs: usize) -> bool {
+        false
+    }
+}
+
+// Built-in strategies should follow their thresholds.
+#[test]
+fn builtin_strategies_decide_compaction() {
+    let threshold = ThresholdStrategy { threshold: 100 };
+    assert!(!threshold.should_compact(100, 1000, 1));
+    assert!(threshold.should_compact(101, 1000, 1));
+
+    let ratio = RatioStrategy {
+        ratio: 0.5,
+        min_uncompacted: 10,
+    };
+    assert!(!ratio.should_compact(5, 6, 1));
+    assert!(!ratio.should_compact(50, 100, 1));
+    assert!(ratio.should_compact(51, 100, 1));
+}
+
+// The configured strategy should be consulted instead of the default one.
+#[test]
+fn custom_strategy_is_used() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = GrausDbOptions::default().compaction_strategy(Arc::new(NeverCompact));
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+
+    let mut previous_size = 0;
+    for iter in 0..5000 {
+        store.set(b"key1".to_vec(), format!("{:0>500}", iter).as_bytes())?;
+        let size = store.disk_size()?;
+        assert!(size > previous_size, "Unexpected compaction");
+        previous_size = size;
+    }
+    assert!(previous_size > 2 * 1024 * 1024);
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = GrausDbOptions::default().compaction_strategy(Arc::new(RatioStrategy {
+        ratio: 0.5,
+        min_uncompacted: 0,
+    }));
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    store.set(b"key1".to_vec(), b"value1")?;
+    store.set(b"key2".to_vec(), b"value2")?;
+    store.set(b"key1".to_vec(), b"value3")?;
+    store.set(b"key1".to_vec(), b"value4")?;
+    let size = store.disk_size()?;
+    // More than half of the data is stale now
+    store.set(b"key1".to_vec(), b"value4")?;
+    assert!(store.disk_size()? < size);
+    assert_eq!(store.get(b"key1")?, Some(b"value4".to_vec()));
+    assert_eq!(store.get(b"key2")?, Some(b"value2".to_vec()));
+    Ok(())
+}
+
+// Empty logs left by a crash should be ignored and removed when opening the database.
+#[test]
+fn empty_logs_are_removed_on_open() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"key1".to_vec(), b"value1")?;
+    drop(store);
+
+    let log_path = |log_id: u64| temp_dir.path().join(format!("{}.log", log_id));
+    File::create(log_path(5))?;
+    File::create(log_path(10))?;
+
+    let store = GrausDb::open(temp_dir.path())?;
+    assert_eq!(store.get(b"key1")?, Some(b"value1".to_vec()));
+    assert!(!log_path(5).exists());
+    assert!(!log_path(10).exists());
+
+    // The empty trailing log is not used as the base of the new log ids
+    store.set(b"key2".to_vec(), b"value2")?;
+    assert!(log_path(2).exists());
+    drop(store);
+
+    let store = GrausDb::open(temp_dir.path())?;
+    assert_eq!(store.get(b"key1")?, Some(b"value1".to_vec()));
+    assert_eq!(store.get(b"key2")?, Some(b"value2".to_vec()));
+    Ok(())
+}
+
+// Compacting a database without keys should not create an empty compaction log.
+#[test]
+fn compaction_skips_empty_log() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options =
+        GrausDbOptions::default().compaction_strategy(Arc::new(ThresholdStrategy { threshold: 0 }));
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    store.set(b"key1".to_vec(), b"value1")?;
+    store.remove(b"key1")?;
+
+    let log_ids: Vec<String> = fs::read_dir(temp_dir.path())?
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    // Only the new active log remains, which holds the sequence number of the last write
+    assert_eq!(log_ids, vec!["3.log".to_owned()]);
+    assert_eq!(store.disk_size()?, 1 + 8);
+    Ok(())
+}
+
+// Writes during compactions should not be lost or overwritten by the copies. The writer
+// is not locked while a compaction copies the logs, so the other threads keep writing.
+#[test]
+fn writes_proceed_during_compaction() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = GrausDbOptions::default().compaction_strategy(Arc::new(ThresholdStrategy {
+        threshold: 8 * 1024,
+    }));
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+
+    // Every thread writes its own set of keys
+    let handles: Vec<_> = (0..4)
+        .map(|thread_id| {
+            let store = store.clone();
+            thread::spawn(move || -> Result<()> {
+                for round in 0..20 {
+                    for i in 0..50 {
+                        let key = format!("{}-key{}", thread_id, i).into_bytes();
+                        store.set(key.clone(), format!("{}-", round).as_bytes())?;
+                        store.append(key.clone(), &[b'x'; 64])?;
+                        if i % 5 == 0 {
+                            store.remove(&key)?;
+                        }
+                    }
+                }
+                Ok(())
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap()?;
+    }
+    assert!(store.compaction_count() > 0);
+
+    let mut expected = b"19-".to_vec();
+    expected.extend_from_slice(&[b'x'; 64]);
+    let check = |store: &GrausDb| -> Result<()> {
+        for thread_id in 0..4 {
+            for i in 0..50 {
+                let key = format!("{}-key{}", thread_id, i).into_bytes();
+                let value = if i % 5 == 0 {
+                    None
+                } else {
+                    Some(expected.clone())
+                };
+                assert_eq!(store.get(&key)?, value);
+            }
+        }
+        Ok(())
+    };
+    check(&store)?;
+
+    // Open from disk again and check persistent data
+    drop(store);
+    let store = GrausDb::open(temp_dir.path())?;
+    check(&store)?;
+    Ok(())
+}
+
+// Compacting a single key should fold its value into a fresh copy while it is read
+#[test]
+fn compact_key_rewrites_value() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    let mut expected = Vec::new();
+    for i in 0..200 {
+        let chunk = format!("{},", i).into_bytes();
+        store.append(b"hot".to_vec(), &chunk)?;
+        expected.extend_from_slice(&chunk);
+    }
+    store.set(b"other".to_vec(), b"value")?;
+    let dead_bytes = store.compaction_estimate().dead_bytes;
+    let version = store.get_versioned(b"hot")?.map(|(_, version)| version);
+
+    let readers: Vec<_> = (0..4)
+        .map(|_| {
+            let store = store.clone();
+            let expected = expected.clone();
+            thread::spawn(move || -> Result<()> {
+                for _ in 0..200 {
+                    assert_eq!(store.get(b"hot")?, Some(expected.clone()));
+                }
+                Ok(())
+            })
+        })
+        .collect();
+    for _ in 0..20 {
+        store.compact_key(b"hot")?;
+    }
+    for reader in readers {
+        reader.join().unwrap()?;
+    }
+
+    let (_, cmd_pos) = store
+        .index_snapshot()
+        .into_iter()
+        .find(|(key, _)| key == b"hot")
+        .expect("key not found");
+    assert_eq!(cmd_pos.appended_len, 0);
+    assert_eq!(store.get(b"hot")?, Some(expected.clone()));
+    assert_eq!(
+        store.get_versioned(b"hot")?.map(|(_, version)| version),
+        version
+    );
+    assert!(store.compaction_estimate().dead_bytes > dead_bytes);
+    assert!(matches!(
+        store.compact_key(b"missing"),
+        Err(GrausError::KeyNotFound)
+    ));
+
+    drop(store);
+    let store = GrausDb::open(temp_dir.path())?;
+    assert_eq!(store.get(b"hot")?, Some(expected));
+    Ok(())
+}
+
+// Compacting into a new directory should copy the live entries and leave the source as is
+#[test]
+fn compact_into_copies_live_entries() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = GrausDbOptions::default().compaction_strategy(Arc::new(ThresholdStrategy {
+        threshold: u64::MAX,
+    }));
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    for iter in 0..100 {
+        for key_id in 0..10 {
+            let key = format!("key{}", key_id).into_bytes();
+            store.set(key, format!("value{}", iter).as_bytes())?;
+        }
+    }
+    store.append(b"key1".to_vec(), b"!")?;
+    store.remove(b"key2")?;
+    let source_files: Vec<_> = fs::read_dir(temp_dir.path())?
+        .map(|entry| entry.unwrap().path())
+        .collect();
+
+    let dest_dir = TempDir::new().expect("unable to create temporary working directory");
+    let dest_path = dest_dir.path().join("compacted");
+    store.compact_into(&dest_path)?;
+    let source_len: u64 = source_files
+        .iter()
+        .map(|path| fs::metadata(path).unwrap().len())
+        .sum();
+    assert_eq!(store.disk_size()?, source_len);
+    // The destination must be empty
+    assert!(store.compact_into(&dest_path).is_err());
+
+    let compacted = GrausDb::open(&dest_path)?;
+    assert!(compacted.disk_size()? < store.disk_size()? / 10);
+    // Keys are in arbitrary order with the `hash-index` feature
+    let mut keys = store.list_keys(None, usize::MAX)?;
+    let mut compacted_keys = compacted.list_keys(None, usize::MAX)?;
+    keys.sort();
+    compacted_keys.sort();
+    assert_eq!(compacted_keys, keys);
+    for key in keys {
+        assert_eq!(compacted.get_versioned(&key)?, store.get_versioned(&key)?);
+    }
+    assert_eq!(compacted.get(b"key1")?, Some(b"value99!".to_vec()));
+    assert_eq!(compacted.get(b"key2")?, None);
+    Ok(())
+}
+
+// Compactions should apply the value transform to every live value they copy, and update
+// the secondary indexes with the transformed values.
+#[test]
+fn compaction_transforms_values() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"key1".to_vec(), b"value1")?;
+    store.set(b"key2".to_vec(), b"value2")?;
+    drop(store);
+
+    // Every write compacts the logs
+    let options = GrausDbOptions::default()
+        .compaction_strategy(Arc::new(ThresholdStrategy { threshold: 0 }))
+        .compaction_value_transform(Arc::new(|value: &[u8]| value.to_ascii_uppercase()));
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    store.register_index(
+        "first",
+        Arc::new(|value: &[u8]| value.first().map(|b| vec![*b])),
+    )?;
+    assert_eq!(store.get(b"key1")?, Some(b"value1".to_vec()));
+
+    store.set(b"key2".to_vec(), b"other2")?;
+    assert_eq!(store.compaction_count(), 1);
+    assert_eq!(store.get(b"key1")?, Some(b"VALUE1".to_vec()));
+    assert_eq!(store.get(b"key2")?, Some(b"OTHER2".to_vec()));
+    assert_eq!(
+        store.lookup_by_index("first", b"V")?,
+        vec![b"key1".to_vec()]
+    );
+    assert!(store.lookup_by_index("first", b"v")?.is_empty());
+    drop(store);
+
+    let store = GrausDb::open(temp_dir.path())?;
+    assert_eq!(store.get(b"key1")?, Some(b"VALUE1".to_vec()));
+    assert_eq!(store.get(b"key2")?, Some(b"OTHER2".to_vec()));
+    Ok(())
+}
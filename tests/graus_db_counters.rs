@@ -0,0 +1,84 @@
+use graus_db::{CounterOverflow, GrausDb, GrausError, Result};
+use std::thread;
+use tempfile::TempDir;
+
+// Should add positive and negative deltas, starting from 0
+#[test]
+fn counters_incr_get_and_reset() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    let counters = store.counters();
+
+    assert_eq!(counters.get(b"counter")?, 0);
+    assert_eq!(counters.incr(b"counter".to_vec(), 5)?, 5);
+    assert_eq!(counters.incr(b"counter".to_vec(), -7)?, -2);
+    assert_eq!(store.get(b"counter")?, Some((-2i64).to_le_bytes().to_vec()));
+
+    counters.reset(b"counter")?;
+    counters.reset(b"missing")?;
+    assert_eq!(counters.get(b"counter")?, 0);
+    assert!(!store.contains_key(b"counter"));
+    Ok(())
+}
+
+// Should saturate by default and wrap when configured
+#[test]
+fn counters_handle_overflow() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+
+    store.counters().incr(b"saturating".to_vec(), i64::MAX)?;
+    assert_eq!(store.counters().incr(b"saturating".to_vec(), 1)?, i64::MAX);
+
+    let wrapping = store.counters().overflow(CounterOverflow::Wrapping);
+    wrapping.incr(b"wrapping".to_vec(), i64::MAX)?;
+    assert_eq!(wrapping.incr(b"wrapping".to_vec(), 1)?, i64::MIN);
+    Ok(())
+}
+
+// Should fail without modifying values that are not counters
+#[test]
+fn counters_reject_malformed_values() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"key".to_vec(), b"value")?;
+
+    let counters = store.counters();
+    assert!(matches!(
+        counters.get(b"key"),
+        Err(GrausError::InvalidCounter { len: 5 })
+    ));
+    assert!(matches!(
+        counters.incr(b"key".to_vec(), 1),
+        Err(GrausError::InvalidCounter { len: 5 })
+    ));
+    assert_eq!(store.get(b"key")?, Some(b"value".to_vec()));
+    Ok(())
+}
+
+// Should not lose any increment when used from multiple threads
+#[test]
+fn counters_incr_is_atomic() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+
+    let mut handles = Vec::new();
+    for i in 0..50 {
+        let store = store.clone();
+        handles.push(thread::spawn(move || {
+            let delta = if i % 2 == 0 { 3 } else { -1 };
+            for _ in 0..10 {
+                store.counters().incr(b"counter".to_vec(), delta).unwrap();
+            }
+        }));
+    }
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    assert_eq!(store.counters().get(b"counter")?, 25 * 10 * 3 - 25 * 10);
+    drop(store);
+    let store = GrausDb::open(temp_dir.path())?;
+    assert_eq!(store.counters().get(b"counter")?, 500);
+    Ok(())
+}
@@ -0,0 +1,217 @@
+use graus_db::{
+    GrausDb, GrausDbOptions, GrausError, Nonce, Result, ThresholdStrategy, ValueCipher,
+};
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+// Toy cipher for the tests: XORs the values with the key and the nonce, and appends a tag
+// so values sealed with another key or nonce fail to open.
+struct XorCipher {
+    key: u8,
+}
+
+impl XorCipher {
+    fn keystream(&self, nonce: &Nonce, i: usize) -> u8 {
+        self.key ^ nonce[i % nonce.len()] ^ (i as u8)
+    }
+
+    fn tag(&self, nonce: &Nonce, plaintext: &[u8]) -> u8 {
+        plaintext
+            .iter()
+            .chain(nonce)
+            .fold(self.key, |tag, byte| tag.rotate_left(1) ^ byte)
+    }
+}
+
+impl ValueCipher for XorCipher {
+    fn seal(&self, nonce: &Nonce, plaintext: &[u8]) -> Vec<u8> {
+        let mut ciphertext: Vec<u8> = plaintext
+            .iter()
+            .enumerate()
+            .map(|(i, byte)| byte ^ self.keystream(nonce, i))
+            .collect();
+        ciphertext.push(self.tag(nonce, plaintext));
+        ciphertext
+    }
+
+    fn open(&self, nonce: &Nonce, ciphertext: &[u8]) -> Option<Vec<u8>> {
+        let (tag, ciphertext) = ciphertext.split_last()?;
+        let plaintext: Vec<u8> = ciphertext
+            .iter()
+            .enumerate()
+            .map(|(i, byte)| byte ^ self.keystream(nonce, i))
+            .collect();
+        (self.tag(nonce, &plaintext) == *tag).then_some(plaintext)
+    }
+}
+
+fn encrypted(key: u8) -> GrausDbOptions {
+    GrausDbOptions::default().value_cipher(Arc::new(XorCipher { key }))
+}
+
+// Returns whether `needle` appears in any log file of the database, value logs included.
+fn logs_contain(path: &Path, needle: &[u8]) -> Result<bool> {
+    for entry in fs::read_dir(path)? {
+        let path = entry?.path();
+        let found = if path.is_dir() {
+            logs_contain(&path, needle)?
+        } else {
+            let bytes = fs::read(path)?;
+            bytes.windows(needle.len()).any(|window| window == needle)
+        };
+        if found {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+// Values should be encrypted in the logs, and decrypted when they are read
+#[test]
+fn values_are_encrypted_at_rest() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open_with_options(temp_dir.path(), encrypted(42))?;
+    store.set(b"key1".to_vec(), b"secret value")?;
+    store.set(b"key2".to_vec(), b"another secret")?;
+    store.append(b"key2".to_vec(), b" appended")?;
+
+    assert_eq!(store.get(b"key1")?, Some(b"secret value".to_vec()));
+    assert_eq!(
+        store.get(b"key2")?,
+        Some(b"another secret appended".to_vec())
+    );
+    let mut streamed = Vec::new();
+    store
+        .get_stream(b"key2")?
+        .unwrap()
+        .read_to_end(&mut streamed)?;
+    assert_eq!(streamed, b"another secret appended");
+    assert_eq!(
+        store.get_history(b"key2")?.last().unwrap().1,
+        Some(b"another secret appended".to_vec())
+    );
+    drop(store);
+
+    assert!(!logs_contain(temp_dir.path(), b"secret")?);
+    // Keys are not encrypted
+    assert!(logs_contain(temp_dir.path(), b"key1")?);
+
+    let store = GrausDb::open_with_options(temp_dir.path(), encrypted(42))?;
+    assert_eq!(store.get(b"key1")?, Some(b"secret value".to_vec()));
+    assert_eq!(
+        store.get(b"key2")?,
+        Some(b"another secret appended".to_vec())
+    );
+    Ok(())
+}
+
+// Encrypted values should not be readable without the cipher they were written with
+#[test]
+fn reading_without_cipher_fails() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open_with_options(temp_dir.path(), encrypted(42))?;
+    store.set(b"key1".to_vec(), b"secret value")?;
+    drop(store);
+
+    let store = GrausDb::open(temp_dir.path())?;
+    assert!(matches!(
+        store.get(b"key1"),
+        Err(GrausError::DecryptionFailed)
+    ));
+    assert!(matches!(
+        store.get_stream(b"key1"),
+        Err(GrausError::DecryptionFailed)
+    ));
+    drop(store);
+
+    let store = GrausDb::open_with_options(temp_dir.path(), encrypted(7))?;
+    assert!(matches!(
+        store.get(b"key1"),
+        Err(GrausError::DecryptionFailed)
+    ));
+    Ok(())
+}
+
+// Values written before the cipher was set should still be readable
+#[test]
+fn plaintext_logs_are_readable_with_cipher() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"key1".to_vec(), b"plain value")?;
+    drop(store);
+
+    let store = GrausDb::open_with_options(temp_dir.path(), encrypted(42))?;
+    store.append(b"key1".to_vec(), b" and secret chunk")?;
+    assert_eq!(
+        store.get(b"key1")?,
+        Some(b"plain value and secret chunk".to_vec())
+    );
+    assert!(!logs_contain(temp_dir.path(), b"secret chunk")?);
+    Ok(())
+}
+
+// Compactions should keep the values encrypted, including folded appends
+#[test]
+fn compaction_keeps_values_encrypted() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = encrypted(42).compaction_strategy(Arc::new(ThresholdStrategy { threshold: 0 }));
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    store.set(b"key1".to_vec(), b"secret value")?;
+    store.set(b"key2".to_vec(), b"secret")?;
+    store.append(b"key2".to_vec(), b" folded")?;
+    store.set(b"key3".to_vec(), b"overwritten")?;
+    store.remove(b"key3")?;
+    assert!(store.compaction_count() > 0);
+
+    assert_eq!(store.get(b"key1")?, Some(b"secret value".to_vec()));
+    assert_eq!(store.get(b"key2")?, Some(b"secret folded".to_vec()));
+    assert!(!logs_contain(temp_dir.path(), b"secret")?);
+    Ok(())
+}
+
+// Values stored in value logs should be encrypted too, also when a compaction rewrites them
+#[test]
+fn value_logs_are_encrypted() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let value = b"secret ".repeat(100);
+    let options = encrypted(42).value_log_threshold(100);
+    let store = GrausDb::open_with_options(temp_dir.path(), options.clone())?;
+    for key in [b"key1", b"key2", b"key3"] {
+        store.set(key.to_vec(), &value)?;
+    }
+    assert!(temp_dir.path().join("values").join("1.log").exists());
+    assert_eq!(store.get(b"key1")?, Some(value.clone()));
+    let mut streamed = Vec::new();
+    store
+        .get_stream(b"key1")?
+        .unwrap()
+        .read_to_end(&mut streamed)?;
+    assert_eq!(streamed, value);
+    drop(store);
+
+    // Every write compacts the logs. Less than half of the value log is live once two
+    // keys are removed, so the value of the last one is rewritten.
+    let compacting = options
+        .clone()
+        .compaction_strategy(Arc::new(ThresholdStrategy { threshold: 0 }));
+    let store = GrausDb::open_with_options(temp_dir.path(), compacting)?;
+    store.remove(b"key2")?;
+    store.remove(b"key3")?;
+    assert!(!temp_dir.path().join("values").join("1.log").exists());
+    assert_eq!(store.get(b"key1")?, Some(value.clone()));
+    drop(store);
+    assert!(!logs_contain(temp_dir.path(), b"secret")?);
+
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    assert_eq!(store.get(b"key1")?, Some(value));
+    drop(store);
+    let store = GrausDb::open(temp_dir.path())?;
+    assert!(matches!(
+        store.get_stream(b"key1"),
+        Err(GrausError::DecryptionFailed)
+    ));
+    Ok(())
+}
@@ -0,0 +1,105 @@
+use graus_db::{GrausDb, Result};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use tempfile::TempDir;
+
+fn increment(value: &mut [u8]) {
+    let num = u64::from_le_bytes(value[..8].try_into().expect("incorrect length")) + 1;
+    value.copy_from_slice(&num.to_le_bytes());
+}
+
+// Should insert the default value when the key does not exist
+#[test]
+fn entry_inserts_default_when_not_exists() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+
+    let value = store
+        .entry(b"counter".to_vec())
+        .and_modify(|value| increment(value))
+        .or_insert(1u64.to_le_bytes().to_vec())?;
+
+    assert_eq!(value, 1u64.to_le_bytes().to_vec());
+    assert_eq!(store.get(b"counter")?, Some(1u64.to_le_bytes().to_vec()));
+    Ok(())
+}
+
+// Should modify the existing value and not call the default function
+#[test]
+fn entry_modifies_value_when_exists() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"counter".to_vec(), &5u64.to_le_bytes())?;
+
+    let value = store
+        .entry(b"counter".to_vec())
+        .and_modify(|value| increment(value))
+        .and_modify(|value| increment(value))
+        .or_insert_with(|| panic!("default should not be called"))?;
+
+    assert_eq!(value, 7u64.to_le_bytes().to_vec());
+    // Open from disk again and check persistent data
+    drop(store);
+    let store = GrausDb::open(temp_dir.path())?;
+    assert_eq!(store.get(b"counter")?, Some(7u64.to_le_bytes().to_vec()));
+    Ok(())
+}
+
+// Should not lose any increment when used from multiple threads
+#[test]
+fn entry_counter_is_atomic() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+
+    let mut handles = Vec::new();
+    for _ in 0..100 {
+        let store = store.clone();
+        handles.push(thread::spawn(move || {
+            store
+                .entry(b"counter".to_vec())
+                .and_modify(|value| increment(value))
+                .or_insert(1u64.to_le_bytes().to_vec())
+                .unwrap();
+        }));
+    }
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    assert_eq!(store.get(b"counter")?, Some(100u64.to_le_bytes().to_vec()));
+    Ok(())
+}
+
+// Should compute the default only once and return the same value to every thread
+#[test]
+fn get_or_set_runs_default_once() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    let calls = Arc::new(AtomicUsize::new(0));
+
+    let handles: Vec<_> = (0..20)
+        .map(|thread_id| {
+            let store = store.clone();
+            let calls = Arc::clone(&calls);
+            thread::spawn(move || {
+                store
+                    .get_or_set(b"key1".to_vec(), || {
+                        calls.fetch_add(1, Ordering::SeqCst);
+                        format!("value{}", thread_id).into_bytes()
+                    })
+                    .unwrap()
+            })
+        })
+        .collect();
+    let values: Vec<Vec<u8>> = handles
+        .into_iter()
+        .map(|handle| handle.join().unwrap())
+        .collect();
+
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+    let stored = store.get(b"key1")?.expect("key not found");
+    assert!(values.iter().all(|value| *value == stored));
+    assert_eq!(store.get_or_set(b"key1".to_vec(), Vec::new)?, stored);
+    Ok(())
+}
@@ -0,0 +1,39 @@
+use graus_db::{GrausDb, GrausError, Result};
+use std::fs;
+use tempfile::TempDir;
+
+// Should report the directory that could not be created
+#[test]
+fn open_reports_path_of_io_error() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let file_path = temp_dir.path().join("file");
+    fs::write(&file_path, b"not a directory")?;
+
+    match GrausDb::open(&file_path) {
+        Err(GrausError::FileIo { path, op, .. }) => {
+            assert_eq!(path, file_path);
+            assert_eq!(op, "create directory");
+        }
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+    Ok(())
+}
+
+// Should report the log that was deleted from outside when reading a value
+#[test]
+fn get_reports_missing_log() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"key".to_vec(), b"value")?;
+    let log_id = store.stat_key(b"key").expect("key not found").log_id;
+
+    // A clone opens its own handles to the logs
+    let clone = store.clone();
+    fs::remove_file(temp_dir.path().join(format!("{}.log", log_id)))?;
+
+    match clone.get(b"key") {
+        Err(GrausError::LogFileMissing { log_id: missing }) => assert_eq!(missing, log_id),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    Ok(())
+}
@@ -0,0 +1,102 @@
+use graus_db::{Executor, GrausDb, GrausDbOptions, Result, Task, ThreadPool, ThresholdStrategy};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+// Executor that counts the tasks before running them in a shared pool
+struct CountingExecutor {
+    pool: ThreadPool,
+    tasks: AtomicUsize,
+}
+
+impl Executor for CountingExecutor {
+    fn execute(&self, task: Task) {
+        self.tasks.fetch_add(1, Ordering::SeqCst);
+        self.pool.execute(task);
+    }
+}
+
+fn wait_until(condition: impl Fn() -> Result<bool>) -> Result<()> {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !condition()? {
+        assert!(Instant::now() < deadline, "timed out");
+        thread::sleep(Duration::from_millis(10));
+    }
+    Ok(())
+}
+
+// Compactions of several databases should run in the background on the same executor
+#[test]
+fn databases_share_the_executor() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let executor = Arc::new(CountingExecutor {
+        pool: ThreadPool::new(1),
+        tasks: AtomicUsize::new(0),
+    });
+    let options = GrausDbOptions::default()
+        .compaction_strategy(Arc::new(ThresholdStrategy { threshold: 10_000 }))
+        .executor(executor.clone());
+
+    let stores = (0..4)
+        .map(|i| GrausDb::open_with_options(temp_dir.path().join(i.to_string()), options.clone()))
+        .collect::<Result<Vec<GrausDb>>>()?;
+    for store in &stores {
+        for iter in 0..100 {
+            store.set(b"key".to_vec(), format!("{:0100}", iter).as_bytes())?;
+        }
+    }
+    // One compaction is scheduled for every database until it runs
+    assert!(executor.tasks.load(Ordering::SeqCst) >= stores.len());
+
+    for store in &stores {
+        wait_until(|| Ok(store.disk_size()? < 10_000))?;
+        assert_eq!(
+            store.get(b"key")?,
+            Some(format!("{:0100}", 99).into_bytes())
+        );
+    }
+    Ok(())
+}
+
+// Writes should not wait for the background compaction
+#[test]
+fn writes_do_not_run_compactions() -> Result<()> {
+    struct NeverExecutor;
+    impl Executor for NeverExecutor {
+        fn execute(&self, _task: Task) {}
+    }
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = GrausDbOptions::default()
+        .compaction_strategy(Arc::new(ThresholdStrategy { threshold: 0 }))
+        .executor(Arc::new(NeverExecutor));
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    for _ in 0..10 {
+        store.set(b"key".to_vec(), b"value")?;
+    }
+    // The stale values are still in the logs
+    assert!(store.get_history(b"key")?.len() == 10);
+    Ok(())
+}
+
+// A database dropped before its compaction runs should not fail the executor
+#[test]
+fn dropped_database_skips_compaction() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let pool = Arc::new(ThreadPool::new(1));
+    let options = GrausDbOptions::default()
+        .compaction_strategy(Arc::new(ThresholdStrategy { threshold: 0 }))
+        .executor(pool.clone());
+    {
+        let store = GrausDb::open_with_options(temp_dir.path(), options.clone())?;
+        store.set(b"key".to_vec(), b"value")?;
+    }
+
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    store.set(b"key".to_vec(), b"new value")?;
+    wait_until(|| Ok(store.get_history(b"key")?.len() == 1))?;
+    assert_eq!(store.get(b"key")?, Some(b"new value".to_vec()));
+    Ok(())
+}
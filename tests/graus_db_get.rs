@@ -1,4 +1,5 @@
-use graus_db::{GrausDb, Result};
+use graus_db::{GrausDb, GrausDbOptions, Result};
+use std::io::Read;
 use tempfile::TempDir;
 
 // Should get previously stored value
@@ -36,3 +37,171 @@ fn get_returns_value_when_not_exists() -> Result<()> {
 
     Ok(())
 }
+
+// Should return the default without storing it when the key does not exist
+#[test]
+fn get_or_returns_default() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"key1".to_vec(), b"value1")?;
+
+    assert_eq!(store.get_or(b"key1", b"default".to_vec())?, b"value1");
+    assert_eq!(store.get_or(b"key2", b"default".to_vec())?, b"default");
+    assert_eq!(
+        store.get_or_else(b"key1", || panic!("default computed for an existing key"))?,
+        b"value1"
+    );
+    assert_eq!(
+        store.get_or_else(b"key2", || b"computed".to_vec())?,
+        b"computed"
+    );
+    assert_eq!(store.get(b"key2")?, None);
+    Ok(())
+}
+
+// Should stream the stored value
+#[test]
+fn get_stream_reads_value() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    let value: Vec<u8> = (0..100_000u32).map(|i| i as u8).collect();
+    store.set(b"key1".to_vec(), &value)?;
+    store.set(b"key2".to_vec(), b"value2")?;
+
+    let mut stream = store.get_stream(b"key1")?.expect("key not found");
+    // Overwriting the key does not affect the open stream
+    store.set(b"key1".to_vec(), b"new value")?;
+    let mut streamed = Vec::new();
+    stream.read_to_end(&mut streamed)?;
+    assert_eq!(streamed, value);
+
+    let mut streamed = Vec::new();
+    store
+        .get_stream(b"key1")?
+        .expect("key not found")
+        .read_to_end(&mut streamed)?;
+    assert_eq!(streamed, b"new value");
+    assert!(store.get_stream(b"key3")?.is_none());
+    Ok(())
+}
+
+// Should return the values of a key from all the logs until they are compacted
+#[test]
+fn get_history_returns_uncompacted_values() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"key1".to_vec(), b"value1")?;
+    store.set(b"key2".to_vec(), b"other")?;
+    store.set(b"key1".to_vec(), b"value2")?;
+    drop(store);
+
+    let store = GrausDb::open(temp_dir.path())?;
+    store.remove(b"key1")?;
+    store.set(b"key1".to_vec(), b"value3")?;
+    assert_eq!(
+        store.get_history(b"key1")?,
+        vec![
+            (1, Some(b"value1".to_vec())),
+            (1, Some(b"value2".to_vec())),
+            (2, None),
+            (2, Some(b"value3".to_vec())),
+        ]
+    );
+    assert_eq!(store.get_history(b"missing")?, vec![]);
+    Ok(())
+}
+
+// Should read the same values as get directly from the logs
+#[test]
+fn get_uncached_reads_from_disk() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"key1".to_vec(), b"value1")?;
+    store.append(b"key1".to_vec(), b"+")?;
+    assert_eq!(store.get_uncached(b"key1")?, store.get(b"key1")?);
+    assert_eq!(store.get_uncached(b"missing")?, None);
+
+    // Open from disk again and check persistent data
+    drop(store);
+    let store = GrausDb::open(temp_dir.path())?;
+    assert_eq!(store.get_uncached(b"key1")?, Some(b"value1+".to_vec()));
+    Ok(())
+}
+
+// Values should be read and written with any buffer capacity
+#[test]
+fn custom_buffer_capacities() -> Result<()> {
+    for capacity in [1, 100, 1024 * 1024] {
+        let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+        let options = GrausDbOptions::default()
+            .flush_each_write(false)
+            .read_buffer_capacity(capacity)
+            .write_buffer_capacity(capacity);
+        let store = GrausDb::open_with_options(temp_dir.path(), options.clone())?;
+        for i in 0..100 {
+            store.set(format!("key{}", i).into_bytes(), &vec![i as u8; i * 10])?;
+        }
+        assert_eq!(store.get(b"key99")?, Some(vec![99; 990]));
+
+        drop(store);
+        let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+        for i in 0..100 {
+            let key = format!("key{}", i).into_bytes();
+            assert_eq!(store.get(&key)?, Some(vec![i as u8; i * 10]));
+        }
+    }
+    Ok(())
+}
+
+// Should return the bytes of the last command of a key as they are in its log
+#[test]
+fn raw_record_matches_log_bytes() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"key1".to_vec(), b"value1")?;
+    store.set(b"key2".to_vec(), b"value2")?;
+    store.set(b"key1".to_vec(), b"value3")?;
+    assert_eq!(store.raw_record(b"missing")?, None);
+
+    let record = store.raw_record(b"key1")?.expect("key not found");
+    assert!(record.ends_with(b"value3"));
+    let (_, cmd_pos) = store
+        .index_snapshot()
+        .into_iter()
+        .find(|(key, _)| key == b"key1")
+        .expect("key not found");
+    let log = std::fs::read(temp_dir.path().join(format!("{}.log", cmd_pos.log_id)))?;
+    let start = cmd_pos.pos as usize;
+    assert_eq!(record, &log[start..start + cmd_pos.len as usize]);
+    Ok(())
+}
+
+// Should get the values of a batch of keys lazily, in the order of the keys.
+#[test]
+fn get_batch_stream_follows_key_order() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    for key_id in 0..100 {
+        store.set(
+            format!("key{}", key_id).into_bytes(),
+            format!("value{}", key_id).as_bytes(),
+        )?;
+    }
+
+    let keys = [42, 7, 1000, 99, 7].map(|key_id| format!("key{}", key_id).into_bytes());
+    let mut values = store.get_batch_stream(keys.into_iter());
+    assert_eq!(values.next().transpose()?, Some(Some(b"value42".to_vec())));
+    // Keys written after the iterator was created are seen when it reaches them
+    store.set(b"key99".to_vec(), b"new")?;
+    let values: Vec<Option<Vec<u8>>> = values.collect::<Result<_>>()?;
+    assert_eq!(
+        values,
+        vec![
+            Some(b"value7".to_vec()),
+            None,
+            Some(b"new".to_vec()),
+            Some(b"value7".to_vec()),
+        ]
+    );
+    Ok(())
+}
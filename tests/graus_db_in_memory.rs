@@ -0,0 +1,56 @@
+use graus_db::{GrausDb, Result};
+use std::io::Read;
+
+// Should read, write and remove keys without a directory
+#[test]
+fn in_memory_reads_and_writes() -> Result<()> {
+    let store = GrausDb::open_in_memory()?;
+    store.set(b"key1".to_vec(), b"value1")?;
+    store.set(b"key2".to_vec(), b"value2")?;
+    store.append(b"key2".to_vec(), b"-appended")?;
+    store.remove(b"key1")?;
+
+    assert_eq!(store.get(b"key1")?, None);
+    assert_eq!(store.get(b"key2")?, Some(b"value2-appended".to_vec()));
+    let mut value = Vec::new();
+    store
+        .get_stream(b"key2")?
+        .expect("key not found")
+        .read_to_end(&mut value)?;
+    assert_eq!(value, b"value2-appended");
+    assert_eq!(store.get_history(b"key1")?.len(), 2);
+    assert!(store.disk_size()? > 0);
+
+    // Clones share the same logs
+    let clone = store.clone();
+    clone.set(b"key3".to_vec(), b"value3")?;
+    assert_eq!(store.get(b"key3")?, Some(b"value3".to_vec()));
+    Ok(())
+}
+
+// Compactions should drop the stale commands from the logs in memory
+#[test]
+fn in_memory_compacts_logs() -> Result<()> {
+    let store = GrausDb::open_in_memory()?;
+    let snapshot = store.snapshot()?;
+    for i in 0..10_000 {
+        store.set(format!("key{}", i % 10).into_bytes(), &[0; 100])?;
+    }
+    assert!(store.compaction_count() > 0);
+    // The snapshot keeps the compacted logs until it is dropped
+    assert!(snapshot.is_empty());
+    drop(snapshot);
+    store.set(b"key0".to_vec(), &[0; 100])?;
+    // The values alone take 1 MB without compactions
+    assert!(store.disk_size()? < 500_000);
+    for i in 0..10 {
+        assert_eq!(
+            store.get(format!("key{}", i).as_bytes())?,
+            Some(vec![0; 100])
+        );
+    }
+
+    // Every database in memory is independent
+    assert!(GrausDb::open_in_memory()?.get(b"key0")?.is_none());
+    Ok(())
+}
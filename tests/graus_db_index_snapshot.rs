@@ -0,0 +1,149 @@
+use graus_db::{GrausDb, GrausDbOptions, Result, SnapshotVerification};
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+
+fn options() -> GrausDbOptions {
+    GrausDbOptions::default().index_snapshot(true)
+}
+
+// Opening from a snapshot should produce the same index as replaying the logs
+#[test]
+fn snapshot_open_matches_replay() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let snapshot_path = temp_dir.path().join("index.snapshot");
+    let store = GrausDb::open_with_options(temp_dir.path(), options())?;
+    for i in 0..100 {
+        store.set(format!("key{}", i).into_bytes(), b"value")?;
+    }
+    for i in 0..10 {
+        store.remove(format!("key{}", i).as_bytes())?;
+        store.append(format!("key{}", i + 10).into_bytes(), b"1")?;
+    }
+    store.close()?;
+    assert!(snapshot_path.exists());
+
+    let store = GrausDb::open_with_options(temp_dir.path(), options())?;
+    // The snapshot is only used once
+    assert!(!snapshot_path.exists());
+    let mut from_snapshot = store.index_snapshot();
+    let estimate = store.compaction_estimate();
+    assert_eq!(store.get(b"key10")?, Some(b"value1".to_vec()));
+    assert_eq!(store.get(b"key0")?, None);
+    store.set(b"key100".to_vec(), b"value")?;
+    drop(store);
+
+    let store = GrausDb::open(temp_dir.path())?;
+    let mut replayed = store.index_snapshot();
+    replayed.retain(|(key, _)| key != b"key100");
+    // The hash index is not ordered
+    from_snapshot.sort_by(|(a, _), (b, _)| a.cmp(b));
+    replayed.sort_by(|(a, _), (b, _)| a.cmp(b));
+    assert_eq!(from_snapshot, replayed);
+    assert_eq!(store.compaction_estimate().dead_bytes, estimate.dead_bytes);
+    assert_eq!(store.get(b"key100")?, Some(b"value".to_vec()));
+    Ok(())
+}
+
+// Writes after closing should invalidate the snapshot, so the logs are replayed
+#[test]
+fn snapshot_is_discarded_if_logs_changed() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open_with_options(temp_dir.path(), options())?;
+    let other = store.clone();
+    store.set(b"key1".to_vec(), b"value1")?;
+    store.close()?;
+    other.set(b"key2".to_vec(), b"value2")?;
+    other.remove(b"key1")?;
+    drop(other);
+
+    let store = GrausDb::open_with_options(temp_dir.path(), options())?;
+    assert_eq!(store.get(b"key1")?, None);
+    assert_eq!(store.get(b"key2")?, Some(b"value2".to_vec()));
+    Ok(())
+}
+
+// A corrupted snapshot should be ignored
+#[test]
+fn corrupted_snapshot_is_ignored() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let snapshot_path = temp_dir.path().join("index.snapshot");
+    let store = GrausDb::open_with_options(temp_dir.path(), options())?;
+    store.set(b"key1".to_vec(), b"value1")?;
+    store.close()?;
+
+    let mut bytes = fs::read(&snapshot_path)?;
+    let last = bytes.len() - 10;
+    bytes[last] ^= 0xFF;
+    fs::write(&snapshot_path, bytes)?;
+
+    let store = GrausDb::open_with_options(temp_dir.path(), options())?;
+    assert_eq!(store.get(b"key1")?, Some(b"value1".to_vec()));
+    assert!(!snapshot_path.exists());
+    Ok(())
+}
+
+// Without the option, closing should not save a snapshot
+#[test]
+fn snapshot_is_disabled_by_default() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"key1".to_vec(), b"value1")?;
+    store.close()?;
+    assert!(!temp_dir.path().join("index.snapshot").exists());
+    Ok(())
+}
+
+// Creates two sealed logs of the same length, with a key each, and a snapshot of them.
+fn logs_of_same_length(path: &Path) -> Result<()> {
+    let store = GrausDb::open(path)?;
+    store.set(b"key1".to_vec(), b"value1")?;
+    drop(store);
+    let store = GrausDb::open(path)?;
+    store.set(b"key2".to_vec(), b"value2")?;
+    drop(store);
+    // Seals both logs and saves the snapshot
+    GrausDb::open_with_options(path, options())?.close()?;
+    assert_eq!(
+        fs::metadata(path.join("1.log"))?.len(),
+        fs::metadata(path.join("2.log"))?.len()
+    );
+    Ok(())
+}
+
+// Swaps the content of two logs, which keeps their lengths.
+fn swap_logs(path: &Path) -> Result<()> {
+    fs::rename(path.join("1.log"), path.join("tmp"))?;
+    fs::rename(path.join("2.log"), path.join("1.log"))?;
+    fs::rename(path.join("tmp"), path.join("2.log"))?;
+    Ok(())
+}
+
+// A snapshot that doesn't match the logs should be discarded when it is verified
+#[test]
+fn mismatched_snapshot_triggers_replay() -> Result<()> {
+    for verification in [SnapshotVerification::Sample(1), SnapshotVerification::Full] {
+        let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+        logs_of_same_length(temp_dir.path())?;
+        swap_logs(temp_dir.path())?;
+
+        let options = options().snapshot_verification(verification);
+        let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+        assert_eq!(store.get(b"key1")?, Some(b"value1".to_vec()));
+        assert_eq!(store.get(b"key2")?, Some(b"value2".to_vec()));
+    }
+    Ok(())
+}
+
+// Without verification, only the length of the logs is checked
+#[test]
+fn unverified_snapshot_is_trusted() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    logs_of_same_length(temp_dir.path())?;
+    swap_logs(temp_dir.path())?;
+
+    let options = options().snapshot_verification(SnapshotVerification::Off);
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    assert_eq!(store.stat_key(b"key1").map(|stat| stat.log_id), Some(1));
+    Ok(())
+}
@@ -0,0 +1,78 @@
+use graus_db::{GrausDb, GrausDbOptions, GrausError, Result};
+use std::cmp::Ordering;
+use tempfile::TempDir;
+
+// Orders 8-byte little-endian integer keys numerically.
+fn le_u64_comparator(a: &[u8], b: &[u8]) -> Ordering {
+    let a = u64::from_le_bytes(a.try_into().expect("incorrect length"));
+    let b = u64::from_le_bytes(b.try_into().expect("incorrect length"));
+    a.cmp(&b)
+}
+
+// Should set, get and remove values when using a custom comparator
+#[test]
+#[cfg_attr(feature = "hash-index", ignore = "the hash index has no comparator")]
+fn custom_comparator_stores_and_retrieves_values() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = GrausDbOptions::default().key_comparator(le_u64_comparator);
+    let store = GrausDb::open_with_options(temp_dir.path(), options.clone())?;
+
+    for i in 0..100u64 {
+        store.set(i.to_le_bytes().to_vec(), format!("value{}", i).as_bytes())?;
+    }
+    store.remove(&7u64.to_le_bytes())?;
+
+    assert_eq!(store.get(&3u64.to_le_bytes())?, Some(b"value3".to_vec()));
+    assert_eq!(store.get(&7u64.to_le_bytes())?, None);
+
+    // Open from disk again and check persistent data
+    drop(store);
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    for i in 0..100u64 {
+        let expected = (i != 7).then(|| format!("value{}", i).into_bytes());
+        assert_eq!(store.get(&i.to_le_bytes())?, expected);
+    }
+    Ok(())
+}
+
+// Should drain the keys under a prefix in the order of a custom comparator
+#[test]
+#[cfg_attr(feature = "hash-index", ignore = "the hash index has no comparator")]
+fn custom_comparator_drain_prefix() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = GrausDbOptions::default().key_comparator(|a, b| b.cmp(a));
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    store.set(b"a".to_vec(), b"value")?;
+    store.set(b"job:1".to_vec(), b"value1")?;
+    store.set(b"job:2".to_vec(), b"value2")?;
+    store.set(b"z".to_vec(), b"value")?;
+
+    let keys: Vec<Vec<u8>> = store
+        .drain_prefix(b"job:")?
+        .into_iter()
+        .map(|(key, _)| key)
+        .collect();
+    assert_eq!(keys, vec![b"job:2".to_vec(), b"job:1".to_vec()]);
+    assert!(store.contains_key(b"a") && store.contains_key(b"z"));
+    Ok(())
+}
+
+// The hash index should be rejected with the options it can't work with.
+#[test]
+#[cfg_attr(
+    not(feature = "hash-index"),
+    ignore = "only the hash index rejects them"
+)]
+fn hash_index_with_incompatible_options_fails_to_open() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = GrausDbOptions::default().key_comparator(le_u64_comparator);
+    assert!(matches!(
+        GrausDb::open_with_options(temp_dir.path(), options),
+        Err(GrausError::IncompatibleOptions(_))
+    ));
+    let options = GrausDbOptions::default().enable_bloom_filter(0.01);
+    assert!(matches!(
+        GrausDb::open_with_options(temp_dir.path(), options),
+        Err(GrausError::IncompatibleOptions(_))
+    ));
+}
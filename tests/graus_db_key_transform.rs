@@ -0,0 +1,115 @@
+use graus_db::{GrausDb, GrausDbOptions, GrausError, KeyTransform, Result};
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+fn options() -> GrausDbOptions {
+    let invert: KeyTransform = Arc::new(|key| key.iter().map(|byte| !byte).collect());
+    GrausDbOptions::default().key_transform(invert)
+}
+
+fn logs_contain(root: &Path, needle: &[u8]) -> bool {
+    fs::read_dir(root).unwrap().any(|entry| {
+        let path = entry.unwrap().path();
+        path.extension().is_some_and(|ext| ext == "log")
+            && fs::read(&path)
+                .unwrap()
+                .windows(needle.len())
+                .any(|window| window == needle)
+    })
+}
+
+fn is_incompatible<T>(result: Result<T>) -> bool {
+    matches!(result, Err(GrausError::IncompatibleOptions(_)))
+}
+
+// Keys should be transformed before they are stored, and on every lookup.
+#[test]
+fn keys_are_transformed_at_rest() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open_with_options(temp_dir.path(), options())?;
+    store.set(b"secret1".to_vec(), b"value1")?;
+    store.set(b"secret2".to_vec(), b"value2")?;
+    store.set(b"secret3".to_vec(), b"value3")?;
+    store.remove(b"secret3")?;
+    store.flush()?;
+    assert!(!logs_contain(temp_dir.path(), b"secret"));
+    assert!(logs_contain(temp_dir.path(), b"value1"));
+
+    assert_eq!(store.get(b"secret1")?, Some(b"value1".to_vec()));
+    assert!(store.contains_key(b"secret2"));
+    assert_eq!(store.get(b"secret3")?, None);
+    assert!(store.stat_key(b"secret1").is_some());
+    assert!(matches!(
+        store.remove(b"secret3"),
+        Err(GrausError::KeyNotFound)
+    ));
+    let snapshot = store.snapshot()?;
+    assert_eq!(snapshot.get(b"secret1")?, Some(b"value1".to_vec()));
+    assert!(!snapshot.contains_key(b"secret3"));
+    drop(snapshot);
+
+    store.transaction(|tx| {
+        tx.set(b"secret4".to_vec(), b"value4");
+        tx.remove(b"secret1");
+        Ok(())
+    })?;
+    assert!(store.rename(b"secret2", b"secret5".to_vec(), false)?);
+    store.update_if(
+        b"secret5".to_vec(),
+        |value| value.push(b'!'),
+        Some(b"secret4"),
+        Some(|value: &[u8]| value == b"value4"),
+    )?;
+    assert_eq!(store.counters().incr(b"counter".to_vec(), 2)?, 2);
+    store.flush()?;
+    assert!(!logs_contain(temp_dir.path(), b"secret"));
+    assert!(!logs_contain(temp_dir.path(), b"counter"));
+    drop(store);
+
+    let store = GrausDb::open_with_options(temp_dir.path(), options())?;
+    assert_eq!(store.get(b"secret1")?, None);
+    assert_eq!(store.get(b"secret2")?, None);
+    assert_eq!(store.get(b"secret4")?, Some(b"value4".to_vec()));
+    assert_eq!(store.get(b"secret5")?, Some(b"value2!".to_vec()));
+    assert_eq!(store.counters().get(b"counter")?, 2);
+    // Without the transform, the keys are not found
+    drop(store);
+    let store = GrausDb::open(temp_dir.path())?;
+    assert_eq!(store.get(b"secret4")?, None);
+    Ok(())
+}
+
+// The operations that return or scan keys should fail when the keys are transformed.
+#[test]
+fn scans_fail_with_transformed_keys() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open_with_options(temp_dir.path(), options())?;
+    store.set(b"key1".to_vec(), b"value1")?;
+    store.set(7u64.to_be_bytes().to_vec(), b"value2")?;
+    assert!(is_incompatible(store.list_keys(None, 10)));
+    assert!(is_incompatible(store.scan_prefixes(&[b"key"])));
+    assert!(is_incompatible(store.remove_prefix(b"key")));
+    assert!(is_incompatible(store.drain_prefix(b"key")));
+    assert!(is_incompatible(store.retain_scan(|_, _| true)));
+    assert!(is_incompatible(store.scan_values_containing(b"value")));
+    assert!(is_incompatible(store.scan_values_containing_cancellable(
+        b"value",
+        &AtomicBool::new(false)
+    )));
+    assert!(is_incompatible(store.value_counts()));
+    assert!(is_incompatible(store.namespace(b"ns").scan()));
+    assert!(is_incompatible(store.lookup_by_index("index", b"value")));
+    let physical: Vec<_> = store.iter_physical().collect();
+    assert_eq!(physical.len(), 1);
+    assert!(is_incompatible(physical.into_iter().next().unwrap()));
+    let ints: Vec<_> = store.scan_int_range(0, 10).collect();
+    assert_eq!(ints.len(), 1);
+    assert!(is_incompatible(ints.into_iter().next().unwrap()));
+
+    // Nothing was removed by the prefix operations
+    assert_eq!(store.get(b"key1")?, Some(b"value1".to_vec()));
+    Ok(())
+}
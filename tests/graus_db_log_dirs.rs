@@ -0,0 +1,104 @@
+use graus_db::{GrausDb, GrausDbOptions, Result};
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+use walkdir::WalkDir;
+
+// Returns the paths of the logs, relative to the database directory
+fn log_paths(path: &Path) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = WalkDir::new(path)
+        .into_iter()
+        .map(|entry| entry.expect("unable to list the directory").into_path())
+        .filter(|path| path.extension() == Some("log".as_ref()))
+        .map(|log_path| log_path.strip_prefix(path).unwrap().to_path_buf())
+        .collect();
+    paths.sort();
+    paths
+}
+
+// Should store every log in the subdirectory of its range of ids
+#[test]
+fn logs_are_stored_in_subdirectories() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = GrausDbOptions::default().logs_per_directory(2);
+    for i in 0..3 {
+        let store = GrausDb::open_with_options(temp_dir.path(), options.clone())?;
+        store.set(format!("key{}", i).into_bytes(), b"value")?;
+    }
+
+    let expected: Vec<PathBuf> = ["0/1.log", "1/2.log", "1/3.log"]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+    assert_eq!(log_paths(temp_dir.path()), expected);
+
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    for i in 0..3 {
+        assert_eq!(
+            store.get(format!("key{}", i).as_bytes())?,
+            Some(b"value".to_vec())
+        );
+    }
+    Ok(())
+}
+
+// Should move the existing logs when the layout changes between opens
+#[test]
+fn logs_are_moved_when_the_layout_changes() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"key1".to_vec(), b"value1")?;
+    drop(store);
+
+    let options = GrausDbOptions::default().logs_per_directory(10);
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    store.set(b"key2".to_vec(), b"value2")?;
+    assert_eq!(
+        log_paths(temp_dir.path()),
+        vec![PathBuf::from("0/1.log"), PathBuf::from("0/2.log")]
+    );
+    drop(store);
+
+    // Back to the flat layout, the empty subdirectory is removed
+    let store = GrausDb::open(temp_dir.path())?;
+    assert_eq!(
+        log_paths(temp_dir.path()),
+        vec![
+            PathBuf::from("1.log"),
+            PathBuf::from("2.log"),
+            PathBuf::from("3.log")
+        ]
+    );
+    assert!(!temp_dir.path().join("0").exists());
+    assert_eq!(store.get(b"key1")?, Some(b"value1".to_vec()));
+    assert_eq!(store.get(b"key2")?, Some(b"value2".to_vec()));
+    Ok(())
+}
+
+// Should only create the active log on the first write when it is not created on open
+#[test]
+fn active_log_is_created_on_first_write() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"key1".to_vec(), b"value1")?;
+    drop(store);
+
+    let options = GrausDbOptions::default().create_active_log(false);
+    let store = GrausDb::open_with_options(temp_dir.path(), options.clone())?;
+    assert_eq!(store.get(b"key1")?, Some(b"value1".to_vec()));
+    store.flush()?;
+    store.close()?;
+    assert_eq!(log_paths(temp_dir.path()), vec![PathBuf::from("1.log")]);
+
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    store.set(b"key2".to_vec(), b"value2")?;
+    assert_eq!(store.get(b"key2")?, Some(b"value2".to_vec()));
+    assert_eq!(
+        log_paths(temp_dir.path()),
+        vec![PathBuf::from("1.log"), PathBuf::from("2.log")]
+    );
+    drop(store);
+    let store = GrausDb::open(temp_dir.path())?;
+    assert_eq!(store.get(b"key1")?, Some(b"value1".to_vec()));
+    assert_eq!(store.get(b"key2")?, Some(b"value2".to_vec()));
+    Ok(())
+}
@@ -0,0 +1,58 @@
+use graus_db::{GrausDb, Result};
+use std::fs::{self, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use tempfile::TempDir;
+
+const FOOTER_LEN: usize = 17;
+
+// Logs that will not be written again should end with a footer after reopening
+#[test]
+fn logs_are_sealed_on_open() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"key1".to_vec(), b"value1")?;
+    store.set(b"key2".to_vec(), b"value2")?;
+    drop(store);
+
+    let log_path = temp_dir.path().join("1.log");
+    let unsealed_len = fs::metadata(&log_path)?.len();
+    let store = GrausDb::open(temp_dir.path())?;
+    let log = fs::read(&log_path)?;
+    assert_eq!(log.len() as u64, unsealed_len + FOOTER_LEN as u64);
+    assert_eq!(&log[log.len() - FOOTER_LEN..][1..5], b"GRSF");
+    assert_eq!(store.get(b"key1")?, Some(b"value1".to_vec()));
+    drop(store);
+
+    // Open from disk again, the footer is verified and not written twice
+    let store = GrausDb::open(temp_dir.path())?;
+    assert_eq!(fs::read(&log_path)?, log);
+    assert_eq!(store.get(b"key1")?, Some(b"value1".to_vec()));
+    assert_eq!(store.get(b"key2")?, Some(b"value2".to_vec()));
+    Ok(())
+}
+
+// A log whose footer doesn't match should still be loaded by scanning it
+#[test]
+fn corrupted_footer_falls_back_to_full_scan() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"key1".to_vec(), b"value1")?;
+    drop(store);
+    drop(GrausDb::open(temp_dir.path())?);
+
+    // Corrupt the checksum of the footer
+    let mut log = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(temp_dir.path().join("1.log"))?;
+    let mut last_byte = [0; 1];
+    log.seek(SeekFrom::End(-1))?;
+    log.read_exact(&mut last_byte)?;
+    log.seek(SeekFrom::End(-1))?;
+    log.write_all(&[!last_byte[0]])?;
+    drop(log);
+
+    let store = GrausDb::open(temp_dir.path())?;
+    assert_eq!(store.get(b"key1")?, Some(b"value1".to_vec()));
+    Ok(())
+}
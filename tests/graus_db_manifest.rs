@@ -0,0 +1,95 @@
+use graus_db::{DbMetadata, GrausDb, GrausError, Result};
+use std::fs;
+use std::time::{Duration, UNIX_EPOCH};
+use tempfile::TempDir;
+
+// Should keep the metadata in the manifest across reopens
+#[test]
+fn set_metadata_persists() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    // Databases without a manifest have the default metadata
+    assert_eq!(store.metadata(), DbMetadata::default());
+    assert!(!temp_dir.path().join("manifest").exists());
+
+    let mut metadata = DbMetadata {
+        schema_version: 3,
+        created_at: Some(UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+        ..DbMetadata::default()
+    };
+    metadata.tags.insert("app".to_owned(), "orders".to_owned());
+    metadata.tags.insert("owner".to_owned(), String::new());
+    store.set_metadata(metadata.clone())?;
+    assert_eq!(store.metadata(), metadata);
+    store.set(b"key".to_vec(), b"value")?;
+    drop(store);
+
+    let store = GrausDb::open(temp_dir.path())?;
+    assert_eq!(store.metadata(), metadata);
+    assert_eq!(store.get(b"key")?, Some(b"value".to_vec()));
+    Ok(())
+}
+
+// A corrupted manifest should fail to open instead of losing the metadata
+#[test]
+fn corrupted_manifest_fails_to_open() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set_metadata(DbMetadata {
+        schema_version: 1,
+        ..DbMetadata::default()
+    })?;
+    drop(store);
+
+    let manifest_path = temp_dir.path().join("manifest");
+    let mut manifest = fs::read(&manifest_path)?;
+    manifest[12] ^= 0xFF;
+    fs::write(&manifest_path, manifest)?;
+    assert!(matches!(
+        GrausDb::open(temp_dir.path()),
+        Err(GrausError::SerializationError(_))
+    ));
+    Ok(())
+}
+
+// Databases in memory should keep the metadata without writing a manifest
+#[test]
+fn in_memory_metadata() -> Result<()> {
+    let store = GrausDb::open_in_memory()?;
+    let metadata = DbMetadata {
+        schema_version: 2,
+        ..DbMetadata::default()
+    };
+    store.set_metadata(metadata.clone())?;
+    assert_eq!(store.clone().metadata(), metadata);
+    Ok(())
+}
+
+// A database stamped with a newer format version should not be misread
+#[test]
+fn future_format_version_fails_to_open() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"key".to_vec(), b"value")?;
+    store.set_metadata(DbMetadata::default())?;
+    drop(store);
+
+    // The rest of a newer manifest may not even be parseable
+    let manifest_path = temp_dir.path().join("manifest");
+    let mut manifest = b"GRSM".to_vec();
+    manifest.extend_from_slice(&1000u64.to_le_bytes());
+    manifest.extend_from_slice(b"unknown fields");
+    fs::write(&manifest_path, manifest)?;
+    let log_len = fs::metadata(temp_dir.path().join("1.log"))?.len();
+    assert!(matches!(
+        GrausDb::open(temp_dir.path()),
+        Err(GrausError::UnsupportedFormatVersion {
+            found: 1000,
+            supported: 2
+        })
+    ));
+    // The logs are left untouched
+    assert_eq!(fs::metadata(temp_dir.path().join("1.log"))?.len(), log_len);
+    assert!(!temp_dir.path().join("2.log").exists());
+    Ok(())
+}
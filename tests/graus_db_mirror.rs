@@ -0,0 +1,103 @@
+use graus_db::{GrausDb, GrausDbOptions, MirrorMode, Result, ThresholdStrategy};
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+// Returns the name and content of every log in the directory.
+fn logs(dir: &Path) -> BTreeMap<String, Vec<u8>> {
+    fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension() == Some("log".as_ref()))
+        .map(|path| {
+            let name = path.file_name().unwrap().to_string_lossy().into_owned();
+            (name, fs::read(path).unwrap())
+        })
+        .collect()
+}
+
+// Every write and compaction should be copied into the mirror
+#[test]
+fn writes_are_mirrored() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mirror_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = GrausDbOptions::default()
+        .mirror(mirror_dir.path(), MirrorMode::Required)
+        .compaction_strategy(Arc::new(ThresholdStrategy { threshold: 1024 }));
+    let store = GrausDb::open_with_options(temp_dir.path(), options.clone())?;
+    for i in 0..100 {
+        store.set(format!("key{}", i % 10).into_bytes(), b"value")?;
+    }
+    store.remove(b"key0")?;
+    store.append(b"key1".to_vec(), b"1")?;
+    assert!(store.compaction_count() > 0);
+    assert_eq!(logs(temp_dir.path()), logs(mirror_dir.path()));
+
+    // Logs sealed when opening them again are copied too
+    drop(store);
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    store.flush()?;
+    assert_eq!(logs(temp_dir.path()), logs(mirror_dir.path()));
+    Ok(())
+}
+
+// Corrupted logs should be restored from the mirror
+#[test]
+fn corrupted_logs_are_restored_from_mirror() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mirror_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = GrausDbOptions::default().mirror(mirror_dir.path(), MirrorMode::Required);
+    let store = GrausDb::open_with_options(temp_dir.path(), options.clone())?;
+    store.set(b"key1".to_vec(), b"value1")?;
+    store.set(b"key2".to_vec(), b"value2")?;
+    drop(store);
+
+    // Overwrite the type of the first command
+    let mut file = OpenOptions::new()
+        .write(true)
+        .open(temp_dir.path().join("1.log"))?;
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(&[0xFF])?;
+    drop(file);
+    assert!(GrausDb::open(temp_dir.path()).is_err());
+
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    assert_eq!(store.get(b"key1")?, Some(b"value1".to_vec()));
+    assert_eq!(store.get(b"key2")?, Some(b"value2".to_vec()));
+    drop(store);
+    let store = GrausDb::open(temp_dir.path())?;
+    assert_eq!(store.get(b"key1")?, Some(b"value1".to_vec()));
+    Ok(())
+}
+
+// Failing to write into the mirror should only fail the writes in `MirrorMode::Required`
+#[test]
+fn mirror_mode_decides_failures() -> Result<()> {
+    for mode in [MirrorMode::Required, MirrorMode::BestEffort] {
+        let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+        let mirror_dir = TempDir::new().expect("unable to create temporary working directory");
+        let mirror_path = mirror_dir.path().join("mirror");
+        let options = GrausDbOptions::default()
+            .mirror(&mirror_path, mode)
+            .compaction_strategy(Arc::new(ThresholdStrategy { threshold: 0 }));
+        let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+        store.set(b"key1".to_vec(), b"value1")?;
+
+        // New logs can't be created in the mirror, so the compaction fails to write into it
+        fs::remove_dir_all(&mirror_path)?;
+        fs::write(&mirror_path, b"")?;
+        let result = store.set(b"key1".to_vec(), b"value2");
+        assert_eq!(result.is_err(), mode == MirrorMode::Required);
+        // The failed write is still in the logs
+        assert_eq!(store.get(b"key1")?, Some(b"value2".to_vec()));
+        if mode == MirrorMode::BestEffort {
+            // The mirror is not written anymore
+            store.set(b"key1".to_vec(), b"value3")?;
+            assert_eq!(store.get(b"key1")?, Some(b"value3".to_vec()));
+        }
+    }
+    Ok(())
+}
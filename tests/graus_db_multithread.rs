@@ -1,6 +1,8 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
 use std::thread;
 
-use graus_db::{GrausDb, Result};
+use graus_db::{GrausDb, GrausDbOptions, Result, ThresholdStrategy};
 use std::convert::TryInto;
 use tempfile::TempDir;
 
@@ -104,7 +106,7 @@ fn concurrent_update_if() -> Result<()> {
     let store = GrausDb::open(temp_dir.path())?;
     let key = "key1";
     let initial_value = 1001u64.to_le_bytes();
-    store.set(key.as_bytes().to_vec(), &initial_value.to_vec())?;
+    store.set(key.as_bytes().to_vec(), &initial_value)?;
 
     let mut handles = Vec::new();
     for _ in 0..1000 {
@@ -136,7 +138,7 @@ fn concurrent_update_if() -> Result<()> {
 
     // Test with predicate
     let value = 25u64.to_le_bytes();
-    store.set(key.as_bytes().to_vec(), &value.to_vec())?;
+    store.set(key.as_bytes().to_vec(), &value)?;
 
     let mut handles = Vec::new();
     for _ in 0..1000 {
@@ -179,3 +181,129 @@ fn concurrent_update_if() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn concurrent_get_during_compaction() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    let value =
+        |key_id: usize, round: usize| format!("value{}-{}-{}", key_id, round, "x".repeat(500));
+    for key_id in 0..2000 {
+        store.set(
+            format!("key{}", key_id).into_bytes(),
+            value(key_id, 0).as_bytes(),
+        )?;
+    }
+
+    // Overwrite every key several times, so multiple compactions run while reading
+    let done = Arc::new(AtomicBool::new(false));
+    let writer = {
+        let store = store.clone();
+        let done = Arc::clone(&done);
+        thread::spawn(move || {
+            for round in 1..20 {
+                for key_id in 0..2000 {
+                    store
+                        .set(
+                            format!("key{}", key_id).into_bytes(),
+                            value(key_id, round).as_bytes(),
+                        )
+                        .unwrap();
+                }
+            }
+            done.store(true, Ordering::SeqCst);
+        })
+    };
+
+    let mut handles = Vec::new();
+    for thread_id in 0..8 {
+        let store = store.clone();
+        let done = Arc::clone(&done);
+        let handle = thread::spawn(move || {
+            let mut i = thread_id;
+            while !done.load(Ordering::SeqCst) {
+                let key_id = i % 2000;
+                let stored = store
+                    .get(format!("key{}", key_id).as_bytes())
+                    .unwrap()
+                    .expect("key not found");
+                assert!(stored.starts_with(format!("value{}-", key_id).as_bytes()));
+                i += 7;
+            }
+        });
+        handles.push(handle);
+    }
+
+    writer.join().unwrap();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    for key_id in 0..2000 {
+        assert_eq!(
+            store.get(format!("key{}", key_id).as_bytes())?,
+            Some(value(key_id, 19).into_bytes())
+        );
+    }
+
+    Ok(())
+}
+
+// After a write returns, reads from any thread should see it, even if writes are not
+// flushed right away and the logs are compacted meanwhile
+#[test]
+fn read_your_writes() -> Result<()> {
+    for flush_each_write in [true, false] {
+        let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+        let options = GrausDbOptions::default()
+            .flush_each_write(flush_each_write)
+            .compaction_strategy(Arc::new(ThresholdStrategy { threshold: 4096 }));
+        let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+        // Last value written by every writer
+        let written: Arc<Vec<AtomicU64>> = Arc::new((0..4).map(|_| AtomicU64::new(0)).collect());
+        let done = Arc::new(AtomicBool::new(false));
+
+        let writers: Vec<_> = (0..4)
+            .map(|writer_id| {
+                let store = store.clone();
+                let written = Arc::clone(&written);
+                thread::spawn(move || {
+                    let key = format!("key{}", writer_id).into_bytes();
+                    for i in 1..=500u64 {
+                        store.set(key.clone(), &i.to_le_bytes()).unwrap();
+                        written[writer_id].store(i, Ordering::SeqCst);
+                        assert_eq!(store.get(&key).unwrap(), Some(i.to_le_bytes().to_vec()));
+                    }
+                })
+            })
+            .collect();
+        let readers: Vec<_> = (0..4)
+            .map(|reader_id| {
+                let store = store.clone();
+                let written = Arc::clone(&written);
+                let done = Arc::clone(&done);
+                thread::spawn(move || {
+                    let mut writer_id = reader_id;
+                    while !done.load(Ordering::SeqCst) {
+                        writer_id = (writer_id + 1) % 4;
+                        let written = written[writer_id].load(Ordering::SeqCst);
+                        let key = format!("key{}", writer_id).into_bytes();
+                        let read = store.get(&key).unwrap().map_or(0, |value| {
+                            u64::from_le_bytes(value.try_into().expect("incorrect length"))
+                        });
+                        assert!(read >= written, "read {} after {}", read, written);
+                    }
+                })
+            })
+            .collect();
+
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        done.store(true, Ordering::SeqCst);
+        for reader in readers {
+            reader.join().unwrap();
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,38 @@
+use graus_db::{GrausDb, GrausError, Result};
+use tempfile::TempDir;
+
+// The same key in different namespaces should not collide
+#[test]
+fn namespaces_do_not_collide() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    let users = store.namespace(b"users");
+    // Its prefix would start with the one of "users" without the length
+    let users2 = store.namespace(b"users2");
+
+    users.set(b"2key".to_vec(), b"user")?;
+    users2.set(b"key".to_vec(), b"user2")?;
+    users.set(b"key".to_vec(), b"user")?;
+    assert_eq!(users.get(b"key")?, Some(b"user".to_vec()));
+    assert_eq!(users2.get(b"key")?, Some(b"user2".to_vec()));
+    assert_eq!(users2.get(b"2key")?, None);
+    assert_eq!(store.get(b"key")?, None);
+
+    // The keys are not ordered with the `hash-index` feature
+    let mut entries = users.scan()?;
+    entries.sort();
+    assert_eq!(
+        entries,
+        vec![
+            (b"2key".to_vec(), b"user".to_vec()),
+            (b"key".to_vec(), b"user".to_vec())
+        ]
+    );
+    assert_eq!(users2.scan()?, vec![(b"key".to_vec(), b"user2".to_vec())]);
+
+    users.remove(b"key")?;
+    assert_eq!(users.get(b"key")?, None);
+    assert_eq!(users2.get(b"key")?, Some(b"user2".to_vec()));
+    assert!(matches!(users.remove(b"key"), Err(GrausError::KeyNotFound)));
+    Ok(())
+}
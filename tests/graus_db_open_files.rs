@@ -0,0 +1,80 @@
+#![cfg(target_os = "linux")]
+
+use graus_db::{GrausDb, GrausDbOptions, Result, ThresholdStrategy};
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+fn open_files() -> usize {
+    fs::read_dir("/proc/self/fd").unwrap().count()
+}
+
+// Reading from many logs should keep at most `max_open_readers` of them open
+#[test]
+fn readers_stay_under_max_open_readers() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    // Every open creates a new log
+    for i in 0..30 {
+        let store = GrausDb::open(temp_dir.path())?;
+        store.set(format!("key{}", i).into_bytes(), b"value")?;
+    }
+
+    let files_before = open_files();
+    let options = GrausDbOptions::default().max_open_readers(4);
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    for _ in 0..2 {
+        for i in 0..30 {
+            assert_eq!(
+                store.get(format!("key{}", i).as_bytes())?,
+                Some(b"value".to_vec())
+            );
+            // The readers and the active log
+            assert!(open_files() - files_before <= 4 + 1);
+        }
+    }
+    Ok(())
+}
+
+// Returns the open files in `dir` that have been deleted.
+fn open_deleted_files(dir: &Path) -> usize {
+    fs::read_dir("/proc/self/fd")
+        .unwrap()
+        .filter_map(|fd| fs::read_link(fd.unwrap().path()).ok())
+        .filter(|target| {
+            target.starts_with(dir) && target.to_string_lossy().ends_with(" (deleted)")
+        })
+        .count()
+}
+
+// Should close the handles of the logs deleted by a compaction in another clone
+#[test]
+fn release_stale_handles_closes_deleted_logs() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    // Every open creates a new log
+    for i in 0..10 {
+        let store = GrausDb::open(temp_dir.path())?;
+        store.set(format!("key{}", i).into_bytes(), b"value")?;
+    }
+
+    let options =
+        GrausDbOptions::default().compaction_strategy(Arc::new(ThresholdStrategy { threshold: 0 }));
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    let idle = store.clone();
+    for i in 0..10 {
+        idle.get(format!("key{}", i).as_bytes())?;
+    }
+
+    // The overwrite compacts the logs read by the idle clone
+    store.set(b"key0".to_vec(), b"value")?;
+    // Both clones keep a handle to every deleted log
+    assert_eq!(open_deleted_files(temp_dir.path()), 20);
+
+    // Only the handles of the given clone are closed
+    idle.release_stale_handles();
+    assert_eq!(open_deleted_files(temp_dir.path()), 10);
+    store.release_stale_handles();
+    assert_eq!(open_deleted_files(temp_dir.path()), 0);
+    assert_eq!(idle.get(b"key9")?, Some(b"value".to_vec()));
+    Ok(())
+}
@@ -0,0 +1,102 @@
+use graus_db::{GrausDb, GrausDbOptions, Result, ThresholdStrategy};
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+const PREALLOCATE_LEN: u64 = 64 * 1024;
+
+fn log_len(dir: &Path, log_id: u64) -> u64 {
+    fs::metadata(dir.join(format!("{}.log", log_id)))
+        .unwrap()
+        .len()
+}
+
+// A log that was preallocated but only partially written should load its commands and
+// be sealed without the unused space
+#[test]
+fn partially_written_log_loads() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = GrausDbOptions::default().preallocate_logs(PREALLOCATE_LEN);
+    let store = GrausDb::open_with_options(temp_dir.path(), options.clone())?;
+    assert_eq!(log_len(temp_dir.path(), 1), PREALLOCATE_LEN);
+    for i in 0..10 {
+        store.set(format!("key{}", i).into_bytes(), b"value")?;
+    }
+    store.append(b"key0".to_vec(), b"1")?;
+    store.remove(b"key1")?;
+    // The empty key and value are serialized mostly as zeros
+    store.set(Vec::new(), b"")?;
+    assert_eq!(store.get(b"key0")?, Some(b"value1".to_vec()));
+    assert_eq!(log_len(temp_dir.path(), 1), PREALLOCATE_LEN);
+
+    // Dropped without closing, so the preallocated space is still there
+    drop(store);
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    assert_eq!(store.get(b"key0")?, Some(b"value1".to_vec()));
+    assert_eq!(store.get(b"key1")?, None);
+    assert_eq!(store.get(b"key9")?, Some(b"value".to_vec()));
+    assert_eq!(store.get(b"")?, Some(Vec::new()));
+    assert_eq!(store.get_history(b"")?.len(), 1);
+    assert!(log_len(temp_dir.path(), 1) < 1024);
+    assert_eq!(log_len(temp_dir.path(), 2), PREALLOCATE_LEN);
+
+    // Closing drops the unused space of the active log
+    store.set(b"key10".to_vec(), b"value")?;
+    store.close()?;
+    assert!(log_len(temp_dir.path(), 2) < 1024);
+    let store = GrausDb::open(temp_dir.path())?;
+    assert_eq!(store.get(b"key10")?, Some(b"value".to_vec()));
+    assert_eq!(store.get(b"key0")?, Some(b"value1".to_vec()));
+    Ok(())
+}
+
+// A preallocated log that was never written should be removed when opening the database
+#[test]
+fn unwritten_log_is_removed() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = GrausDbOptions::default().preallocate_logs(PREALLOCATE_LEN);
+    drop(GrausDb::open_with_options(
+        temp_dir.path(),
+        options.clone(),
+    )?);
+
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    store.set(b"key".to_vec(), b"value")?;
+    assert_eq!(store.index_snapshot().len(), 1);
+    assert_eq!(store.index_snapshot()[0].1.log_id, 1);
+    drop(store);
+    let store = GrausDb::open(temp_dir.path())?;
+    assert_eq!(store.get(b"key")?, Some(b"value".to_vec()));
+    Ok(())
+}
+
+// Compactions should work on preallocated logs, and the compacted log should only
+// contain its commands
+#[test]
+fn compaction_with_preallocated_logs() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = GrausDbOptions::default()
+        .preallocate_logs(PREALLOCATE_LEN)
+        .compaction_strategy(Arc::new(ThresholdStrategy { threshold: 1024 }));
+    let store = GrausDb::open_with_options(temp_dir.path(), options.clone())?;
+    for i in 0..1000 {
+        store.set(
+            format!("key{}", i % 10).into_bytes(),
+            i.to_string().as_bytes(),
+        )?;
+    }
+    assert!(store.compaction_count() > 0);
+    for i in 990..1000 {
+        let key = format!("key{}", i % 10).into_bytes();
+        assert_eq!(store.get(&key)?, Some(i.to_string().into_bytes()));
+    }
+
+    drop(store);
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    for i in 990..1000 {
+        let key = format!("key{}", i % 10).into_bytes();
+        assert_eq!(store.get(&key)?, Some(i.to_string().into_bytes()));
+    }
+    Ok(())
+}
@@ -0,0 +1,198 @@
+use graus_db::{GrausDb, GrausDbOptions, GrausError, RecoveryMode, RecoverySummary, Result};
+use std::fs::{self, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::Path;
+use tempfile::TempDir;
+
+// Writes 3 keys in the first log and corrupts the type of the second command.
+fn create_corrupted_log(path: &Path) -> Result<()> {
+    let store = GrausDb::open(path)?;
+    store.set(b"key1".to_vec(), b"value1")?;
+    let corrupted_pos = store.disk_size()?;
+    store.set(b"key2".to_vec(), b"value2")?;
+    store.set(b"key3".to_vec(), b"value3")?;
+    drop(store);
+
+    let mut log = OpenOptions::new().write(true).open(path.join("1.log"))?;
+    log.seek(SeekFrom::Start(corrupted_pos))?;
+    log.write_all(&[0xFF])?;
+    Ok(())
+}
+
+// Should fail to open a corrupted database by default
+#[test]
+fn strict_mode_fails_on_corrupted_record() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    create_corrupted_log(temp_dir.path())?;
+
+    assert!(GrausDb::open(temp_dir.path()).is_err());
+    Ok(())
+}
+
+// Should skip the corrupted record, keep the valid ones and report the data lost
+#[test]
+fn salvage_mode_skips_corrupted_record() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    create_corrupted_log(temp_dir.path())?;
+    let log_len = fs::metadata(temp_dir.path().join("1.log"))?.len();
+
+    let options = GrausDbOptions::default().recovery_mode(RecoveryMode::Salvage);
+    let store = GrausDb::open_with_options(temp_dir.path(), options.clone())?;
+    assert_eq!(store.get(b"key1")?, Some(b"value1".to_vec()));
+    assert_eq!(store.get(b"key3")?, Some(b"value3".to_vec()));
+    let summary = store.recovery_summary();
+    assert_eq!(summary.dropped_records, 1);
+    // At most the corrupted command is skipped
+    assert!(summary.dropped_bytes > 0 && summary.dropped_bytes <= log_len / 3);
+
+    // The corrupted log is not sealed, so it is salvaged again on the next open
+    drop(store);
+    assert_eq!(fs::metadata(temp_dir.path().join("1.log"))?.len(), log_len);
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    assert_eq!(store.get(b"key3")?, Some(b"value3".to_vec()));
+    assert_eq!(store.recovery_summary(), summary);
+    Ok(())
+}
+
+// Should drop a command truncated by a crash at the end of the log
+#[test]
+fn salvage_mode_drops_truncated_command() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"key1".to_vec(), b"value1")?;
+    store.set(b"key2".to_vec(), b"value2")?;
+    drop(store);
+    let log = OpenOptions::new()
+        .write(true)
+        .open(temp_dir.path().join("1.log"))?;
+    log.set_len(log.metadata()?.len() - 3)?;
+    drop(log);
+
+    assert!(GrausDb::open(temp_dir.path()).is_err());
+    let options = GrausDbOptions::default().recovery_mode(RecoveryMode::Salvage);
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    assert_eq!(store.get(b"key1")?, Some(b"value1".to_vec()));
+    assert_eq!(store.get(b"key2")?, None);
+    assert_eq!(
+        store.recovery_summary(),
+        RecoverySummary {
+            dropped_records: 1,
+            dropped_bytes: 41,
+        }
+    );
+    Ok(())
+}
+
+// Values larger than the read buffer should be skipped while loading, and still be read
+// whole once the log is reopened.
+#[test]
+fn reopen_log_with_value_larger_than_read_buffer() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let value = vec![b'v'; 1024 * 1024];
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"large".to_vec(), &value)?;
+    store.set(b"small".to_vec(), b"value")?;
+    drop(store);
+
+    // The second open loads the sealed log
+    for _ in 0..2 {
+        let store = GrausDb::open(temp_dir.path())?;
+        assert_eq!(store.get(b"large")?, Some(value.clone()));
+        assert_eq!(store.get(b"small")?, Some(b"value".to_vec()));
+    }
+    Ok(())
+}
+
+// A corrupted length prefix larger than the log should fail cleanly instead of allocating it
+#[test]
+fn absurd_length_prefix_fails_to_open() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"key1".to_vec(), b"value1")?;
+    store.set(b"key2".to_vec(), b"value2")?;
+    drop(store);
+
+    // The length of a key is stored right before it
+    let log_path = temp_dir.path().join("1.log");
+    let mut content = fs::read(&log_path)?;
+    let key_pos = content
+        .windows(4)
+        .position(|window| window == b"key2")
+        .expect("key not found in the log");
+    content[key_pos - 4..key_pos].copy_from_slice(&u32::MAX.to_le_bytes());
+    fs::write(&log_path, content)?;
+
+    assert!(matches!(
+        GrausDb::open(temp_dir.path()),
+        Err(GrausError::SerializationError(_))
+    ));
+    let options = GrausDbOptions::default().recovery_mode(RecoveryMode::Salvage);
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    assert_eq!(store.get(b"key1")?, Some(b"value1".to_vec()));
+    assert_eq!(store.get(b"key2")?, None);
+    Ok(())
+}
+
+// Should pick up a log copied into the directory after the database was opened
+#[test]
+fn rebuild_index_replays_imported_log() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"key1".to_vec(), b"value1")?;
+    store.set(b"key2".to_vec(), b"value2")?;
+    drop(store);
+
+    let other_dir = TempDir::new().expect("unable to create temporary working directory");
+    let other = GrausDb::open(other_dir.path())?;
+    other.set(b"imported".to_vec(), b"value")?;
+    other.set(b"key2".to_vec(), b"older")?;
+    drop(other);
+
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"key3".to_vec(), b"value3")?;
+    // The imported log is older than the logs of the database
+    fs::copy(
+        other_dir.path().join("1.log"),
+        temp_dir.path().join("0.log"),
+    )?;
+    assert_eq!(store.get(b"imported")?, None);
+
+    store.rebuild_index()?;
+    assert_eq!(store.get(b"imported")?, Some(b"value".to_vec()));
+    assert_eq!(store.get(b"key1")?, Some(b"value1".to_vec()));
+    assert_eq!(store.get(b"key2")?, Some(b"value2".to_vec()));
+    assert_eq!(store.get(b"key3")?, Some(b"value3".to_vec()));
+    Ok(())
+}
+
+// Should show the values as of an earlier log, without writing or touching the logs
+#[test]
+fn open_up_to_earlier_log() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"key1".to_vec(), b"value1")?;
+    drop(store);
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"key1".to_vec(), b"value2")?;
+    store.set(b"key2".to_vec(), b"value2")?;
+    drop(store);
+    let log_len = |log_id: u64| fs::metadata(temp_dir.path().join(format!("{}.log", log_id)));
+    let len2 = log_len(2)?.len();
+
+    let store = GrausDb::open_up_to(temp_dir.path(), 1)?;
+    assert_eq!(store.get(b"key1")?, Some(b"value1".to_vec()));
+    assert_eq!(store.get(b"key2")?, None);
+    assert!(matches!(
+        store.set(b"key1".to_vec(), b"value3"),
+        Err(GrausError::ReadOnly)
+    ));
+    assert!(matches!(store.remove(b"key1"), Err(GrausError::ReadOnly)));
+    drop(store);
+    assert_eq!(log_len(2)?.len(), len2);
+    assert!(log_len(3).is_err());
+
+    let store = GrausDb::open(temp_dir.path())?;
+    assert_eq!(store.get(b"key1")?, Some(b"value2".to_vec()));
+    assert_eq!(store.get(b"key2")?, Some(b"value2".to_vec()));
+    Ok(())
+}
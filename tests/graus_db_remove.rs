@@ -1,4 +1,5 @@
 use graus_db::{GrausDb, GrausError, Result};
+use std::thread;
 use tempfile::TempDir;
 
 #[test]
@@ -16,9 +17,137 @@ fn remove_returns_key_not_found_when_not_exists() -> Result<()> {
     let temp_dir = TempDir::new().expect("unable to create temporary working directory");
     let store = GrausDb::open(temp_dir.path())?;
     let result = store.remove(b"key1");
-    match result {
-        Err(GrausError::KeyNotFound) => assert!(true),
-        _ => assert!(false),
+    assert!(matches!(result, Err(GrausError::KeyNotFound)));
+    Ok(())
+}
+
+// Should remove existing keys in batch and report which ones existed
+#[test]
+fn remove_many_reports_existing_keys() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"key1".to_vec(), b"value1")?;
+    store.set(b"key2".to_vec(), b"value2")?;
+    store.set(b"key3".to_vec(), b"value3")?;
+
+    let removed = store.remove_many(vec![
+        b"key1".to_vec(),
+        b"missing".to_vec(),
+        b"key3".to_vec(),
+        b"key1".to_vec(),
+    ])?;
+    assert_eq!(removed, vec![true, false, true, false]);
+
+    assert_eq!(store.get(b"key1")?, None);
+    assert_eq!(store.get(b"key2")?, Some(b"value2".to_vec()));
+    assert_eq!(store.get(b"key3")?, None);
+
+    // Open from disk again and check persistent data
+    drop(store);
+    let store = GrausDb::open(temp_dir.path())?;
+    assert_eq!(store.get(b"key1")?, None);
+    assert_eq!(store.get(b"key2")?, Some(b"value2".to_vec()));
+    assert_eq!(store.get(b"key3")?, None);
+    Ok(())
+}
+
+// Should return and remove only the keys under the prefix
+#[test]
+#[cfg_attr(feature = "hash-index", ignore = "the hash index is not ordered")]
+fn drain_prefix_removes_matching_keys() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"job:2".to_vec(), b"value2")?;
+    store.set(b"job:1".to_vec(), b"value1")?;
+    store.set(b"other".to_vec(), b"value3")?;
+
+    let drained = store.drain_prefix(b"job:")?;
+    assert_eq!(
+        drained,
+        vec![
+            (b"job:1".to_vec(), b"value1".to_vec()),
+            (b"job:2".to_vec(), b"value2".to_vec())
+        ]
+    );
+    assert_eq!(store.drain_prefix(b"job:")?, vec![]);
+    assert_eq!(store.get(b"other")?, Some(b"value3".to_vec()));
+
+    // Open from disk again and check persistent data
+    drop(store);
+    let store = GrausDb::open(temp_dir.path())?;
+    assert_eq!(store.get(b"job:1")?, None);
+    assert_eq!(store.get(b"other")?, Some(b"value3".to_vec()));
+    Ok(())
+}
+
+// Keys written while draining should be drained exactly once
+#[test]
+fn drain_prefix_with_concurrent_producer() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+
+    let producer = {
+        let store = store.clone();
+        thread::spawn(move || {
+            for i in 0..1000 {
+                store
+                    .set(format!("job:{:04}", i).into_bytes(), b"work")
+                    .unwrap();
+            }
+        })
+    };
+
+    let mut drained = Vec::new();
+    while !producer.is_finished() {
+        drained.extend(store.drain_prefix(b"job:")?);
+    }
+    producer.join().unwrap();
+    drained.extend(store.drain_prefix(b"job:")?);
+
+    let mut keys: Vec<Vec<u8>> = drained.into_iter().map(|(key, _)| key).collect();
+    keys.sort();
+    let expected: Vec<Vec<u8>> = (0..1000)
+        .map(|i| format!("job:{:04}", i).into_bytes())
+        .collect();
+    assert_eq!(keys, expected);
+    Ok(())
+}
+
+// Should remove only the keys under the prefix and return how many were removed
+#[test]
+fn remove_prefix_removes_matching_keys() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    for i in 0..10 {
+        store.set(format!("user:{}", i).into_bytes(), b"value")?;
+    }
+    store.set(b"user".to_vec(), b"value")?;
+    store.set(b"users".to_vec(), b"value")?;
+
+    assert_eq!(store.remove_prefix(b"user:")?, 10);
+    assert_eq!(store.remove_prefix(b"user:")?, 0);
+    assert_eq!(store.get(b"user:3")?, None);
+    assert!(store.contains_key(b"user") && store.contains_key(b"users"));
+
+    // Open from disk again and check persistent data
+    drop(store);
+    let store = GrausDb::open(temp_dir.path())?;
+    assert_eq!(store.get(b"user:3")?, None);
+    assert_eq!(store.get(b"users")?, Some(b"value".to_vec()));
+    Ok(())
+}
+
+// The tombstones of a large prefix should trigger a compaction
+#[test]
+fn remove_prefix_triggers_compaction() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    for i in 0..2000 {
+        store.set(format!("key{}", i).into_bytes(), &[0; 500])?;
     }
+    let size = store.disk_size()?;
+
+    assert_eq!(store.remove_prefix(b"key")?, 2000);
+    assert!(store.disk_size()? < size);
     Ok(())
 }
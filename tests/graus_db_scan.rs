@@ -0,0 +1,314 @@
+use graus_db::{GrausDb, GrausDbOptions, Result};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use tempfile::TempDir;
+
+// Should return the keys whose values contain the needle
+#[test]
+fn scan_values_containing_returns_matching_keys() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"key1".to_vec(), b"hello world")?;
+    store.set(b"key2".to_vec(), b"goodbye")?;
+    store.set(b"key3".to_vec(), b"world")?;
+    store.set(b"key4".to_vec(), b"wor")?;
+    store.set(b"key5".to_vec(), b"world")?;
+    store.remove(b"key5")?;
+    store.append(b"key6".to_vec(), b"wo")?;
+    store.append(b"key6".to_vec(), b"rld")?;
+
+    let mut keys = store.scan_values_containing(b"world")?;
+    // The hash index is not ordered
+    keys.sort();
+    assert_eq!(
+        keys,
+        vec![b"key1".to_vec(), b"key3".to_vec(), b"key6".to_vec()]
+    );
+    assert_eq!(
+        store.scan_values_containing(b"missing")?,
+        Vec::<Vec<u8>>::new()
+    );
+    assert_eq!(store.scan_values_containing(b"")?.len(), 5);
+    Ok(())
+}
+
+// Should stop once the maximum number of results is found
+#[test]
+#[cfg_attr(feature = "hash-index", ignore = "the hash index is not ordered")]
+fn scan_values_containing_bounded_stops_early() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    for i in 0..10 {
+        store.set(format!("key{}", i).into_bytes(), b"value")?;
+    }
+
+    assert_eq!(
+        store.scan_values_containing_bounded(b"val", 2)?,
+        vec![b"key0".to_vec(), b"key1".to_vec()]
+    );
+    assert_eq!(store.scan_values_containing_bounded(b"val", 0)?.len(), 0);
+    assert_eq!(store.scan_values_containing_bounded(b"val", 100)?.len(), 10);
+    Ok(())
+}
+
+// Should return the 8-byte big-endian keys in the range, decoded
+#[test]
+fn scan_int_range_decodes_keys() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    for i in [1u64, 5, 255, 256, 1000, u64::MAX] {
+        store.set(i.to_be_bytes().to_vec(), format!("value{}", i).as_bytes())?;
+    }
+    store.remove(&1000u64.to_be_bytes())?;
+    // Keys that are not 8 bytes long are ignored, even if their prefix is in the range
+    store.set([&300u64.to_be_bytes()[..], b"suffix"].concat(), b"ignored")?;
+    store.set(b"key".to_vec(), b"ignored")?;
+
+    let entries = store.scan_int_range(5, 2000).collect::<Result<Vec<_>>>()?;
+    assert_eq!(
+        entries,
+        vec![
+            (5, b"value5".to_vec()),
+            (255, b"value255".to_vec()),
+            (256, b"value256".to_vec())
+        ]
+    );
+    assert_eq!(store.scan_int_range(0, u64::MAX).count(), 4);
+    assert_eq!(store.scan_int_range(10, 10).count(), 0);
+    assert_eq!(store.scan_int_range(10, 1).count(), 0);
+    Ok(())
+}
+
+// Should order the keys numerically with any comparator
+#[test]
+#[cfg_attr(feature = "hash-index", ignore = "the hash index has no comparator")]
+fn scan_int_range_is_ordered_with_custom_comparator() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = GrausDbOptions::default().key_comparator(|a, b| b.cmp(a));
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    for i in 0..10u64 {
+        store.set(i.to_be_bytes().to_vec(), &[i as u8])?;
+    }
+
+    let keys: Vec<u64> = store
+        .scan_int_range(2, 6)
+        .map(|entry| entry.map(|(key, _)| key))
+        .collect::<Result<_>>()?;
+    assert_eq!(keys, vec![2, 3, 4, 5]);
+    Ok(())
+}
+
+// Should list the keys page by page, continuing after keys removed between pages
+#[test]
+fn list_keys_paginates() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    for i in 0..25 {
+        store.set(format!("key{:02}", i).into_bytes(), b"value")?;
+    }
+
+    let mut keys = Vec::new();
+    let mut after = None;
+    loop {
+        let page = store.list_keys(after.as_deref(), 10)?;
+        if page.is_empty() {
+            break;
+        }
+        assert!(page.len() <= 10);
+        after = page.last().cloned();
+        keys.extend(page);
+    }
+    let expected: Vec<Vec<u8>> = (0..25)
+        .map(|i| format!("key{:02}", i).into_bytes())
+        .collect();
+    assert_eq!(keys, expected);
+
+    // The last key of the page was removed and keys were written before and after it
+    let page = store.list_keys(None, 10)?;
+    assert_eq!(page.last(), Some(&b"key09".to_vec()));
+    store.remove(b"key09")?;
+    store.remove(b"key10")?;
+    store.set(b"key00a".to_vec(), b"value")?;
+    store.set(b"key09a".to_vec(), b"value")?;
+    let page = store.list_keys(page.last().map(Vec::as_slice), 3)?;
+    assert_eq!(
+        page,
+        vec![b"key09a".to_vec(), b"key11".to_vec(), b"key12".to_vec()]
+    );
+    assert_eq!(store.list_keys(Some(b"key24"), 10)?, Vec::<Vec<u8>>::new());
+    assert_eq!(store.list_keys(None, 0)?, Vec::<Vec<u8>>::new());
+    Ok(())
+}
+
+// Should list the keys in the order of a custom comparator
+#[test]
+#[cfg_attr(feature = "hash-index", ignore = "the hash index has no comparator")]
+fn list_keys_follows_comparator() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = GrausDbOptions::default().key_comparator(|a, b| b.cmp(a));
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    for key in [b"a", b"b", b"c", b"d"] {
+        store.set(key.to_vec(), b"value")?;
+    }
+    assert_eq!(
+        store.list_keys(Some(b"c"), 10)?,
+        vec![b"b".to_vec(), b"a".to_vec()]
+    );
+    Ok(())
+}
+
+// Should group the entries by the longest prefix they match
+#[test]
+fn scan_prefixes_groups_by_longest_prefix() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"user:1".to_vec(), b"ana")?;
+    store.set(b"user:2".to_vec(), b"bob")?;
+    store.set(b"user:admin:1".to_vec(), b"root")?;
+    store.set(b"order:1".to_vec(), b"book")?;
+    store.set(b"other".to_vec(), b"value")?;
+    store.set(b"user:3".to_vec(), b"removed")?;
+    store.remove(b"user:3")?;
+
+    let mut groups = store.scan_prefixes(&[b"user:", b"user:admin:", b"order:", b"missing"])?;
+    assert_eq!(groups.len(), 4);
+    // The hash index is not ordered
+    for group in groups.values_mut() {
+        group.sort();
+    }
+    assert_eq!(
+        groups[b"user:".as_slice()],
+        vec![
+            (b"user:1".to_vec(), b"ana".to_vec()),
+            (b"user:2".to_vec(), b"bob".to_vec())
+        ]
+    );
+    assert_eq!(
+        groups[b"user:admin:".as_slice()],
+        vec![(b"user:admin:1".to_vec(), b"root".to_vec())]
+    );
+    assert_eq!(
+        groups[b"order:".as_slice()],
+        vec![(b"order:1".to_vec(), b"book".to_vec())]
+    );
+    assert!(groups[b"missing".as_slice()].is_empty());
+
+    // Duplicated and empty prefixes
+    let groups = store.scan_prefixes(&[b"", b"order:", b"order:"])?;
+    assert_eq!(groups.len(), 2);
+    assert_eq!(groups[b"".as_slice()].len(), 4);
+    assert_eq!(groups[b"order:".as_slice()].len(), 1);
+    Ok(())
+}
+
+// Should return the live entries in the order they were written to the logs
+#[test]
+fn iter_physical_follows_log_order() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"c".to_vec(), b"1")?;
+    store.set(b"a".to_vec(), b"2")?;
+    store.set(b"b".to_vec(), b"3")?;
+    store.set(b"c".to_vec(), b"4")?;
+    store.append(b"a".to_vec(), b"5")?;
+    store.set(b"d".to_vec(), b"6")?;
+    store.remove(b"d")?;
+    drop(store);
+
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"e".to_vec(), b"7")?;
+    let entries = store.iter_physical().collect::<Result<Vec<_>>>()?;
+    assert_eq!(
+        entries,
+        vec![
+            (b"b".to_vec(), b"3".to_vec()),
+            (b"c".to_vec(), b"4".to_vec()),
+            (b"a".to_vec(), b"25".to_vec()),
+            (b"e".to_vec(), b"7".to_vec()),
+        ]
+    );
+
+    // Keys written or removed while iterating are read again
+    let mut entries = store.iter_physical();
+    assert_eq!(
+        entries.next().transpose()?,
+        Some((b"b".to_vec(), b"3".to_vec()))
+    );
+    store.remove(b"c")?;
+    store.set(b"a".to_vec(), b"8")?;
+    assert_eq!(
+        entries.next().transpose()?,
+        Some((b"a".to_vec(), b"8".to_vec()))
+    );
+    assert_eq!(
+        entries.next().transpose()?,
+        Some((b"e".to_vec(), b"7".to_vec()))
+    );
+    assert!(entries.next().is_none());
+    Ok(())
+}
+
+// Should return the entries accepted by the filter, up to the maximum number of results
+#[test]
+fn retain_scan_filters_entries() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    for i in 0..10 {
+        store.set(format!("key{}", i).into_bytes(), i.to_string().as_bytes())?;
+    }
+    store.set(b"other".to_vec(), b"2")?;
+    store.remove(b"key4")?;
+
+    let is_even = |value: &[u8]| value[0].is_multiple_of(2);
+    let mut entries = store.retain_scan(|key, value| key.starts_with(b"key") && is_even(value))?;
+    // The hash index is not ordered
+    entries.sort();
+    let expected: Vec<(Vec<u8>, Vec<u8>)> = [0, 2, 6, 8]
+        .iter()
+        .map(|i| (format!("key{}", i).into_bytes(), i.to_string().into_bytes()))
+        .collect();
+    assert_eq!(entries, expected);
+    assert_eq!(store.retain_scan(|_, _| true)?.len(), 10);
+
+    let entries = store.retain_scan_bounded(|_, value| is_even(value), 2)?;
+    assert_eq!(entries.len(), 2);
+    assert!(entries.iter().all(|(_, value)| is_even(value)));
+    assert!(store.retain_scan_bounded(|_, _| true, 0)?.is_empty());
+    Ok(())
+}
+
+// Setting the cancel flag should stop the scan before reading the next value
+#[test]
+fn cancellable_scans_stop_when_cancelled() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    for i in 0..1000 {
+        store.set(format!("key{:0>4}", i).into_bytes(), b"value")?;
+    }
+
+    let cancel = AtomicBool::new(false);
+    let visited = AtomicUsize::new(0);
+    let entries = store.retain_scan_cancellable(
+        |_, _| {
+            if visited.fetch_add(1, Ordering::SeqCst) + 1 == 10 {
+                cancel.store(true, Ordering::SeqCst);
+            }
+            true
+        },
+        &cancel,
+    )?;
+    assert_eq!(visited.load(Ordering::SeqCst), 10);
+    assert_eq!(entries.len(), 10);
+
+    // An already cancelled scan reads nothing
+    assert!(store
+        .scan_values_containing_cancellable(b"value", &cancel)?
+        .is_empty());
+    let not_cancelled = AtomicBool::new(false);
+    assert_eq!(
+        store
+            .scan_values_containing_cancellable(b"value", &not_cancelled)?
+            .len(),
+        1000
+    );
+    Ok(())
+}
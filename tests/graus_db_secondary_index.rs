@@ -0,0 +1,50 @@
+use graus_db::{GrausDb, GrausError, Result};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+// Indexes users ("<name>:<city>") by city.
+fn city_extractor(value: &[u8]) -> Option<Vec<u8>> {
+    let separator = value.iter().position(|&byte| byte == b':')?;
+    Some(value[separator + 1..].to_vec())
+}
+
+// Should index existing and new values, and clean removed and updated ones
+#[test]
+fn secondary_index_tracks_writes() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"user1".to_vec(), b"ricardo:madrid")?;
+    store.set(b"user2".to_vec(), b"ana:paris")?;
+    store.set(b"user3".to_vec(), b"no city")?;
+
+    store.register_index("city", Arc::new(city_extractor))?;
+    assert_eq!(
+        store.lookup_by_index("city", b"madrid")?,
+        vec![b"user1".to_vec()]
+    );
+
+    store.set(b"user4".to_vec(), b"luis:madrid")?;
+    store.set(b"user2".to_vec(), b"ana:madrid")?;
+    assert_eq!(
+        store.lookup_by_index("city", b"madrid")?,
+        vec![b"user1".to_vec(), b"user2".to_vec(), b"user4".to_vec()]
+    );
+    assert!(store.lookup_by_index("city", b"paris")?.is_empty());
+
+    store.remove(b"user1")?;
+    assert_eq!(
+        store.lookup_by_index("city", b"madrid")?,
+        vec![b"user2".to_vec(), b"user4".to_vec()]
+    );
+    Ok(())
+}
+
+// Should return IndexNotFound when the index is not registered
+#[test]
+fn lookup_by_index_returns_error_when_index_not_exists() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    let result = store.lookup_by_index("city", b"madrid");
+    assert!(matches!(result, Err(GrausError::IndexNotFound(_))));
+    Ok(())
+}
@@ -1,4 +1,12 @@
-use graus_db::{GrausDb, Result};
+use graus_db::{
+    GrausDb, GrausDbOptions, GrausError, MemoryStorage, Result, Storage, ThresholdStrategy,
+};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::sync::{Arc, Barrier, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 use tempfile::TempDir;
 
 // Should overwrite existent value
@@ -19,3 +27,403 @@ fn set_overwrites_value_if_exists() -> Result<()> {
     assert_eq!(store.get(b"key1")?, Some(b"value3".to_vec()));
     Ok(())
 }
+
+// Should persist the data when the store is explicitly closed
+#[test]
+fn close_persists_data() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"key1".to_vec(), b"value1")?;
+    store.close()?;
+
+    let store = GrausDb::open(temp_dir.path())?;
+    assert_eq!(store.get(b"key1")?, Some(b"value1".to_vec()));
+    Ok(())
+}
+
+// Should read unflushed values and persist them once flushed
+#[test]
+fn set_without_flushing_each_write() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = GrausDbOptions::default().flush_each_write(false);
+    let store = GrausDb::open_with_options(temp_dir.path(), options.clone())?;
+
+    for i in 0..100 {
+        store.set(
+            format!("key{}", i).into_bytes(),
+            format!("value{}", i).as_bytes(),
+        )?;
+    }
+    assert_eq!(store.get(b"key99")?, Some(b"value99".to_vec()));
+
+    store.set(b"key100".to_vec(), b"value100")?;
+    store.update_if::<_, fn(&[u8]) -> bool>(
+        b"key100".to_vec(),
+        |value| value.extend_from_slice(b"-updated"),
+        None,
+        None,
+    )?;
+    assert_eq!(store.get(b"key100")?, Some(b"value100-updated".to_vec()));
+
+    store.flush()?;
+    let reopened = GrausDb::open_with_options(temp_dir.path(), options)?;
+    for i in 0..100 {
+        assert_eq!(
+            reopened.get(format!("key{}", i).as_bytes())?,
+            Some(format!("value{}", i).into_bytes())
+        );
+    }
+    assert_eq!(reopened.get(b"key100")?, Some(b"value100-updated".to_vec()));
+    Ok(())
+}
+
+// Concurrent writes waiting to be durable should share fsyncs and all be persisted
+#[test]
+fn set_with_group_commit() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = GrausDbOptions::default()
+        .sync_each_write(true)
+        .compaction_strategy(Arc::new(ThresholdStrategy { threshold: 4096 }));
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+
+    let handles: Vec<_> = (0..8)
+        .map(|thread_id| {
+            let store = store.clone();
+            thread::spawn(move || {
+                for i in 0..100 {
+                    let key = format!("key{}", i % 20);
+                    let value = format!("value{}_{}", thread_id, i);
+                    store.set(key.into_bytes(), value.as_bytes()).unwrap();
+                }
+                store
+                    .remove_many(vec![format!("removed{}", thread_id).into_bytes()])
+                    .unwrap();
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    let values: Vec<_> = (0..20)
+        .map(|i| store.get(format!("key{}", i).as_bytes()))
+        .collect::<Result<_>>()?;
+
+    // Open from disk again and check persistent data
+    drop(store);
+    let store = GrausDb::open(temp_dir.path())?;
+    for (i, value) in values.into_iter().enumerate() {
+        assert!(value.is_some());
+        assert_eq!(store.get(format!("key{}", i).as_bytes())?, value);
+    }
+    Ok(())
+}
+
+// Buffered writes should be flushed by the background thread, which stops on drop
+#[test]
+fn set_with_periodic_flush() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = GrausDbOptions::default()
+        .flush_each_write(false)
+        .flush_interval(Duration::from_millis(10));
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    let log_path = temp_dir.path().join("1.log");
+    store.set(b"key".to_vec(), b"value")?;
+
+    let start = Instant::now();
+    while fs::metadata(&log_path)?.len() == 0 {
+        assert!(
+            start.elapsed() < Duration::from_secs(10),
+            "log never flushed"
+        );
+        thread::sleep(Duration::from_millis(5));
+    }
+    drop(store);
+    let reopened = GrausDb::open(temp_dir.path())?;
+    assert_eq!(reopened.get(b"key")?, Some(b"value".to_vec()));
+    drop(reopened);
+
+    // Dropping the last handle doesn't wait for the interval to stop the thread
+    let options = GrausDbOptions::default().flush_interval(Duration::from_secs(3600));
+    let start = Instant::now();
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    drop(store.clone());
+    drop(store);
+    assert!(start.elapsed() < Duration::from_secs(60));
+    Ok(())
+}
+
+// Storage that keeps track of the bytes of every log that were synced, to simulate a
+// power failure
+#[derive(Default)]
+struct PowerLossStorage {
+    inner: MemoryStorage,
+    synced: Mutex<HashMap<u64, u64>>,
+}
+
+impl PowerLossStorage {
+    // Copies the logs as they would be after a power failure, without the unsynced bytes
+    fn durable_copy(&self) -> io::Result<MemoryStorage> {
+        let copy = MemoryStorage::default();
+        let synced = self.synced.lock().unwrap();
+        for log_id in self.inner.log_ids()? {
+            let mut bytes = vec![0; *synced.get(&log_id).unwrap_or(&0) as usize];
+            self.inner.read_at(log_id, 0, &mut bytes)?;
+            copy.create_log(log_id)?;
+            copy.append(log_id, &bytes)?;
+        }
+        Ok(copy)
+    }
+}
+
+impl Storage for PowerLossStorage {
+    fn create_log(&self, log_id: u64) -> io::Result<()> {
+        self.synced.lock().unwrap().remove(&log_id);
+        self.inner.create_log(log_id)
+    }
+
+    fn read_at(&self, log_id: u64, pos: u64, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read_at(log_id, pos, buf)
+    }
+
+    fn append(&self, log_id: u64, bytes: &[u8]) -> io::Result<()> {
+        self.inner.append(log_id, bytes)
+    }
+
+    fn log_len(&self, log_id: u64) -> io::Result<u64> {
+        self.inner.log_len(log_id)
+    }
+
+    fn truncate(&self, log_id: u64, len: u64) -> io::Result<()> {
+        self.inner.truncate(log_id, len)
+    }
+
+    fn sync(&self, log_id: u64) -> io::Result<()> {
+        let len = self.inner.log_len(log_id)?;
+        self.synced.lock().unwrap().insert(log_id, len);
+        Ok(())
+    }
+
+    fn remove_log(&self, log_id: u64) -> io::Result<()> {
+        self.inner.remove_log(log_id)
+    }
+
+    fn log_ids(&self) -> io::Result<Vec<u64>> {
+        self.inner.log_ids()
+    }
+}
+
+// Keys that can be read should survive a power failure when writes are synced before
+// they become visible
+#[test]
+fn set_synced_before_visible() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let storage = Arc::new(PowerLossStorage::default());
+    let options = GrausDbOptions::default().storage(storage.clone());
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    store.set(b"key".to_vec(), b"value")?;
+    assert_eq!(store.get(b"key")?, Some(b"value".to_vec()));
+    // By default the visible key is only flushed, so the power failure loses it
+    let options = GrausDbOptions::default().storage(Arc::new(storage.durable_copy()?));
+    let recovered = GrausDb::open_with_options(temp_dir.path(), options)?;
+    assert_eq!(recovered.get(b"key")?, None);
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let storage = Arc::new(PowerLossStorage::default());
+    let options = GrausDbOptions::default()
+        .storage(storage.clone())
+        .sync_before_visible(true);
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    for key_id in 0..100 {
+        let key = format!("key{}", key_id).into_bytes();
+        store.set(key.clone(), b"value")?;
+        if key_id % 10 == 0 {
+            store.remove(&key)?;
+        }
+        // Simulate a power failure every time a write becomes visible
+        let options = GrausDbOptions::default().storage(Arc::new(storage.durable_copy()?));
+        let recovered = GrausDb::open_with_options(temp_dir.path(), options)?;
+        assert_eq!(recovered.get(&key)?, store.get(&key)?);
+    }
+    Ok(())
+}
+
+// Writes up to a sequence number should survive a power failure once they are synced
+#[test]
+fn sync_until_makes_writes_durable() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let storage = Arc::new(PowerLossStorage::default());
+    let options = GrausDbOptions::default().storage(storage.clone());
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    store.sync_until(0)?;
+    store.set(b"key1".to_vec(), b"value1")?;
+    store.set(b"key2".to_vec(), b"value2")?;
+    let seq = store.current_seq();
+    assert!(matches!(
+        store.sync_until(seq + 1),
+        Err(GrausError::SeqNotWritten { current, .. }) if current == seq
+    ));
+
+    let threads: Vec<_> = (0..4)
+        .map(|_| {
+            let store = store.clone();
+            thread::spawn(move || store.sync_until(seq))
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap()?;
+    }
+    store.set(b"key3".to_vec(), b"value3")?;
+    let options = GrausDbOptions::default().storage(Arc::new(storage.durable_copy()?));
+    let recovered = GrausDb::open_with_options(temp_dir.path(), options)?;
+    assert_eq!(recovered.get(b"key1")?, Some(b"value1".to_vec()));
+    assert_eq!(recovered.get(b"key2")?, Some(b"value2".to_vec()));
+    assert_eq!(recovered.get(b"key3")?, None);
+    Ok(())
+}
+
+// A set should give up if another write holds the writer for longer than its timeout
+#[test]
+fn set_timeout_gives_up_on_contention() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"slow".to_vec(), b"value")?;
+    store.set_timeout(b"key".to_vec(), b"value1", Duration::from_millis(10))?;
+
+    let locked = Arc::new(Barrier::new(2));
+    let slow_write = {
+        let store = store.clone();
+        let locked = Arc::clone(&locked);
+        // update_if runs the update with the writer locked
+        thread::spawn(move || {
+            store.update_if(
+                b"slow".to_vec(),
+                |_| {
+                    locked.wait();
+                    thread::sleep(Duration::from_millis(300));
+                },
+                None,
+                None::<fn(&[u8]) -> bool>,
+            )
+        })
+    };
+    locked.wait();
+    let start = Instant::now();
+    assert!(matches!(
+        store.set_timeout(b"key".to_vec(), b"value2", Duration::from_millis(20)),
+        Err(GrausError::WriteTimeout)
+    ));
+    assert!(start.elapsed() < Duration::from_millis(300));
+    assert_eq!(store.get(b"key")?, Some(b"value1".to_vec()));
+
+    // A longer timeout waits for the other write
+    store.set_timeout(b"key".to_vec(), b"value3", Duration::from_secs(10))?;
+    slow_write.join().unwrap()?;
+    assert_eq!(store.get(b"key")?, Some(b"value3".to_vec()));
+    Ok(())
+}
+
+// A bulk load should compact at most once, at the end, and be durable once it returns
+#[test]
+fn bulk_load_compacts_once_and_syncs() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let storage = Arc::new(PowerLossStorage::default());
+    let options = GrausDbOptions::default()
+        .storage(storage.clone())
+        .compaction_strategy(Arc::new(ThresholdStrategy { threshold: 0 }));
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    // Every key is overwritten, which would compact on every write
+    let entries = (0..1000).map(|i| {
+        let key = format!("key{}", i % 100).into_bytes();
+        (key, format!("value{}", i).into_bytes())
+    });
+    store.bulk_load(entries)?;
+    assert_eq!(store.compaction_count(), 1);
+
+    let options = GrausDbOptions::default().storage(Arc::new(storage.durable_copy()?));
+    let recovered = GrausDb::open_with_options(temp_dir.path(), options)?;
+    for key_id in 0..100 {
+        let key = format!("key{}", key_id).into_bytes();
+        let value = format!("value{}", 900 + key_id).into_bytes();
+        assert_eq!(store.get(&key)?, Some(value.clone()));
+        assert_eq!(recovered.get(&key)?, Some(value));
+    }
+    Ok(())
+}
+
+// Verifying the writes should read them back without changing the data
+#[test]
+fn verify_writes_keeps_data() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = GrausDbOptions::default()
+        .verify_writes(true)
+        .value_log_threshold(100);
+    let store = GrausDb::open_with_options(temp_dir.path(), options.clone())?;
+    store.set(b"small".to_vec(), b"value1")?;
+    store.set(b"small".to_vec(), b"value2")?;
+    store.set(b"large".to_vec(), &[7; 1000])?;
+    store.bulk_load((0..100).map(|i| (format!("key{}", i).into_bytes(), vec![i; 10])))?;
+    store.compact_key(b"small")?;
+    drop(store);
+
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    assert_eq!(store.get(b"small")?, Some(b"value2".to_vec()));
+    assert_eq!(store.get(b"large")?, Some(vec![7; 1000]));
+    for i in 0..100 {
+        assert_eq!(
+            store.get(format!("key{}", i).as_bytes())?,
+            Some(vec![i; 10])
+        );
+    }
+    Ok(())
+}
+
+// Should move the value only when the destination can be written
+#[test]
+fn rename_moves_value() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"from".to_vec(), b"value1")?;
+    store.set(b"taken".to_vec(), b"value2")?;
+
+    assert!(!store.rename(b"missing", b"to".to_vec(), true)?);
+    assert!(!store.rename(b"from", b"taken".to_vec(), false)?);
+    assert_eq!(store.get(b"from")?, Some(b"value1".to_vec()));
+    assert!(store.rename(b"from", b"to".to_vec(), false)?);
+    assert_eq!(store.get(b"from")?, None);
+    assert_eq!(store.get(b"to")?, Some(b"value1".to_vec()));
+    assert!(store.rename(b"to", b"taken".to_vec(), true)?);
+    assert_eq!(store.get(b"to")?, None);
+    assert_eq!(store.get(b"taken")?, Some(b"value1".to_vec()));
+    Ok(())
+}
+
+// Only one of the threads racing to rename the same key should move it
+#[test]
+fn concurrent_renames_move_once() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"from".to_vec(), b"value")?;
+
+    let barrier = Arc::new(Barrier::new(8));
+    let handles: Vec<_> = (0..8)
+        .map(|i| {
+            let store = store.clone();
+            let barrier = Arc::clone(&barrier);
+            thread::spawn(move || {
+                barrier.wait();
+                store
+                    .rename(b"from", format!("to{}", i).into_bytes(), false)
+                    .unwrap()
+            })
+        })
+        .collect();
+    let renamed: Vec<bool> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+    assert_eq!(renamed.iter().filter(|renamed| **renamed).count(), 1);
+
+    assert_eq!(store.get(b"from")?, None);
+    for (i, renamed) in renamed.into_iter().enumerate() {
+        let expected = renamed.then(|| b"value".to_vec());
+        assert_eq!(store.get(format!("to{}", i).as_bytes())?, expected);
+    }
+    Ok(())
+}
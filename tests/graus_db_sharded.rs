@@ -0,0 +1,202 @@
+use graus_db::{GrausDbOptions, GrausError, MirrorMode, Result, ShardedGrausDb};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::thread;
+use tempfile::TempDir;
+
+// Should route keys to their shards and keep them after reopening
+#[test]
+fn sharded_set_get_remove() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = ShardedGrausDb::open(temp_dir.path(), 4)?;
+    for i in 0..100 {
+        store.set(
+            format!("key{}", i).into_bytes(),
+            format!("value{}", i).as_bytes(),
+        )?;
+    }
+    store.remove(b"key0")?;
+    assert!(matches!(
+        store.remove(b"key0"),
+        Err(GrausError::KeyNotFound)
+    ));
+
+    // Keys are spread over all the shards
+    let mut used_shards: Vec<usize> = (0..100)
+        .map(|i| store.shard_of(format!("key{}", i).as_bytes()))
+        .collect();
+    used_shards.sort_unstable();
+    used_shards.dedup();
+    assert_eq!(used_shards, vec![0, 1, 2, 3]);
+
+    // Open from disk again and check persistent data
+    store.close()?;
+    let store = ShardedGrausDb::open(temp_dir.path(), 4)?;
+    assert_eq!(store.get(b"key0")?, None);
+    assert!(!store.contains_key(b"key0"));
+    for i in 1..100 {
+        assert_eq!(
+            store.get(format!("key{}", i).as_bytes())?,
+            Some(format!("value{}", i).into_bytes())
+        );
+    }
+    Ok(())
+}
+
+// Should refuse to open a database with another number of shards
+#[test]
+fn sharded_open_checks_number_of_shards() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    drop(ShardedGrausDb::open(temp_dir.path(), 4)?);
+    assert!(matches!(
+        ShardedGrausDb::open(temp_dir.path(), 2),
+        Err(GrausError::ShardCountMismatch {
+            found: 4,
+            expected: 2
+        })
+    ));
+    Ok(())
+}
+
+// Should merge the entries drained from every shard
+#[test]
+fn sharded_drain_prefix() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = ShardedGrausDb::open(temp_dir.path(), 3)?;
+    for i in 0..10 {
+        store.set(format!("job:{}", i).into_bytes(), b"work")?;
+    }
+    store.set(b"other".to_vec(), b"value")?;
+
+    let keys: Vec<Vec<u8>> = store
+        .drain_prefix(b"job:")?
+        .into_iter()
+        .map(|(key, _)| key)
+        .collect();
+    let expected: Vec<Vec<u8>> = (0..10).map(|i| format!("job:{}", i).into_bytes()).collect();
+    assert_eq!(keys, expected);
+    assert_eq!(store.get(b"other")?, Some(b"value".to_vec()));
+    Ok(())
+}
+
+// Should merge the entries and the keys of every shard in order
+#[test]
+fn sharded_scan_prefix_and_list_keys() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = ShardedGrausDb::open(temp_dir.path(), 3)?;
+    for i in 0..10 {
+        store.set(
+            format!("job:{}", i).into_bytes(),
+            format!("work{}", i).as_bytes(),
+        )?;
+    }
+    store.set(b"other".to_vec(), b"value")?;
+
+    let entries = store.scan_prefix(b"job:")?;
+    let expected: Vec<(Vec<u8>, Vec<u8>)> = (0..10)
+        .map(|i| {
+            (
+                format!("job:{}", i).into_bytes(),
+                format!("work{}", i).into_bytes(),
+            )
+        })
+        .collect();
+    assert_eq!(entries, expected);
+    assert_eq!(store.get(b"other")?, Some(b"value".to_vec()));
+
+    let page = store.list_keys(Some(b"job:3"), 4)?;
+    let expected: Vec<Vec<u8>> = (4..8).map(|i| format!("job:{}", i).into_bytes()).collect();
+    assert_eq!(page, expected);
+    let page = store.list_keys(Some(b"job:7"), 10)?;
+    assert_eq!(
+        page,
+        vec![b"job:8".to_vec(), b"job:9".to_vec(), b"other".to_vec()]
+    );
+    Ok(())
+}
+
+// The disk size mark should bound the size of all the shards, not of each one
+#[test]
+fn sharded_disk_size_mark_is_split() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = GrausDbOptions::default().disk_size_high_water_mark(4096);
+    let store = ShardedGrausDb::open_with_options(temp_dir.path(), 4, options)?;
+    let mut written = 0;
+    let error = loop {
+        match store.set(format!("key{}", written).into_bytes(), &[0; 100]) {
+            Ok(()) => written += 1,
+            Err(e) => break e,
+        }
+    };
+    assert!(matches!(error, GrausError::WriteStalled));
+    // Every shard stops a write after crossing its quarter of the mark
+    assert!(store.disk_size()? < 4096 + 4 * 256);
+    Ok(())
+}
+
+// Should accept writes from several threads
+#[test]
+fn sharded_concurrent_set() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = ShardedGrausDb::open(temp_dir.path(), 4)?;
+    let handles: Vec<_> = (0..4)
+        .map(|thread_id| {
+            let store = store.clone();
+            thread::spawn(move || {
+                for i in 0..250 {
+                    let key = format!("key{}_{}", thread_id, i);
+                    store.set(key.into_bytes(), b"value").unwrap();
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    for thread_id in 0..4 {
+        for i in 0..250 {
+            let key = format!("key{}_{}", thread_id, i);
+            assert_eq!(store.get(key.as_bytes())?, Some(b"value".to_vec()));
+        }
+    }
+    Ok(())
+}
+
+// Every shard should mirror its own logs, and be restored from them
+#[test]
+fn sharded_mirror() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mirror_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = GrausDbOptions::default().mirror(mirror_dir.path(), MirrorMode::Required);
+    let store = ShardedGrausDb::open_with_options(temp_dir.path(), 2, options.clone())?;
+    for i in 0..20 {
+        store.set(
+            format!("key{}", i).into_bytes(),
+            format!("value{}", i).as_bytes(),
+        )?;
+    }
+    store.close()?;
+    for shard in ["shard-0", "shard-1"] {
+        assert_eq!(
+            fs::read(temp_dir.path().join(shard).join("1.log"))?,
+            fs::read(mirror_dir.path().join(shard).join("1.log"))?
+        );
+    }
+
+    // Overwrite the type of the first command of the first shard
+    let mut file = OpenOptions::new()
+        .write(true)
+        .open(temp_dir.path().join("shard-0").join("1.log"))?;
+    file.write_all(&[0xFF])?;
+    drop(file);
+
+    let store = ShardedGrausDb::open_with_options(temp_dir.path(), 2, options)?;
+    for i in 0..20 {
+        assert_eq!(
+            store.get(format!("key{}", i).as_bytes())?,
+            Some(format!("value{}", i).into_bytes())
+        );
+    }
+    Ok(())
+}
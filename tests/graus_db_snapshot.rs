@@ -0,0 +1,71 @@
+use graus_db::{GrausDb, GrausDbOptions, Result, ThresholdStrategy};
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+fn log_count(dir: &Path) -> usize {
+    fs::read_dir(dir)
+        .unwrap()
+        .filter(|entry| entry.as_ref().unwrap().path().extension() == Some("log".as_ref()))
+        .count()
+}
+
+// Reads through a snapshot should return the values when it was created
+#[test]
+fn snapshot_reads_values_at_creation() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"key1".to_vec(), b"value1")?;
+    store.set(b"key2".to_vec(), b"value2")?;
+    store.append(b"key2".to_vec(), b"+")?;
+
+    let snapshot = store.snapshot()?;
+    store.set(b"key1".to_vec(), b"new value")?;
+    store.remove(b"key2")?;
+    store.set(b"key3".to_vec(), b"value3")?;
+
+    assert_eq!(snapshot.len(), 2);
+    assert_eq!(snapshot.get(b"key1")?, Some(b"value1".to_vec()));
+    assert_eq!(snapshot.get(b"key2")?, Some(b"value2+".to_vec()));
+    assert_eq!(snapshot.get(b"key3")?, None);
+    assert!(!snapshot.contains_key(b"key3"));
+    assert_eq!(store.get(b"key1")?, Some(b"new value".to_vec()));
+    assert_eq!(store.get(b"key2")?, None);
+    Ok(())
+}
+
+// Compactions should not delete the logs read by a live snapshot
+#[test]
+fn compaction_keeps_logs_of_live_snapshot() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = GrausDbOptions::default()
+        .compaction_strategy(Arc::new(ThresholdStrategy { threshold: 1024 }));
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    for i in 0..100 {
+        store.set(format!("key{}", i).into_bytes(), b"old")?;
+    }
+
+    let snapshot = store.snapshot()?;
+    for _ in 0..10 {
+        for i in 0..100 {
+            store.set(format!("key{}", i).into_bytes(), b"new")?;
+        }
+    }
+    assert!(store.compaction_count() > 0);
+    assert!(log_count(temp_dir.path()) > 2);
+    for i in 0..100 {
+        let key = format!("key{}", i).into_bytes();
+        assert_eq!(snapshot.get(&key)?, Some(b"old".to_vec()));
+        assert_eq!(store.get(&key)?, Some(b"new".to_vec()));
+    }
+
+    // The stale logs are deleted when the snapshot is dropped
+    drop(snapshot);
+    assert!(log_count(temp_dir.path()) <= 2);
+    assert_eq!(store.get(b"key0")?, Some(b"new".to_vec()));
+    drop(store);
+    let store = GrausDb::open(temp_dir.path())?;
+    assert_eq!(store.get(b"key99")?, Some(b"new".to_vec()));
+    Ok(())
+}
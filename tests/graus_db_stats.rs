@@ -0,0 +1,278 @@
+use graus_db::{
+    CompactionEstimate, GrausDb, GrausDbOptions, LatencyReport, LogLayout, Result, SizeHistogram,
+    ThresholdStrategy,
+};
+use std::fs;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+// Should group key and value lengths in power-of-two buckets
+#[test]
+fn size_histogram_counts_lengths() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"k".to_vec(), b"")?;
+    store.set(b"key".to_vec(), &[0; 100])?;
+    store.set(b"key2".to_vec(), &[0; 120])?;
+    store.set(b"removed".to_vec(), &[0; 1000])?;
+    store.remove(b"removed")?;
+
+    let histogram = store.size_histogram();
+    assert_eq!(SizeHistogram::bucket(100), 7);
+    assert_eq!(histogram.key_lengths, vec![0, 1, 1, 1]);
+    assert_eq!(histogram.value_lengths, vec![1, 0, 0, 0, 0, 0, 0, 2]);
+    Ok(())
+}
+
+// Should report the value length without reading it
+#[test]
+fn stat_key_returns_value_length() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"key1".to_vec(), &[7; 1234])?;
+
+    let stat = store.stat_key(b"key1").expect("key not found");
+    assert_eq!(stat.value_len, 1234);
+    assert_eq!(stat.log_id, 1);
+    assert_eq!(store.stat_key(b"key2"), None);
+
+    // Open from disk again and check persistent data
+    drop(store);
+    let store = GrausDb::open(temp_dir.path())?;
+    assert_eq!(
+        store.stat_key(b"key1").map(|stat| stat.value_len),
+        Some(1234)
+    );
+    Ok(())
+}
+
+// Should return the position of the last command of every key
+#[test]
+#[cfg_attr(feature = "hash-index", ignore = "the hash index is not ordered")]
+fn index_snapshot_returns_positions() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"key2".to_vec(), b"value2")?;
+    store.set(b"key1".to_vec(), b"value1")?;
+    store.set(b"key2".to_vec(), b"value22")?;
+
+    let snapshot = store.index_snapshot();
+    let keys: Vec<&[u8]> = snapshot.iter().map(|(key, _)| key.as_slice()).collect();
+    assert_eq!(keys, vec![b"key1".as_slice(), b"key2".as_slice()]);
+
+    // The positions can be used to read the values from the log files
+    let log = fs::read(temp_dir.path().join("1.log"))?;
+    let (_, cmd_pos) = snapshot[1];
+    assert_eq!(cmd_pos.log_id, 1);
+    // The version is the sequence number of the third write
+    assert_eq!(cmd_pos.version, 3);
+    let value_pos = cmd_pos.value_pos() as usize;
+    assert_eq!(
+        &log[value_pos..value_pos + cmd_pos.value_len as usize],
+        b"value22"
+    );
+    Ok(())
+}
+
+// Should stream the same positions as the index snapshot
+#[test]
+fn scan_with_pos_streams_positions() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = GrausDbOptions::default().flush_each_write(false);
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    for key_id in 0..100 {
+        let key = format!("key{}", key_id);
+        store.set(
+            key.clone().into_bytes(),
+            format!("value of {}", key).as_bytes(),
+        )?;
+    }
+    store.remove(b"key0")?;
+    let entries: Vec<_> = store.scan_with_pos().collect();
+    assert_eq!(entries, store.index_snapshot());
+    assert_eq!(entries.len(), 99);
+
+    // Once flushed, the values can be read from the log files
+    store.flush()?;
+    let log = fs::read(temp_dir.path().join("1.log"))?;
+    for (key, cmd_pos) in store.scan_with_pos() {
+        let value_pos = cmd_pos.value_pos() as usize;
+        let value = &log[value_pos..value_pos + cmd_pos.value_len as usize];
+        assert_eq!(value, [b"value of ".as_slice(), &key].concat());
+    }
+    Ok(())
+}
+
+// Should estimate the space reclaimed by a compaction without running it
+#[test]
+fn compaction_estimate_counts_dead_bytes() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    assert_eq!(store.compaction_estimate(), CompactionEstimate::default());
+
+    store.set(b"key1".to_vec(), &[0; 100])?;
+    store.set(b"key2".to_vec(), &[0; 100])?;
+    let estimate = store.compaction_estimate();
+    assert_eq!(estimate.dead_bytes, 0);
+    assert_eq!(estimate.live_bytes, store.disk_size()?);
+    assert_eq!(estimate.dead_ratio(), 0.0);
+
+    // The overwritten value and the removal are dead
+    store.set(b"key1".to_vec(), &[1; 100])?;
+    store.remove(b"key2")?;
+    let estimate = store.compaction_estimate();
+    assert_eq!(
+        estimate.live_bytes + estimate.dead_bytes,
+        store.disk_size()?
+    );
+    assert!(estimate.dead_bytes > 200);
+    assert!(estimate.dead_ratio() > 0.5);
+
+    // The projected size is the size of the logs after a compaction
+    drop(store);
+    let store = GrausDb::open_with_options(
+        temp_dir.path(),
+        GrausDbOptions::default().compaction_strategy(Arc::new(ThresholdStrategy { threshold: 0 })),
+    )?;
+    let projected_size = store.compaction_estimate().projected_size;
+    store.set(b"key1".to_vec(), &[1; 100])?;
+    assert_eq!(store.disk_size()?, projected_size);
+    assert_eq!(store.compaction_estimate().dead_bytes, 0);
+    Ok(())
+}
+
+// Should count the compactions and record when the last one completed
+#[test]
+fn compaction_count_and_last_compaction() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options =
+        GrausDbOptions::default().compaction_strategy(Arc::new(ThresholdStrategy { threshold: 0 }));
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    assert_eq!(store.compaction_count(), 0);
+    assert_eq!(store.last_compaction(), None);
+
+    // Every overwrite compacts the logs
+    let before = Instant::now();
+    store.set(b"key".to_vec(), b"value1")?;
+    store.set(b"key".to_vec(), b"value2")?;
+    store.set(b"key".to_vec(), b"value3")?;
+    assert_eq!(store.compaction_count(), 2);
+    let last_compaction = store.last_compaction().expect("no compaction");
+    assert!(last_compaction >= before);
+
+    // Reopening resets the stats
+    drop(store);
+    let store = GrausDb::open(temp_dir.path())?;
+    assert_eq!(store.compaction_count(), 0);
+    assert_eq!(store.last_compaction(), None);
+    Ok(())
+}
+
+// Should count the values of every key left in the logs until they are compacted
+#[test]
+fn value_counts_tally_uncompacted_values() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"key1".to_vec(), b"value1")?;
+    store.set(b"key1".to_vec(), b"value2")?;
+    store.append(b"key1".to_vec(), b"!")?;
+    store.set(b"key2".to_vec(), b"value")?;
+    store.set(b"key3".to_vec(), b"value")?;
+    store.remove(b"key3")?;
+    let counts = store.value_counts()?;
+    assert_eq!(
+        counts.into_iter().collect::<Vec<_>>(),
+        vec![
+            (b"key1".to_vec(), 3),
+            (b"key2".to_vec(), 1),
+            (b"key3".to_vec(), 1),
+        ]
+    );
+
+    // The old values are counted until the next compaction drops them
+    store.compact_key(b"key1")?;
+    assert_eq!(store.value_counts()?.get(b"key1".as_slice()), Some(&4));
+    Ok(())
+}
+
+// Should account for the keys held in memory by the index
+#[test]
+fn index_memory_estimate_grows_with_keys() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    let empty = store.index_memory_estimate();
+    for i in 0..1000 {
+        store.set(format!("{:0>100}", i).into_bytes(), b"value")?;
+    }
+    let estimate = store.index_memory_estimate();
+    assert!(estimate > empty + 1000 * 100);
+
+    // Values are not held in memory
+    for i in 0..1000 {
+        store.set(format!("{:0>100}", i).into_bytes(), &[0; 1000])?;
+    }
+    assert!(store.index_memory_estimate() < estimate + 1000 * 1000);
+    Ok(())
+}
+
+// Should list the logs along with the active one and the output of the last compaction
+#[test]
+fn log_ids_show_roles() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"key1".to_vec(), b"value1")?;
+    assert_eq!(
+        store.log_ids()?,
+        LogLayout {
+            log_ids: vec![1],
+            active: 1,
+            last_compaction: None,
+        }
+    );
+    drop(store);
+
+    // Every write compacts the logs
+    let options =
+        GrausDbOptions::default().compaction_strategy(Arc::new(ThresholdStrategy { threshold: 0 }));
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    store.set(b"key1".to_vec(), b"value2")?;
+    assert_eq!(
+        store.log_ids()?,
+        LogLayout {
+            log_ids: vec![3, 4],
+            active: 4,
+            last_compaction: Some(3),
+        }
+    );
+    Ok(())
+}
+
+// Should report the percentiles of the latencies only when they are recorded
+#[test]
+fn latency_report_counts_operations() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"key".to_vec(), b"value")?;
+    assert_eq!(store.latency_report(), None);
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = GrausDbOptions::default().record_latencies(true);
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    assert_eq!(store.latency_report(), Some(LatencyReport::default()));
+    for i in 0..100 {
+        store.set(format!("key{}", i).into_bytes(), b"value")?;
+    }
+    // Sets with a timeout are recorded along with the other sets
+    store.set_timeout(b"key100".to_vec(), b"value", Duration::from_secs(10))?;
+    for i in 0..10 {
+        store.get(format!("key{}", i).as_bytes())?;
+    }
+    let report = store.latency_report().expect("latencies are recorded");
+    assert_eq!(report.set.count, 101);
+    assert_eq!(report.get.count, 10);
+    assert!(report.set.p50 > Duration::ZERO);
+    assert!(report.set.p50 <= report.set.p99);
+    assert!(report.get.p50 <= report.get.p99);
+    Ok(())
+}
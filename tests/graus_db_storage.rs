@@ -0,0 +1,107 @@
+use graus_db::{GrausDb, GrausDbOptions, MemoryStorage, Result, Storage};
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tempfile::TempDir;
+use walkdir::WalkDir;
+
+// Storage that counts the appends to a MemoryStorage
+#[derive(Default)]
+struct CountingStorage {
+    inner: MemoryStorage,
+    appends: AtomicUsize,
+}
+
+impl Storage for CountingStorage {
+    fn create_log(&self, log_id: u64) -> io::Result<()> {
+        self.inner.create_log(log_id)
+    }
+
+    fn read_at(&self, log_id: u64, pos: u64, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read_at(log_id, pos, buf)
+    }
+
+    fn append(&self, log_id: u64, bytes: &[u8]) -> io::Result<()> {
+        self.appends.fetch_add(1, Ordering::SeqCst);
+        self.inner.append(log_id, bytes)
+    }
+
+    fn log_len(&self, log_id: u64) -> io::Result<u64> {
+        self.inner.log_len(log_id)
+    }
+
+    fn truncate(&self, log_id: u64, len: u64) -> io::Result<()> {
+        self.inner.truncate(log_id, len)
+    }
+
+    fn sync(&self, log_id: u64) -> io::Result<()> {
+        self.inner.sync(log_id)
+    }
+
+    fn remove_log(&self, log_id: u64) -> io::Result<()> {
+        self.inner.remove_log(log_id)
+    }
+
+    fn log_ids(&self) -> io::Result<Vec<u64>> {
+        self.inner.log_ids()
+    }
+}
+
+// Writes, compacts and reopens a database with the given options
+fn write_compact_and_reopen(dir: &TempDir, options: GrausDbOptions) -> Result<()> {
+    let store = GrausDb::open_with_options(dir.path(), options.clone())?;
+    for iter in 0..1000 {
+        for key_id in 0..100 {
+            let key = format!("key{}", key_id);
+            let value = format!("{}", iter);
+            store.set(key.into_bytes(), value.as_bytes())?;
+        }
+    }
+    store.remove(b"key0")?;
+    store.append(b"key1".to_vec(), b"!")?;
+    drop(store);
+
+    let store = GrausDb::open_with_options(dir.path(), options)?;
+    assert_eq!(store.get(b"key0")?, None);
+    assert_eq!(store.get(b"key1")?, Some(b"999!".to_vec()));
+    for key_id in 2..100 {
+        let key = format!("key{}", key_id);
+        assert_eq!(store.get(key.as_bytes())?, Some(b"999".to_vec()));
+    }
+    Ok(())
+}
+
+fn log_files(dir: &TempDir) -> usize {
+    WalkDir::new(dir.path())
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "log"))
+        .count()
+}
+
+// The same operations should behave the same on disk and in a storage
+#[test]
+fn storages_behave_like_disk() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    write_compact_and_reopen(&temp_dir, GrausDbOptions::default())?;
+    assert!(log_files(&temp_dir) > 0);
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = GrausDbOptions::default().storage(Arc::new(MemoryStorage::default()));
+    write_compact_and_reopen(&temp_dir, options)?;
+    assert_eq!(log_files(&temp_dir), 0);
+    Ok(())
+}
+
+// A custom storage should receive every write of the database
+#[test]
+fn custom_storage_receives_writes() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let storage = Arc::new(CountingStorage::default());
+    let options = GrausDbOptions::default().storage(storage.clone());
+    write_compact_and_reopen(&temp_dir, options)?;
+    assert!(storage.appends.load(Ordering::SeqCst) > 0);
+    assert!(!storage.log_ids()?.is_empty());
+    assert_eq!(log_files(&temp_dir), 0);
+    Ok(())
+}
@@ -0,0 +1,49 @@
+use graus_db::{GrausDb, Result};
+use std::fs;
+use std::time::{Duration, SystemTime};
+use tempfile::TempDir;
+
+// Should return the time when the value was written
+#[test]
+fn get_with_timestamp_returns_write_time() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    assert_eq!(store.get_with_timestamp(b"key1")?, None);
+
+    let before = SystemTime::now() - Duration::from_millis(1);
+    store.set(b"key1".to_vec(), b"value1")?;
+    let after = SystemTime::now() + Duration::from_millis(1);
+
+    let (value, time) = store.get_with_timestamp(b"key1")?.unwrap();
+    assert_eq!(value, b"value1".to_vec());
+    let time = time.expect("timestamp not recorded");
+    assert!(before <= time && time <= after);
+
+    // Open from disk again and check persistent data
+    drop(store);
+    let store = GrausDb::open(temp_dir.path())?;
+    assert_eq!(
+        store.get_with_timestamp(b"key1")?,
+        Some((b"value1".to_vec(), Some(time)))
+    );
+    Ok(())
+}
+
+// Values written in the legacy format should have no timestamp
+#[test]
+fn legacy_values_have_no_timestamp() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut log = vec![0];
+    log.extend_from_slice(&4u32.to_le_bytes());
+    log.extend_from_slice(b"key1");
+    log.extend_from_slice(&6u32.to_le_bytes());
+    log.extend_from_slice(b"value1");
+    fs::write(temp_dir.path().join("1.log"), log)?;
+
+    let store = GrausDb::open(temp_dir.path())?;
+    assert_eq!(
+        store.get_with_timestamp(b"key1")?,
+        Some((b"value1".to_vec(), None))
+    );
+    Ok(())
+}
@@ -0,0 +1,99 @@
+use graus_db::{GrausDb, GrausError, Result};
+use std::fs;
+use tempfile::TempDir;
+
+// The writes of a transaction should be applied when it commits
+#[test]
+fn transaction_applies_writes_on_commit() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"alice".to_vec(), b"100")?;
+    store.set(b"carol".to_vec(), b"5")?;
+
+    let moved = store.transaction(|tx| {
+        let balance = tx.get(b"alice")?.expect("key not found");
+        tx.set(b"bob".to_vec(), &balance);
+        tx.remove(b"alice");
+        tx.remove(b"missing");
+        // Reads see the writes of the transaction, but the store doesn't yet
+        assert_eq!(tx.get(b"bob")?, Some(b"100".to_vec()));
+        assert_eq!(tx.get(b"alice")?, None);
+        assert_eq!(store.get(b"alice")?, Some(b"100".to_vec()));
+        tx.set(b"carol".to_vec(), b"6");
+        tx.set(b"carol".to_vec(), b"7");
+        Ok(balance)
+    })?;
+    assert_eq!(moved, b"100");
+    assert_eq!(store.get(b"alice")?, None);
+    assert_eq!(store.get(b"bob")?, Some(b"100".to_vec()));
+    assert_eq!(store.get(b"carol")?, Some(b"7".to_vec()));
+
+    drop(store);
+    let store = GrausDb::open(temp_dir.path())?;
+    assert_eq!(store.get(b"alice")?, None);
+    assert_eq!(store.get(b"bob")?, Some(b"100".to_vec()));
+    assert_eq!(store.get(b"carol")?, Some(b"7".to_vec()));
+    Ok(())
+}
+
+// A transaction whose closure fails should leave the store unchanged
+#[test]
+fn failed_transaction_leaves_store_unchanged() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"key1".to_vec(), b"value1")?;
+    let disk_size = store.disk_size()?;
+
+    let result: Result<()> = store.transaction(|tx| {
+        tx.set(b"key1".to_vec(), b"new value");
+        tx.set(b"key2".to_vec(), b"value2");
+        tx.remove(b"key1");
+        Err(GrausError::KeyNotFound)
+    });
+    assert!(matches!(result, Err(GrausError::KeyNotFound)));
+    assert_eq!(store.get(b"key1")?, Some(b"value1".to_vec()));
+    assert_eq!(store.get(b"key2")?, None);
+    assert_eq!(store.disk_size()?, disk_size);
+    Ok(())
+}
+
+// A commit cut off by a crash should be invisible once the store is reopened, including the
+// writes of the transaction that reached the log whole
+#[test]
+fn commit_cut_off_is_invisible_after_reopen() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"alice".to_vec(), b"100")?;
+    store.flush()?;
+    let log_path = temp_dir.path().join("1.log");
+    let committed_len = fs::metadata(&log_path)?.len() as usize;
+    store.transaction(|tx| {
+        tx.set(b"bob".to_vec(), b"100");
+        tx.set(b"carol".to_vec(), b"5");
+        tx.remove(b"alice");
+        Ok(())
+    })?;
+    drop(store);
+    let log = fs::read(&log_path)?;
+
+    // Cut in the header of the batch, in its first command and in its last one. The rest
+    // of the log is either missing or zeroed, like preallocated space.
+    for cut in [committed_len + 4, committed_len + 20, log.len() - 1] {
+        let mut zeroed = log[..cut].to_vec();
+        zeroed.resize(log.len() + 100, 0);
+        for content in [&log[..cut], &zeroed[..]] {
+            let cut_dir = TempDir::new().expect("unable to create temporary working directory");
+            fs::write(cut_dir.path().join("1.log"), content)?;
+            let store = GrausDb::open(cut_dir.path())?;
+            assert_eq!(store.get(b"alice")?, Some(b"100".to_vec()));
+            assert_eq!(store.get(b"bob")?, None);
+            assert_eq!(store.get(b"carol")?, None);
+        }
+    }
+
+    let store = GrausDb::open(temp_dir.path())?;
+    assert_eq!(store.get(b"alice")?, None);
+    assert_eq!(store.get(b"bob")?, Some(b"100".to_vec()));
+    assert_eq!(store.get(b"carol")?, Some(b"5".to_vec()));
+    Ok(())
+}
@@ -1,4 +1,6 @@
 use graus_db::{GrausDb, GrausError, Result};
+use std::sync::{Arc, Barrier};
+use std::thread;
 use tempfile::TempDir;
 
 #[test]
@@ -64,10 +66,7 @@ fn update_if_returns_predicate_error_when_predicate_is_not_satisfied_for_existin
 
     let result = store.update_if(b"key1".to_vec(), update_fn, Some(b"key2"), Some(predicate));
 
-    match result {
-        Err(GrausError::PredicateNotSatisfied) => assert!(true),
-        _ => assert!(false),
-    }
+    assert!(matches!(result, Err(GrausError::PredicateNotSatisfied)));
     assert_eq!(store.get(b"key1").unwrap(), Some(b"value1".to_vec()));
     Ok(())
 }
@@ -88,9 +87,52 @@ fn update_if_returns_key_not_found_error_when_data_not_exists() -> Result<()> {
 
     let result = store.update_if::<_, fn(&[u8]) -> bool>(b"key1".to_vec(), update_fn, None, None);
 
-    match result {
-        Err(GrausError::KeyNotFound) => assert!(true),
-        _ => assert!(false),
-    }
+    assert!(matches!(result, Err(GrausError::KeyNotFound)));
+    Ok(())
+}
+
+#[test]
+fn remove_if_removes_only_when_predicate_is_satisfied() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"key".to_vec(), b"pending")?;
+
+    assert!(!store.remove_if(b"key", |value| value == b"done")?);
+    assert_eq!(store.get(b"key")?, Some(b"pending".to_vec()));
+    assert!(store.remove_if(b"key", |value| value == b"pending")?);
+    assert_eq!(store.get(b"key")?, None);
+    assert!(!store.remove_if(b"key", |_| true)?);
+    Ok(())
+}
+
+#[test]
+fn remove_if_succeeds_only_for_thread_seeing_expected_state() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"job".to_vec(), b"pending")?;
+
+    // All the threads see the expected state before removing, but only one can remove it
+    let threads = 8;
+    let barrier = Arc::new(Barrier::new(threads));
+    let handles: Vec<_> = (0..threads)
+        .map(|_| {
+            let store = store.clone();
+            let barrier = Arc::clone(&barrier);
+            thread::spawn(move || {
+                barrier.wait();
+                store
+                    .remove_if(b"job", |value| value == b"pending")
+                    .unwrap()
+            })
+        })
+        .collect();
+    let removed = handles
+        .into_iter()
+        .map(|handle| handle.join().unwrap())
+        .filter(|&removed| removed)
+        .count();
+
+    assert_eq!(removed, 1);
+    assert_eq!(store.get(b"job")?, None);
     Ok(())
 }
@@ -0,0 +1,141 @@
+use graus_db::{GrausDb, GrausDbOptions, Result, ThresholdStrategy};
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+use tempfile::TempDir;
+
+// Uppercases the values in the logs, so only the cached values are still read as written.
+fn overwrite_values_on_disk(root: &Path) {
+    for entry in fs::read_dir(root).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|ext| ext == "log") {
+            let mut log = fs::read(&path).unwrap();
+            for i in 0..log.len().saturating_sub(4) {
+                if &log[i..i + 5] == b"value" {
+                    log[i..i + 5].copy_from_slice(b"VALUE");
+                }
+            }
+            fs::write(&path, log).unwrap();
+        }
+    }
+}
+
+fn reads_from_disk(store: &GrausDb, key: &[u8], written: &[u8]) -> bool {
+    !matches!(store.get_uncached(key), Ok(Some(value)) if value == written)
+}
+
+// Repeated gets should be served by the cache until the key is written or removed.
+#[test]
+fn value_cache_serves_repeated_gets() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = GrausDbOptions::default().value_cache_bytes(1024);
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    store.set(b"key1".to_vec(), b"value1")?;
+    assert_eq!(store.get(b"key1")?, Some(b"value1".to_vec()));
+
+    overwrite_values_on_disk(temp_dir.path());
+    assert!(reads_from_disk(&store, b"key1", b"value1"));
+    assert_eq!(store.get(b"key1")?, Some(b"value1".to_vec()));
+    assert_eq!(store.clone().get(b"key1")?, Some(b"value1".to_vec()));
+
+    store.set(b"key1".to_vec(), b"new")?;
+    assert_eq!(store.get(b"key1")?, Some(b"new".to_vec()));
+    store.remove(b"key1")?;
+    assert_eq!(store.get(b"key1")?, None);
+    Ok(())
+}
+
+// The least recently read values should be evicted when the cache is full.
+#[test]
+fn value_cache_evicts_least_recently_used() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    // Fits two keys of 4 bytes with values of 6 bytes
+    let options = GrausDbOptions::default().value_cache_bytes(20);
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    for key in [b"key1", b"key2", b"key3"] {
+        store.set(key.to_vec(), b"value1")?;
+    }
+    store.get(b"key1")?;
+    store.get(b"key2")?;
+    store.get(b"key1")?;
+    store.get(b"key3")?;
+
+    overwrite_values_on_disk(temp_dir.path());
+    assert_eq!(store.get(b"key1")?, Some(b"value1".to_vec()));
+    assert_eq!(store.get(b"key3")?, Some(b"value1".to_vec()));
+    assert!(!matches!(store.get(b"key2"), Ok(Some(value)) if value == b"value1"));
+    Ok(())
+}
+
+// A cache split in shards should serve the gets of every thread.
+#[test]
+fn sharded_value_cache_serves_concurrent_gets() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = GrausDbOptions::default().value_cache_bytes(1024 * 1024);
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    let keys: Vec<Vec<u8>> = (0..100).map(|i| format!("key{}", i).into_bytes()).collect();
+    for key in &keys {
+        store.set(key.clone(), b"value")?;
+        store.get(key)?;
+    }
+
+    overwrite_values_on_disk(temp_dir.path());
+    thread::scope(|scope| {
+        for keys in keys.chunks(25) {
+            let store = store.clone();
+            scope.spawn(move || {
+                for key in keys {
+                    assert_eq!(store.get(key).unwrap(), Some(b"value".to_vec()));
+                }
+            });
+        }
+    });
+    assert!(reads_from_disk(&store, &keys[0], b"value"));
+    Ok(())
+}
+
+// Pinned keys should stay cached even without a value cache, until they are unpinned.
+#[test]
+fn pinned_keys_stay_cached() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"key1".to_vec(), b"value1")?;
+    store.pin(b"key1")?;
+    store.pin(b"missing")?;
+
+    overwrite_values_on_disk(temp_dir.path());
+    assert!(reads_from_disk(&store, b"key1", b"value1"));
+    assert_eq!(store.get(b"key1")?, Some(b"value1".to_vec()));
+    assert_eq!(store.get(b"missing")?, None);
+
+    // Writes replace the pinned value once it is read again
+    store.set(b"key1".to_vec(), b"new")?;
+    assert_eq!(store.get(b"key1")?, Some(b"new".to_vec()));
+
+    store.set(b"key2".to_vec(), b"value2")?;
+    store.pin(b"key2")?;
+    store.unpin(b"key2");
+    overwrite_values_on_disk(temp_dir.path());
+    assert!(!matches!(store.get(b"key2"), Ok(Some(value)) if value == b"value2"));
+    Ok(())
+}
+
+// Pinned values should stay cached when a compaction moves their commands.
+#[test]
+fn pinned_keys_stay_cached_after_compaction() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options =
+        GrausDbOptions::default().compaction_strategy(Arc::new(ThresholdStrategy { threshold: 0 }));
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    store.set(b"key1".to_vec(), b"value1")?;
+    store.pin(b"key1")?;
+    store.set(b"key2".to_vec(), b"value2")?;
+    store.set(b"key2".to_vec(), b"value3")?;
+    assert!(store.compaction_count() > 0);
+
+    overwrite_values_on_disk(temp_dir.path());
+    assert!(reads_from_disk(&store, b"key1", b"value1"));
+    assert_eq!(store.get(b"key1")?, Some(b"value1".to_vec()));
+    Ok(())
+}
@@ -0,0 +1,63 @@
+use graus_db::{GrausDb, GrausDbOptions, GrausError, Result};
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+
+// Flips a byte of the first occurrence of `bytes` in a log.
+fn corrupt(log_path: &Path, bytes: &[u8]) {
+    let mut content = fs::read(log_path).unwrap();
+    let pos = content
+        .windows(bytes.len())
+        .position(|window| window == bytes)
+        .expect("bytes not found in the log");
+    content[pos] ^= 0xFF;
+    fs::write(log_path, content).unwrap();
+}
+
+// Reading a value whose bytes were corrupted on disk should fail
+#[test]
+fn get_detects_corrupted_value() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = GrausDbOptions::default().value_checksums(true);
+    let store = GrausDb::open_with_options(temp_dir.path(), options.clone())?;
+    store.set(b"key1".to_vec(), b"first value")?;
+    store.set(b"key2".to_vec(), b"second value")?;
+    store.append(b"key2".to_vec(), b" and chunk")?;
+    drop(store);
+
+    corrupt(&temp_dir.path().join("1.log"), b"first value");
+    corrupt(&temp_dir.path().join("1.log"), b" and chunk");
+    // The values are not read when the logs are loaded, so the database still opens
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    assert!(matches!(
+        store.get(b"key1"),
+        Err(GrausError::ChecksumMismatch)
+    ));
+    assert!(matches!(
+        store.get(b"key2"),
+        Err(GrausError::ChecksumMismatch)
+    ));
+
+    // Overwriting the value makes it readable again
+    store.set(b"key1".to_vec(), b"new value")?;
+    assert_eq!(store.get(b"key1")?, Some(b"new value".to_vec()));
+    Ok(())
+}
+
+// Values written without checksums should not be verified
+#[test]
+fn values_without_checksum_are_not_verified() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"key".to_vec(), b"value")?;
+    drop(store);
+
+    corrupt(&temp_dir.path().join("1.log"), b"value");
+    let options = GrausDbOptions::default().value_checksums(true);
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    let value = store.get(b"key")?.expect("key not found");
+    assert_ne!(value, b"value");
+    store.set(b"other".to_vec(), b"value")?;
+    assert_eq!(store.get(b"other")?, Some(b"value".to_vec()));
+    Ok(())
+}
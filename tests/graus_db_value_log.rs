@@ -0,0 +1,197 @@
+use graus_db::{
+    GrausDb, GrausDbOptions, GrausError, MemoryStorage, MirrorMode, Result, ThresholdStrategy,
+};
+use std::io::Read;
+use std::path::Path;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+fn large_value(key_id: u32, len: usize) -> Vec<u8> {
+    format!("{:0>1$}", key_id, len).into_bytes()
+}
+
+fn value_log_exists(root: &Path, log_id: u64) -> bool {
+    root.join("values").join(format!("{}.log", log_id)).exists()
+}
+
+// Large values should be stored in value logs and read back across reopens, while small
+// ones stay in their commands.
+#[test]
+fn large_values_are_stored_in_value_logs() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = GrausDbOptions::default().value_log_threshold(100);
+    let store = GrausDb::open_with_options(temp_dir.path(), options.clone())?;
+    store.set(b"small".to_vec(), b"value")?;
+    store.set(b"large".to_vec(), &large_value(1, 1000))?;
+    assert!(value_log_exists(temp_dir.path(), 1));
+    assert!(store.disk_size()? > 1000);
+
+    let separated = |store: &GrausDb, key: &[u8]| {
+        store
+            .index_snapshot()
+            .into_iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, cmd_pos)| cmd_pos.is_separated())
+    };
+    assert_eq!(separated(&store, b"small"), Some(false));
+    assert_eq!(separated(&store, b"large"), Some(true));
+    assert_eq!(store.get(b"large")?, Some(large_value(1, 1000)));
+    let mut streamed = Vec::new();
+    store
+        .get_stream(b"large")?
+        .expect("key not found")
+        .read_to_end(&mut streamed)?;
+    assert_eq!(streamed, large_value(1, 1000));
+    drop(store);
+
+    // The manifest keeps older versions from opening the database
+    assert!(temp_dir.path().join("manifest").exists());
+    let store = GrausDb::open(temp_dir.path())?;
+    assert_eq!(store.get(b"small")?, Some(b"value".to_vec()));
+    assert_eq!(store.get(b"large")?, Some(large_value(1, 1000)));
+    Ok(())
+}
+
+// Compactions should keep the value logs that are mostly live, and rewrite the live
+// values of the others before deleting them.
+#[test]
+fn compaction_rewrites_sparse_value_logs() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = GrausDbOptions::default().value_log_threshold(100);
+    let store = GrausDb::open_with_options(temp_dir.path(), options.clone())?;
+    for key_id in 0..10 {
+        store.set(
+            format!("key{}", key_id).into_bytes(),
+            &large_value(key_id, 1000),
+        )?;
+    }
+    drop(store);
+
+    // Every write compacts the logs
+    let options = options.compaction_strategy(Arc::new(ThresholdStrategy { threshold: 0 }));
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    store.set(b"key0".to_vec(), &large_value(100, 1000))?;
+    assert_eq!(store.compaction_count(), 1);
+    assert!(value_log_exists(temp_dir.path(), 1));
+
+    for key_id in 1..6 {
+        store.set(
+            format!("key{}", key_id).into_bytes(),
+            &large_value(100 + key_id, 1000),
+        )?;
+    }
+    assert!(!value_log_exists(temp_dir.path(), 1));
+    for key_id in 0..10 {
+        let expected = if key_id < 6 {
+            large_value(100 + key_id, 1000)
+        } else {
+            large_value(key_id, 1000)
+        };
+        assert_eq!(
+            store.get(format!("key{}", key_id).as_bytes())?,
+            Some(expected)
+        );
+    }
+    Ok(())
+}
+
+// Snapshots should keep reading the values of the value logs deleted by a compaction.
+#[test]
+fn snapshots_keep_value_logs() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = GrausDbOptions::default().value_log_threshold(100);
+    let store = GrausDb::open_with_options(temp_dir.path(), options.clone())?;
+    store.set(b"key".to_vec(), &large_value(1, 1000))?;
+    drop(store);
+
+    let options = options.compaction_strategy(Arc::new(ThresholdStrategy { threshold: 0 }));
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    let snapshot = store.snapshot()?;
+    store.set(b"key".to_vec(), &large_value(2, 1000))?;
+    assert!(value_log_exists(temp_dir.path(), 1));
+    assert_eq!(snapshot.get(b"key")?, Some(large_value(1, 1000)));
+    assert_eq!(store.get(b"key")?, Some(large_value(2, 1000)));
+
+    drop(snapshot);
+    assert!(!value_log_exists(temp_dir.path(), 1));
+    Ok(())
+}
+
+// Chunks appended to a value in a value log should be read after it, and folded with it
+// by compactions.
+#[test]
+fn append_to_separated_value() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = GrausDbOptions::default().value_log_threshold(100);
+    let store = GrausDb::open_with_options(temp_dir.path(), options.clone())?;
+    let mut expected = large_value(1, 1000);
+    store.set(b"key".to_vec(), &expected)?;
+    for i in 0..10 {
+        let chunk = format!("{},", i).into_bytes();
+        store.append(b"key".to_vec(), &chunk)?;
+        expected.extend_from_slice(&chunk);
+    }
+    assert_eq!(store.get(b"key")?, Some(expected.clone()));
+    let mut streamed = Vec::new();
+    store
+        .get_stream(b"key")?
+        .expect("key not found")
+        .read_to_end(&mut streamed)?;
+    assert_eq!(streamed, expected);
+    drop(store);
+
+    let options = options.compaction_strategy(Arc::new(ThresholdStrategy { threshold: 0 }));
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    store.set(b"other".to_vec(), b"value")?;
+    let (_, cmd_pos) = store
+        .index_snapshot()
+        .into_iter()
+        .find(|(key, _)| key == b"key")
+        .expect("key not found");
+    assert!(cmd_pos.is_separated());
+    assert_eq!(cmd_pos.value_len, expected.len() as u64);
+    assert_eq!(store.get(b"key")?, Some(expected));
+    assert!(!value_log_exists(temp_dir.path(), 1));
+    Ok(())
+}
+
+// Copies of the database should have their own value logs.
+#[test]
+fn compact_into_copies_value_logs() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let dest_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = GrausDbOptions::default().value_log_threshold(100);
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    store.set(b"small".to_vec(), b"value")?;
+    store.set(b"large".to_vec(), &large_value(1, 1000))?;
+    store.compact_into(dest_dir.path())?;
+    drop(store);
+
+    assert!(value_log_exists(dest_dir.path(), 1));
+    assert!(dest_dir.path().join("manifest").exists());
+    let copy = GrausDb::open(dest_dir.path())?;
+    assert_eq!(copy.get(b"small")?, Some(b"value".to_vec()));
+    assert_eq!(copy.get(b"large")?, Some(large_value(1, 1000)));
+    Ok(())
+}
+
+// Value logs should be rejected with the options they can't work with.
+#[test]
+fn value_logs_with_incompatible_options_fail_to_open() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mirror_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = GrausDbOptions::default()
+        .value_log_threshold(100)
+        .storage(Arc::new(MemoryStorage::default()));
+    assert!(matches!(
+        GrausDb::open_with_options(temp_dir.path(), options),
+        Err(GrausError::IncompatibleOptions(_))
+    ));
+    let options = GrausDbOptions::default()
+        .value_log_threshold(100)
+        .mirror(mirror_dir.path(), MirrorMode::Required);
+    assert!(matches!(
+        GrausDb::open_with_options(temp_dir.path(), options),
+        Err(GrausError::IncompatibleOptions(_))
+    ));
+}
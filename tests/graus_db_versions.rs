@@ -0,0 +1,174 @@
+use graus_db::{GrausDb, GrausDbOptions, Result, ThresholdStrategy};
+use std::sync::Arc;
+use std::thread;
+use tempfile::TempDir;
+
+// Should increase the version of a key on every write and keep it after reopening
+#[test]
+fn get_versioned_increases_version() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    assert_eq!(store.get_versioned(b"key1")?, None);
+
+    store.set(b"key1".to_vec(), b"value1")?;
+    assert_eq!(store.get_versioned(b"key1")?, Some((b"value1".to_vec(), 1)));
+    store.set(b"key1".to_vec(), b"value2")?;
+    assert_eq!(store.get_versioned(b"key1")?, Some((b"value2".to_vec(), 2)));
+
+    // Open from disk again and check persistent data
+    drop(store);
+    let store = GrausDb::open(temp_dir.path())?;
+    assert_eq!(store.get_versioned(b"key1")?, Some((b"value2".to_vec(), 2)));
+
+    // The version keeps increasing after the key is removed, as it is the sequence number
+    // of the write
+    store.remove(b"key1")?;
+    store.set(b"key1".to_vec(), b"value3")?;
+    assert_eq!(store.get_versioned(b"key1")?, Some((b"value3".to_vec(), 4)));
+    Ok(())
+}
+
+// Should only write the value when the version matches
+#[test]
+fn set_if_version() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+
+    assert!(!store.set_if_version(b"key1".to_vec(), b"value1", 1)?);
+    assert!(store.set_if_version(b"key1".to_vec(), b"value1", 0)?);
+    assert!(!store.set_if_version(b"key1".to_vec(), b"value2", 0)?);
+    assert!(store.set_if_version(b"key1".to_vec(), b"value2", 1)?);
+    assert_eq!(store.get_versioned(b"key1")?, Some((b"value2".to_vec(), 2)));
+    Ok(())
+}
+
+// Should not match a version read before the key was removed and written again
+#[test]
+fn set_if_version_after_remove() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"key1".to_vec(), b"value1")?;
+    let (_, old_version) = store.get_versioned(b"key1")?.unwrap();
+    store.remove(b"key1")?;
+    store.set(b"key1".to_vec(), b"value2")?;
+
+    assert!(!store.set_if_version(b"key1".to_vec(), b"value3", old_version)?);
+    assert_eq!(store.get(b"key1")?, Some(b"value2".to_vec()));
+    Ok(())
+}
+
+// Should only write the value when the key exists
+#[test]
+fn set_if_exists() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+
+    assert!(!store.set_if_exists(b"key1".to_vec(), b"value1")?);
+    assert_eq!(store.get(b"key1")?, None);
+    store.set(b"key1".to_vec(), b"value1")?;
+    assert!(store.set_if_exists(b"key1".to_vec(), b"value2")?);
+    assert_eq!(store.get_versioned(b"key1")?, Some((b"value2".to_vec(), 2)));
+
+    store.remove(b"key1")?;
+    assert!(!store.set_if_exists(b"key1".to_vec(), b"value3")?);
+    assert_eq!(store.get(b"key1")?, None);
+    Ok(())
+}
+
+// Should apply exactly one write per version when threads race on the same key
+#[test]
+fn concurrent_set_if_version() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    store.set(b"counter".to_vec(), &0u64.to_le_bytes())?;
+
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            let store = store.clone();
+            thread::spawn(move || {
+                for _ in 0..100 {
+                    loop {
+                        let (value, version) = store.get_versioned(b"counter").unwrap().unwrap();
+                        let counter = u64::from_le_bytes(value.try_into().unwrap()) + 1;
+                        if store
+                            .set_if_version(b"counter".to_vec(), &counter.to_le_bytes(), version)
+                            .unwrap()
+                        {
+                            break;
+                        }
+                    }
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    let (value, version) = store.get_versioned(b"counter")?.unwrap();
+    assert_eq!(u64::from_le_bytes(value.try_into().unwrap()), 400);
+    assert_eq!(version, 401);
+    Ok(())
+}
+
+// Should number every write in order and resume the numbering after reopening
+#[test]
+fn current_seq_orders_writes() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = GrausDb::open(temp_dir.path())?;
+    assert_eq!(store.current_seq(), 0);
+    store.set(b"key1".to_vec(), b"value1")?;
+    store.append(b"key1".to_vec(), b"!")?;
+    store.set(b"key2".to_vec(), b"value2")?;
+    store.remove(b"key2")?;
+    assert_eq!(store.current_seq(), 4);
+    // Failed writes are not numbered
+    assert!(store.remove(b"key2").is_err());
+    assert_eq!(store.current_seq(), 4);
+    drop(store);
+
+    let store = GrausDb::open(temp_dir.path())?;
+    assert_eq!(store.current_seq(), 4);
+    let threads: Vec<_> = (0..8)
+        .map(|i| {
+            let store = store.clone();
+            thread::spawn(move || store.set(format!("key{}", i).into_bytes(), b"value"))
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap()?;
+    }
+    assert_eq!(store.current_seq(), 12);
+    drop(store);
+
+    // The index snapshot keeps the numbering without replaying the logs
+    let options = GrausDbOptions::default().index_snapshot(true);
+    let store = GrausDb::open_with_options(temp_dir.path(), options.clone())?;
+    store.set(b"key1".to_vec(), b"value3")?;
+    store.close()?;
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    assert_eq!(store.current_seq(), 13);
+    Ok(())
+}
+
+// Should not reuse the numbers of the writes dropped by a compaction after reopening
+#[test]
+fn current_seq_survives_compaction() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options =
+        GrausDbOptions::default().compaction_strategy(Arc::new(ThresholdStrategy { threshold: 0 }));
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    store.set(b"key1".to_vec(), b"value1")?;
+    store.set(b"key2".to_vec(), b"value2")?;
+    store.remove(b"key2")?;
+    assert!(store.compaction_count() > 0);
+    let seq = store.current_seq();
+    assert_eq!(seq, 3);
+    drop(store);
+
+    let store = GrausDb::open(temp_dir.path())?;
+    assert_eq!(store.current_seq(), seq);
+    store.set(b"key2".to_vec(), b"value3")?;
+    assert_eq!(store.current_seq(), seq + 1);
+    Ok(())
+}
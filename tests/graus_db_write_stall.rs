@@ -0,0 +1,103 @@
+use graus_db::{Executor, GrausDb, GrausDbOptions, GrausError, Result, Task, ThresholdStrategy};
+use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
+
+// Executor that keeps the tasks until they are run explicitly
+#[derive(Default)]
+struct ManualExecutor {
+    tasks: Mutex<Vec<Task>>,
+}
+
+impl ManualExecutor {
+    fn run_all(&self) {
+        let tasks: Vec<Task> = self.tasks.lock().unwrap().drain(..).collect();
+        for task in tasks {
+            task();
+        }
+    }
+}
+
+impl Executor for ManualExecutor {
+    fn execute(&self, task: Task) {
+        self.tasks.lock().unwrap().push(task);
+    }
+}
+
+// The compaction strategy never compacts, only the high-water marks do
+fn never_compact() -> GrausDbOptions {
+    GrausDbOptions::default().compaction_strategy(Arc::new(ThresholdStrategy {
+        threshold: u64::MAX,
+    }))
+}
+
+// Stalled writes should compact the logs during the write when there is no executor
+#[test]
+fn stalled_writes_compact_inline() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = never_compact().uncompacted_high_water_mark(4096);
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    for i in 0..1000 {
+        store.set(b"key".to_vec(), format!("value{}", i).as_bytes())?;
+    }
+    assert!(store.compaction_count() > 0);
+    assert!(store.compaction_estimate().dead_bytes < 8192);
+    assert_eq!(store.get(b"key")?, Some(b"value999".to_vec()));
+    Ok(())
+}
+
+// Writes should fail while the live data exceeds the disk size mark, but not removes
+#[test]
+fn disk_size_mark_rejects_writes() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = never_compact().disk_size_high_water_mark(4096);
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    let mut written = 0;
+    let error = loop {
+        match store.set(format!("key{}", written).into_bytes(), &[0; 100]) {
+            Ok(()) => written += 1,
+            Err(e) => break e,
+        }
+    };
+    assert!(matches!(error, GrausError::WriteStalled));
+    assert!(written > 0);
+    assert!(matches!(
+        store.append(b"key0".to_vec(), b"chunk"),
+        Err(GrausError::WriteStalled)
+    ));
+
+    // Removing keys lets the next write compact the logs below the mark
+    for i in 0..written {
+        store.remove(format!("key{}", i).as_bytes())?;
+    }
+    store.set(b"key".to_vec(), b"value")?;
+    assert!(store.compaction_count() > 0);
+    assert_eq!(store.get(b"key")?, Some(b"value".to_vec()));
+    Ok(())
+}
+
+// With an executor, writes should fail until the scheduled compaction runs
+#[test]
+fn stalled_writes_wait_for_background_compaction() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let executor = Arc::new(ManualExecutor::default());
+    let options = never_compact()
+        .uncompacted_high_water_mark(1024)
+        .executor(executor.clone());
+    let store = GrausDb::open_with_options(temp_dir.path(), options)?;
+    let error = loop {
+        if let Err(e) = store.set(b"key".to_vec(), b"value") {
+            break e;
+        }
+    };
+    assert!(matches!(error, GrausError::WriteStalled));
+    assert!(matches!(
+        store.set(b"key".to_vec(), b"value"),
+        Err(GrausError::WriteStalled)
+    ));
+
+    executor.run_all();
+    assert_eq!(store.compaction_count(), 1);
+    store.set(b"key".to_vec(), b"new value")?;
+    assert_eq!(store.get(b"key")?, Some(b"new value".to_vec()));
+    Ok(())
+}